@@ -0,0 +1,128 @@
+use crate::{rfr::DFHackExt, DFMapCoords, BASE};
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// Main subject of an engraved image, drawn as a simplified glyph
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArtGlyph {
+    Creature,
+    Plant,
+    Tree,
+    Shape,
+    Item,
+}
+
+impl ArtGlyph {
+    fn from_df(element_type: &str) -> Option<Self> {
+        match element_type {
+            "CREATURE" => Some(Self::Creature),
+            "PLANT" => Some(Self::Plant),
+            "TREE" => Some(Self::Tree),
+            "SHAPE" => Some(Self::Shape),
+            "ITEM" => Some(Self::Item),
+            _ => None,
+        }
+    }
+
+    /// Silhouette of the glyph, rows going from north to south
+    #[rustfmt::skip]
+    fn pattern(&self) -> [[bool; BASE]; BASE] {
+        match self {
+            ArtGlyph::Creature => [
+                [false, true, false],
+                [true, true, true],
+                [true, false, true],
+            ],
+            ArtGlyph::Plant => [
+                [true, false, true],
+                [false, true, false],
+                [false, true, false],
+            ],
+            ArtGlyph::Tree => [
+                [true, true, true],
+                [false, true, false],
+                [false, true, false],
+            ],
+            ArtGlyph::Shape => [
+                [true, true, true],
+                [true, false, true],
+                [true, true, true],
+            ],
+            ArtGlyph::Item => [
+                [true, false, false],
+                [false, true, false],
+                [false, false, true],
+            ],
+        }
+    }
+
+    /// Recolor the top surface of the center tile of a building model with the glyph
+    pub fn stamp(&self, model: &mut dot_vox::Model, color: u8) {
+        let base = BASE as u8;
+        // model y is going north, the center tile is the one the pattern is drawn on
+        let origin_x = (model.size.x as u8 / base / 2) * base;
+        let origin_y = (model.size.y as u8 / base / 2) * base;
+        for (row, line) in self.pattern().iter().enumerate() {
+            for (column, engraved) in line.iter().enumerate() {
+                if !engraved {
+                    continue;
+                }
+                let x = origin_x + column as u8;
+                let y = origin_y + base - 1 - row as u8;
+                if let Some(voxel) = model
+                    .voxels
+                    .iter_mut()
+                    .filter(|voxel| voxel.x == x && voxel.y == y)
+                    .max_by_key(|voxel| voxel.z)
+                {
+                    voxel.i = color;
+                }
+            }
+        }
+    }
+}
+
+/// Read the subject of the first image engraved on the items of the buildings,
+/// by building origin
+pub fn read_art_images(
+    client: &mut dfhack_remote::Client,
+) -> Result<HashMap<DFMapCoords, ArtGlyph>> {
+    let output = client.lua_output(
+        r#"local function subject(item)
+  if not df.item_constructed:is_instance(item) then return nil end
+  for _, improvement in ipairs(item.improvements) do
+    if df.itemimprovement_art_imagest:is_instance(improvement) then
+      local ref = improvement.image
+      -- The chunks of images are only loaded when viewed in game
+      local ok, element_type = pcall(function()
+        local chunk = df.art_image_chunk.find(ref.id)
+        local image = chunk and chunk.images[ref.subid]
+        if image and #image.elements > 0 then
+          return df.art_image_element_type[image.elements[0]:getType()]
+        end
+      end)
+      if ok and element_type then return element_type end
+    end
+  end
+end
+for _, building in ipairs(df.global.world.buildings.all) do
+  for _, contained in ipairs(building.contained_items) do
+    local element_type = subject(contained.item)
+    if element_type then
+      emit(building.x1, building.y1, building.z, element_type)
+      break
+    end
+  end
+end"#,
+    )?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let x = parts.next()?.parse().ok()?;
+            let y = parts.next()?.parse().ok()?;
+            let z = parts.next()?.parse().ok()?;
+            Some((DFMapCoords::new(x, y, z), ArtGlyph::from_df(parts.next()?)?))
+        })
+        .collect())
+}
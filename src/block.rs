@@ -1,121 +1,214 @@
-use std::collections::HashMap;
-
-use dfhack_remote::MapBlock;
-use dot_vox::{Model, Size};
-use itertools::Itertools;
-
-use crate::{
-    context::DFContext,
-    coords::DotVoxModelCoords,
-    dot_vox_builder::{DotVoxBuilder, NodeId},
-    export::{Layers, Models},
-    flow::FlowInfoExt,
-    rfr, WithDFCoords, BASE, HEIGHT,
-};
-
-pub const BLOCK_SIZE: usize = 16;
-
-pub const BLOCK_VOX_SIZE: Size = Size {
-    x: (BLOCK_SIZE * BASE) as u32,
-    y: (BLOCK_SIZE * BASE) as u32,
-    z: HEIGHT as u32,
-};
-
-/// All the voxel models constituing a block
-#[derive(Default)]
-pub struct BlockModels {
-    pub models: HashMap<Layers, Model>,
-}
-
-pub fn build(
-    block: &MapBlock,
-    map: &crate::map::Map,
-    context: &DFContext,
-    vox: &mut DotVoxBuilder,
-    palette: &mut crate::palette::Palette,
-    level_group_id: NodeId,
-) {
-    // Collect all the tiles of the block
-    let tiles: Vec<_> = rfr::TileIterator::new(block, &context.tile_types).collect();
-
-    if tiles.is_empty() {
-        // The block is empty, skip the construction
-        return;
-    }
-
-    // Create the parent group for all the objects of this block
-    let x = block.map_x() * BASE as i32 - context.max_vox_x() + 24;
-    let y = context.max_vox_y() - block.map_y() * BASE as i32 - 23;
-
-    if tiles.iter().all(|t| t.hidden()) {
-        // The full block is hidden, skip the construction and add the
-        // hidden model to save space
-        let block_group = vox.insert_group_node_simple(
-            level_group_id,
-            format!("block {} {}", block.map_x(), block.map_y(),),
-            Some(DotVoxModelCoords::new(x, y, 0)),
-            Layers::All.id(),
-        );
-        vox.insert_shape_node_simple(
-            block_group,
-            "hidden",
-            None,
-            Layers::Hidden.id(),
-            Models::HiddenBlock.id(),
-        );
-        return;
-    }
-
-    let mut models = BlockModels::default();
-
-    for tile in tiles {
-        tile.build(&mut models, map, context, palette);
-
-        for flow in block
-            .flows
-            .iter()
-            .filter(|flow| flow.coords() == tile.global_coords())
-        {
-            models.extend(Layers::Flows, flow.build(context, palette));
-        }
-    }
-
-    if models.is_empty() {
-        // Empty groups are shown as big cubes, skip
-        return;
-    }
-
-    let block_group = vox.insert_group_node_simple(
-        level_group_id,
-        format!("block {} {}", block.map_x(), block.map_y(),),
-        Some(DotVoxModelCoords::new(x, y, 0)),
-        Layers::All.id(),
-    );
-
-    models.build(vox, block_group);
-}
-
-impl BlockModels {
-    pub fn is_empty(&self) -> bool {
-        self.models.values().all(|m| m.voxels.is_empty())
-    }
-
-    pub fn get(&mut self, layer: Layers) -> &mut Model {
-        self.models
-            .entry(layer)
-            .or_insert_with(|| DotVoxBuilder::new_model(BLOCK_VOX_SIZE))
-    }
-
-    pub fn extend(&mut self, layer: Layers, voxels: impl IntoIterator<Item = dot_vox::Voxel>) {
-        self.get(layer).voxels.extend(voxels);
-    }
-
-    pub fn build(self, vox: &mut DotVoxBuilder, group_id: NodeId) {
-        for (layer, model) in self.models.into_iter().sorted_by_key(|(l, _)| *l).rev() {
-            if model.voxels.is_empty() {
-                continue;
-            }
-            vox.insert_model_and_shape_node(group_id, None, model, layer.id(), layer.to_string());
-        }
-    }
-}
+use std::collections::HashMap;
+
+use dfhack_remote::MapBlock;
+use dot_vox::{Model, Size};
+use itertools::Itertools;
+
+use crate::{
+    context::DFContext,
+    coords::DotVoxModelCoords,
+    dot_vox_builder::{DotVoxBuilder, NodeId},
+    export::{Layers, Models},
+    flow::{self, FlowInfoExt},
+    rfr, WithDFCoords, BASE, HEIGHT,
+};
+
+pub const BLOCK_SIZE: usize = 16;
+
+pub const BLOCK_VOX_SIZE: Size = Size {
+    x: (BLOCK_SIZE * BASE) as u32,
+    y: (BLOCK_SIZE * BASE) as u32,
+    z: HEIGHT as u32,
+};
+
+/// All the voxel models constituing a block
+#[derive(Default)]
+pub struct BlockModels {
+    pub models: HashMap<Layers, Model>,
+    /// Models with one frame per animation step
+    pub animated: HashMap<Layers, Vec<Model>>,
+}
+
+/// Models of a block, built apart from the scene so that the blocks can be built in parallel
+pub enum BlockContent {
+    /// All the tiles are hidden, the shared hidden model is used instead
+    Hidden,
+    Models(BlockModels),
+}
+
+/// Build the models of several blocks on all the cores, in the order of the blocks
+pub fn build_contents(
+    blocks: &[&MapBlock],
+    map: &crate::map::Map,
+    context: &DFContext,
+    palette: &crate::palette::Palette,
+) -> Vec<Option<BlockContent>> {
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    let chunk_size = blocks.len().div_ceil(threads).max(1);
+    std::thread::scope(|scope| {
+        let handles = blocks
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|block| build_content(block, map, context, palette))
+                        .collect_vec()
+                })
+            })
+            .collect_vec();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+/// Build the models of a block, None if there is nothing to show
+pub fn build_content(
+    block: &MapBlock,
+    map: &crate::map::Map,
+    context: &DFContext,
+    palette: &crate::palette::Palette,
+) -> Option<BlockContent> {
+    // Collect all the tiles of the block
+    let tiles: Vec<_> = rfr::TileIterator::new(block, &context.tile_types).collect();
+
+    if tiles.is_empty() {
+        // The block is empty, skip the construction
+        return None;
+    }
+
+    if tiles.iter().all(|t| t.hidden()) {
+        // The full block is hidden, skip the construction and use the
+        // hidden model to save space
+        return Some(BlockContent::Hidden);
+    }
+
+    let mut models = BlockModels::default();
+
+    for tile in tiles {
+        tile.build(&mut models, map, context, palette);
+
+        if let Some(warmth) = context.warm_rock.get(&tile.global_coords()) {
+            let crust = flow::build_cooling_crust(&tile, *warmth, context, palette);
+            models.extend(Layers::Flows, crust);
+        }
+
+        let frames = context.settings.options.flow_frames;
+        for flow in block
+            .flows
+            .iter()
+            .filter(|_| !context.settings.options.terrain_only)
+            .filter(|flow| flow.coords() == tile.global_coords())
+        {
+            if frames > 1 {
+                for frame in 0..frames {
+                    models.extend_frame(
+                        Layers::Flows,
+                        frame as usize,
+                        frames as usize,
+                        flow.build(map, context, palette, frame, frames),
+                    );
+                }
+            } else {
+                models.extend(Layers::Flows, flow.build(map, context, palette, 0, 0));
+            }
+        }
+    }
+
+    if models.is_empty() {
+        // Empty groups are shown as big cubes, skip
+        return None;
+    }
+    Some(BlockContent::Models(models))
+}
+
+/// Insert the models of a block in the level group
+pub fn insert(
+    block: &MapBlock,
+    content: BlockContent,
+    context: &DFContext,
+    vox: &mut DotVoxBuilder,
+    level_group_id: NodeId,
+) {
+    // Create the parent group for all the objects of this block
+    let x = block.map_x() * BASE as i32 - context.max_vox_x() + 24;
+    let y = context.max_vox_y() - block.map_y() * BASE as i32 - 23;
+    let block_group = vox.insert_group_node_simple(
+        level_group_id,
+        format!("block {} {}", block.map_x(), block.map_y(),),
+        Some(DotVoxModelCoords::new(x, y, 0)),
+        Layers::All.id(),
+    );
+
+    match content {
+        BlockContent::Hidden => {
+            vox.insert_shape_node_simple(
+                block_group,
+                "hidden",
+                None,
+                context.layer_rules.layer(Layers::Hidden),
+                Models::HiddenBlock.id(),
+            );
+        }
+        BlockContent::Models(models) => models.build(vox, block_group, context),
+    }
+}
+
+impl BlockModels {
+    pub fn is_empty(&self) -> bool {
+        self.models.values().all(|m| m.voxels.is_empty())
+            && self
+                .animated
+                .values()
+                .flatten()
+                .all(|m| m.voxels.is_empty())
+    }
+
+    pub fn get(&mut self, layer: Layers) -> &mut Model {
+        self.models
+            .entry(layer)
+            .or_insert_with(|| DotVoxBuilder::new_model(BLOCK_VOX_SIZE))
+    }
+
+    pub fn extend(&mut self, layer: Layers, voxels: impl IntoIterator<Item = dot_vox::Voxel>) {
+        self.get(layer).voxels.extend(voxels);
+    }
+
+    pub fn extend_frame(
+        &mut self,
+        layer: Layers,
+        frame: usize,
+        frames: usize,
+        voxels: impl IntoIterator<Item = dot_vox::Voxel>,
+    ) {
+        let models = self.animated.entry(layer).or_insert_with(|| {
+            (0..frames)
+                .map(|_| DotVoxBuilder::new_model(BLOCK_VOX_SIZE))
+                .collect()
+        });
+        models[frame].voxels.extend(voxels);
+    }
+
+    pub fn build(self, vox: &mut DotVoxBuilder, group_id: NodeId, context: &DFContext) {
+        for (layer, model) in self.models.into_iter().sorted_by_key(|(l, _)| *l).rev() {
+            if model.voxels.is_empty() {
+                continue;
+            }
+            let layer_id = context.layer_rules.layer(layer);
+            vox.insert_model_and_shape_node(group_id, None, model, layer_id, layer.to_string());
+        }
+        for (layer, models) in self.animated.into_iter().sorted_by_key(|(l, _)| *l).rev() {
+            if models.iter().all(|model| model.voxels.is_empty()) {
+                continue;
+            }
+            vox.insert_animated_models_and_shape_node(
+                group_id,
+                models,
+                context.layer_rules.layer(layer),
+                layer.to_string(),
+            );
+        }
+    }
+}
@@ -1,124 +1,589 @@
-use crate::{
-    context::DFContext,
-    coords::WithBoundingBox,
-    direction::DirectionFlat,
-    dot_vox_builder::{DotVoxBuilder, NodeId},
-    export::Layers,
-    map::Map,
-    prefabs::FromPrefab,
-    DFBoundingBox, DFMapCoords, WithDFCoords,
-};
-use dfhack_remote::{BuildingInstance, MatPair};
-use easy_ext::ext;
-
-impl WithDFCoords for BuildingInstance {
-    fn coords(&self) -> DFMapCoords {
-        DFMapCoords::new(self.pos_x_min(), self.pos_y_min(), self.pos_z_min())
-    }
-}
-
-impl FromPrefab for BuildingInstance {
-    fn build_materials(&self) -> Box<dyn Iterator<Item = MatPair> + '_> {
-        Box::new(
-            self.items
-                .iter()
-                .filter_map(|item| {
-                    if item.mode() == 2 {
-                        Some(item.item.material.get_or_default().to_owned())
-                    } else {
-                        None
-                    }
-                })
-                .cycle(),
-        )
-    }
-
-    fn content_materials(&self) -> Box<dyn Iterator<Item = MatPair> + '_> {
-        Box::new(self.items.iter().filter_map(|item| {
-            if item.mode() != 2 {
-                Some(item.item.material.get_or_default().to_owned())
-            } else {
-                None
-            }
-        }))
-    }
-
-    fn df_orientation(&self) -> Option<DirectionFlat> {
-        self.direction
-            .and_then(|dir| dir.enum_value().ok())
-            .and_then(|dir| DirectionFlat::maybe_from_df(&dir))
-    }
-
-    fn self_connectivity(
-        &self,
-        map: &Map,
-        context: &DFContext,
-    ) -> crate::direction::NeighbouringFlat<bool> {
-        let def = context.building_definition(&self.building_type);
-        let coords = self.coords();
-        map.neighbouring_flat(coords, |o| {
-            o.buildings
-                .iter()
-                .any(|b| def == context.building_definition(&b.building_type))
-        })
-    }
-}
-
-impl WithBoundingBox for BuildingInstance {
-    fn bounding_box(&self) -> DFBoundingBox {
-        DFBoundingBox::new(
-            self.pos_x_min()..=self.pos_x_max(),
-            self.pos_y_min()..=self.pos_y_max(),
-            self.pos_z_min()..=self.pos_z_max(),
-        )
-    }
-}
-
-#[ext(BuildingInstanceExt)]
-pub impl BuildingInstance {
-    fn build(
-        &self,
-        map: &Map,
-        context: &DFContext,
-        vox: &mut DotVoxBuilder,
-        palette: &mut crate::palette::Palette,
-        group: NodeId,
-    ) {
-        if let Some((name, model)) = self.do_build(map, context, palette) {
-            let coords = self
-                .bounding_box()
-                .level_dot_vox_coords()
-                .into_level_global_coords(context.max_vox_x(), context.max_vox_y());
-
-            vox.insert_model_and_shape_node(
-                group,
-                Some(coords),
-                model,
-                Layers::Building.id(),
-                name,
-            );
-        }
-    }
-    fn do_build(
-        &self,
-        map: &crate::map::Map,
-        context: &DFContext,
-        palette: &mut crate::palette::Palette,
-    ) -> Option<(String, dot_vox::Model)> {
-        let building_definition =
-            context.building_definition(self.building_type.get_or_default())?;
-
-        let name = building_definition.name();
-        let prefab = crate::prefabs::MODELS.building(building_definition.id())?;
-        let model = prefab.build(self, map, context, palette);
-        Some((name.to_string(), model))
-    }
-
-    fn is_chair(&self, context: &DFContext) -> bool {
-        if let Some(def) = context.building_definition(&self.building_type) {
-            def.id() == "Chair"
-        } else {
-            false
-        }
-    }
-}
+use crate::{
+    context::DFContext,
+    coords::WithBoundingBox,
+    direction::DirectionFlat,
+    dot_vox_builder::{DotVoxBuilder, NodeId},
+    export::Layers,
+    map::Map,
+    palette::{DefaultMaterials, Material},
+    prefabs::FromPrefab,
+    rfr::{BuildingTypeExt, DFHackExt},
+    DFBoundingBox, DFMapCoords, WithDFCoords, BASE, HEIGHT,
+};
+use anyhow::Result;
+use dfhack_remote::{BuildingInstance, MatPair, TiletypeShape};
+use easy_ext::ext;
+use itertools::Itertools;
+use std::collections::{HashMap, HashSet};
+
+/// Read the descriptions of the statues and of the buildings made of artifacts,
+/// such as "granite statue of Urist McPlanter and dragons", by building origin
+pub fn read_descriptions(
+    client: &mut dfhack_remote::Client,
+) -> Result<HashMap<DFMapCoords, String>> {
+    let output = client.lua_output(
+        r#"local describe = dfhack.items.getReadableDescription or function(item)
+  return dfhack.items.getDescription(item, 0, true)
+end
+for _, building in ipairs(df.global.world.buildings.all) do
+  for _, contained in ipairs(building.contained_items) do
+    local item = contained.item
+    if item.flags.artifact or df.item_statuest:is_instance(item) then
+      emit(building.x1, building.y1, building.z, describe(item))
+      break
+    end
+  end
+end"#,
+    )?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(4, ' ');
+            let x = parts.next()?.parse().ok()?;
+            let y = parts.next()?.parse().ok()?;
+            let z = parts.next()?.parse().ok()?;
+            Some((DFMapCoords::new(x, y, z), parts.next()?.to_string()))
+        })
+        .collect())
+}
+
+/// Content of a coffin or of a nest box
+#[derive(Debug, Clone, Default)]
+pub struct Occupancy {
+    /// Holds a body, or eggs
+    pub occupied: bool,
+    /// Owner of the tomb the coffin is in
+    pub owner: Option<String>,
+}
+
+/// Read the content of the coffins and nest boxes, by building origin
+pub fn read_occupancies(
+    client: &mut dfhack_remote::Client,
+) -> Result<HashMap<DFMapCoords, Occupancy>> {
+    let output = client.lua_output(
+        r#"local name = dfhack.units.getReadableName or function(unit)
+  return dfhack.TranslateName(dfhack.units.getVisibleName(unit))
+end
+local function holds(building, is_content)
+  for _, contained in ipairs(building.contained_items) do
+    if contained.use_mode == 0 and is_content(contained.item) then return 1 end
+  end
+  return 0
+end
+for _, coffin in ipairs(df.global.world.buildings.other.COFFIN) do
+  local owner = ""
+  for _, zone in ipairs(coffin.relations) do
+    local unit = zone.type == df.civzone_type.Tomb and df.unit.find(zone.assigned_unit_id)
+    if unit then owner = name(unit) end
+  end
+  emit(coffin.x1, coffin.y1, coffin.z, holds(coffin, function() return true end), owner)
+end
+for _, box in ipairs(df.global.world.buildings.other.NEST_BOX) do
+  emit(box.x1, box.y1, box.z, holds(box, function(item) return df.item_eggst:is_instance(item) end))
+end"#,
+    )?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(5, ' ');
+            let x = parts.next()?.parse().ok()?;
+            let y = parts.next()?.parse().ok()?;
+            let z = parts.next()?.parse().ok()?;
+            let occupied = parts.next()?.trim() == "1";
+            let owner = parts
+                .next()
+                .map(str::trim)
+                .filter(|owner| !owner.is_empty())
+                .map(str::to_string);
+            Some((DFMapCoords::new(x, y, z), Occupancy { occupied, owner }))
+        })
+        .collect())
+}
+
+/// Read the levels holding a coffin or a tomb zone, in map coordinates, sorted
+pub fn read_burial_levels(client: &mut dfhack_remote::Client) -> Result<Vec<i32>> {
+    let output = client.lua_output(
+        r#"for _, coffin in ipairs(df.global.world.buildings.other.COFFIN) do
+  emit(coffin.z)
+end
+local ok, tombs = pcall(function() return df.global.world.buildings.other.ZONE_TOMB end)
+if ok then
+  for _, tomb in ipairs(tombs) do
+    emit(tomb.z)
+  end
+end"#,
+    )?;
+    Ok(output
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .sorted()
+        .dedup()
+        .collect())
+}
+
+/// Read the progress of the unfinished constructions, by building origin
+///
+/// Each step of a construction counts the same: every material brought to it, then
+/// a dwarf at work on it. The construction itself appears once the work is over.
+pub fn read_construction_progress(
+    client: &mut dfhack_remote::Client,
+) -> Result<HashMap<DFMapCoords, f32>> {
+    let output = client.lua_output(
+        r#"local function remaining(job)
+  -- The job items were wrapped in a structure in the recent versions
+  local ok, elements = pcall(function() return job.job_items.elements end)
+  if not ok then elements = job.job_items end
+  local count = 0
+  for _, job_item in ipairs(elements) do
+    count = count + math.max(job_item.quantity, 0)
+  end
+  return count
+end
+for _, construction in ipairs(df.global.world.buildings.other.CONSTRUCTION) do
+  local brought = 0
+  for _, contained in ipairs(construction.contained_items) do
+    if contained.use_mode == 2 then brought = brought + 1 end
+  end
+  local needed = 0
+  local worked = 0
+  for _, job in ipairs(construction.jobs) do
+    local left = remaining(job)
+    needed = needed + left
+    -- The dwarf brings the last material, then starts building
+    if left == 0 and dfhack.job.getWorker(job) then worked = 1 end
+  end
+  emit(construction.x1, construction.y1, construction.z, brought + worked, brought + needed + 1)
+end"#,
+    )?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let values: Vec<i32> = line
+                .split_whitespace()
+                .filter_map(|v| v.parse().ok())
+                .collect();
+            match values[..] {
+                [x, y, z, done, steps] => Some((
+                    DFMapCoords::new(x, y, z),
+                    if steps > 0 {
+                        done as f32 / steps as f32
+                    } else {
+                        0.0
+                    },
+                )),
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+/// Read the origin of the raised drawbridges
+pub fn read_raised_bridges(client: &mut dfhack_remote::Client) -> Result<HashSet<DFMapCoords>> {
+    let output = client.lua_output(
+        r#"for _, bridge in ipairs(df.global.world.buildings.other.BRIDGE) do
+  if bridge.gate_flags.raised then
+    emit(bridge.x1, bridge.y1, bridge.z)
+  end
+end"#,
+    )?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let values: Vec<i32> = line
+                .split_whitespace()
+                .filter_map(|v| v.parse().ok())
+                .collect();
+            match values[..] {
+                [x, y, z] => Some(DFMapCoords::new(x, y, z)),
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+impl WithDFCoords for BuildingInstance {
+    fn coords(&self) -> DFMapCoords {
+        DFMapCoords::new(self.pos_x_min(), self.pos_y_min(), self.pos_z_min())
+    }
+}
+
+impl FromPrefab for BuildingInstance {
+    fn build_materials(&self) -> Box<dyn Iterator<Item = MatPair> + '_> {
+        Box::new(
+            self.items
+                .iter()
+                .filter_map(|item| {
+                    if item.mode() == 2 {
+                        Some(item.item.material.get_or_default().to_owned())
+                    } else {
+                        None
+                    }
+                })
+                .cycle(),
+        )
+    }
+
+    fn content_materials(&self) -> Box<dyn Iterator<Item = MatPair> + '_> {
+        Box::new(self.items.iter().filter_map(|item| {
+            if item.mode() != 2 {
+                Some(item.item.material.get_or_default().to_owned())
+            } else {
+                None
+            }
+        }))
+    }
+
+    fn df_orientation(&self) -> Option<DirectionFlat> {
+        self.direction
+            .and_then(|dir| dir.enum_value().ok())
+            .and_then(|dir| DirectionFlat::maybe_from_df(&dir))
+    }
+
+    fn self_connectivity_at(
+        &self,
+        coords: DFMapCoords,
+        map: &Map,
+        _context: &DFContext,
+    ) -> crate::direction::NeighbouringFlat<bool> {
+        // Comparing the keys is enough, and much cheaper than the definitions
+        let key = self.building_type.key();
+        map.neighbouring_flat(coords, |o| {
+            o.buildings.iter().any(|b| b.building_type.key() == key)
+        })
+    }
+}
+
+impl WithBoundingBox for BuildingInstance {
+    fn bounding_box(&self) -> DFBoundingBox {
+        DFBoundingBox::new(
+            self.pos_x_min()..=self.pos_x_max(),
+            self.pos_y_min()..=self.pos_y_max(),
+            self.pos_z_min()..=self.pos_z_max(),
+        )
+    }
+}
+
+#[ext(BuildingInstanceExt)]
+pub impl BuildingInstance {
+    fn build(
+        &self,
+        map: &Map,
+        context: &DFContext,
+        vox: &mut DotVoxBuilder,
+        palette: &crate::palette::Palette,
+        group: NodeId,
+    ) {
+        if let Some((name, model)) = self.do_build(map, context, palette) {
+            let layer = context
+                .building_definition(self.building_type.get_or_default())
+                .map_or_else(
+                    || context.layer_rules.layer(Layers::Building),
+                    |definition| context.layer_rules.building_layer(definition.id()),
+                );
+            let coords = self
+                .bounding_box()
+                .level_dot_vox_coords()
+                .into_level_global_coords(context.max_vox_x(), context.max_vox_y());
+
+            vox.insert_model_and_shape_node(group, Some(coords), model, layer, name);
+        }
+    }
+    fn do_build(
+        &self,
+        map: &crate::map::Map,
+        context: &DFContext,
+        palette: &crate::palette::Palette,
+    ) -> Option<(String, dot_vox::Model)> {
+        let building_definition =
+            context.building_definition(self.building_type.get_or_default())?;
+
+        let occupancy = context.occupancies.get(&self.coords());
+        let name = match (context.descriptions.get(&self.coords()), occupancy) {
+            (Some(description), _) => description.clone(),
+            (
+                None,
+                Some(Occupancy {
+                    owner: Some(owner), ..
+                }),
+            ) => {
+                format!("{} of {owner}", building_definition.name())
+            }
+            _ => building_definition.name().to_string(),
+        };
+        if context.raised_bridges.contains(&self.coords()) {
+            let model = self.build_raised_bridge(context, palette)?;
+            return Some((name, model));
+        }
+        if let Some(progress) = context.construction_progress.get(&self.coords()) {
+            if let Some(construction) = building_definition.id().strip_prefix("Construction/") {
+                let model = self.build_scaffolding(construction, *progress, context, palette);
+                return Some((name, model));
+            }
+        }
+        let prefab = crate::prefabs::MODELS.for_definition(building_definition)?;
+        let mut model = prefab.build(self, map, context, palette);
+        if let Some(glyph) = context.art_images.get(&self.coords()) {
+            if let Some(material) = self.build_materials().next() {
+                glyph.stamp(
+                    &mut model,
+                    palette.get(&Material::DarkGeneric(material), context),
+                );
+            }
+        }
+        if occupancy.is_some_and(|occupancy| occupancy.occupied) {
+            self.add_occupant(&mut model, building_definition.id(), context, palette);
+        }
+        if context.settings.options.sag_bridges && building_definition.id() == "Bridge" {
+            self.sag(&mut model, map);
+        }
+        if context.settings.options.siege_crew
+            && building_definition.id().starts_with("SiegeEngine/")
+        {
+            self.add_crew(&mut model, context, palette);
+        }
+        let tint = self
+            .build_materials()
+            .next()
+            .and_then(|material| context.magma_safety.tint(&material));
+        if let Some(tint) = tint {
+            let tint = palette.get(&tint, context);
+            for voxel in model.voxels.iter_mut() {
+                voxel.i = tint;
+            }
+        }
+        Some((name, model))
+    }
+
+    /// Closed lid with a memorial marker on the occupied coffins, eggs in the nest boxes
+    fn add_occupant(
+        &self,
+        model: &mut dot_vox::Model,
+        id: &str,
+        context: &DFContext,
+        palette: &crate::palette::Palette,
+    ) {
+        let top = model
+            .voxels
+            .iter()
+            .map(|voxel| voxel.z)
+            .max()
+            .unwrap_or_default();
+        match id {
+            "Coffin" => {
+                let lid = model
+                    .voxels
+                    .iter()
+                    .filter(|voxel| voxel.z == top)
+                    .map(|voxel| dot_vox::Voxel {
+                        z: top + 1,
+                        ..*voxel
+                    })
+                    .collect_vec();
+                let center = |voxel: &&dot_vox::Voxel| {
+                    (voxel.x as i32 - 1).pow(2) + (voxel.y as i32 - 1).pow(2)
+                };
+                let marker = lid
+                    .iter()
+                    .min_by_key(center)
+                    .zip(self.build_materials().next());
+                if let Some((marker, material)) = marker {
+                    model.voxels.push(dot_vox::Voxel {
+                        z: top + 2,
+                        i: palette.get(&Material::DarkGeneric(material), context),
+                        ..*marker
+                    });
+                }
+                model.voxels.extend(lid);
+            }
+            "NestBox" => {
+                let egg = palette.get(&Material::Color(240, 230, 205), context);
+                model.voxels.push(dot_vox::Voxel {
+                    x: 1,
+                    y: 1,
+                    z: top + 1,
+                    i: egg,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// Operator standing in the back corner of a siege engine
+    fn add_crew(
+        &self,
+        model: &mut dot_vox::Model,
+        context: &DFContext,
+        palette: &crate::palette::Palette,
+    ) {
+        let Some(facing) = self.df_orientation() else {
+            return;
+        };
+        let (max_x, max_y) = (model.size.x as u8 - 1, model.size.y as u8 - 1);
+        // model y is going north, the crew stands on the left behind the engine
+        let (x, y) = match facing {
+            DirectionFlat::North => (1, 1),
+            DirectionFlat::East => (1, max_y - 1),
+            DirectionFlat::South => (max_x - 1, max_y - 1),
+            DirectionFlat::West => (max_x - 1, 1),
+        };
+        let color = palette.get(&Material::Default(DefaultMaterials::Crew), context);
+        model
+            .voxels
+            .retain(|voxel| voxel.x != x || voxel.y != y || voxel.z > 2);
+        model
+            .voxels
+            .extend((0..3).map(|z| dot_vox::Voxel { x, y, z, i: color }));
+    }
+
+    /// Sag the deck of a bridge hanging over open space between two supports, like a rope bridge
+    fn sag(&self, model: &mut dot_vox::Model, map: &Map) {
+        let bounding_box = self.bounding_box();
+        let (x, y, z) = (bounding_box.x, bounding_box.y, *bounding_box.z.start());
+        let solid = |x: i32, y: i32| {
+            map.occupancy
+                .get(&DFMapCoords::new(x, y, z))
+                .and_then(|occupancy| occupancy.block_tile.as_ref())
+                .is_some_and(|tile| tile.tile_type().shape() != TiletypeShape::EMPTY)
+        };
+        let (center_x, center_y) = ((x.start() + x.end()) / 2, (y.start() + y.end()) / 2);
+        if solid(center_x, center_y) {
+            // Resting on the ground
+            return;
+        }
+        let along_x = solid(x.start() - 1, center_y) && solid(x.end() + 1, center_y);
+        let along_y = solid(center_x, y.start() - 1) && solid(center_x, y.end() + 1);
+        let (span, along_x) = match (along_x, along_y) {
+            // Supported on all sides, hanging along the longest side
+            (true, true) if model.size.x >= model.size.y => (model.size.x, true),
+            (true, false) => (model.size.x, true),
+            (_, true) => (model.size.y, false),
+            (false, false) => return,
+        };
+        // Deepest sag in the middle of the span, in voxels
+        let depth = (span as usize / BASE / 3).min(HEIGHT) as u8;
+        if depth == 0 {
+            return;
+        }
+        // Grow the model on both sides to keep it centered on the level
+        model.size.z += 2 * depth as u32;
+        for voxel in &mut model.voxels {
+            let position = if along_x { voxel.x } else { voxel.y };
+            let t = (position as f32 + 0.5) / span as f32;
+            let sag = (depth as f32 * 4.0 * t * (1.0 - t)).round() as u8;
+            voxel.z = voxel.z + depth - sag;
+        }
+    }
+
+    /// Tiles of the building standing as a wall, ie the edge of a raised bridge
+    ///
+    /// Retracting bridges have no direction, and nothing left standing when raised.
+    fn raised_wall_tiles(&self, context: &DFContext) -> Vec<DFMapCoords> {
+        if !context.raised_bridges.contains(&self.coords()) {
+            return Vec::new();
+        }
+        let Some(direction) = self.df_orientation() else {
+            return Vec::new();
+        };
+        let bounding_box = self.bounding_box();
+        let (x, y, z) = (bounding_box.x, bounding_box.y, *bounding_box.z.start());
+        match direction {
+            DirectionFlat::North => x.map(|x| DFMapCoords::new(x, *y.start(), z)).collect(),
+            DirectionFlat::South => x.map(|x| DFMapCoords::new(x, *y.end(), z)).collect(),
+            DirectionFlat::West => y.map(|y| DFMapCoords::new(*x.start(), y, z)).collect(),
+            DirectionFlat::East => y.map(|y| DFMapCoords::new(*x.end(), y, z)).collect(),
+        }
+    }
+
+    /// A raised bridge is a vertical slab on the edge it was raised towards
+    fn build_raised_bridge(
+        &self,
+        context: &DFContext,
+        palette: &crate::palette::Palette,
+    ) -> Option<dot_vox::Model> {
+        let material = palette.get(&Material::Generic(self.build_materials().next()?), context);
+        let bounding_box = self.bounding_box();
+        let mut model = DotVoxBuilder::new_model(dot_vox::Size::from(bounding_box.dimension()));
+        let base = BASE as u8;
+        // Voxels of the outer side of the edge tiles
+        let (slab_x, slab_y) = match self.df_orientation()? {
+            DirectionFlat::North => (0..base, base - 1..base),
+            DirectionFlat::South => (0..base, 0..1),
+            DirectionFlat::West => (0..1, 0..base),
+            DirectionFlat::East => (base - 1..base, 0..base),
+        };
+        for tile in self.raised_wall_tiles(context) {
+            // model y is going north while df y is going south
+            let tile_x = (tile.x - bounding_box.x.start()) as u8 * base;
+            let tile_y = (bounding_box.y.end() - tile.y) as u8 * base;
+            for x in slab_x.clone() {
+                for y in slab_y.clone() {
+                    for z in 0..model.size.z as u8 {
+                        model.voxels.push(dot_vox::Voxel {
+                            x: tile_x + x,
+                            y: tile_y + y,
+                            z,
+                            i: material,
+                        });
+                    }
+                }
+            }
+        }
+        Some(model)
+    }
+
+    /// Wooden poles at the corners of an unfinished construction, and the layers
+    /// of its final shape filled from the bottom in proportion to its progress
+    fn build_scaffolding(
+        &self,
+        construction: &str,
+        progress: f32,
+        context: &DFContext,
+        palette: &crate::palette::Palette,
+    ) -> dot_vox::Model {
+        let bounding_box = self.bounding_box();
+        let mut model = DotVoxBuilder::new_model(dot_vox::Size::from(bounding_box.dimension()));
+        let (size_x, size_y, size_z) = (model.size.x as u8, model.size.y as u8, model.size.z as u8);
+        let pole = palette.get(&Material::Default(DefaultMaterials::Wood), context);
+        for (x, y) in [
+            (0, 0),
+            (size_x - 1, 0),
+            (0, size_y - 1),
+            (size_x - 1, size_y - 1),
+        ] {
+            model
+                .voxels
+                .extend((0..size_z).map(|z| dot_vox::Voxel { x, y, z, i: pole }));
+        }
+        // Walls and stairs fill the whole tile, ramps half of it, the rest is a floor
+        let height = match construction {
+            "Wall" | "Fortification" | "UpStair" | "DownStair" | "UpDownStair" => size_z,
+            "Ramp" => size_z.div_ceil(2),
+            _ => 1,
+        };
+        let Some(material) = self.build_materials().next() else {
+            return model;
+        };
+        let material = palette.get(&Material::Generic(material), context);
+        let filled = (progress.clamp(0.0, 1.0) * height as f32).round() as u8;
+        for z in 0..filled {
+            for x in 0..size_x {
+                for y in 0..size_y {
+                    let corner = (x == 0 || x == size_x - 1) && (y == 0 || y == size_y - 1);
+                    if !corner {
+                        model.voxels.push(dot_vox::Voxel {
+                            x,
+                            y,
+                            z,
+                            i: material,
+                        });
+                    }
+                }
+            }
+        }
+        model
+    }
+
+    fn is_chair(&self, context: &DFContext) -> bool {
+        context.is_chair(&self.building_type)
+    }
+}
@@ -0,0 +1,114 @@
+use anyhow::{Context, Result};
+use dfhack_remote::BlockRequest;
+
+/// Versions and optional features of the connected DFHack instance
+///
+/// Older DFHack builds do not stream everything, the optional
+/// export passes are skipped when their data is not available.
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    pub df_version: String,
+    pub dfhack_version: String,
+    pub rfr_version: String,
+    /// Blocks carry spatter piles (blood, snow, fallen leaves...)
+    pub spatter_piles: bool,
+    /// Blocks carry the grass coverage of each tile
+    pub grass_percent: bool,
+    /// The images engraved on the buildings can be read
+    pub engravings: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            df_version: "unknown".to_string(),
+            dfhack_version: "unknown".to_string(),
+            rfr_version: "unknown".to_string(),
+            spatter_piles: true,
+            grass_percent: true,
+            engravings: true,
+        }
+    }
+}
+
+impl Capabilities {
+    pub fn probe(client: &mut dfhack_remote::Client) -> Result<Self> {
+        let version_info = client
+            .remote_fortress_reader()
+            .get_version_info()
+            .context("RemoteFortressReader is not available. Is DFHack up to date?")?;
+        let dfhack_version = version_info.dfhack_version().to_string();
+
+        // Request a single block around the view to check which fields are populated
+        let view_info = client.remote_fortress_reader().get_view_info()?;
+        let z = view_info.view_pos_z();
+        client.remote_fortress_reader().reset_map_hashes()?;
+        let block_list = client
+            .remote_fortress_reader()
+            .get_block_list(BlockRequest {
+                blocks_needed: Some(1),
+                min_x: Some(0),
+                max_x: Some(1000),
+                min_y: Some(0),
+                max_y: Some(1000),
+                min_z: Some(z),
+                max_z: Some(z + 1),
+                ..Default::default()
+            })?;
+        let block = block_list.map_blocks.iter().find(|b| !b.tiles.is_empty());
+
+        // When nothing could be probed, optimistically assume the features are there,
+        // the export tolerates missing data anyway
+        let spatter_piles = !block.is_some_and(|b| b.spatterPile.is_empty());
+        let grass_percent = !block.is_some_and(|b| b.grass_percent.is_empty());
+        // Engravings are streamed by the Steam era DFHack releases (50.xx)
+        let engravings = dfhack_major_version(&dfhack_version).is_some_and(|major| major >= 50);
+
+        Ok(Self {
+            df_version: version_info.dwarf_fortress_version().to_string(),
+            dfhack_version,
+            rfr_version: version_info.remote_fortress_reader_version().to_string(),
+            spatter_piles,
+            grass_percent,
+            engravings,
+        })
+    }
+
+    /// Human readable notes about the missing optional features
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let missing = |feature: &str| {
+            format!(
+                "DFHack {} does not provide {}, they will not be exported.",
+                self.dfhack_version, feature
+            )
+        };
+        if !self.spatter_piles {
+            warnings.push(missing("spatters"));
+        }
+        if !self.grass_percent {
+            warnings.push(missing("grass coverage"));
+        }
+        if !self.engravings {
+            warnings.push(missing("engravings"));
+        }
+        warnings
+    }
+}
+
+/// Major version of a DFHack version string, such as "50.13-r2" or "0.47.05-r8"
+fn dfhack_major_version(version: &str) -> Option<u32> {
+    version.split('.').next()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_dfhack_major_version() {
+        assert_eq!(Some(50), dfhack_major_version("50.13-r2"));
+        assert_eq!(Some(0), dfhack_major_version("0.47.05-r8"));
+        assert_eq!(None, dfhack_major_version("unknown"));
+    }
+}
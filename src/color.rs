@@ -0,0 +1,109 @@
+use clap::ValueEnum;
+use palette::Srgb;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter};
+
+/// Space in which the colors are darkened, shifted and graded
+#[derive(Clone, Copy, Debug, PartialEq, Display, EnumIter, Serialize, Deserialize, ValueEnum)]
+pub enum ColorSpace {
+    /// Physically based, the darker shades keep their saturation
+    Linear,
+    /// Perceptual, the shades are closer to the in-game colors
+    Srgb,
+}
+
+/// Color in the working space, with channels from 0 to 1
+#[derive(Debug, Clone, Copy)]
+pub struct WorkingColor {
+    rgb: [f32; 3],
+    space: ColorSpace,
+}
+
+impl WorkingColor {
+    pub fn from_srgb(color: Srgb<u8>, space: ColorSpace) -> Self {
+        let decode = |channel: u8| {
+            let channel = channel as f32 / 255.0;
+            match space {
+                ColorSpace::Srgb => channel,
+                ColorSpace::Linear if channel <= 0.04045 => channel / 12.92,
+                ColorSpace::Linear => ((channel + 0.055) / 1.055).powf(2.4),
+            }
+        };
+        Self {
+            rgb: [decode(color.red), decode(color.green), decode(color.blue)],
+            space,
+        }
+    }
+
+    pub fn into_srgb(self) -> Srgb<u8> {
+        let encode = |channel: f32| {
+            let channel = channel.clamp(0.0, 1.0);
+            let channel = match self.space {
+                ColorSpace::Srgb => channel,
+                ColorSpace::Linear if channel <= 0.0031308 => channel * 12.92,
+                ColorSpace::Linear => 1.055 * channel.powf(1.0 / 2.4) - 0.055,
+            };
+            (channel * 255.0).round() as u8
+        };
+        Srgb::new(
+            encode(self.rgb[0]),
+            encode(self.rgb[1]),
+            encode(self.rgb[2]),
+        )
+    }
+
+    /// Hue in degrees, saturation and value
+    pub fn hsv(&self) -> [f32; 3] {
+        let [r, g, b] = self.rgb;
+        let max = r.max(g).max(b);
+        let delta = max - r.min(g).min(b);
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        let saturation = if max == 0.0 { 0.0 } else { delta / max };
+        [hue, saturation, max]
+    }
+
+    pub fn from_hsv([hue, saturation, value]: [f32; 3], space: ColorSpace) -> Self {
+        let chroma = value * saturation;
+        let sector = hue.rem_euclid(360.0) / 60.0;
+        let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+        let (r, g, b) = match sector as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let m = value - chroma;
+        Self {
+            rgb: [r + m, g + m, b + m],
+            space,
+        }
+    }
+
+    /// Darker color, 0 for the same color and 1 for black
+    pub fn darken(self, amount: f32) -> Self {
+        Self {
+            rgb: self.rgb.map(|channel| channel * (1.0 - amount)),
+            space: self.space,
+        }
+    }
+
+    /// Apply the gamma and the saturation factor of the export
+    pub fn graded(self, gamma: f32, saturation: f32) -> Self {
+        let [hue, s, value] = self.hsv();
+        let graded = Self::from_hsv([hue, (s * saturation).clamp(0.0, 1.0), value], self.space);
+        Self {
+            rgb: graded.rgb.map(|channel| channel.max(0.0).powf(1.0 / gamma)),
+            space: self.space,
+        }
+    }
+}
@@ -1,72 +1,336 @@
-use std::collections::HashMap;
-
-use anyhow::Result;
-use dfhack_remote::{
-    BasicMaterialInfo, BasicMaterialInfoMask, BuildingDefinition, BuildingType, ListEnumsOut,
-    ListMaterialsIn, MapInfo, MaterialList, PlantRawList, TiletypeList,
-};
-use protobuf::MessageField;
-
-use crate::{block::BLOCK_SIZE, export::ExportSettings, rfr::create_building_def_map, BASE};
-
-pub struct DFContext {
-    pub settings: ExportSettings,
-    pub tile_types: TiletypeList,
-    pub materials: MaterialList,
-    pub map_info: MapInfo,
-    pub plant_raws: PlantRawList,
-    pub enums: ListEnumsOut,
-    pub building_map: HashMap<(i32, i32, i32), BuildingDefinition>,
-    pub inorganic_materials_map: HashMap<(i32, i32), BasicMaterialInfo>,
-}
-
-impl DFContext {
-    pub fn try_new(client: &mut dfhack_remote::Client, settings: ExportSettings) -> Result<Self> {
-        let inorganics_materials = client.core().list_materials(ListMaterialsIn {
-            mask: MessageField::some(BasicMaterialInfoMask {
-                flags: Some(true),
-                reaction: Some(true),
-                ..Default::default()
-            }),
-            inorganic: Some(true),
-            builtin: Some(true),
-            ..Default::default()
-        })?;
-        let inorganic_materials_map: HashMap<(i32, i32), BasicMaterialInfo> = inorganics_materials
-            .value
-            .into_iter()
-            .map(|mat| ((mat.type_(), mat.index()), mat))
-            .collect();
-        Ok(Self {
-            settings,
-            tile_types: client.remote_fortress_reader().get_tiletype_list()?,
-            materials: client.remote_fortress_reader().get_material_list()?,
-            map_info: client.remote_fortress_reader().get_map_info()?,
-            plant_raws: client.remote_fortress_reader().get_plant_raws()?,
-            enums: client.core().list_enums()?,
-            building_map: create_building_def_map(
-                client.remote_fortress_reader().get_building_def_list()?,
-            ),
-            inorganic_materials_map,
-        })
-    }
-
-    pub fn building_definition<'a>(
-        &'a self,
-        building_type: &BuildingType,
-    ) -> Option<&'a BuildingDefinition> {
-        self.building_map.get(&(
-            building_type.building_type(),
-            building_type.building_subtype(),
-            building_type.building_custom(),
-        ))
-    }
-
-    pub fn max_vox_x(&self) -> i32 {
-        (self.map_info.block_size_x() * (BLOCK_SIZE * BASE) as i32) / 2
-    }
-
-    pub fn max_vox_y(&self) -> i32 {
-        (self.map_info.block_size_y() * (BLOCK_SIZE * BASE) as i32) / 2
-    }
-}
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "dev")]
+use std::path::Path;
+
+#[cfg(feature = "dev")]
+use anyhow::Context;
+use anyhow::Result;
+use dfhack_remote::{
+    BasicMaterialInfo, BasicMaterialInfoMask, BuildingDefinition, BuildingType, ListEnumsOut,
+    ListMaterialsIn, ListMaterialsOut, MapInfo, MatPair, MaterialDefinition, MaterialList,
+    PlantRawList, TiletypeList,
+};
+use protobuf::MessageField;
+
+use crate::{
+    art::{self, ArtGlyph},
+    block::BLOCK_SIZE,
+    building::{self, Occupancy},
+    capabilities::Capabilities,
+    export::{DataOverlay, ExportOptions, ExportSettings},
+    layer_rules::LayerRules,
+    magma::{self, MagmaSafety},
+    material_table::MaterialTable,
+    rfr::{create_building_def_map, BuildingTypeExt, DFHackExt},
+    DFMapCoords, BASE,
+};
+
+pub struct DFContext {
+    pub settings: ExportSettings,
+    pub capabilities: Capabilities,
+    pub tile_types: TiletypeList,
+    pub materials: MaterialList,
+    /// Position of the materials in `materials`, by (mat_type, mat_index)
+    material_index: HashMap<(i32, i32), usize>,
+    pub map_info: MapInfo,
+    pub plant_raws: PlantRawList,
+    pub enums: ListEnumsOut,
+    pub building_map: HashMap<(i32, i32, i32), BuildingDefinition>,
+    /// Building definitions of the chairs, looked up around each table
+    chairs: HashSet<(i32, i32, i32)>,
+    pub inorganic_materials_map: HashMap<(i32, i32), BasicMaterialInfo>,
+    /// Descriptions of the statues and artifacts, by building origin
+    pub descriptions: HashMap<DFMapCoords, String>,
+    /// Origin of the raised bridges
+    pub raised_bridges: HashSet<DFMapCoords>,
+    /// Content of the coffins and nest boxes, by building origin
+    pub occupancies: HashMap<DFMapCoords, Occupancy>,
+    /// Materials configured by the user
+    pub material_table: MaterialTable,
+    /// Layers rearranged by the user
+    pub layer_rules: LayerRules,
+    /// True if it is raining over the fortress, and the weather effects are enabled
+    pub rain: bool,
+    /// Magma safety of the construction materials, when audited
+    pub magma_safety: MagmaSafety,
+    /// Tiles still warm from the magma, from 0 (cooled down) to 1 (as hot as the magma).
+    /// Only read for the exported levels, along with the blocks
+    pub warm_rock: HashMap<DFMapCoords, f64>,
+    /// Subject of the images engraved on the buildings, by building origin
+    pub art_images: HashMap<DFMapCoords, ArtGlyph>,
+    /// Progress of the unfinished constructions, by building origin
+    pub construction_progress: HashMap<DFMapCoords, f32>,
+    /// Decorative data that could not be read, reported as warnings of the export
+    pub warnings: Vec<String>,
+}
+
+impl DFContext {
+    pub fn try_new(
+        client: &mut dfhack_remote::Client,
+        settings: ExportSettings,
+        capabilities: Capabilities,
+    ) -> Result<Self> {
+        let inorganics_materials = client
+            .core()
+            .list_materials(inorganic_materials_request())?;
+        let inorganic_materials_map = inorganic_materials_map(inorganics_materials);
+        let materials = client.remote_fortress_reader().get_material_list()?;
+        let building_map =
+            create_building_def_map(client.remote_fortress_reader().get_building_def_list()?);
+        let mut context = Self {
+            capabilities,
+            tile_types: client.remote_fortress_reader().get_tiletype_list()?,
+            material_index: material_index(&materials),
+            materials,
+            map_info: client.remote_fortress_reader().get_map_info()?,
+            plant_raws: client.remote_fortress_reader().get_plant_raws()?,
+            enums: client.core().list_enums()?,
+            chairs: chairs(&building_map),
+            building_map,
+            inorganic_materials_map,
+            material_table: MaterialTable::default(),
+            layer_rules: LayerRules::default(),
+            rain: false,
+            magma_safety: MagmaSafety::default(),
+            warm_rock: HashMap::new(),
+            descriptions: HashMap::new(),
+            raised_bridges: HashSet::new(),
+            occupancies: HashMap::new(),
+            art_images: HashMap::new(),
+            construction_progress: HashMap::new(),
+            settings,
+            warnings: Vec::new(),
+        };
+        context.read_options(client, None)?;
+        Ok(context)
+    }
+
+    /// Switch to new settings, only reading again the data of the changed options
+    pub fn update_settings(
+        &mut self,
+        client: &mut dfhack_remote::Client,
+        settings: ExportSettings,
+    ) -> Result<()> {
+        let previous = std::mem::replace(&mut self.settings, settings);
+        self.read_options(client, Some(&previous))
+    }
+
+    /// Read the data depending on the options, all of it without previous settings
+    fn read_options(
+        &mut self,
+        client: &mut dfhack_remote::Client,
+        previous: Option<&ExportSettings>,
+    ) -> Result<()> {
+        let options = &self.settings.options;
+        let changed = |option: &dyn Fn(&ExportOptions) -> bool| {
+            previous.map_or(true, |previous| {
+                option(&previous.options) != option(options)
+            })
+        };
+        if previous.map_or(true, |previous| {
+            previous.options.material_table != options.material_table
+        }) {
+            self.material_table = match &options.material_table {
+                Some(path) => MaterialTable::load(path)?,
+                None => MaterialTable::default(),
+            };
+        }
+        if previous.map_or(true, |previous| {
+            previous.options.layer_rules != options.layer_rules
+        }) {
+            self.layer_rules = match &options.layer_rules {
+                Some(path) => LayerRules::load(path)?,
+                None => LayerRules::default(),
+            };
+        }
+        let warnings = &mut self.warnings;
+        if changed(&|options| options.weather_effects) {
+            self.rain =
+                options.weather_effects && decorative(read_rain(client), "weather", warnings);
+        }
+        if changed(&|options| options.overlays.contains(&DataOverlay::MagmaSafety)) {
+            self.magma_safety = if options.overlays.contains(&DataOverlay::MagmaSafety) {
+                magma::read_magma_safety(client)?
+            } else {
+                MagmaSafety::default()
+            };
+        }
+        if changed(&|options| options.terrain_only) {
+            let terrain_only = options.terrain_only;
+            self.descriptions = if terrain_only {
+                HashMap::new()
+            } else {
+                let descriptions = building::read_descriptions(client);
+                decorative(descriptions, "statue descriptions", warnings)
+            };
+            self.raised_bridges = if terrain_only {
+                HashSet::new()
+            } else {
+                let raised_bridges = building::read_raised_bridges(client);
+                decorative(raised_bridges, "raised bridges", warnings)
+            };
+            self.occupancies = if terrain_only {
+                HashMap::new()
+            } else {
+                let occupancies = building::read_occupancies(client);
+                decorative(occupancies, "coffin occupants", warnings)
+            };
+            self.art_images = if terrain_only || !self.capabilities.engravings {
+                HashMap::new()
+            } else {
+                let art_images = art::read_art_images(client);
+                decorative(art_images, "engraved images", warnings)
+            };
+        }
+        if changed(&|options| options.scaffolding && !options.terrain_only) {
+            self.construction_progress = if options.scaffolding && !options.terrain_only {
+                building::read_construction_progress(client)?
+            } else {
+                HashMap::new()
+            };
+        }
+        Ok(())
+    }
+
+    /// Build the context from the lists saved by `dev regen-test-data`, without Dwarf Fortress
+    #[cfg(feature = "dev")]
+    pub fn from_test_data(folder: &Path, settings: ExportSettings) -> Result<Self> {
+        fn read<M: protobuf::Message>(folder: &Path, filename: &str) -> Result<M> {
+            let path = folder.join(filename);
+            let bytes = std::fs::read(&path).with_context(|| {
+                format!(
+                    "Reading {}, try to run `dev regen-test-data` first",
+                    path.display()
+                )
+            })?;
+            Ok(M::parse_from_bytes(&bytes)?)
+        }
+        let materials = read(folder, "materials.dat")?;
+        let building_map = create_building_def_map(read(folder, "building_defs.dat")?);
+        Ok(Self {
+            settings,
+            capabilities: Capabilities::default(),
+            tile_types: read(folder, "tiletypes.dat")?,
+            material_index: material_index(&materials),
+            materials,
+            map_info: read(folder, "map_info.dat")?,
+            plant_raws: read(folder, "plant_raws.dat")?,
+            enums: read(folder, "enums.dat")?,
+            chairs: chairs(&building_map),
+            building_map,
+            inorganic_materials_map: inorganic_materials_map(read(folder, "inorganics.dat")?),
+            descriptions: HashMap::new(),
+            raised_bridges: HashSet::new(),
+            occupancies: HashMap::new(),
+            material_table: MaterialTable::default(),
+            layer_rules: LayerRules::default(),
+            rain: false,
+            magma_safety: MagmaSafety::default(),
+            warm_rock: HashMap::new(),
+            art_images: HashMap::new(),
+            construction_progress: HashMap::new(),
+            warnings: Vec::new(),
+        })
+    }
+
+    pub fn building_definition<'a>(
+        &'a self,
+        building_type: &BuildingType,
+    ) -> Option<&'a BuildingDefinition> {
+        self.building_map.get(&building_type.key())
+    }
+
+    pub fn is_chair(&self, building_type: &BuildingType) -> bool {
+        self.chairs.contains(&building_type.key())
+    }
+
+    /// Definition of a Dwarf Fortress material
+    pub fn material(&self, matpair: &MatPair) -> Option<&MaterialDefinition> {
+        self.material_index
+            .get(&(matpair.mat_type(), matpair.mat_index()))
+            .and_then(|index| self.materials.material_list.get(*index))
+    }
+
+    pub fn max_vox_x(&self) -> i32 {
+        (self.map_info.block_size_x() * (BLOCK_SIZE * BASE) as i32) / 2
+    }
+
+    pub fn max_vox_y(&self) -> i32 {
+        (self.map_info.block_size_y() * (BLOCK_SIZE * BASE) as i32) / 2
+    }
+}
+
+/// Data only used for decoration, left out with a warning when it can't be read,
+/// as older DFHack versions lack some of the structures
+pub fn decorative<T: Default>(read: Result<T>, what: &str, warnings: &mut Vec<String>) -> T {
+    read.unwrap_or_else(|err| {
+        warnings.push(format!(
+            "Could not read the {what}, left out of the export: {err:#}"
+        ));
+        T::default()
+    })
+}
+
+/// True if it is raining on any part of the embark
+fn read_rain(client: &mut dfhack_remote::Client) -> Result<bool> {
+    let output = client.lua_output(
+        r#"local rain = false
+for _, column in ipairs(df.global.current_weather) do
+  for _, weather in ipairs(column) do
+    if weather == df.weather_type.Rain then
+      rain = true
+      break
+    end
+  end
+  if rain then
+    break
+  end
+end
+if rain then
+  emit("rain")
+end"#,
+    )?;
+    Ok(output.trim() == "rain")
+}
+
+/// Request for the inorganic and builtin materials, with their flags
+pub fn inorganic_materials_request() -> ListMaterialsIn {
+    ListMaterialsIn {
+        mask: MessageField::some(BasicMaterialInfoMask {
+            flags: Some(true),
+            reaction: Some(true),
+            ..Default::default()
+        }),
+        inorganic: Some(true),
+        builtin: Some(true),
+        ..Default::default()
+    }
+}
+
+/// Position of each material in the list, the first one wins on duplicates
+fn material_index(materials: &MaterialList) -> HashMap<(i32, i32), usize> {
+    let mut index = HashMap::new();
+    for (position, material) in materials.material_list.iter().enumerate() {
+        let matpair = material.mat_pair.get_or_default();
+        index
+            .entry((matpair.mat_type(), matpair.mat_index()))
+            .or_insert(position);
+    }
+    index
+}
+
+fn chairs(building_map: &HashMap<(i32, i32, i32), BuildingDefinition>) -> HashSet<(i32, i32, i32)> {
+    building_map
+        .iter()
+        .filter(|(_, definition)| definition.id() == "Chair")
+        .map(|(key, _)| *key)
+        .collect()
+}
+
+fn inorganic_materials_map(materials: ListMaterialsOut) -> HashMap<(i32, i32), BasicMaterialInfo> {
+    materials
+        .value
+        .into_iter()
+        .map(|mat| ((mat.type_(), mat.index()), mat))
+        .collect()
+}
@@ -0,0 +1,67 @@
+use crate::{
+    context::DFContext,
+    map::{Map, Occupancy},
+    overlay::Overlay,
+    palette::{Material, Palette},
+    tile::BlockTileExt,
+    DFMapCoords, VoxelCoords, BASE,
+};
+use dfhack_remote::TiletypeShape;
+use std::ops::Range;
+
+/// Height of the merlons, in voxels
+const MERLON_HEIGHT: usize = 2;
+
+/// Merlons along the exterior edges of the wall tops that are open to the sky
+pub fn build_crenellations(
+    map: &Map,
+    z_range: &Range<i32>,
+    context: &DFContext,
+    palette: &Palette,
+) -> Overlay {
+    let sky = |occupancy: &Occupancy| {
+        occupancy
+            .block_tile
+            .as_ref()
+            .is_some_and(|tile| tile.outside() && tile.tile_type().shape() == TiletypeShape::EMPTY)
+    };
+    let outside = |occupancy: &Occupancy| {
+        occupancy
+            .block_tile
+            .as_ref()
+            .is_some_and(|tile| tile.outside() && !tile.is_wall())
+    };
+
+    let mut overlay = Overlay::default();
+    for (coords, occupancy) in &map.occupancy {
+        let Some(tile) = &occupancy.block_tile else {
+            continue;
+        };
+        if tile.hidden() || !tile.is_wall() || !z_range.contains(&(coords.z + 1)) {
+            continue;
+        }
+        if !map.neighbouring(*coords, sky).a {
+            continue;
+        }
+        let beside = map.neighbouring_flat(*coords, outside);
+        let color = palette.get(&Material::Generic(tile.material().clone()), context);
+        let above = DFMapCoords::new(coords.x, coords.y, coords.z + 1);
+        for sub_x in 0..BASE {
+            for sub_y in 0..BASE {
+                let on_edge = (beside.n && sub_y == 0)
+                    || (beside.s && sub_y == BASE - 1)
+                    || (beside.w && sub_x == 0)
+                    || (beside.e && sub_x == BASE - 1);
+                let base = VoxelCoords::from_df(above, sub_x, sub_y, 0);
+                // Alternate the merlons and the gaps along the whole wall
+                if !on_edge || (base.x + base.y).rem_euclid(2) != 0 {
+                    continue;
+                }
+                for sub_z in 0..MERLON_HEIGHT {
+                    overlay.add(VoxelCoords::from_df(above, sub_x, sub_y, sub_z), color);
+                }
+            }
+        }
+    }
+    overlay
+}
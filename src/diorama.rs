@@ -0,0 +1,94 @@
+use crate::{
+    block::BLOCK_SIZE,
+    context::DFContext,
+    map::Map,
+    overlay::Overlay,
+    palette::{Material, Palette},
+    VoxelCoords, BASE, HEIGHT,
+};
+use dfhack_remote::TiletypeShape;
+use std::{collections::HashMap, ops::Range};
+
+/// Neutral color of the base and of the edge walls
+const DIORAMA_COLOR: Material = Material::Color(110, 110, 110);
+
+/// Size of the map in tiles
+fn map_size(context: &DFContext) -> (i32, i32) {
+    (
+        context.map_info.block_size_x() * BLOCK_SIZE as i32,
+        context.map_info.block_size_y() * BLOCK_SIZE as i32,
+    )
+}
+
+/// Voxel layer right under the lowest exported level
+fn bottom(z_range: &Range<i32>) -> i32 {
+    z_range.start * HEIGHT as i32 - 1
+}
+
+/// Slab one voxel thick under the whole map
+pub fn build_base(z_range: &Range<i32>, context: &DFContext, palette: &Palette) -> Overlay {
+    let color = palette.get(&DIORAMA_COLOR, context);
+    let (width, height) = map_size(context);
+    let mut overlay = Overlay::default();
+    for x in 0..width * BASE as i32 {
+        for y in 0..height * BASE as i32 {
+            overlay.add(VoxelCoords::new(x, y, bottom(z_range)), color);
+        }
+    }
+    overlay
+}
+
+/// Walls one voxel thick around the map, up to the terrain along the edges,
+/// hiding the cut through the ground
+pub fn build_edge_walls(
+    map: &Map,
+    z_range: &Range<i32>,
+    context: &DFContext,
+    palette: &Palette,
+) -> Overlay {
+    let color = palette.get(&DIORAMA_COLOR, context);
+    let (width, height) = map_size(context);
+    let bottom = bottom(z_range);
+
+    // Top voxel of the terrain on the edge tiles
+    let mut tops: HashMap<(i32, i32), i32> = HashMap::new();
+    for (coords, occupancy) in &map.occupancy {
+        let edge =
+            coords.x == 0 || coords.y == 0 || coords.x == width - 1 || coords.y == height - 1;
+        if !edge || !z_range.contains(&coords.z) {
+            continue;
+        }
+        let solid = occupancy.block_tile.as_ref().is_some_and(|tile| {
+            tile.hidden()
+                || !matches!(
+                    tile.tile_type().shape(),
+                    TiletypeShape::EMPTY | TiletypeShape::NO_SHAPE
+                )
+        });
+        if solid {
+            let top = (coords.z + 1) * HEIGHT as i32 - 1;
+            let entry = tops.entry((coords.x, coords.y)).or_insert(top);
+            *entry = (*entry).max(top);
+        }
+    }
+
+    let mut overlay = Overlay::default();
+    let mut wall = |x: i32, y: i32, tile: (i32, i32)| {
+        let top = tops.get(&tile).copied().unwrap_or(bottom);
+        for z in bottom..=top {
+            overlay.add(VoxelCoords::new(x, y, z), color);
+        }
+    };
+    let (max_x, max_y) = (width * BASE as i32, height * BASE as i32);
+    for x in -1..=max_x {
+        let tile_x = x.div_euclid(BASE as i32).clamp(0, width - 1);
+        wall(x, -1, (tile_x, 0));
+        wall(x, max_y, (tile_x, height - 1));
+    }
+    for y in 0..max_y {
+        let tile_y = y.div_euclid(BASE as i32);
+        wall(-1, y, (0, tile_y));
+        wall(max_x, y, (width - 1, tile_y));
+    }
+    overlay
+}
@@ -1,330 +1,1737 @@
-use crate::{
-    block::BLOCK_VOX_SIZE,
-    building::BuildingInstanceExt,
-    calendar::TimeOfTheYear,
-    context::DFContext,
-    coords::DotVoxModelCoords,
-    dot_vox_builder::{DotVoxBuilder, LayerId, ModelId},
-    map::Map,
-    palette::{DefaultMaterials, Material, Palette},
-    rfr::{self, DFHackExt},
-    FromDwarfFortress, HEIGHT,
-};
-use anyhow::Result;
-use dot_vox::{DotVoxData, Model, Size};
-use itertools::Itertools;
-use serde::{Deserialize, Serialize};
-use std::{
-    fmt::Display,
-    fs::File,
-    ops::{Add, Range, Sub},
-    path::PathBuf,
-    sync::mpsc::{Receiver, Sender},
-    thread::JoinHandle,
-};
-use strum::{Display, EnumIter, IntoEnumIterator};
-
-/// List of displayed layers
-/// The order is important, when building objects they are created in reverse order
-/// As a result, each layer is rendered on top of the next one
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, EnumIter, Display)]
-#[repr(usize)]
-pub enum Layers {
-    All,
-    Building,
-    Terrain,
-    Vegetation,
-    Roughness,
-    Liquid,
-    Spatter,
-    Fire,
-    Flows,
-    Hidden,
-}
-
-#[derive(Debug, Clone, Copy, EnumIter, Display)]
-#[repr(usize)]
-pub enum Models {
-    HiddenBlock,
-}
-
-impl Layers {
-    pub fn id(&self) -> LayerId {
-        LayerId(*self as usize)
-    }
-}
-
-impl Models {
-    pub fn id(&self) -> ModelId {
-        ModelId(*self as usize)
-    }
-}
-
-pub struct ExportParams {
-    pub elevation_low: Elevation,
-    pub elevation_high: Elevation,
-    pub time: TimeOfTheYear,
-    pub path: PathBuf,
-}
-
-pub struct ExportSettings {
-    pub year_tick: i32,
-}
-
-pub enum Progress {
-    Undetermined {
-        message: &'static str,
-    },
-    Start {
-        message: &'static str,
-        total: usize,
-    },
-    Update {
-        message: &'static str,
-        curr: usize,
-        total: usize,
-    },
-    Done {
-        path: PathBuf,
-    },
-    Error(anyhow::Error),
-}
-
-impl Progress {
-    pub fn undetermined(message: &'static str) -> Self {
-        Self::Undetermined { message }
-    }
-
-    pub fn start(message: &'static str, total: usize) -> Self {
-        Self::Start { message, total }
-    }
-
-    pub fn update(message: &'static str, curr: usize, total: usize) -> Self {
-        Self::Update {
-            message,
-            curr,
-            total,
-        }
-    }
-
-    pub fn done(path: PathBuf) -> Self {
-        Self::Done { path }
-    }
-
-    pub fn error(error: anyhow::Error) -> Self {
-        Self::Error(error)
-    }
-}
-
-pub struct Cancel;
-
-#[derive(Serialize, Deserialize, Clone, Copy)]
-pub struct Elevation(pub i32);
-
-impl FromDwarfFortress for Elevation {
-    fn read_from_df(&mut self, df: &mut dfhack_remote::Client) -> Result<()> {
-        self.0 = df.elevation()?;
-        Ok(())
-    }
-}
-
-impl Add<i32> for Elevation {
-    type Output = Self;
-
-    fn add(self, rhs: i32) -> Self::Output {
-        Self(self.0 + rhs)
-    }
-}
-
-impl Sub<i32> for Elevation {
-    type Output = Self;
-
-    fn sub(self, rhs: i32) -> Self::Output {
-        Self(self.0 - rhs)
-    }
-}
-
-impl Display for Elevation {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
-    }
-}
-
-pub fn try_export_voxels(
-    client: &mut dfhack_remote::Client,
-    elevation_range: Range<Elevation>,
-    year_tick: i32,
-    path: PathBuf,
-    progress_tx: Sender<Progress>,
-    cancel_rx: Receiver<Cancel>,
-) -> Result<()> {
-    progress_tx.send(Progress::undetermined("Starting..."))?;
-    client.remote_fortress_reader().set_pause_state(true)?;
-    client.remote_fortress_reader().reset_map_hashes()?;
-    let z_offset = client.elevation_offset()?;
-    let z_range = (elevation_range.start.0 - z_offset)..(elevation_range.end.0 - z_offset);
-    let settings = ExportSettings { year_tick };
-    let context = DFContext::try_new(client, settings)?;
-    let block_list_iterator =
-        rfr::BlockListIterator::try_new(client, 100, 0..1000, 0..1000, z_range.clone())?;
-    let (block_list_count, _) = block_list_iterator.size_hint();
-
-    let mut map = Map::default();
-
-    let mut blocks = Vec::new();
-
-    progress_tx.send(Progress::start("Reading...", block_list_count))?;
-    for (progress, block_list) in block_list_iterator.enumerate() {
-        if cancel_rx.try_iter().next().is_some() {
-            return Ok(());
-        }
-
-        progress_tx.send(Progress::update("Reading...", progress, block_list_count))?;
-
-        for block in block_list?.map_blocks {
-            blocks.push(block);
-        }
-    }
-
-    let tot = blocks.len();
-    progress_tx.send(Progress::start("Assembling...", tot))?;
-    for (curr, block) in blocks.iter().enumerate() {
-        progress_tx.send(Progress::update("Assembling...", curr, tot))?;
-        map.add_block(block, &context);
-    }
-
-    progress_tx.send(Progress::undetermined("Cleaning..."))?;
-
-    // Setup the palette, with the default material pre-inserted
-    // to be easily findable
-    let mut palette = Palette::default();
-    palette.cache_default_materials(&context);
-
-    let mut vox = DotVoxBuilder::default();
-    vox.data
-        .models
-        .resize_with(Models::iter().count(), || Model {
-            size: Size { x: 0, y: 0, z: 0 },
-            voxels: vec![],
-        });
-    // Setup the default models
-    {
-        vox.data.models[*Models::HiddenBlock.id()].size = BLOCK_VOX_SIZE;
-        for x in 0..BLOCK_VOX_SIZE.x {
-            for y in 0..BLOCK_VOX_SIZE.y {
-                for z in 0..BLOCK_VOX_SIZE.z {
-                    vox.data.models[*Models::HiddenBlock.id()]
-                        .voxels
-                        .push(dot_vox::Voxel {
-                            x: x as u8,
-                            y: y as u8,
-                            z: z as u8,
-                            i: palette.get(&Material::Default(DefaultMaterials::Hidden), &context),
-                        });
-                }
-            }
-        }
-    }
-
-    // Setup the layers
-    for layer in Layers::iter() {
-        vox.data.layers[*layer.id()]
-            .attributes
-            .insert("_name".to_string(), format!("{}", layer).to_lowercase());
-    }
-    vox.data.layers[*Layers::Hidden.id()]
-        .attributes
-        .insert("_hidden".to_string(), "1".to_string());
-
-    let min_z = z_range.start * HEIGHT as i32;
-    let block_count = map.levels.values().map(|l| l.blocks.len()).sum();
-    progress_tx.send(Progress::start("Building blocks...", block_count))?;
-    let mut progress = 0;
-
-    for (level, level_data) in map.levels.iter().sorted_by_key(|(l, _)| *l) {
-        // Create a group for the layer
-        let z = HEIGHT as i32 / 2 + level * HEIGHT as i32 - min_z;
-        let level_group = vox.insert_group_node_simple(
-            vox.root_group,
-            format!("level {}", level + z_offset),
-            Some(DotVoxModelCoords::new(0, 0, z)),
-            Layers::All.id(),
-        );
-
-        for block in &level_data.blocks {
-            progress += 1;
-            progress_tx.send(Progress::update(
-                "Building blocks...",
-                progress,
-                block_count,
-            ))?;
-            if cancel_rx.try_iter().next().is_some() {
-                return Ok(());
-            }
-
-            // Create the terrain model
-            crate::block::build(block, &map, &context, &mut vox, &mut palette, level_group);
-        }
-
-        if !level_data.buildings.is_empty() {
-            let building_group_id =
-                vox.insert_group_node_simple(level_group, "buildings", None, Layers::Building.id());
-            for building in &level_data.buildings {
-                building.build(&map, &context, &mut vox, &mut palette, building_group_id);
-            }
-        }
-    }
-
-    let mut vox: DotVoxData = vox.into();
-
-    progress_tx.send(Progress::undetermined("Writing the palette..."))?;
-    palette.write_palette(&mut vox);
-    progress_tx.send(Progress::undetermined("Saving the file..."))?;
-    let mut f = File::create(path.clone())?;
-    vox.write_vox(&mut f)?;
-    progress_tx.send(Progress::done(path))?;
-    Ok(())
-}
-
-pub fn try_run_export(
-    params: ExportParams,
-    df: Option<dfhack_remote::Client>,
-    progress_tx: Sender<Progress>,
-    cancel_rx: Receiver<Cancel>,
-) -> Result<()> {
-    let mut df = match df {
-        Some(df) => df,
-        None => dfhack_remote::connect()?,
-    };
-
-    let ticks = params.time.ticks(&mut df);
-
-    try_export_voxels(
-        &mut df,
-        params.elevation_low..(params.elevation_high + 1),
-        ticks,
-        params.path,
-        progress_tx,
-        cancel_rx,
-    )?;
-
-    Ok(())
-}
-
-/// Run the export in a background thread, returns progress and cancellation channels
-pub fn run_export_thread(
-    params: ExportParams,
-    df: Option<dfhack_remote::Client>,
-) -> (Receiver<Progress>, Sender<Cancel>, JoinHandle<()>) {
-    let (progress_tx, progress_rx) = std::sync::mpsc::channel();
-    let (cancel_tx, cancel_rx) = std::sync::mpsc::channel();
-
-    let handle = std::thread::spawn(move || {
-        if let Err(err) = try_run_export(params, df, progress_tx.clone(), cancel_rx) {
-            // eat send error
-            let _ = progress_tx.send(Progress::error(err));
-        }
-    });
-
-    (progress_rx, cancel_tx, handle)
-}
+use crate::{
+    block::BLOCK_VOX_SIZE,
+    building::BuildingInstanceExt,
+    calendar::{Month, TimeOfTheYear},
+    capabilities::Capabilities,
+    color::ColorSpace,
+    context::{decorative, DFContext},
+    coords::DotVoxModelCoords,
+    crenellation, diorama,
+    dot_vox_builder::{self, DotVoxBuilder, LayerId, ModelId},
+    flair::{self, CivFlair},
+    gox, heightmap,
+    item::{self, Container},
+    language::{NameLanguage, Names},
+    location::{self, Location},
+    magma,
+    manifest::{CloseUps, Manifest, UpAxis},
+    map::Map,
+    mechanism::{self, MechanismLink},
+    palette::{self, DefaultMaterials, Material, Palette},
+    profile,
+    rfr::{self, DFHackExt},
+    route, sea,
+    surroundings::{self, RegionColumn},
+    traffic,
+    unit::{self, Projectile, UnitFigure},
+    watermark, workshop, DFBoundingBox, DFMapCoords, FromDwarfFortress, VoxelCoords, BASE, HEIGHT,
+};
+use anyhow::{anyhow, bail, Result};
+use clap::{Args, ValueEnum};
+use dfhack_remote::MapBlock;
+use dot_vox::{DotVoxData, Model, Size};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs::File,
+    ops::{Add, Range, Sub},
+    path::{Path, PathBuf},
+    sync::mpsc::{Receiver, Sender},
+    thread::JoinHandle,
+    time::Duration,
+};
+use strum::{Display, EnumIter, IntoEnumIterator};
+
+/// List of displayed layers
+/// The order is important, when building objects they are created in reverse order
+/// As a result, each layer is rendered on top of the next one
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    EnumIter,
+    Display,
+    Serialize,
+    Deserialize,
+    ValueEnum,
+)]
+#[repr(usize)]
+pub enum Layers {
+    All,
+    Building,
+    Items,
+    Terrain,
+    Vegetation,
+    Roughness,
+    Liquid,
+    Spatter,
+    Fire,
+    Flows,
+    Mechanisms,
+    Flair,
+    Traffic,
+    Units,
+    Watermark,
+    Hidden,
+}
+
+/// Data drawn over the fortress
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Display, EnumIter, Serialize, Deserialize, ValueEnum,
+)]
+pub enum DataOverlay {
+    /// Heat map of the tiles the units walk on the most
+    Traffic,
+    /// Route of the wagons from the edge of the map to the trade depots
+    WagonRoute,
+    /// Constructions and buildings tinted green if their material is magma-safe, red otherwise
+    MagmaSafety,
+}
+
+#[derive(Debug, Clone, Copy, EnumIter, Display)]
+#[repr(usize)]
+pub enum Models {
+    HiddenBlock,
+}
+
+impl Layers {
+    pub fn id(&self) -> LayerId {
+        LayerId(*self as usize)
+    }
+}
+
+impl Models {
+    pub fn id(&self) -> ModelId {
+        ModelId(*self as usize)
+    }
+}
+
+pub struct ExportParams {
+    pub elevation_low: Elevation,
+    pub elevation_high: Elevation,
+    /// Other elevation ranges exported in the same model, both ends included.
+    /// The levels in between are skipped
+    pub extra_ranges: Vec<(Elevation, Elevation)>,
+    pub time: TimeOfTheYear,
+    pub path: PathBuf,
+    pub options: ExportOptions,
+}
+
+/// Optional behaviours of the export
+#[derive(Args, Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(default)]
+pub struct ExportOptions {
+    /// Write one .vox file per layer instead of a single file
+    #[arg(long)]
+    pub split_layers: bool,
+    /// Draw the links between the levers, pressure plates and their targets
+    #[arg(long)]
+    pub mechanisms: bool,
+    /// Number of animation frames for the smoke, mist and fire, 0 for a still export
+    #[arg(long, default_value_t = 0)]
+    pub flow_frames: u8,
+    /// Language of the fortress and month names in the scene
+    #[arg(long, value_enum, default_value_t)]
+    pub names: NameLanguage,
+    /// Also write a heightmap and a top-down color map as .png
+    #[arg(long)]
+    pub heightmap: bool,
+    /// Write a .json report of the palette entries and of the materials that could be merged
+    #[arg(long)]
+    pub palette_report: bool,
+    /// Do not pause the game during the export. The model may be torn if the map changes meanwhile
+    #[arg(long)]
+    pub no_pause: bool,
+    /// Restore the previous pause state once the export is over
+    #[arg(long)]
+    pub restore_pause: bool,
+    /// Use a single flat color per material, without the darker variants of the buildings
+    /// and the paving checkerboards. Leaves more room in the palette
+    #[arg(long)]
+    pub flat_shading: bool,
+    /// Space in which the colors are darkened and shifted. By default, linear except
+    /// for the shades of depth and relief, darkened channel by channel as before
+    #[arg(long, value_enum)]
+    pub color_space: Option<ColorSpace>,
+    /// Gamma correction of the palette, 1 by default. Above 1 brightens the colors
+    #[arg(long, value_parser = parse_gamma)]
+    pub gamma: Option<f32>,
+    /// Saturation factor of the palette, 1 by default
+    #[arg(long)]
+    pub saturation: Option<f32>,
+    /// Darken the deeper levels to give a sense of depth
+    #[arg(long)]
+    pub depth_cue: bool,
+    /// Tint the soils by kind and vary the stone layers, to show the strata in the cliffs
+    #[arg(long)]
+    pub strata: bool,
+    /// Wet the exposed floors and roofs when it is raining
+    #[arg(long)]
+    pub weather_effects: bool,
+    /// Number of map blocks requested at once, 100 by default. Lower it on low memory hosts
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..=10_000))]
+    pub block_chunk: Option<i32>,
+    /// Tune the number of map blocks requested at once from the response times
+    #[arg(long)]
+    pub adaptive_chunk: bool,
+    /// Add an operator behind each siege engine
+    #[arg(long)]
+    pub siege_crew: bool,
+    /// Reuse the palette of a previous export, to keep its hand-tweaked colors and materials
+    #[arg(long)]
+    pub palette_from: Option<PathBuf>,
+    /// Palette colors left untouched for hand-painted models, such as `1..8` for the first row.
+    /// Can be repeated
+    #[arg(
+        long = "reserve-palette",
+        value_name = "FIRST..LAST",
+        value_parser = palette::parse_palette_range
+    )]
+    pub reserved_palette: Vec<(u8, u8)>,
+    /// When the materials do not fit in the palette, split the export in several files
+    /// by elevation, each with its own palette, instead of merging the closest colors
+    #[arg(long)]
+    pub split_palette: bool,
+    /// YAML table of custom materials, replacing the default materials named after them
+    /// and the Dwarf Fortress materials matching their patterns
+    #[arg(long)]
+    pub material_table: Option<PathBuf>,
+    /// YAML rules moving the buildings matching their patterns, or whole layers,
+    /// to other layers or new ones
+    #[arg(long)]
+    pub layer_rules: Option<PathBuf>,
+    /// Extend the ocean and the magma sea up to the edges of the map where blocks are missing
+    #[arg(long)]
+    pub extend_seas: bool,
+    /// Reveal the whole map, including the caverns the dwarves did not discover yet,
+    /// instead of what the player knows of it
+    #[arg(long)]
+    pub reveal: bool,
+    /// Only export the terrain as plain blocks, skipping the buildings, flows, spatters and vegetation.
+    /// Much faster on large maps, for quick previews
+    #[arg(long)]
+    pub terrain_only: bool,
+    /// Number of region tiles of low detail terrain to render around the embark, one block
+    /// each, 0 to disable
+    #[arg(long, default_value_t = 0)]
+    pub surroundings: u8,
+    /// Sag the bridges hanging over open space between their supports, like rope bridges
+    #[arg(long)]
+    pub sag_bridges: bool,
+    /// Experimental: staggered pattern on the floors, and bevelled wall corners and tops
+    #[arg(long)]
+    pub stylized: bool,
+    /// Leave out the random grass blades, stones, moss, worn paths and growths,
+    /// for crisp architectural renders
+    #[arg(long)]
+    pub clean: bool,
+    /// Render the unfinished constructions as scaffolding, filled in steps as their
+    /// materials are brought and a dwarf starts building them
+    #[arg(long)]
+    pub scaffolding: bool,
+    /// Blend the growth colors from one print to the next, and thin out the growths before
+    /// they fall, so that the exports of consecutive dates do not pop
+    #[arg(long)]
+    pub growth_blend: bool,
+    /// Draw the heavy blood spatters as streaks dragged from the heavier pools around,
+    /// rather than speckles
+    #[arg(long)]
+    pub blood_streaks: bool,
+    /// Add a thin neutral slab under the exported levels, to present the model as a diorama
+    #[arg(long)]
+    pub base_slab: bool,
+    /// Enclose the sides of the map with neutral walls up to the terrain,
+    /// hiding the cut through the ground
+    #[arg(long)]
+    pub edge_walls: bool,
+    /// Render the units as small figures dressed in the materials of their worn equipment.
+    /// The soldiers wear the color of their squad and hold out their weapon.
+    /// The figures use the voxels of the tiles, four voxels tall
+    #[arg(long)]
+    pub units: bool,
+    /// Also write the units in a `_close-ups` file as figures DETAIL times finer than the tiles,
+    /// for the close-up renders. Scaled down by DETAIL, it lines up with the scene
+    #[arg(
+        long,
+        value_name = "DETAIL",
+        requires = "units",
+        value_parser = clap::value_parser!(u8).range(2..=8)
+    )]
+    pub unit_close_ups: Option<u8>,
+    /// Render the arrows, bolts and thrown items in flight
+    #[arg(long)]
+    pub projectiles: bool,
+    /// Add crenellations on the exterior wall tops open to the sky
+    #[arg(long)]
+    pub crenellations: bool,
+    /// Decorate the trade depots and floodgates with the banners of the civilization
+    #[arg(long)]
+    pub flair: bool,
+    /// Decorate the temples, guildhalls and taverns, and name them in the scene
+    #[arg(long)]
+    pub locations: bool,
+    /// Add a plaque with the fortress name and the date next to the model, on its own layer
+    #[arg(long)]
+    pub watermark: bool,
+    /// When exporting a winter month, freeze the surface water and hang icicles under the overhangs
+    #[arg(long)]
+    pub winter_ice: bool,
+    /// Data overlay to draw over the fortress. Can be repeated
+    #[arg(long = "overlay", value_enum)]
+    pub overlays: Vec<DataOverlay>,
+    /// Duration of the sampling of the traffic overlay in seconds, 30 by default.
+    /// The game runs meanwhile
+    #[arg(long)]
+    pub traffic_seconds: Option<u16>,
+    /// Include the ambushers and thieves the player did not discover in the traffic and the units.
+    /// Left out by default, not to spoil the sieges
+    #[arg(long)]
+    pub ambushers: bool,
+    /// Vertical axis of the tool importing the model, the scene is rotated accordingly
+    #[arg(long, value_enum, default_value_t)]
+    pub up_axis: UpAxis,
+    /// Intended real-world size of a voxel in meters, recorded in the manifest
+    #[arg(long)]
+    pub unit_scale: Option<f32>,
+    /// Write a .json manifest describing the export, for the importers of other tools
+    #[arg(long)]
+    pub manifest: bool,
+    /// When the export is cancelled while building the model, save the levels completed so far
+    #[arg(long)]
+    pub save_partial: bool,
+    /// Comma separated layers hidden when opening the model, only `hidden` by default
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub hidden_layers: Option<Vec<Layers>>,
+    /// Hide the levels above this elevation when opening the model
+    #[arg(long, allow_hyphen_values = true)]
+    pub hide_above: Option<i32>,
+    /// Export even when the estimated size is above the safety limits
+    #[arg(long)]
+    #[serde(skip)]
+    pub force: bool,
+    /// Bundle of options to start from: cinematic, planning, minimal,
+    /// or one defined in profiles.yaml in the configuration folder
+    #[arg(long)]
+    #[serde(skip)]
+    pub profile: Option<String>,
+    /// Options given on the command line, kept over the ones of the profile
+    #[arg(skip)]
+    #[serde(skip)]
+    pub explicit: Vec<String>,
+}
+
+impl ExportOptions {
+    /// Space in which the colors are darkened and shifted
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space.unwrap_or(ColorSpace::Linear)
+    }
+
+    /// Layers hidden when opening the model
+    pub fn hidden_layers(&self) -> Vec<Layers> {
+        self.hidden_layers
+            .clone()
+            .unwrap_or_else(|| vec![Layers::Hidden])
+    }
+}
+
+/// Parse a gamma correction, which must be strictly positive
+fn parse_gamma(gamma: &str) -> Result<f32, String> {
+    let gamma: f32 = gamma
+        .parse()
+        .map_err(|_| format!("invalid gamma {gamma}"))?;
+    if gamma > 0.0 && gamma.is_finite() {
+        Ok(gamma)
+    } else {
+        Err(format!("the gamma must be above 0, got {gamma}"))
+    }
+}
+
+/// Size of an export, measured on the visible tiles before reading the map
+pub struct ExportEstimate {
+    /// Blocks with some visible tiles, each one becoming a group of models
+    pub blocks: usize,
+    /// Voxels of the terrain, the bulk of the scene
+    pub voxels: usize,
+}
+
+impl ExportEstimate {
+    /// MagicaVoxel loads every voxel of the scene in memory, at 4 bytes each in the file.
+    /// Above this, the scene takes gigabytes and the editor becomes unusable.
+    const MAX_VOXELS: usize = 500_000_000;
+
+    pub fn compute(
+        client: &mut dfhack_remote::Client,
+        elevation_ranges: &[Range<Elevation>],
+    ) -> Result<Self> {
+        let z_offset = client.elevation_offset()?;
+        let ranges = elevation_ranges
+            .iter()
+            .map(|range| {
+                format!(
+                    "{{{}, {}}}",
+                    range.start.0 - z_offset,
+                    range.end.0 - z_offset
+                )
+            })
+            .join(", ");
+        let output = client.lua_output(&format!(
+            r#"local ranges = {{{ranges}}}
+local function exported(z)
+  for _, range in ipairs(ranges) do
+    if z >= range[1] and z < range[2] then
+      return true
+    end
+  end
+  return false
+end
+local blocks, walls, floors = 0, 0, 0
+for _, block in ipairs(df.global.world.map.map_blocks) do
+  if exported(block.map_pos.z) then
+    local visible = false
+    for x = 0, 15 do
+      for y = 0, 15 do
+        if not block.designation[x][y].hidden then
+          local shape = df.tiletype.attrs[block.tiletype[x][y]].shape
+          if shape == df.tiletype_shape.WALL then
+            walls = walls + 1
+            visible = true
+          elseif shape ~= df.tiletype_shape.EMPTY then
+            floors = floors + 1
+            visible = true
+          end
+        end
+      end
+    end
+    if visible then
+      blocks = blocks + 1
+    end
+  end
+end
+emit(blocks, walls, floors)"#
+        ))?;
+        let counts: Vec<usize> = output
+            .split_whitespace()
+            .filter_map(|count| count.parse().ok())
+            .collect();
+        let [blocks, walls, floors] = counts[..] else {
+            bail!("Unexpected answer when measuring the export: {output}");
+        };
+        Ok(Self::from_tiles(blocks, walls, floors))
+    }
+
+    /// Estimate from the visible tiles, the walls are full columns and the rest about a floor
+    fn from_tiles(blocks: usize, walls: usize, floors: usize) -> Self {
+        Self {
+            blocks,
+            voxels: walls * BASE * BASE * HEIGHT + floors * BASE * BASE,
+        }
+    }
+
+    /// Explanation when the export is likely too big
+    pub fn warning(&self) -> Option<String> {
+        (self.voxels > Self::MAX_VOXELS).then(|| {
+            format!(
+                "This export covers {} blocks, about {} million voxels. It may run out of memory or be too heavy for MagicaVoxel. Consider reducing the elevation range.",
+                self.blocks,
+                self.voxels / 1_000_000
+            )
+        })
+    }
+}
+
+pub struct ExportSettings {
+    pub year_tick: i32,
+    pub options: ExportOptions,
+}
+
+pub enum Progress {
+    Undetermined {
+        message: &'static str,
+    },
+    Start {
+        message: &'static str,
+        total: usize,
+    },
+    Update {
+        message: &'static str,
+        curr: usize,
+        total: usize,
+    },
+    /// Export finished, with the files written
+    Done {
+        paths: Vec<PathBuf>,
+    },
+    Summary(ExportSummary),
+    Info(String),
+    Warning(String),
+    Error(anyhow::Error),
+}
+
+impl Progress {
+    pub fn undetermined(message: &'static str) -> Self {
+        Self::Undetermined { message }
+    }
+
+    pub fn start(message: &'static str, total: usize) -> Self {
+        Self::Start { message, total }
+    }
+
+    /// Line of the export log, None for the progress updates
+    pub fn log_line(&self) -> Option<String> {
+        match self {
+            Progress::Undetermined { message } | Progress::Start { message, .. } => {
+                Some(message.to_string())
+            }
+            Progress::Update { .. } => None,
+            Progress::Done { paths } => Some(format!(
+                "Saved to {}",
+                paths.iter().map(|path| path.display()).join(", ")
+            )),
+            Progress::Summary(summary) => Some(summary.to_string()),
+            Progress::Info(info) => Some(info.clone()),
+            Progress::Warning(warning) => Some(format!("Warning: {warning}")),
+            Progress::Error(err) => Some(format!("Error: {err:?}")),
+        }
+    }
+
+    pub fn update(message: &'static str, curr: usize, total: usize) -> Self {
+        Self::Update {
+            message,
+            curr,
+            total,
+        }
+    }
+
+    pub fn done(paths: Vec<PathBuf>) -> Self {
+        Self::Done { paths }
+    }
+
+    pub fn summary(summary: ExportSummary) -> Self {
+        Self::Summary(summary)
+    }
+
+    pub fn info(info: impl Into<String>) -> Self {
+        Self::Info(info.into())
+    }
+
+    pub fn warning(warning: impl Into<String>) -> Self {
+        Self::Warning(warning.into())
+    }
+
+    pub fn error(error: anyhow::Error) -> Self {
+        Self::Error(error)
+    }
+}
+
+/// Statistics of a finished export
+#[derive(Debug, Clone, Copy)]
+pub struct ExportSummary {
+    pub voxels: usize,
+    pub models: usize,
+    pub palette_entries: usize,
+    /// Size of the written model files, in bytes
+    pub file_size: u64,
+}
+
+impl Display for ExportSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} voxels in {} models, {} palette entries used, {:.1} MB",
+            self.voxels,
+            self.models,
+            self.palette_entries,
+            self.file_size as f64 / 1_000_000.0
+        )
+    }
+}
+
+/// Receiver of the export progress
+///
+/// The GUI and CLI receive it through a channel, other frontends can
+/// implement their own sink.
+pub trait ProgressSink {
+    fn on_progress(&mut self, progress: Progress);
+
+    /// Files of an export split by elevation or by layer, reported as done together
+    fn on_split_saved(&mut self, files: Vec<PathBuf>) {
+        self.on_progress(Progress::done(files));
+    }
+}
+
+impl ProgressSink for Sender<Progress> {
+    fn on_progress(&mut self, progress: Progress) {
+        // The export can be stopped through the cancellation channel, a closed receiver is not an error
+        let _ = self.send(progress);
+    }
+}
+
+pub struct Cancel;
+
+/// Number of darkening steps of the depth cueing
+const DEPTH_CUE_SHADES: u8 = 4;
+/// Number of blocks built in parallel between two progress updates
+const BUILD_BATCH: usize = 256;
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct Elevation(pub i32);
+
+impl FromDwarfFortress for Elevation {
+    fn read_from_df(&mut self, df: &mut dfhack_remote::Client) -> Result<()> {
+        self.0 = df.elevation()?;
+        Ok(())
+    }
+}
+
+impl Add<i32> for Elevation {
+    type Output = Self;
+
+    fn add(self, rhs: i32) -> Self::Output {
+        Self(self.0 + rhs)
+    }
+}
+
+impl Sub<i32> for Elevation {
+    type Output = Self;
+
+    fn sub(self, rhs: i32) -> Self::Output {
+        Self(self.0 - rhs)
+    }
+}
+
+impl Display for Elevation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+pub fn try_export_voxels(
+    client: &mut dfhack_remote::Client,
+    elevation_ranges: &[Range<Elevation>],
+    year_tick: i32,
+    path: PathBuf,
+    options: ExportOptions,
+    progress: &mut dyn ProgressSink,
+    cancel_rx: &Receiver<Cancel>,
+) -> Result<()> {
+    progress.on_progress(Progress::undetermined("Starting..."));
+    // The estimate is only a safeguard, the export goes on without it
+    match ExportEstimate::compute(client, elevation_ranges) {
+        Ok(estimate) => {
+            if let Some(warning) = estimate.warning() {
+                if !options.force {
+                    bail!("{warning} Use --force to export anyway.");
+                }
+                progress.on_progress(Progress::warning(warning));
+            }
+        }
+        Err(err) => progress.on_progress(Progress::warning(format!(
+            "Could not estimate the size of the export: {err:#}"
+        ))),
+    }
+    let capabilities = Capabilities::probe(client)?;
+    for warning in capabilities.warnings() {
+        progress.on_progress(Progress::warning(warning));
+    }
+    // The units are sampled while the game runs, before pausing it
+    let visits = if options.overlays.contains(&DataOverlay::Traffic) {
+        progress.on_progress(Progress::undetermined("Sampling the traffic..."));
+        let duration = Duration::from_secs(options.traffic_seconds.unwrap_or(30) as u64);
+        let ambushers = options.ambushers;
+        match traffic::sample_traffic(client, duration, ambushers, cancel_rx)? {
+            Some(visits) => visits,
+            None => return Ok(()),
+        }
+    } else {
+        HashMap::new()
+    };
+    if !options.no_pause {
+        client.remote_fortress_reader().set_pause_state(true)?;
+    }
+    client.remote_fortress_reader().reset_map_hashes()?;
+    let settings = ExportSettings { year_tick, options };
+    let Some(data) = ExportData::read(
+        client,
+        elevation_ranges,
+        settings,
+        capabilities,
+        visits,
+        progress,
+        cancel_rx,
+    )?
+    else {
+        return Ok(());
+    };
+    let map = data.assemble(progress);
+    let Some(scene) = build_scene(&data, &map, progress, cancel_rx)? else {
+        return Ok(());
+    };
+    let Scene { palette, title, .. } = &scene;
+    let context = &data.context;
+
+    if context.settings.options.split_palette && palette.overflow() {
+        if let Some(bands) = split_elevations(elevation_ranges) {
+            progress.on_progress(Progress::info(
+                "The materials do not fit in a single palette, splitting the export by elevation",
+            ));
+            let mut manifest = Manifest::new(
+                title.clone(),
+                year_tick,
+                context.settings.options.up_axis,
+                context.settings.options.unit_scale,
+            );
+            manifest.elevations = elevation_ranges
+                .iter()
+                .map(|range| [range.start.0, range.end.0 - 1])
+                .collect();
+            let files = export_bands(
+                client,
+                &bands,
+                year_tick,
+                &path,
+                &context.settings.options,
+                progress,
+                cancel_rx,
+            )?;
+            if files.is_empty() {
+                // Cancelled
+                return Ok(());
+            }
+            manifest.files = files
+                .iter()
+                .filter_map(|file| file.file_name().map(PathBuf::from))
+                .collect();
+            if context.settings.options.manifest {
+                manifest.write(&path)?;
+            }
+            if context.settings.options.heightmap {
+                progress.on_progress(Progress::undetermined("Saving the heightmap..."));
+                heightmap::write_maps(&map, context, &data.z_range(), &path)?;
+            }
+            progress.on_split_saved(files);
+            return Ok(());
+        }
+    }
+
+    save_scene(scene, &data, &map, elevation_ranges, path, progress)
+}
+
+/// Everything read from Dwarf Fortress for an export, before building the scene
+pub struct ExportData {
+    pub context: DFContext,
+    pub names: Names,
+    /// Offset between the levels of the map and the elevations shown in the game
+    pub z_offset: i32,
+    /// Levels of the exported elevation ranges
+    pub z_ranges: Vec<Range<i32>>,
+    pub blocks: Vec<MapBlock>,
+    pub links: Vec<MechanismLink>,
+    pub containers: Vec<Container>,
+    pub furnaces: Vec<DFMapCoords>,
+    pub units: Vec<UnitFigure>,
+    pub projectiles: Vec<Projectile>,
+    pub region_columns: Vec<RegionColumn>,
+    pub depots: Vec<DFBoundingBox>,
+    pub civ_flair: CivFlair,
+    pub locations: Vec<Location>,
+    /// Traffic sampled before the export, by tile
+    pub visits: HashMap<DFMapCoords, u32>,
+}
+
+impl ExportData {
+    /// Read the map and the objects of the export, None if it was cancelled meanwhile
+    fn read(
+        client: &mut dfhack_remote::Client,
+        elevation_ranges: &[Range<Elevation>],
+        settings: ExportSettings,
+        capabilities: Capabilities,
+        visits: HashMap<DFMapCoords, u32>,
+        progress: &mut dyn ProgressSink,
+        cancel_rx: &Receiver<Cancel>,
+    ) -> Result<Option<Self>> {
+        let z_offset = client.elevation_offset()?;
+        let z_ranges = elevation_ranges
+            .iter()
+            .map(|range| (range.start.0 - z_offset)..(range.end.0 - z_offset))
+            .collect_vec();
+        let names = Names::read(client, settings.options.names)?;
+        let mut context = DFContext::try_new(client, settings, capabilities)?;
+        if !context.settings.options.terrain_only {
+            let warm_rock = magma::read_warm_rock(client, &z_ranges);
+            context.warm_rock = decorative(warm_rock, "warm rock", &mut context.warnings);
+        }
+        for warning in &context.warnings {
+            progress.on_progress(Progress::warning(warning.clone()));
+        }
+        let block_per_it = context
+            .settings
+            .options
+            .block_chunk
+            .unwrap_or(rfr::DEFAULT_BLOCKS_PER_IT);
+
+        let mut blocks = Vec::new();
+
+        for z_range in &z_ranges {
+            let mut block_list_iterator = rfr::BlockListIterator::try_new(
+                client,
+                block_per_it,
+                0..1000,
+                0..1000,
+                z_range.clone(),
+            )?
+            .adaptive(context.settings.options.adaptive_chunk);
+            let (block_list_count, _) = block_list_iterator.size_hint();
+
+            progress.on_progress(Progress::start("Reading...", block_list_count));
+            let mut current = 0;
+            // The number of requests changes when the chunk size is adapted
+            while let Some(block_list) = block_list_iterator.next() {
+                if cancel_rx.try_iter().next().is_some() {
+                    return Ok(None);
+                }
+
+                let total = current + block_list_iterator.size_hint().0;
+                progress.on_progress(Progress::update("Reading...", current, total));
+                current += 1;
+
+                for mut block in block_list?.map_blocks {
+                    if context.settings.options.reveal {
+                        // Omniscient export, the tiles are shown whether they were discovered or not
+                        block.hidden.fill(false);
+                    }
+                    blocks.push(block);
+                }
+            }
+        }
+
+        let terrain_only = context.settings.options.terrain_only;
+        let links = if context.settings.options.mechanisms && !terrain_only {
+            progress.on_progress(Progress::undetermined("Reading the mechanisms..."));
+            mechanism::read_links(client)?
+        } else {
+            Vec::new()
+        };
+
+        let (containers, furnaces) = if terrain_only {
+            (Vec::new(), Vec::new())
+        } else {
+            let mut warnings = Vec::new();
+            progress.on_progress(Progress::undetermined("Reading the stockpiles..."));
+            let containers = item::read_containers(client);
+            let containers = decorative(containers, "stockpile containers", &mut warnings);
+            progress.on_progress(Progress::undetermined("Reading the workshops..."));
+            let furnaces = workshop::read_active_furnaces(client);
+            let furnaces = decorative(furnaces, "furnace lights", &mut warnings);
+            for warning in warnings {
+                progress.on_progress(Progress::warning(warning));
+            }
+            (containers, furnaces)
+        };
+
+        let units = if context.settings.options.units && !terrain_only {
+            progress.on_progress(Progress::undetermined("Reading the units..."));
+            unit::read_units(client, context.settings.options.ambushers)?
+        } else {
+            Vec::new()
+        };
+
+        let projectiles = if context.settings.options.projectiles && !terrain_only {
+            progress.on_progress(Progress::undetermined("Reading the projectiles..."));
+            unit::read_projectiles(client)?
+        } else {
+            Vec::new()
+        };
+
+        let region_columns = if context.settings.options.surroundings > 0 {
+            progress.on_progress(Progress::undetermined("Reading the surroundings..."));
+            let ring = context.settings.options.surroundings as i32;
+            surroundings::read_surroundings(client, &context, ring)?
+        } else {
+            Vec::new()
+        };
+
+        let depots = if context
+            .settings
+            .options
+            .overlays
+            .contains(&DataOverlay::WagonRoute)
+        {
+            progress.on_progress(Progress::undetermined("Reading the trade depots..."));
+            route::read_accessible_depots(client)?
+        } else {
+            Vec::new()
+        };
+
+        let civ_flair = if context.settings.options.flair && !terrain_only {
+            progress.on_progress(Progress::undetermined("Reading the banners..."));
+            flair::read_flair(client)?
+        } else {
+            CivFlair::default()
+        };
+
+        let locations = if context.settings.options.locations && !terrain_only {
+            progress.on_progress(Progress::undetermined("Reading the locations..."));
+            location::read_locations(client, context.settings.options.names)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Some(Self {
+            context,
+            names,
+            z_offset,
+            z_ranges,
+            blocks,
+            links,
+            containers,
+            furnaces,
+            units,
+            projectiles,
+            region_columns,
+            depots,
+            civ_flair,
+            locations,
+            visits,
+        }))
+    }
+
+    /// Blocks of the test data, without any other object, to build the scene offline
+    pub fn from_test_data(context: DFContext, blocks: Vec<MapBlock>) -> Self {
+        let levels = blocks.iter().map(|block| block.map_z()).collect_vec();
+        let z_range = levels.iter().copied().min().unwrap_or_default()
+            ..levels.iter().copied().max().map_or(0, |z| z + 1);
+        Self {
+            context,
+            names: Names {
+                fortress: "testdata".to_string(),
+                year: 0,
+                months: HashMap::new(),
+            },
+            z_offset: 0,
+            z_ranges: vec![z_range],
+            blocks,
+            links: Vec::new(),
+            containers: Vec::new(),
+            furnaces: Vec::new(),
+            units: Vec::new(),
+            projectiles: Vec::new(),
+            region_columns: Vec::new(),
+            depots: Vec::new(),
+            civ_flair: CivFlair::default(),
+            locations: Vec::new(),
+            visits: HashMap::new(),
+        }
+    }
+
+    /// Whole span of the export, including the levels skipped between the ranges
+    pub fn z_range(&self) -> Range<i32> {
+        let start = self.z_ranges.iter().map(|range| range.start).min();
+        let end = self.z_ranges.iter().map(|range| range.end).max();
+        start.unwrap_or_default()..end.unwrap_or_default()
+    }
+
+    /// Place the blocks and the buildings on the map
+    pub fn assemble(&self, progress: &mut dyn ProgressSink) -> Map<'_> {
+        let mut map = Map::default();
+        let tot = self.blocks.len();
+        progress.on_progress(Progress::start("Assembling...", tot));
+        for (curr, block) in self.blocks.iter().enumerate() {
+            progress.on_progress(Progress::update("Assembling...", curr, tot));
+            map.add_block(block, &self.context);
+        }
+        map
+    }
+}
+
+/// Scene built from the data of an export, before writing its palette
+pub struct Scene {
+    pub vox: DotVoxBuilder,
+    /// Finer figures of the units, sharing the palette of the scene
+    pub close_ups: Option<DotVoxBuilder>,
+    pub palette: Palette,
+    /// Name of the root group, such as "Boatmurdered, Granite 250"
+    pub title: String,
+}
+
+/// Build the models of the export, None if it was cancelled meanwhile
+pub fn build_scene(
+    data: &ExportData,
+    map: &Map,
+    progress: &mut dyn ProgressSink,
+    cancel_rx: &Receiver<Cancel>,
+) -> Result<Option<Scene>> {
+    let ExportData {
+        context,
+        names,
+        z_offset,
+        z_ranges,
+        links,
+        containers,
+        furnaces,
+        units,
+        projectiles,
+        region_columns,
+        depots,
+        civ_flair,
+        locations,
+        visits,
+        ..
+    } = data;
+    // Month picked for the export, rather than the current one of the game
+    let month = Month::from_year_tick(context.settings.year_tick);
+    let z_range = data.z_range();
+    progress.on_progress(Progress::undetermined("Cleaning..."));
+
+    // Setup the palette, with the default material pre-inserted
+    // to be easily findable
+    let mut palette = Palette::default();
+    if let Some(previous) = &context.settings.options.palette_from {
+        let previous_vox = dot_vox::load(&previous.to_string_lossy()).map_err(|err| {
+            anyhow!(
+                "Failed to load the palette of {}: {err}",
+                previous.display()
+            )
+        })?;
+        palette.pin_from(&previous_vox);
+    }
+    for (first, last) in &context.settings.options.reserved_palette {
+        palette.reserve(*first, *last)?;
+    }
+    palette.cache_default_materials(context);
+
+    let mut vox = DotVoxBuilder::default();
+    let title = names.title(month);
+    vox.set_root_name(title.clone());
+    if let Some(rotation) = context.settings.options.up_axis.rotation() {
+        vox.set_root_rotation(rotation);
+    }
+    vox.data
+        .models
+        .resize_with(Models::iter().count(), || Model {
+            size: Size { x: 0, y: 0, z: 0 },
+            voxels: vec![],
+        });
+    // Setup the default models
+    {
+        vox.data.models[*Models::HiddenBlock.id()].size = BLOCK_VOX_SIZE;
+        for x in 0..BLOCK_VOX_SIZE.x {
+            for y in 0..BLOCK_VOX_SIZE.y {
+                for z in 0..BLOCK_VOX_SIZE.z {
+                    vox.data.models[*Models::HiddenBlock.id()]
+                        .voxels
+                        .push(dot_vox::Voxel {
+                            x: x as u8,
+                            y: y as u8,
+                            z: z as u8,
+                            i: palette.get(&Material::Default(DefaultMaterials::Hidden), context),
+                        });
+                }
+            }
+        }
+    }
+
+    // Setup the layers
+    let hidden_layers = context.settings.options.hidden_layers();
+    for layer in Layers::iter() {
+        let attributes = &mut vox.data.layers[*layer.id()].attributes;
+        attributes.insert("_name".to_string(), format!("{}", layer).to_lowercase());
+        if hidden_layers.contains(&layer) {
+            attributes.insert("_hidden".to_string(), "1".to_string());
+        }
+    }
+    for (layer_id, name) in context.layer_rules.custom_layers() {
+        let attributes = &mut vox.data.layers[*layer_id].attributes;
+        attributes.insert("_name".to_string(), name.to_string());
+    }
+
+    let min_z = z_range.start * HEIGHT as i32;
+    let block_count = map.levels.values().map(|l| l.blocks.len()).sum();
+    progress.on_progress(Progress::start("Building blocks...", block_count));
+    let mut built = 0;
+
+    // Models of each level, for the post-passes
+    let mut level_models = Vec::new();
+    let mut cancelled = false;
+    for (level, level_data) in map.levels.iter().sorted_by_key(|(l, _)| *l) {
+        if cancelled {
+            break;
+        }
+        let first_model = vox.data.models.len();
+        // Create a group for the layer
+        let z = HEIGHT as i32 / 2 + level * HEIGHT as i32 - min_z;
+        let level_group = vox.insert_group_node_simple(
+            vox.root_group,
+            format!("level {}", level + *z_offset),
+            Some(DotVoxModelCoords::new(0, 0, z)),
+            Layers::All.id(),
+        );
+        if context
+            .settings
+            .options
+            .hide_above
+            .is_some_and(|elevation| level + *z_offset > elevation)
+        {
+            vox.set_hidden(level_group);
+        }
+
+        // The blocks are built in parallel by batches, to keep reporting the progress
+        for batch in level_data.blocks.chunks(BUILD_BATCH) {
+            if !cancelled && cancel_rx.try_iter().next().is_some() {
+                if !context.settings.options.save_partial {
+                    return Ok(None);
+                }
+                // Complete the current level, and save the levels built so far
+                cancelled = true;
+            }
+
+            // Create the terrain models
+            let contents = crate::block::build_contents(batch, map, context, &palette);
+            for (block, content) in batch.iter().zip(contents) {
+                if let Some(content) = content {
+                    crate::block::insert(block, content, context, &mut vox, level_group);
+                }
+            }
+            built += batch.len();
+            progress.on_progress(Progress::update("Building blocks...", built, block_count));
+        }
+
+        if !level_data.buildings.is_empty() {
+            let building_group_id =
+                vox.insert_group_node_simple(level_group, "buildings", None, Layers::Building.id());
+            for building in &level_data.buildings {
+                building.build(map, context, &mut vox, &palette, building_group_id);
+            }
+        }
+        level_models.push((*level, first_model..vox.data.models.len()));
+    }
+    if cancelled {
+        progress.on_progress(Progress::warning(
+            "The export was cancelled, only the completed levels are saved",
+        ));
+    }
+
+    // The overlays stop at the last level built, when the export is cancelled
+    let top_level = level_models
+        .last()
+        .map_or(z_range.start, |(level, _)| *level);
+    let exported = |coords: &VoxelCoords| {
+        let level = coords.z.div_euclid(HEIGHT as i32);
+        level <= top_level && z_ranges.iter().any(|range| range.contains(&level))
+    };
+
+    if context.settings.options.depth_cue {
+        let top = z_range.end - 1;
+        let depth = (z_range.end - z_range.start).max(1);
+        for (level, models) in level_models {
+            // A few shades over the whole range, to keep the palette small
+            let shade = ((top - level) * DEPTH_CUE_SHADES as i32 / depth) as u8;
+            for model in &mut vox.data.models[models] {
+                for voxel in &mut model.voxels {
+                    voxel.i = palette.shaded(voxel.i, shade, context);
+                }
+            }
+        }
+    }
+
+    let mut links = mechanism::build_links(links, &z_range, context, &palette);
+    links.retain(exported);
+    if !links.is_empty() {
+        let root_group = vox.root_group;
+        links.insert(
+            &mut vox,
+            root_group,
+            context.layer_rules.layer(Layers::Mechanisms),
+            "mechanisms",
+            context,
+            min_z,
+        );
+    }
+
+    let mut containers = item::build_containers(containers, &z_range, context, &palette);
+    containers.retain(exported);
+    if !containers.is_empty() {
+        let root_group = vox.root_group;
+        containers.insert(
+            &mut vox,
+            root_group,
+            context.layer_rules.layer(Layers::Items),
+            "stockpiles",
+            context,
+            min_z,
+        );
+    }
+
+    if context.settings.options.extend_seas {
+        let seas = sea::extend_seas(map, &z_range, context, &palette);
+        if !seas.is_empty() {
+            let root_group = vox.root_group;
+            seas.insert(
+                &mut vox,
+                root_group,
+                context.layer_rules.layer(Layers::Liquid),
+                "seas",
+                context,
+                min_z,
+            );
+        }
+    }
+
+    let region = surroundings::build_surroundings(region_columns, context, &palette);
+    if !region.is_empty() {
+        let root_group = vox.root_group;
+        region.insert(
+            &mut vox,
+            root_group,
+            context.layer_rules.layer(Layers::Terrain),
+            "surroundings",
+            context,
+            min_z,
+        );
+    }
+
+    if context.settings.options.base_slab {
+        let base = diorama::build_base(&z_range, context, &palette);
+        let root_group = vox.root_group;
+        base.insert(
+            &mut vox,
+            root_group,
+            context.layer_rules.layer(Layers::Terrain),
+            "base",
+            context,
+            min_z,
+        );
+    }
+
+    if context.settings.options.edge_walls {
+        let walls = diorama::build_edge_walls(map, &z_range, context, &palette);
+        let root_group = vox.root_group;
+        walls.insert(
+            &mut vox,
+            root_group,
+            context.layer_rules.layer(Layers::Terrain),
+            "edge walls",
+            context,
+            min_z,
+        );
+    }
+
+    if context.settings.options.crenellations {
+        let mut crenellations = crenellation::build_crenellations(map, &z_range, context, &palette);
+        crenellations.retain(exported);
+        if !crenellations.is_empty() {
+            let root_group = vox.root_group;
+            crenellations.insert(
+                &mut vox,
+                root_group,
+                context.layer_rules.layer(Layers::Terrain),
+                "crenellations",
+                context,
+                min_z,
+            );
+        }
+    }
+
+    let (mut fires, mut smokes) = workshop::build_fires(furnaces, &z_range, context, &palette);
+    fires.retain(exported);
+    smokes.retain(exported);
+    let root_group = vox.root_group;
+    if !fires.is_empty() {
+        fires.insert(
+            &mut vox,
+            root_group,
+            context.layer_rules.layer(Layers::Fire),
+            "furnace fires",
+            context,
+            min_z,
+        );
+    }
+    if !smokes.is_empty() {
+        smokes.insert(
+            &mut vox,
+            root_group,
+            context.layer_rules.layer(Layers::Flows),
+            "chimney smoke",
+            context,
+            min_z,
+        );
+    }
+
+    let mut banners = flair::build_banners(civ_flair, &z_range, context, &palette);
+    banners.retain(exported);
+    if !banners.is_empty() {
+        banners.insert(
+            &mut vox,
+            root_group,
+            context.layer_rules.layer(Layers::Flair),
+            "banners",
+            context,
+            min_z,
+        );
+    }
+
+    for location in locations {
+        let mut decor = location::build_decor(location, &z_range, context, &palette);
+        decor.retain(exported);
+        if !decor.is_empty() {
+            decor.insert(
+                &mut vox,
+                root_group,
+                context.layer_rules.layer(Layers::Flair),
+                &location.scene_name(),
+                context,
+                min_z,
+            );
+        }
+    }
+
+    let mut figures = unit::build_units(units, &z_range, context, &palette);
+    figures.retain(exported);
+    if !figures.is_empty() {
+        figures.insert(
+            &mut vox,
+            root_group,
+            context.layer_rules.layer(Layers::Units),
+            "units",
+            context,
+            min_z,
+        );
+    }
+
+    let close_ups = context.settings.options.unit_close_ups.map(|detail| {
+        let mut close_ups = DotVoxBuilder::default();
+        close_ups.set_root_name(format!("{title}, units"));
+        if let Some(rotation) = context.settings.options.up_axis.rotation() {
+            close_ups.set_root_rotation(rotation);
+        }
+        let scale = detail as i32;
+        for (coords, model) in unit::build_close_ups(units, &z_range, context, &palette, detail) {
+            let tile = VoxelCoords::from_df(coords, 0, 0, 0);
+            if !exported(&tile) {
+                continue;
+            }
+            // Tile of the unit in the scene scaled up, the models being centered on it
+            let coordinates = DotVoxModelCoords::new(
+                (tile.x - context.max_vox_x()) * scale + model.size.x as i32 / 2,
+                (context.max_vox_y() - tile.y - BASE as i32 + 1) * scale + model.size.y as i32 / 2,
+                (tile.z - min_z) * scale + model.size.z as i32 / 2,
+            );
+            let root_group = close_ups.root_group;
+            close_ups.insert_model_and_shape_node(
+                root_group,
+                Some(coordinates),
+                model,
+                context.layer_rules.layer(Layers::Units),
+                "unit",
+            );
+        }
+        close_ups
+    });
+
+    let mut missiles = unit::build_projectiles(projectiles, &z_range, context, &palette);
+    missiles.retain(exported);
+    if !missiles.is_empty() {
+        missiles.insert(
+            &mut vox,
+            root_group,
+            context.layer_rules.layer(Layers::Units),
+            "projectiles",
+            context,
+            min_z,
+        );
+    }
+
+    let mut traffic = traffic::build_traffic(visits, &z_range, context, &palette);
+    traffic.retain(exported);
+    if !traffic.is_empty() {
+        traffic.insert(
+            &mut vox,
+            root_group,
+            context.layer_rules.layer(Layers::Traffic),
+            "traffic",
+            context,
+            min_z,
+        );
+    }
+
+    let mut wagon_route = route::build_wagon_route(depots, map, context, &palette);
+    wagon_route.retain(exported);
+    if !wagon_route.is_empty() {
+        wagon_route.insert(
+            &mut vox,
+            root_group,
+            context.layer_rules.layer(Layers::Traffic),
+            "wagon route",
+            context,
+            min_z,
+        );
+    }
+
+    if context.settings.options.watermark {
+        let lines = [
+            names.fortress.clone(),
+            format!("{} {}", names.month(month), names.year),
+        ];
+        let plaque = watermark::build_watermark(&lines, &z_range, context, &palette);
+        plaque.insert(
+            &mut vox,
+            root_group,
+            context.layer_rules.layer(Layers::Watermark),
+            "watermark",
+            context,
+            min_z,
+        );
+    }
+
+    Ok(Some(Scene {
+        vox,
+        close_ups,
+        palette,
+        title,
+    }))
+}
+
+/// Write the palette and save the scene, with its manifest and heightmap if asked
+pub fn save_scene(
+    scene: Scene,
+    data: &ExportData,
+    map: &Map,
+    elevation_ranges: &[Range<Elevation>],
+    path: PathBuf,
+    progress: &mut dyn ProgressSink,
+) -> Result<()> {
+    let Scene {
+        vox,
+        close_ups,
+        palette,
+        title,
+    } = scene;
+    let context = &data.context;
+    let year_tick = context.settings.year_tick;
+    let z_range = data.z_range();
+
+    if let Some(previous) = &context.settings.options.palette_from {
+        if palette.overflow() {
+            bail!(
+                "The materials of this export do not fit in the palette of {}",
+                previous.display()
+            );
+        }
+    }
+
+    let mut vox: DotVoxData = vox.into();
+    let mut close_ups: Option<DotVoxData> = close_ups.map(Into::into);
+
+    progress.on_progress(Progress::undetermined("Writing the palette..."));
+    match &mut close_ups {
+        Some(close_ups) => palette.write_palettes(&mut [&mut vox, close_ups]),
+        None => palette.write_palette(&mut vox),
+    }
+    let report = palette.report(context);
+    progress.on_progress(Progress::info(report.summary()));
+    if context.settings.options.palette_report {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let report_path = path.with_file_name(format!("{stem}_palette.json"));
+        serde_json::to_writer_pretty(File::create(report_path)?, &report)?;
+    }
+    progress.on_progress(Progress::undetermined("Saving the file..."));
+    let goxel = path.extension().is_some_and(|extension| extension == "gox");
+    if goxel && context.settings.options.up_axis.rotation().is_some() {
+        progress.on_progress(Progress::warning(
+            "Goxel has no scene rotation, the .gox keeps the z axis up",
+        ));
+    }
+    if goxel && context.settings.options.flow_frames > 1 {
+        progress.on_progress(Progress::warning(
+            "Goxel has no animations, the .gox only keeps the first frame of the flows",
+        ));
+    }
+    let mut manifest = Manifest::new(
+        title,
+        year_tick,
+        context.settings.options.up_axis,
+        context.settings.options.unit_scale,
+    );
+    manifest.elevations = elevation_ranges
+        .iter()
+        .map(|range| [range.start.0, range.end.0 - 1])
+        .collect();
+    if context.settings.options.split_layers {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = path.extension().unwrap_or_default().to_string_lossy();
+        let layers = Layers::iter()
+            .map(|layer| (layer.id(), layer.to_string().to_lowercase()))
+            .chain(
+                context
+                    .layer_rules
+                    .custom_layers()
+                    .map(|(layer_id, name)| (layer_id, name.to_string())),
+            );
+        for (layer_id, name) in layers {
+            let layer_vox = dot_vox_builder::layer_subset(&vox, layer_id);
+            if layer_vox.models.is_empty() {
+                continue;
+            }
+            let layer_path = path.with_file_name(format!("{stem}_{name}.{extension}"));
+            save(&layer_vox, &layer_path)?;
+            manifest
+                .files
+                .extend(layer_path.file_name().map(PathBuf::from));
+        }
+    } else {
+        save(&vox, &path)?;
+        manifest.files.extend(path.file_name().map(PathBuf::from));
+    }
+    // Kept out of the files of the manifest, at another scale than the scene
+    let mut files = manifest
+        .files
+        .iter()
+        .map(|file| path.with_file_name(file))
+        .collect_vec();
+    if let (Some(close_ups), Some(detail)) = (&close_ups, context.settings.options.unit_close_ups) {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = path.extension().unwrap_or_default().to_string_lossy();
+        let close_ups_path = path.with_file_name(format!("{stem}_close-ups.{extension}"));
+        save(close_ups, &close_ups_path)?;
+        manifest.close_ups = close_ups_path.file_name().map(|file| CloseUps {
+            file: PathBuf::from(file),
+            detail,
+        });
+        files.push(close_ups_path);
+    }
+    progress.on_progress(Progress::summary(ExportSummary {
+        voxels: vox.models.iter().map(|model| model.voxels.len()).sum(),
+        models: vox.models.len(),
+        palette_entries: report.entries.len(),
+        file_size: manifest
+            .files
+            .iter()
+            .filter_map(|file| std::fs::metadata(path.with_file_name(file)).ok())
+            .map(|metadata| metadata.len())
+            .sum(),
+    }));
+    if context.settings.options.manifest {
+        manifest.write(&path)?;
+    }
+    if context.settings.options.heightmap {
+        progress.on_progress(Progress::undetermined("Saving the heightmap..."));
+        heightmap::write_maps(map, context, &z_range, &path)?;
+    }
+    // Split by layer, the destination itself is not written
+    progress.on_split_saved(files);
+    Ok(())
+}
+
+/// Lower and upper halves of the exported elevations, None if there is a single level
+fn split_elevations(elevation_ranges: &[Range<Elevation>]) -> Option<[Vec<Range<Elevation>>; 2]> {
+    let levels = elevation_ranges
+        .iter()
+        .flat_map(|range| range.start.0..range.end.0)
+        .sorted()
+        .collect_vec();
+    if levels.len() < 2 {
+        return None;
+    }
+    let middle = levels[levels.len() / 2];
+    let clip = |low: i32, high: i32| {
+        elevation_ranges
+            .iter()
+            .map(|range| Elevation(range.start.0.max(low))..Elevation(range.end.0.min(high)))
+            .filter(|range| range.start.0 < range.end.0)
+            .collect_vec()
+    };
+    Some([clip(i32::MIN, middle), clip(middle, i32::MAX)])
+}
+
+/// Progress of the export of an elevation band, keeping the saved files for the manifest
+struct BandSink<'a> {
+    inner: &'a mut dyn ProgressSink,
+    saved: Vec<PathBuf>,
+}
+
+impl ProgressSink for BandSink<'_> {
+    fn on_progress(&mut self, progress: Progress) {
+        match progress {
+            Progress::Done { paths } => self.saved.extend(paths),
+            progress => self.inner.on_progress(progress),
+        }
+    }
+
+    fn on_split_saved(&mut self, files: Vec<PathBuf>) {
+        self.saved.extend(files);
+    }
+}
+
+/// Export each elevation band in its own file, with its own palette.
+/// The bands are split again if their materials still do not fit.
+/// Returns the saved files, none if the export was cancelled
+fn export_bands(
+    client: &mut dfhack_remote::Client,
+    bands: &[Vec<Range<Elevation>>],
+    year_tick: i32,
+    path: &Path,
+    options: &ExportOptions,
+    progress: &mut dyn ProgressSink,
+    cancel_rx: &Receiver<Cancel>,
+) -> Result<Vec<PathBuf>> {
+    let mut options = options.clone();
+    // Written once for the whole range
+    options.manifest = false;
+    options.heightmap = false;
+    if options.overlays.contains(&DataOverlay::Traffic) {
+        // Sampling again would let the game run for each band
+        progress.on_progress(Progress::warning(
+            "The traffic overlay is left out of the exports split by elevation",
+        ));
+        options
+            .overlays
+            .retain(|overlay| *overlay != DataOverlay::Traffic);
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    let mut sink = BandSink {
+        inner: progress,
+        saved: Vec::new(),
+    };
+    for band in bands {
+        let (Some(low), Some(high)) = (band.first(), band.last()) else {
+            continue;
+        };
+        let band_path = path.with_file_name(format!(
+            "{stem}_{}-{}.{extension}",
+            low.start.0,
+            high.end.0 - 1
+        ));
+        let saved = sink.saved.len();
+        try_export_voxels(
+            client,
+            band,
+            year_tick,
+            band_path,
+            options.clone(),
+            &mut sink,
+            cancel_rx,
+        )?;
+        if sink.saved.len() == saved {
+            return Ok(Vec::new());
+        }
+    }
+    Ok(sink.saved)
+}
+
+/// Write the model, in the Goxel format if the file is a .gox, MagicaVoxel otherwise
+///
+/// The model is written in a temporary file first, so that a failure never
+/// leaves a truncated file or overwrites a previous export.
+pub fn save(vox: &DotVoxData, path: &Path) -> Result<()> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp_path = path.with_file_name(format!(".{file_name}.tmp"));
+    let written = write_model(vox, path, &tmp_path);
+    if written.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    written?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+fn write_model(vox: &DotVoxData, path: &Path, destination: &Path) -> Result<()> {
+    let mut f = File::create(destination)?;
+    if path.extension().is_some_and(|extension| extension == "gox") {
+        gox::write_gox(vox, &mut f)?;
+    } else {
+        vox.write_vox(&mut f)?;
+    }
+    f.sync_all()?;
+    Ok(())
+}
+
+pub fn try_run_export(
+    params: ExportParams,
+    df: Option<dfhack_remote::Client>,
+    progress: &mut impl ProgressSink,
+    cancel_rx: Receiver<Cancel>,
+) -> Result<()> {
+    let mut df = match df {
+        Some(df) => df,
+        None => dfhack_remote::connect()?,
+    };
+
+    let options = profile::resolve(params.options)?;
+    let ticks = params.time.ticks(&mut df);
+    let was_paused = df.remote_fortress_reader().get_pause_state()?;
+    let restore_pause = options.restore_pause;
+
+    let elevation_ranges = std::iter::once((params.elevation_low, params.elevation_high))
+        .chain(params.extra_ranges)
+        .map(|(low, high)| low..(high + 1))
+        .collect_vec();
+    let result = try_export_voxels(
+        &mut df,
+        &elevation_ranges,
+        ticks,
+        params.path,
+        options,
+        progress,
+        &cancel_rx,
+    );
+
+    // Restore even if the export failed or was canceled, the export error coming first
+    let restored = if restore_pause {
+        df.remote_fortress_reader().set_pause_state(was_paused)
+    } else {
+        Ok(())
+    };
+    result?;
+    restored?;
+    Ok(())
+}
+
+/// Run the export in a background thread, returns progress and cancellation channels
+pub fn run_export_thread(
+    params: ExportParams,
+    df: Option<dfhack_remote::Client>,
+) -> (Receiver<Progress>, Sender<Cancel>, JoinHandle<()>) {
+    let (mut progress_tx, progress_rx) = std::sync::mpsc::channel();
+    let (cancel_tx, cancel_rx) = std::sync::mpsc::channel();
+
+    let handle = std::thread::spawn(move || {
+        if let Err(err) = try_run_export(params, df, &mut progress_tx, cancel_rx) {
+            progress_tx.on_progress(Progress::error(err));
+        }
+    });
+
+    (progress_rx, cancel_tx, handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimate_typical_embark() {
+        // 4x4 embark, 12x12 blocks per level, 30 levels around the surface
+        let blocks = 12 * 12 * 30;
+        let walls = blocks * 256 / 2;
+        let floors = blocks * 256 / 4;
+        assert!(ExportEstimate::from_tiles(blocks, walls, floors)
+            .warning()
+            .is_none());
+    }
+
+    #[test]
+    fn estimate_whole_map() {
+        // 16x16 embark revealed from the magma sea to the sky
+        let blocks = 48 * 48 * 150;
+        let walls = blocks * 256 * 3 / 4;
+        assert!(ExportEstimate::from_tiles(blocks, walls, 0)
+            .warning()
+            .is_some());
+    }
+}
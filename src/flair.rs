@@ -0,0 +1,92 @@
+use crate::{
+    context::DFContext,
+    overlay::Overlay,
+    palette::{DefaultMaterials, Material, Palette},
+    rfr::DFHackExt,
+    DFMapCoords, VoxelCoords,
+};
+use anyhow::Result;
+use std::ops::Range;
+
+/// Colors of the banners when the civilization has none
+const DEFAULT_COLORS: [(u8, u8, u8); 2] = [(150, 30, 30), (230, 200, 60)];
+
+/// Colors of the fortress civilization, and the tiles decorated with its banners
+#[derive(Debug, Default)]
+pub struct CivFlair {
+    pub colors: Vec<(u8, u8, u8)>,
+    pub banners: Vec<DFMapCoords>,
+}
+
+/// Read the civilization colors, and place banners at the corners of the trade depots
+/// and above the floodgates
+pub fn read_flair(client: &mut dfhack_remote::Client) -> Result<CivFlair> {
+    let output = client.lua_output(
+        r#"local civ = df.historical_entity.find(df.global.plotinfo.civ_id)
+if civ then
+  for i, id in ipairs(civ.resources.colors) do
+    if i >= 2 then break end
+    local color = df.global.world.raws.descriptors.colors[id]
+    if color then
+      emit("color", math.floor(color.red * 255), math.floor(color.green * 255), math.floor(color.blue * 255))
+    end
+  end
+end
+for _, building in ipairs(df.global.world.buildings.all) do
+  if df.building_tradedepotst:is_instance(building) then
+    emit("banner", building.x1 - 1, building.y1 - 1, building.z)
+    emit("banner", building.x2 + 1, building.y1 - 1, building.z)
+  elseif df.building_floodgatest:is_instance(building) then
+    emit("banner", building.x1, building.y1, building.z + 1)
+  end
+end"#,
+    )?;
+    let mut flair = CivFlair::default();
+    for line in output.lines() {
+        let mut parts = line.split_whitespace();
+        let kind = parts.next();
+        let values: Vec<i32> = parts.filter_map(|v| v.parse().ok()).collect();
+        match (kind, &values[..]) {
+            (Some("color"), [r, g, b]) => flair.colors.push((
+                (*r).clamp(0, 255) as u8,
+                (*g).clamp(0, 255) as u8,
+                (*b).clamp(0, 255) as u8,
+            )),
+            (Some("banner"), [x, y, z]) => flair.banners.push(DFMapCoords::new(*x, *y, *z)),
+            _ => {}
+        }
+    }
+    Ok(flair)
+}
+
+/// Banners on a wooden pole, striped with the civilization colors
+pub fn build_banners(
+    flair: &CivFlair,
+    z_range: &Range<i32>,
+    context: &DFContext,
+    palette: &Palette,
+) -> Overlay {
+    let pole = palette.get(&Material::Default(DefaultMaterials::Wood), context);
+    let mut colors = flair
+        .colors
+        .iter()
+        .chain(DEFAULT_COLORS.iter().skip(flair.colors.len()))
+        .map(|(r, g, b)| palette.get(&Material::Color(*r, *g, *b), context));
+    let top = colors.next().unwrap_or(pole);
+    let bottom = colors.next().unwrap_or(top);
+
+    let mut overlay = Overlay::default();
+    for banner in &flair.banners {
+        if !z_range.contains(&banner.z) {
+            continue;
+        }
+        for z in 1..=4 {
+            overlay.add(VoxelCoords::from_df(*banner, 0, 1, z), pole);
+        }
+        for x in 1..=2 {
+            overlay.add(VoxelCoords::from_df(*banner, x, 1, 4), top);
+            overlay.add(VoxelCoords::from_df(*banner, x, 1, 3), bottom);
+        }
+    }
+    overlay
+}
@@ -1,78 +1,254 @@
-use std::ops::Add;
-
-use crate::{
-    block::BLOCK_SIZE,
-    context::DFContext,
-    coords::DFLocalCoords,
-    palette::{DefaultMaterials, Material, Palette},
-    shape::{self, slice_empty, Box3D},
-    voxel::voxels_from_uniform_shape,
-    DFMapCoords, StableRng, WithDFCoords,
-};
-use dfhack_remote::{FlowInfo, FlowType};
-use rand::Rng;
-
-#[easy_ext::ext(FlowInfoExt)]
-impl FlowInfo {
-    pub fn build(&self, context: &DFContext, palette: &mut Palette) -> Vec<dot_vox::Voxel> {
-        let coords = self.coords();
-        // The flows are stored in the blocks, but their coords are global
-        let local_coords = DFLocalCoords {
-            x: (coords.x as usize % BLOCK_SIZE) as u8,
-            y: (coords.y as usize % BLOCK_SIZE) as u8,
-        };
-        let mut rng = self.stable_rng();
-        let shape: Box3D<bool> = match self.type_() {
-            FlowType::OceanWave => [
-                slice_empty(),
-                slice_empty(),
-                slice_empty(),
-                shape::slice_from_fn(|_, _| {
-                    rng.gen_ratio(self.density().abs().min(100).max(0) as u32, 400)
-                }),
-                shape::slice_from_fn(|_, _| {
-                    rng.gen_ratio(self.density().abs().min(100).max(0) as u32, 400)
-                }),
-            ],
-            _ => shape::box_from_fn(|_, _, _| {
-                rng.gen_ratio(self.density().abs().min(100).max(0) as u32, 400)
-            }),
-        };
-        let material = match self.type_() {
-            FlowType::Mist | FlowType::SeaFoam | FlowType::Steam => {
-                Material::Default(DefaultMaterials::Mist)
-            }
-            FlowType::OceanWave => Material::Default(DefaultMaterials::Water),
-            FlowType::MagmaMist => Material::Default(DefaultMaterials::Magma),
-            FlowType::Fire | FlowType::CampFire | FlowType::Dragonfire => {
-                Material::Default(DefaultMaterials::Fire)
-            }
-            FlowType::Miasma => Material::Default(DefaultMaterials::Miasma),
-            FlowType::Smoke => Material::Default(DefaultMaterials::Smoke),
-            FlowType::ItemCloud
-            | FlowType::MaterialDust
-            | FlowType::MaterialGas
-            | FlowType::MaterialVapor
-            | FlowType::Web => Material::Generic(self.material.get_or_default().to_owned()),
-        };
-
-        voxels_from_uniform_shape(shape, local_coords, palette.get(&material, context))
-    }
-}
-
-impl WithDFCoords for FlowInfo {
-    fn coords(&self) -> DFMapCoords {
-        self.pos.get_or_default().into()
-    }
-}
-
-impl<T> Add<T> for DFMapCoords
-where
-    T: WithDFCoords,
-{
-    type Output = DFMapCoords;
-
-    fn add(self, rhs: T) -> Self::Output {
-        self + rhs.coords()
-    }
-}
+use std::{f64::consts::TAU, ops::Add};
+
+use crate::{
+    block::BLOCK_SIZE,
+    context::DFContext,
+    coords::DFLocalCoords,
+    map::{Map, Occupancy},
+    palette::{DefaultMaterials, Material, Palette},
+    rfr::BlockTile,
+    shape::{self, slice_empty, Box3D, Slice2D},
+    tile::BlockTileExt,
+    voxel::voxels_from_uniform_shape,
+    DFMapCoords, StableRng, WithDFCoords, BASE, HEIGHT,
+};
+use dfhack_remote::{FlowInfo, FlowType, TiletypeShape};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+#[easy_ext::ext(FlowInfoExt)]
+impl FlowInfo {
+    /// Stable random generator of the tile, told apart by the index of the flow as several
+    /// flows can share a tile
+    fn flow_rng(&self) -> StdRng {
+        let seed: u64 = self.stable_rng().gen();
+        SeedableRng::seed_from_u64(seed ^ self.index() as u64)
+    }
+
+    /// Density of the flow, from 0 to 100
+    fn clamped_density(&self) -> f64 {
+        self.density().abs().min(100) as f64
+    }
+
+    /// Densest flow of the same type on a neighbouring tile, 0 if there is none
+    fn neighbour_density(&self, map: &Map, dx: i32, dy: i32) -> f64 {
+        let coords = self.coords() + DFMapCoords::new(dx, dy, 0);
+        map.occupancy
+            .get(&coords)
+            .and_then(|occupancy| {
+                occupancy
+                    .flows
+                    .iter()
+                    .filter(|flow| flow.type_() == self.type_())
+                    .map(|flow| flow.clamped_density())
+                    .reduce(f64::max)
+            })
+            .unwrap_or(0.0)
+    }
+
+    /// Density of each column of the tile relative to the flow density.
+    /// The border columns are halfway to the neighbouring tiles, so that the clouds
+    /// fade out at their edges instead of ending on a wall
+    fn horizontal_falloff(&self, map: &Map) -> Slice2D<f64> {
+        let own = self.clamped_density().max(1.0);
+        let center = (BASE / 2) as i32;
+        shape::slice_from_fn(|x, y| {
+            let (dx, dy) = (x as i32 - center, y as i32 - center);
+            if dx == 0 && dy == 0 {
+                return 1.0;
+            }
+            (own + self.neighbour_density(map, dx.signum(), dy.signum())) / (2.0 * own)
+        })
+    }
+
+    /// Density of a level of the tile relative to the flow density, z=0 being the floor.
+    /// The heavy clouds settle on the ground, the hot ones rise
+    fn vertical_falloff(&self, z: usize) -> f64 {
+        let height = z as f64 / (HEIGHT - 1) as f64;
+        match self.type_() {
+            FlowType::Mist
+            | FlowType::SeaFoam
+            | FlowType::Miasma
+            | FlowType::MaterialGas
+            | FlowType::MaterialDust => 1.3 - 0.6 * height,
+            FlowType::Smoke | FlowType::Steam | FlowType::MaterialVapor => 0.7 + 0.6 * height,
+            _ => 1.0,
+        }
+    }
+
+    /// Strands of a web, from the center of the tile to the walls and branches holding it.
+    /// A few specks when nothing holds it
+    fn web_strands(&self, map: &Map) -> Box3D<bool> {
+        let mut rng = self.flow_rng();
+        let anchor = |occupancy: &Occupancy| {
+            occupancy.is_wall()
+                || occupancy.block_tile.as_ref().is_some_and(|tile| {
+                    matches!(
+                        tile.tile_type().shape(),
+                        TiletypeShape::BRANCH | TiletypeShape::TRUNK_BRANCH | TiletypeShape::TWIG
+                    )
+                })
+        };
+        let coords = self.coords();
+        let around = map.neighbouring_8flat(coords, anchor);
+        let above = map.neighbouring(coords, |occupancy| {
+            anchor(occupancy)
+                || occupancy
+                    .block_tile
+                    .as_ref()
+                    .is_some_and(|tile| tile.tile_type().shape() == TiletypeShape::FLOOR)
+        });
+        let hub = HEIGHT / 2;
+        let mut strands = shape::box_empty();
+        let mut set = |x: usize, y: usize, z: usize| strands[HEIGHT - 1 - z][y][x] = true;
+        // Edge column of the tile towards each neighbour, y going south
+        let edges = [
+            (around.n, 1, 0),
+            (around.ne, 2, 0),
+            (around.e, 2, 1),
+            (around.se, 2, 2),
+            (around.s, 1, 2),
+            (around.sw, 0, 2),
+            (around.w, 0, 1),
+            (around.nw, 0, 0),
+        ];
+        let mut anchored = false;
+        for (x, y) in edges
+            .into_iter()
+            .filter_map(|(anchor, x, y)| anchor.then_some((x, y)))
+        {
+            // The strands sag or climb a little on their way to the anchor
+            set(x, y, rng.gen_range(hub - 1..=hub + 1));
+            anchored = true;
+        }
+        if above.a {
+            for z in hub..HEIGHT {
+                set(1, 1, z);
+            }
+            anchored = true;
+        }
+        if anchored {
+            set(1, 1, hub);
+        } else {
+            for _ in 0..2 {
+                set(
+                    rng.gen_range(0..BASE),
+                    rng.gen_range(0..BASE),
+                    rng.gen_range(0..HEIGHT),
+                );
+            }
+        }
+        strands
+    }
+
+    /// Build the flow as it looks at a given frame, out of `frames`.
+    /// The density oscillates around the actual one when animated, and falls off
+    /// towards the edges of the cloud and with the altitude.
+    pub fn build(
+        &self,
+        map: &Map,
+        context: &DFContext,
+        palette: &Palette,
+        frame: u8,
+        frames: u8,
+    ) -> Vec<dot_vox::Voxel> {
+        let coords = self.coords();
+        // The flows are stored in the blocks, but their coords are global
+        let local_coords = DFLocalCoords {
+            x: (coords.x as usize % BLOCK_SIZE) as u8,
+            y: (coords.y as usize % BLOCK_SIZE) as u8,
+        };
+        let mut rng = self.flow_rng();
+        let phase: f64 = rng.gen_range(0.0..TAU);
+        let mut density = self.clamped_density();
+        if frames > 1 {
+            density *= 1.0 + 0.3 * (TAU * frame as f64 / frames as f64 + phase).sin();
+        }
+        let horizontal = self.horizontal_falloff(map);
+        // The voxel draws are identical on each frame, only the threshold changes.
+        // This way the voxels appear and vanish progressively
+        let mut include = |x: usize, y: usize, z: usize| {
+            rng.gen::<f64>() * 400.0 < density * horizontal[y][x] * self.vertical_falloff(z)
+        };
+        let shape: Box3D<bool> = match self.type_() {
+            FlowType::OceanWave => [
+                slice_empty(),
+                slice_empty(),
+                slice_empty(),
+                shape::slice_from_fn(|x, y| include(x, y, 1)),
+                shape::slice_from_fn(|x, y| include(x, y, 0)),
+            ],
+            // The level above the floor is left for the cooling crust of the warm rock
+            FlowType::MagmaMist => [
+                shape::slice_from_fn(|x, y| include(x, y, 4)),
+                shape::slice_from_fn(|x, y| include(x, y, 3)),
+                shape::slice_from_fn(|x, y| include(x, y, 2)),
+                slice_empty(),
+                slice_empty(),
+            ],
+            // The webs don't float around, they hang from their anchors
+            FlowType::Web => self.web_strands(map),
+            _ => shape::box_from_fn(&mut include),
+        };
+        let material = match self.type_() {
+            FlowType::Mist | FlowType::SeaFoam | FlowType::Steam => {
+                Material::Default(DefaultMaterials::Mist)
+            }
+            FlowType::OceanWave => Material::Default(DefaultMaterials::Water),
+            FlowType::MagmaMist => Material::Default(DefaultMaterials::Magma),
+            FlowType::Fire | FlowType::CampFire | FlowType::Dragonfire => {
+                Material::Default(DefaultMaterials::Fire)
+            }
+            FlowType::Miasma => Material::Default(DefaultMaterials::Miasma),
+            FlowType::Smoke => Material::Default(DefaultMaterials::Smoke),
+            FlowType::ItemCloud
+            | FlowType::MaterialDust
+            | FlowType::MaterialGas
+            | FlowType::MaterialVapor
+            | FlowType::Web => Material::Generic(self.material.get_or_default().to_owned()),
+        };
+
+        voxels_from_uniform_shape(shape, local_coords, palette.get(&material, context))
+    }
+}
+
+/// Glowing crust over the floors still warm from the magma, denser where they are hotter
+pub fn build_cooling_crust(
+    tile: &BlockTile,
+    warmth: f64,
+    context: &DFContext,
+    palette: &Palette,
+) -> Vec<dot_vox::Voxel> {
+    if tile.tile_type().shape() != TiletypeShape::FLOOR {
+        return Vec::new();
+    }
+    let mut rng = tile.stable_rng();
+    let crust: Box3D<bool> = [
+        slice_empty(),
+        slice_empty(),
+        slice_empty(),
+        shape::slice_from_fn(|_, _| rng.gen::<f64>() < warmth),
+        slice_empty(),
+    ];
+    voxels_from_uniform_shape(
+        crust,
+        tile.local_coords(),
+        palette.get(&Material::Default(DefaultMaterials::CoolingRock), context),
+    )
+}
+
+impl WithDFCoords for FlowInfo {
+    fn coords(&self) -> DFMapCoords {
+        self.pos.get_or_default().into()
+    }
+}
+
+impl<T> Add<T> for DFMapCoords
+where
+    T: WithDFCoords,
+{
+    type Output = DFMapCoords;
+
+    fn add(self, rhs: T) -> Self::Output {
+        self + rhs.coords()
+    }
+}
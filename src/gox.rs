@@ -0,0 +1,262 @@
+use anyhow::Result;
+use dot_vox::{DotVoxData, SceneNode};
+use std::{collections::BTreeMap, io::Write};
+
+const GOX_BLOCK_SIZE: i32 = 16;
+const GOX_BLOCK_LEN: usize = (GOX_BLOCK_SIZE * GOX_BLOCK_SIZE * GOX_BLOCK_SIZE) as usize;
+
+type GoxBlock = Vec<[u8; 4]>;
+
+#[derive(Default)]
+struct GoxLayer {
+    name: String,
+    visible: bool,
+    /// Blocks by their origin
+    blocks: BTreeMap<(i32, i32, i32), GoxBlock>,
+}
+
+impl GoxLayer {
+    fn set(&mut self, x: i32, y: i32, z: i32, color: [u8; 4]) {
+        let origin = (
+            x.div_euclid(GOX_BLOCK_SIZE) * GOX_BLOCK_SIZE,
+            y.div_euclid(GOX_BLOCK_SIZE) * GOX_BLOCK_SIZE,
+            z.div_euclid(GOX_BLOCK_SIZE) * GOX_BLOCK_SIZE,
+        );
+        let block = self
+            .blocks
+            .entry(origin)
+            .or_insert_with(|| vec![[0; 4]; GOX_BLOCK_LEN]);
+        let (x, y, z) = (x - origin.0, y - origin.1, z - origin.2);
+        block[(x + y * GOX_BLOCK_SIZE + z * GOX_BLOCK_SIZE * GOX_BLOCK_SIZE) as usize] = color;
+    }
+}
+
+/// Write the scene as a Goxel project (.gox)
+///
+/// The scene graph is flattened, each MagicaVoxel layer becomes a Goxel layer
+/// made of 16x16x16 blocks stored as 64x64 RGBA png images. Goxel has neither
+/// animations nor scene rotations, only the first frame is written, z up.
+pub fn write_gox(vox: &DotVoxData, writer: &mut impl Write) -> Result<()> {
+    let mut layers: Vec<GoxLayer> = vox
+        .layers
+        .iter()
+        .map(|layer| GoxLayer {
+            name: layer.attributes.get("_name").cloned().unwrap_or_default(),
+            visible: layer
+                .attributes
+                .get("_hidden")
+                .map_or(true, |hidden| hidden != "1"),
+            blocks: BTreeMap::new(),
+        })
+        .collect();
+    flatten_node(vox, 0, (0, 0, 0), 0, &mut layers);
+
+    writer.write_all(b"GOX ")?;
+    writer.write_all(&2i32.to_le_bytes())?;
+
+    // All the blocks are written first, then referenced by index from the layers
+    let mut block_index = 0;
+    let mut layer_chunks = Vec::new();
+    for layer in layers.iter().filter(|layer| !layer.blocks.is_empty()) {
+        let mut layer_chunk = Vec::new();
+        layer_chunk.extend((layer.blocks.len() as i32).to_le_bytes());
+        for ((x, y, z), block) in &layer.blocks {
+            write_chunk(writer, b"BL16", &encode_block(block)?)?;
+            for value in [block_index, *x, *y, *z, 0] {
+                layer_chunk.extend(value.to_le_bytes());
+            }
+            block_index += 1;
+        }
+        write_dict_value(&mut layer_chunk, "name", layer.name.as_bytes());
+        write_dict_value(&mut layer_chunk, "visible", &[layer.visible as u8]);
+        layer_chunk.extend(0i32.to_le_bytes());
+        layer_chunks.push(layer_chunk);
+    }
+    for layer_chunk in layer_chunks {
+        write_chunk(writer, b"LAYR", &layer_chunk)?;
+    }
+    Ok(())
+}
+
+/// Accumulate the translations down to the shapes and copy their voxels in the layers
+fn flatten_node(
+    vox: &DotVoxData,
+    index: u32,
+    translation: (i32, i32, i32),
+    layer: u32,
+    layers: &mut [GoxLayer],
+) {
+    match &vox.scenes[index as usize] {
+        SceneNode::Transform {
+            frames,
+            child,
+            layer_id,
+            ..
+        } => {
+            let (x, y, z) = frames
+                .first()
+                .and_then(|frame| frame.attributes.get("_t"))
+                .and_then(|t| {
+                    let mut t = t.split(' ').filter_map(|v| v.parse::<i32>().ok());
+                    Some((t.next()?, t.next()?, t.next()?))
+                })
+                .unwrap_or_default();
+            let translation = (translation.0 + x, translation.1 + y, translation.2 + z);
+            flatten_node(vox, *child, translation, *layer_id, layers);
+        }
+        SceneNode::Group { children, .. } => {
+            for child in children {
+                flatten_node(vox, *child, translation, layer, layers);
+            }
+        }
+        SceneNode::Shape { models, .. } => {
+            let Some(gox_layer) = layers.get_mut(layer as usize) else {
+                return;
+            };
+            // The animated shapes only keep their first frame
+            let first_frame = models.iter().filter(|shape_model| {
+                shape_model
+                    .attributes
+                    .get("_f")
+                    .map_or(true, |frame| frame == "0")
+            });
+            for shape_model in first_frame {
+                let model = &vox.models[shape_model.model_id as usize];
+                // Models are centered on their translation
+                let (ox, oy, oz) = (
+                    translation.0 - model.size.x as i32 / 2,
+                    translation.1 - model.size.y as i32 / 2,
+                    translation.2 - model.size.z as i32 / 2,
+                );
+                for voxel in &model.voxels {
+                    let color = vox.palette[voxel.i as usize];
+                    gox_layer.set(
+                        ox + voxel.x as i32,
+                        oy + voxel.y as i32,
+                        oz + voxel.z as i32,
+                        [color.r, color.g, color.b, 255],
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn encode_block(block: &GoxBlock) -> Result<Vec<u8>> {
+    let mut png = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png, 64, 64);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(block.concat().as_slice())?;
+    }
+    Ok(png)
+}
+
+fn write_chunk(writer: &mut impl Write, chunk_type: &[u8; 4], data: &[u8]) -> Result<()> {
+    writer.write_all(chunk_type)?;
+    writer.write_all(&(data.len() as i32).to_le_bytes())?;
+    writer.write_all(data)?;
+    // Goxel does not check the crc
+    writer.write_all(&0i32.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_dict_value(chunk: &mut Vec<u8>, key: &str, value: &[u8]) {
+    chunk.extend((key.len() as i32).to_le_bytes());
+    chunk.extend(key.as_bytes());
+    chunk.extend((value.len() as i32).to_le_bytes());
+    chunk.extend(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dot_vox_builder::{DotVoxBuilder, LayerId};
+    use dot_vox::{Color, Size, Voxel};
+
+    fn read_i32(bytes: &mut &[u8]) -> i32 {
+        let value = i32::from_le_bytes(bytes[..4].try_into().unwrap());
+        *bytes = &bytes[4..];
+        value
+    }
+
+    /// Chunks of a .gox file, with their type
+    fn read_chunks(mut gox: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        let mut chunks = Vec::new();
+        assert_eq!(b"GOX ", &gox[..4]);
+        gox = &gox[4..];
+        assert_eq!(2, read_i32(&mut gox));
+        while !gox.is_empty() {
+            let chunk_type = gox[..4].try_into().unwrap();
+            gox = &gox[4..];
+            let len = read_i32(&mut gox) as usize;
+            chunks.push((chunk_type, gox[..len].to_vec()));
+            gox = &gox[len..];
+            read_i32(&mut gox);
+        }
+        chunks
+    }
+
+    #[test]
+    fn write_first_frame() {
+        let mut builder = DotVoxBuilder::default();
+        builder.data.palette[1] = Color {
+            r: 200,
+            g: 10,
+            b: 20,
+            a: 255,
+        };
+        builder.data.palette[2] = Color {
+            r: 10,
+            g: 200,
+            b: 20,
+            a: 255,
+        };
+        let frames = [(1, 1), (2, 2)]
+            .into_iter()
+            .map(|(x, i)| {
+                let mut model = DotVoxBuilder::new_model(Size { x: 4, y: 4, z: 4 });
+                model.voxels.push(Voxel { x, y: 0, z: 0, i });
+                model
+            })
+            .collect();
+        builder.insert_animated_models_and_shape_node(
+            builder.root_group,
+            frames,
+            LayerId(0),
+            "flow",
+        );
+        let mut gox = Vec::new();
+        write_gox(&builder.data, &mut gox).unwrap();
+
+        let chunks = read_chunks(&gox);
+        let blocks = chunks
+            .iter()
+            .filter(|(chunk_type, _)| chunk_type == b"BL16")
+            .collect::<Vec<_>>();
+        assert_eq!(1, blocks.len());
+        assert_eq!(
+            1,
+            chunks
+                .iter()
+                .filter(|(chunk_type, _)| chunk_type == b"LAYR")
+                .count()
+        );
+        let mut reader = png::Decoder::new(blocks[0].1.as_slice())
+            .read_info()
+            .unwrap();
+        let mut image = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut image).unwrap();
+        let voxels = image
+            .chunks(4)
+            .enumerate()
+            .filter(|(_, rgba)| rgba[3] != 0)
+            .collect::<Vec<_>>();
+        // The model is centered on the origin, the voxel lands on -1, -2, -2
+        // in the block at -16, -16, -16
+        let index = 15 + 14 * 16 + 14 * 16 * 16;
+        assert_eq!(vec![(index, &[200, 10, 20, 255][..])], voxels);
+    }
+}
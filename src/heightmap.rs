@@ -0,0 +1,128 @@
+use crate::{
+    context::DFContext,
+    map::Map,
+    palette::{DefaultMaterials, EffectiveMaterial, Material, RGBAColor},
+};
+use anyhow::Result;
+use dfhack_remote::TiletypeShape;
+use itertools::Itertools;
+use std::{collections::HashMap, fs::File, io::BufWriter, ops::Range, path::Path};
+
+/// Highest visible tile of each column, seen from above
+pub struct TopView {
+    pub width: u32,
+    pub height: u32,
+    /// Elevation of each column, scaled over the elevation range, the rows going south
+    pub heights: Vec<u8>,
+    /// RGBA color of each column, transparent where there is nothing to see
+    pub colors: Vec<u8>,
+}
+
+impl TopView {
+    /// View of the map, None if no tile is visible
+    pub fn new(map: &Map, context: &DFContext, z_range: &Range<i32>) -> Option<Self> {
+        // Highest visible tile of each column, with its color
+        let mut top: HashMap<(i32, i32), (i32, (u8, u8, u8, u8))> = HashMap::new();
+        for (coords, occupancy) in &map.occupancy {
+            let Some(tile) = &occupancy.block_tile else {
+                continue;
+            };
+            if tile.hidden()
+                || matches!(
+                    tile.tile_type().shape(),
+                    TiletypeShape::EMPTY | TiletypeShape::NO_SHAPE
+                )
+            {
+                continue;
+            }
+            if top
+                .get(&(coords.x, coords.y))
+                .is_some_and(|(z, _)| *z >= coords.z)
+            {
+                continue;
+            }
+            let color = if tile.water() > 0 {
+                DefaultMaterials::Water.get_rgba()
+            } else if tile.grass_percent() > 0 {
+                DefaultMaterials::LightGrass.get_rgba()
+            } else {
+                let material = EffectiveMaterial::from_material(
+                    &Material::Generic(tile.material().clone()),
+                    context,
+                );
+                (material.r, material.g, material.b, 255)
+            };
+            top.insert(
+                (coords.x, coords.y),
+                (coords.z, (color.0, color.1, color.2, 255)),
+            );
+        }
+
+        let (min_x, max_x) = top.keys().map(|(x, _)| *x).minmax().into_option()?;
+        let (min_y, max_y) = top.keys().map(|(_, y)| *y).minmax().into_option()?;
+        let (width, height) = ((max_x - min_x + 1) as u32, (max_y - min_y + 1) as u32);
+        let levels = (z_range.end - z_range.start - 1).max(1);
+
+        let mut heights = Vec::with_capacity((width * height) as usize);
+        let mut colors = Vec::with_capacity((width * height * 4) as usize);
+        // The rows are going south, like the dwarf fortress y axis
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                match top.get(&(x, y)) {
+                    Some((z, (r, g, b, a))) => {
+                        heights.push(((z - z_range.start) * 255 / levels).clamp(0, 255) as u8);
+                        colors.extend([*r, *g, *b, *a]);
+                    }
+                    None => {
+                        heights.push(0);
+                        colors.extend([0, 0, 0, 0]);
+                    }
+                }
+            }
+        }
+        Some(Self {
+            width,
+            height,
+            heights,
+            colors,
+        })
+    }
+}
+
+/// Write a grayscale heightmap and a top-down color map of the exported region,
+/// named after the model path
+pub fn write_maps(map: &Map, context: &DFContext, z_range: &Range<i32>, path: &Path) -> Result<()> {
+    let Some(view) = TopView::new(map, context, z_range) else {
+        return Ok(());
+    };
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    write_png(
+        &path.with_file_name(format!("{stem}_heightmap.png")),
+        view.width,
+        view.height,
+        png::ColorType::Grayscale,
+        &view.heights,
+    )?;
+    write_png(
+        &path.with_file_name(format!("{stem}_colormap.png")),
+        view.width,
+        view.height,
+        png::ColorType::Rgba,
+        &view.colors,
+    )?;
+    Ok(())
+}
+
+fn write_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    color: png::ColorType,
+    data: &[u8],
+) -> Result<()> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(data)?;
+    Ok(())
+}
@@ -0,0 +1,162 @@
+use crate::{
+    context::DFContext,
+    overlay::Overlay,
+    palette::{DefaultMaterials, Material, Palette},
+    rfr::DFHackExt,
+    DFMapCoords, VoxelCoords,
+};
+use anyhow::Result;
+use dfhack_remote::MatPair;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerKind {
+    Barrel,
+    Bin,
+    Bag,
+    /// Prepared meal, not in a container
+    Meal,
+    /// Plant or fruit, not in a container
+    Plant,
+}
+
+/// Filled container or loose food stored in a stockpile
+#[derive(Debug, Clone)]
+pub struct Container {
+    pub coords: DFMapCoords,
+    pub kind: ContainerKind,
+    pub material: MatPair,
+    /// Materials of the contained items, or of the ingredients of a meal.
+    /// The liquids come first in the barrels
+    pub contents: Vec<MatPair>,
+}
+
+/// Read the filled barrels, bins and bags and the loose food lying in the stockpiles
+pub fn read_containers(client: &mut dfhack_remote::Client) -> Result<Vec<Container>> {
+    let output = client.lua_output(
+        r#"local kinds = {
+  [df.item_type.BARREL] = "barrel",
+  [df.item_type.BIN] = "bin",
+  [df.item_type.BOX] = "bag",
+  [df.item_type.FOOD] = "meal",
+  [df.item_type.PLANT] = "plant",
+  [df.item_type.PLANT_GROWTH] = "plant",
+}
+local loose = { meal = true, plant = true }
+local liquids = { [df.item_type.DRINK] = true, [df.item_type.LIQUID_MISC] = true }
+for _, item in ipairs(df.global.world.items.other.IN_PLAY) do
+  local kind = kinds[item:getType()]
+  if kind and item.flags.on_ground then
+    local building = dfhack.buildings.findAtTile(item.pos)
+    local contents = {}
+    if kind == "meal" then
+      for _, ingredient in ipairs(item.ingredients) do
+        table.insert(contents, {ingredient.mat_type, ingredient.mat_index})
+      end
+    else
+      local items = dfhack.items.getContainedItems(item)
+      -- The liquids show on top of the barrels
+      table.sort(items, function(a, b) return liquids[a:getType()] and not liquids[b:getType()] end)
+      for _, content in ipairs(items) do
+        table.insert(contents, {content:getActualMaterial(), content:getActualMaterialIndex()})
+      end
+    end
+    if building and df.building_stockpilest:is_instance(building) and (loose[kind] or #contents > 0) then
+      local line = {item.pos.x, item.pos.y, item.pos.z, kind, item:getActualMaterial(), item:getActualMaterialIndex()}
+      for i, content in ipairs(contents) do
+        if i > 8 then break end
+        table.insert(line, content[1])
+        table.insert(line, content[2])
+      end
+      emit(table.unpack(line))
+    end
+  end
+end"#,
+    )?;
+    Ok(output.lines().filter_map(parse_container).collect())
+}
+
+fn parse_container(line: &str) -> Option<Container> {
+    let mut parts = line.split_whitespace();
+    let x = parts.next()?.parse().ok()?;
+    let y = parts.next()?.parse().ok()?;
+    let z = parts.next()?.parse().ok()?;
+    let kind = match parts.next()? {
+        "barrel" => ContainerKind::Barrel,
+        "bin" => ContainerKind::Bin,
+        "bag" => ContainerKind::Bag,
+        "meal" => ContainerKind::Meal,
+        "plant" => ContainerKind::Plant,
+        _ => return None,
+    };
+    let numbers: Vec<i32> = parts.filter_map(|p| p.parse().ok()).collect();
+    let mut materials = numbers.chunks_exact(2).map(|pair| MatPair {
+        mat_type: Some(pair[0]),
+        mat_index: Some(pair[1]),
+        ..Default::default()
+    });
+    Some(Container {
+        coords: DFMapCoords::new(x, y, z),
+        kind,
+        material: materials.next()?,
+        contents: materials.collect(),
+    })
+}
+
+/// Small stacks of containers topped with the color of their content,
+/// colorful stacks of meals and green piles of plants
+pub fn build_containers(
+    containers: &[Container],
+    z_range: &Range<i32>,
+    context: &DFContext,
+    palette: &Palette,
+) -> Overlay {
+    let mut overlay = Overlay::default();
+    for container in containers {
+        if !z_range.contains(&container.coords.z) {
+            continue;
+        }
+        let material = palette.get(&Material::Generic(container.material.clone()), context);
+        let mut contents = container
+            .contents
+            .iter()
+            .map(|content| palette.get(&Material::Generic(content.clone()), context))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .cycle();
+        // (x, y, height) of the container columns in the tile, the floor is at z=0
+        let columns: &[(usize, usize, usize)] = match container.kind {
+            ContainerKind::Barrel => &[(1, 1, 2)],
+            ContainerKind::Bin => &[(0, 1, 1), (1, 1, 1), (0, 2, 1), (1, 2, 1)],
+            ContainerKind::Bag => &[(1, 1, 1)],
+            ContainerKind::Meal => {
+                // One layer per ingredient
+                for z in 1..=3 {
+                    let color = contents.next().unwrap_or(material);
+                    overlay.add(VoxelCoords::from_df(container.coords, 1, 1, z), color);
+                }
+                continue;
+            }
+            ContainerKind::Plant => {
+                let leaves = palette.get(&Material::Default(DefaultMaterials::LightGrass), context);
+                for (x, y) in [(1, 1), (0, 1), (2, 1), (1, 0), (1, 2)] {
+                    overlay.add(VoxelCoords::from_df(container.coords, x, y, 1), leaves);
+                }
+                overlay.add(VoxelCoords::from_df(container.coords, 1, 1, 2), material);
+                continue;
+            }
+        };
+        for (x, y, height) in columns {
+            for z in 1..=*height {
+                overlay.add(VoxelCoords::from_df(container.coords, *x, *y, z), material);
+            }
+            if let Some(content) = contents.next() {
+                overlay.add(
+                    VoxelCoords::from_df(container.coords, *x, *y, height + 1),
+                    content,
+                );
+            }
+        }
+    }
+    overlay
+}
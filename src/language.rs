@@ -0,0 +1,107 @@
+use crate::{calendar::Month, rfr::DFHackExt};
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use strum::{Display, EnumIter, IntoEnumIterator};
+
+/// Language of the names written in the scene graph
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Display, EnumIter, Serialize, Deserialize, ValueEnum,
+)]
+pub enum NameLanguage {
+    #[default]
+    English,
+    Dwarven,
+}
+
+/// Names of the fortress and of the months, in the chosen language
+pub struct Names {
+    pub fortress: String,
+    pub year: i32,
+    pub months: HashMap<String, String>,
+}
+
+impl Names {
+    pub fn read(client: &mut dfhack_remote::Client, language: NameLanguage) -> Result<Self> {
+        let world_map = client.remote_fortress_reader().get_world_map()?;
+        let world = match language {
+            NameLanguage::English => world_map.name_english(),
+            NameLanguage::Dwarven => world_map.name(),
+        };
+        // The world name stands in when no site is loaded
+        let fortress = read_site_name(client, language)?.unwrap_or_else(|| world.to_string());
+        let months = match language {
+            NameLanguage::English => HashMap::new(),
+            NameLanguage::Dwarven => read_dwarven_months(client)?,
+        };
+        Ok(Self {
+            fortress,
+            year: world_map.cur_year(),
+            months,
+        })
+    }
+
+    pub fn month(&self, month: Month) -> String {
+        self.months
+            .get(&month.to_string())
+            .cloned()
+            .unwrap_or_else(|| month.to_string())
+    }
+
+    /// Name of the whole scene, such as "Boatmurdered, Granite 250"
+    pub fn title(&self, month: Month) -> String {
+        format!("{}, {} {}", self.fortress, self.month(month), self.year)
+    }
+}
+
+/// Name of the site currently loaded, if any
+pub fn read_site_name(
+    client: &mut dfhack_remote::Client,
+    language: NameLanguage,
+) -> Result<Option<String>> {
+    let output = client.lua_output(&format!(
+        r#"local site = dfhack.isMapLoaded() and dfhack.world.getCurrentSite()
+if site then
+  -- Moved to the translation module in the recent versions
+  local translate = dfhack.TranslateName or dfhack.translation.translateName
+  emit(translate(site.name, {}))
+end"#,
+        language == NameLanguage::English
+    ))?;
+    let name = output.trim();
+    Ok((!name.is_empty()).then(|| name.to_string()))
+}
+
+/// Translate the month names with the dwarven language raws
+fn read_dwarven_months(client: &mut dfhack_remote::Client) -> Result<HashMap<String, String>> {
+    let words = Month::iter()
+        .map(|month| format!("\"{}\"", month.to_string().to_uppercase()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let output = client.lua_output(&format!(
+        r#"local language = df.global.world.raws.language
+local dwarf
+for _, translation in ipairs(language.translations) do
+  if translation.name == "DWARF" then dwarf = translation end
+end
+for _, month in ipairs({{{words}}}) do
+  for i, word in ipairs(language.words) do
+    if dwarf and word.word == month then emit(month, dwarf.words[i].value) end
+  end
+end"#
+    ))?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let (month, word) = line.split_once(' ')?;
+            let mut chars = word.trim().chars();
+            let first = chars.next()?;
+            let month = Month::iter().find(|m| m.to_string().to_uppercase() == month)?;
+            Some((
+                month.to_string(),
+                first.to_uppercase().chain(chars).collect(),
+            ))
+        })
+        .collect())
+}
@@ -0,0 +1,166 @@
+use crate::{
+    dot_vox_builder::{LayerId, LAYER_COUNT},
+    export::Layers,
+};
+use anyhow::{bail, Context, Result};
+use glob_match::glob_match;
+use std::{collections::HashMap, path::Path};
+use strum::IntoEnumIterator;
+
+/// Layers of the scene rearranged by the user, from a YAML table of rules such as
+///
+/// ```yaml
+/// doors: [Door, Hatch]
+/// liquid: [flows]
+/// ```
+///
+/// Each entry names a layer, either a built-in one or a new one, and lists what goes in it:
+/// the content of a built-in layer by name, or glob patterns of the building identifiers.
+/// The rules apply in the order of the file, the first one listing a content wins,
+/// and the new layers are numbered in that order.
+#[derive(Debug, Default)]
+pub struct LayerRules {
+    /// Names of the layers added by the rules, placed after the built-in ones
+    custom: Vec<String>,
+    /// Layer receiving the content of the moved built-in layers
+    moved: HashMap<Layers, LayerId>,
+    /// Glob patterns of the building identifiers, with their layer
+    buildings: Vec<(String, LayerId)>,
+}
+
+impl LayerRules {
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Reading the layer rules {}", path.display()))?;
+        let rules =
+            parse(&bytes).with_context(|| format!("Parsing the layer rules {}", path.display()))?;
+        Self::from_rules(rules)
+            .with_context(|| format!("Loading the layer rules {}", path.display()))
+    }
+
+    fn from_rules(rules: Vec<(String, Vec<String>)>) -> Result<Self> {
+        let mut layer_rules = Self::default();
+        for (name, contents) in rules {
+            let layer = match built_in(&name) {
+                Some(Layers::All) => bail!("The `all` layer only holds the groups"),
+                Some(layer) => layer.id(),
+                None => {
+                    if !valid_name(&name) {
+                        bail!("Invalid layer name `{name}`, use letters, digits, spaces, - and _");
+                    }
+                    let id = LayerId(Layers::iter().count() + layer_rules.custom.len());
+                    if *id >= LAYER_COUNT {
+                        bail!("Too many layers, MagicaVoxel is limited to {LAYER_COUNT}");
+                    }
+                    layer_rules.custom.push(name);
+                    id
+                }
+            };
+            for content in contents {
+                match built_in(&content) {
+                    Some(Layers::All) => bail!("The groups of the `all` layer can not be moved"),
+                    Some(moved) => {
+                        layer_rules.moved.entry(moved).or_insert(layer);
+                    }
+                    None => layer_rules.buildings.push((content, layer)),
+                }
+            }
+        }
+        Ok(layer_rules)
+    }
+
+    /// Layer receiving the content of a built-in layer
+    pub fn layer(&self, layer: Layers) -> LayerId {
+        self.moved
+            .get(&layer)
+            .copied()
+            .unwrap_or_else(|| layer.id())
+    }
+
+    /// Layer of a building, from the first rule matching the identifier of its definition
+    pub fn building_layer(&self, id: &str) -> LayerId {
+        self.buildings
+            .iter()
+            .find(|(pattern, _)| glob_match(pattern, id))
+            .map_or_else(|| self.layer(Layers::Building), |(_, layer)| *layer)
+    }
+
+    /// Layers added by the rules, with their name
+    pub fn custom_layers(&self) -> impl Iterator<Item = (LayerId, &str)> {
+        let first = Layers::iter().count();
+        self.custom
+            .iter()
+            .enumerate()
+            .map(move |(index, name)| (LayerId(first + index), name.as_str()))
+    }
+}
+
+/// Rules in the order of the file
+fn parse(bytes: &[u8]) -> Result<Vec<(String, Vec<String>)>> {
+    let mapping: serde_yaml::Mapping = serde_yaml::from_slice(bytes)?;
+    mapping
+        .into_iter()
+        .map(|(name, contents)| {
+            Ok((
+                serde_yaml::from_value(name)?,
+                serde_yaml::from_value(contents)?,
+            ))
+        })
+        .collect()
+}
+
+/// True if the name of a new layer can be used in a file name
+fn valid_name(name: &str) -> bool {
+    !name.trim().is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+}
+
+/// Built-in layer with the given name, ignoring the case
+fn built_in(name: &str) -> Option<Layers> {
+    Layers::iter().find(|layer| layer.to_string().eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::Itertools;
+
+    #[test]
+    fn rules_apply_in_file_order() {
+        let rules = parse(b"zebra: [Door*]\nalpha: [Door, flows]\nliquid: [flows]").unwrap();
+        let layer_rules = LayerRules::from_rules(rules).unwrap();
+        let first = Layers::iter().count();
+        assert_eq!(
+            vec![(LayerId(first), "zebra"), (LayerId(first + 1), "alpha")],
+            layer_rules.custom_layers().collect_vec()
+        );
+        assert_eq!(LayerId(first), layer_rules.building_layer("Door"));
+        assert_eq!(LayerId(first + 1), layer_rules.layer(Layers::Flows));
+    }
+
+    #[test]
+    fn reject_all_layer() {
+        assert!(LayerRules::from_rules(parse(b"all: [Door]").unwrap()).is_err());
+        assert!(LayerRules::from_rules(parse(b"doors: [all]").unwrap()).is_err());
+    }
+
+    #[test]
+    fn reject_path_in_name() {
+        assert!(LayerRules::from_rules(parse(b"../doors: [Door]").unwrap()).is_err());
+        assert!(LayerRules::from_rules(parse(b"a/b: [Door]").unwrap()).is_err());
+    }
+
+    #[test]
+    fn layer_limit() {
+        let free = LAYER_COUNT - Layers::iter().count();
+        let rules = |count: usize| {
+            (0..count)
+                .map(|index| (format!("layer {index}"), vec![]))
+                .collect_vec()
+        };
+        assert!(LayerRules::from_rules(rules(free)).is_ok());
+        assert!(LayerRules::from_rules(rules(free + 1)).is_err());
+    }
+}
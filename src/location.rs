@@ -0,0 +1,199 @@
+use crate::{
+    context::DFContext,
+    language::NameLanguage,
+    overlay::Overlay,
+    palette::{DefaultMaterials, Material, Palette},
+    rfr::DFHackExt,
+    DFBoundingBox, DFMapCoords, VoxelCoords,
+};
+use anyhow::Result;
+use std::ops::Range;
+use strum::Display;
+
+/// Color of the mugs left on the tavern tables
+const MUG_COLOR: (u8, u8, u8) = (150, 110, 70);
+
+/// Kind of location a zone is assigned to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum LocationKind {
+    Temple,
+    Guildhall,
+    Tavern,
+}
+
+/// Zone assigned to a location, and the furniture decorated in it
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub kind: LocationKind,
+    pub name: String,
+    /// Deity or religion the temple is dedicated to
+    pub deity: Option<String>,
+    pub area: DFBoundingBox,
+    pub altars: Vec<DFMapCoords>,
+    pub tables: Vec<DFMapCoords>,
+    /// Instruments lying in the zone
+    pub instruments: Vec<DFMapCoords>,
+}
+
+impl Location {
+    /// Name of the location in the scene graph, such as "Temple The Golden Shrine (Urist)"
+    pub fn scene_name(&self) -> String {
+        match &self.deity {
+            Some(deity) => format!("{} {} ({deity})", self.kind, self.name),
+            None => format!("{} {}", self.kind, self.name),
+        }
+    }
+}
+
+/// Read the zones assigned to the temples, guildhalls and taverns of the fortress
+pub fn read_locations(
+    client: &mut dfhack_remote::Client,
+    language: NameLanguage,
+) -> Result<Vec<Location>> {
+    let english = language == NameLanguage::English;
+    let output = client.lua_output(&format!(
+        r#"local english = {english}
+local site = dfhack.world.getCurrentSite()
+-- Moved to the translation module in the recent versions
+local translate = dfhack.TranslateName or dfhack.translation.translateName
+local function find_location(id)
+  for _, location in ipairs(site.buildings) do
+    if location.id == id then return location end
+  end
+end
+local function deity_name(location)
+  local ok, name = pcall(function()
+    if location.deity_type == df.religious_practice_type.WORSHIP_HFID then
+      return translate(df.historical_figure.find(location.deity_data.HFID).name, english)
+    else
+      return translate(df.historical_entity.find(location.deity_data.Religion).name, english)
+    end
+  end)
+  if ok and name then return name end
+  return ""
+end
+if site then
+  for _, zone in ipairs(df.global.world.buildings.other.ANY_ZONE) do
+    local location = zone.location_id >= 0 and find_location(zone.location_id)
+    local kind
+    if not location then
+    elseif df.abstract_building_templest:is_instance(location) then kind = "Temple"
+    elseif df.abstract_building_guildhallst:is_instance(location) then kind = "Guildhall"
+    elseif df.abstract_building_inn_tavernst:is_instance(location) then kind = "Tavern" end
+    if kind then
+      local name = translate(location.name, english)
+      local deity = kind == "Temple" and deity_name(location) or ""
+      emit("zone", zone.x1, zone.y1, zone.x2, zone.y2, zone.z, kind, name .. "|" .. deity)
+    end
+  end
+end
+local function each(kind, list)
+  local ok, buildings = pcall(function() return df.global.world.buildings.other[list] end)
+  if not ok then return end
+  for _, building in ipairs(buildings) do
+    emit(kind, building.x1, building.y1, building.z)
+  end
+end
+each("altar", "OFFERING_PLACE")
+each("table", "TABLE")
+for _, item in ipairs(df.global.world.items.other.INSTRUMENT) do
+  if item.flags.on_ground then
+    emit("instrument", item.pos.x, item.pos.y, item.pos.z)
+  end
+end"#
+    ))?;
+
+    let mut locations = Vec::new();
+    let mut props = Vec::new();
+    for line in output.lines() {
+        let mut parts = line.splitn(8, ' ');
+        let Some(kind) = parts.next() else {
+            continue;
+        };
+        let values: Vec<i32> = parts
+            .by_ref()
+            .take(if kind == "zone" { 5 } else { 3 })
+            .filter_map(|v| v.trim().parse().ok())
+            .collect();
+        match (kind, &values[..]) {
+            ("zone", [x1, y1, x2, y2, z]) => {
+                let location_kind = match parts.next() {
+                    Some("Temple") => LocationKind::Temple,
+                    Some("Guildhall") => LocationKind::Guildhall,
+                    Some("Tavern") => LocationKind::Tavern,
+                    _ => continue,
+                };
+                let (name, deity) = parts
+                    .next()
+                    .and_then(|rest| rest.split_once('|'))
+                    .unwrap_or_default();
+                locations.push(Location {
+                    kind: location_kind,
+                    name: name.to_string(),
+                    deity: (!deity.trim().is_empty()).then(|| deity.trim().to_string()),
+                    area: DFBoundingBox::new(*x1..=*x2, *y1..=*y2, *z..=*z),
+                    altars: Vec::new(),
+                    tables: Vec::new(),
+                    instruments: Vec::new(),
+                });
+            }
+            (_, [x, y, z]) => props.push((kind.to_string(), DFMapCoords::new(*x, *y, *z))),
+            _ => {}
+        }
+    }
+
+    for location in &mut locations {
+        for (kind, coords) in &props {
+            if !location.area.contains(*coords) {
+                continue;
+            }
+            match kind.as_str() {
+                "altar" => location.altars.push(*coords),
+                "table" => location.tables.push(*coords),
+                "instrument" => location.instruments.push(*coords),
+                _ => {}
+            }
+        }
+    }
+    Ok(locations)
+}
+
+/// Subtle decorations of a location: a glow above the temple altars,
+/// mugs on the tavern tables and the instruments left around
+pub fn build_decor(
+    location: &Location,
+    z_range: &Range<i32>,
+    context: &DFContext,
+    palette: &Palette,
+) -> Overlay {
+    let glow = palette.get(&Material::Default(DefaultMaterials::AltarGlow), context);
+    let wood = palette.get(&Material::Default(DefaultMaterials::Wood), context);
+    let (r, g, b) = MUG_COLOR;
+    let mug = palette.get(&Material::Color(r, g, b), context);
+
+    let mut overlay = Overlay::default();
+    let mut add = |coords: &DFMapCoords, voxels: &[(usize, usize, usize)], color| {
+        if z_range.contains(&coords.z) {
+            for (x, y, z) in voxels {
+                overlay.add(VoxelCoords::from_df(*coords, *x, *y, *z), color);
+            }
+        }
+    };
+    if location.kind == LocationKind::Temple {
+        for altar in &location.altars {
+            // Flame hovering above the offering place
+            add(altar, &[(1, 1, 3), (1, 1, 4)], glow);
+        }
+    }
+    if location.kind == LocationKind::Tavern {
+        for table in &location.tables {
+            // The table top is at z=2
+            add(table, &[(2, 0, 3)], mug);
+        }
+    }
+    for instrument in &location.instruments {
+        // Small drum lying on the floor
+        add(instrument, &[(0, 2, 1), (1, 2, 1)], wood);
+    }
+    overlay
+}
@@ -0,0 +1,123 @@
+use crate::{palette::Material, rfr::DFHackExt, DFMapCoords};
+use anyhow::Result;
+use dfhack_remote::MatPair;
+use itertools::Itertools;
+use std::{collections::HashMap, ops::Range};
+
+/// Magma-safe materials
+const SAFE_COLOR: (u8, u8, u8) = (60, 190, 70);
+/// Materials melting, burning or boiling in magma
+const UNSAFE_COLOR: (u8, u8, u8) = (210, 40, 30);
+
+/// Magma safety of the materials of the constructions and buildings, by material type and index
+#[derive(Debug, Default)]
+pub struct MagmaSafety(HashMap<(i32, i32), bool>);
+
+impl MagmaSafety {
+    /// Tint of a material for the audit, None if its safety is unknown
+    pub fn tint(&self, material: &MatPair) -> Option<Material> {
+        let safe = self.0.get(&(material.mat_type(), material.mat_index()))?;
+        let (r, g, b) = if *safe { SAFE_COLOR } else { UNSAFE_COLOR };
+        Some(Material::Color(r, g, b))
+    }
+}
+
+/// Read whether the materials of the constructions and buildings withstand magma,
+/// from their melting, boiling, ignition and heat damage points
+pub fn read_magma_safety(client: &mut dfhack_remote::Client) -> Result<MagmaSafety> {
+    let output = client.lua_output(
+        r#"local MAGMA_TEMPERATURE = 12000
+local seen = {}
+local function check(mat_type, mat_index)
+  local key = mat_type .. ":" .. mat_index
+  if seen[key] then return end
+  seen[key] = true
+  local info = dfhack.matinfo.decode(mat_type, mat_index)
+  if not info then return end
+  local heat = info.material.heat
+  -- 60001 stands for never
+  local safe = heat.melting_point > MAGMA_TEMPERATURE
+    and heat.boiling_point > MAGMA_TEMPERATURE
+    and heat.ignite_point > MAGMA_TEMPERATURE
+    and heat.heatdam_point > MAGMA_TEMPERATURE
+  emit(mat_type, mat_index, safe and 1 or 0)
+end
+for _, construction in ipairs(df.global.world.constructions) do
+  check(construction.mat_type, construction.mat_index)
+end
+for _, building in ipairs(df.global.world.buildings.all) do
+  check(building.mat_type, building.mat_index)
+end"#,
+    )?;
+    Ok(MagmaSafety(
+        output
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.split_whitespace();
+                let mat_type = parts.next()?.parse().ok()?;
+                let mat_index = parts.next()?.parse().ok()?;
+                Some(((mat_type, mat_index), parts.next()? == "1"))
+            })
+            .collect(),
+    ))
+}
+
+/// Temperature of the magma, in Urist
+const MAGMA_TEMPERATURE: f64 = 12000.0;
+/// Temperature above which the rock is still warm from the magma, far above the climates
+const WARM_TEMPERATURE: f64 = 10500.0;
+
+/// Read the tiles of the exported levels still warm from the magma, with their warmth
+/// from 0 (cooled down) to 1 (as hot as the magma)
+///
+/// Dwarf Fortress keeps the temperature of each tile, which cools down slowly once the magma
+/// is gone. The highest of the current and previous temperatures is kept.
+pub fn read_warm_rock(
+    client: &mut dfhack_remote::Client,
+    z_ranges: &[Range<i32>],
+) -> Result<HashMap<DFMapCoords, f64>> {
+    let ranges = z_ranges
+        .iter()
+        .map(|range| format!("{{{}, {}}}", range.start, range.end))
+        .join(", ");
+    let output = client.lua_output(&format!(
+        r#"local function exported(z)
+  for _, range in ipairs({{{ranges}}}) do
+    if z >= range[1] and z < range[2] then return true end
+  end
+  return false
+end
+for _, block in ipairs(df.global.world.map.map_blocks) do
+  if exported(block.map_pos.z) then
+    for x = 0, 15 do
+      for y = 0, 15 do
+        local designation = block.designation[x][y]
+        local temperature = math.max(block.temperature_1[x][y], block.temperature_2[x][y])
+        -- 60001 stands for no temperature
+        if temperature > {WARM_TEMPERATURE} and temperature < 60001
+          and designation.flow_size == 0 and not designation.hidden then
+          emit(block.map_pos.x + x, block.map_pos.y + y, block.map_pos.z, temperature)
+        end
+      end
+    end
+  end
+end"#
+    ))?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let values: Vec<i32> = line
+                .split_whitespace()
+                .filter_map(|v| v.parse().ok())
+                .collect();
+            match values[..] {
+                [x, y, z, temperature] => {
+                    let warmth = (temperature as f64 - WARM_TEMPERATURE)
+                        / (MAGMA_TEMPERATURE - WARM_TEMPERATURE);
+                    Some((DFMapCoords::new(x, y, z), warmth.clamp(0.0, 1.0)))
+                }
+                _ => None,
+            }
+        })
+        .collect())
+}
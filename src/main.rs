@@ -1,33 +1,59 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
+mod art;
 mod block;
 mod building;
 mod calendar;
+mod capabilities;
+mod color;
 mod context;
 mod coords;
+mod crenellation;
+mod diorama;
 mod direction;
 mod dot_vox_builder;
 mod export;
+mod flair;
 mod flow;
+mod gox;
+mod heightmap;
+mod item;
+mod language;
+mod layer_rules;
+mod location;
+mod magma;
+mod manifest;
 mod map;
+mod material_table;
+mod mechanism;
+mod merge;
+mod overlay;
 mod palette;
 mod prefabs;
+mod profile;
 mod rfr;
+mod route;
+mod sea;
 mod shape;
+mod surroundings;
 mod tile;
+mod traffic;
 mod traits;
 mod ui;
+mod unit;
 #[cfg(feature = "self-update")]
 mod update;
 mod voxel;
+mod watermark;
+mod workshop;
 
 use std::path::PathBuf;
 
 use calendar::Month;
-use export::Elevation;
+use export::{Elevation, ExportOptions};
 pub use traits::*;
 
-use clap::{Parser, Subcommand};
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
 pub use coords::{DFBoundingBox, DFMapCoords, VoxelCoords, WithDFCoords, BASE, HEIGHT};
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -57,13 +83,32 @@ pub enum Command {
         /// Higher point to export
         #[arg(long, allow_hyphen_values = true)]
         high: Option<i32>,
+        /// Other range to export, such as `40..45`, both ends included. Can be repeated
+        #[arg(
+            long = "range",
+            value_name = "LOW..HIGH",
+            value_parser = parse_range,
+            allow_hyphen_values = true
+        )]
+        ranges: Vec<(i32, i32)>,
+        /// Write each range in its own file instead of a single model
+        #[arg(long)]
+        separate_ranges: bool,
+        /// Open the model once exported
+        #[arg(long)]
+        open: bool,
+        /// Application opening the model, the one associated to the file by default
+        #[arg(long, requires = "open")]
+        viewer: Option<PathBuf>,
         /// Season for export
         #[arg(long)]
         month: Option<Month>,
-        /// Destination file
+        /// Destination file, .vox for MagicaVoxel or .gox for Goxel
         destination: PathBuf,
+        #[command(flatten)]
+        options: ExportOptions,
     },
-    /// Export one .vox file per month of the year
+    /// Export one .vox file per month of the year, and a timeline.json describing the frames
     ExportYear {
         /// Lower point to export
         #[arg(long, allow_hyphen_values = true)]
@@ -73,6 +118,90 @@ pub enum Command {
         high: Option<i32>,
         /// Destination folder
         destination: PathBuf,
+        #[command(flatten)]
+        options: ExportOptions,
+    },
+    /// Load each save of a folder with DFHack, and export the view of every fortress
+    BatchSaves {
+        /// Dwarf Fortress save folder
+        saves: PathBuf,
+        /// Destination folder
+        #[arg(long, default_value = ".")]
+        destination: PathBuf,
+        /// DFHack command returning to the title screen between two saves,
+        /// required when the folder holds several saves
+        #[arg(long)]
+        unload: Option<String>,
+        #[command(flatten)]
+        options: ExportOptions,
+    },
+    /// Stay resident and export the view whenever the trigger file is created,
+    /// for instance by a DFHack keybinding
+    Serve {
+        /// Lower point to export, the view elevation by default
+        #[arg(long, allow_hyphen_values = true)]
+        low: Option<i32>,
+        /// Higher point to export, the view elevation by default
+        #[arg(long, allow_hyphen_values = true)]
+        high: Option<i32>,
+        /// File requesting an export when created, removed once the request is handled
+        #[arg(long, default_value = "vox-uristi.trigger")]
+        trigger: PathBuf,
+        /// Destination folder
+        #[arg(long, default_value = ".")]
+        destination: PathBuf,
+        #[command(flatten)]
+        options: ExportOptions,
+    },
+    /// Export the levels where the invaders are fighting, with the units and the projectiles
+    Siege {
+        /// Destination folder
+        #[arg(long, default_value = ".")]
+        destination: PathBuf,
+        /// Open the model once exported
+        #[arg(long)]
+        open: bool,
+        #[command(flatten)]
+        options: ExportOptions,
+    },
+    /// Export only the levels holding coffins or tombs, opened from above
+    Catacombs {
+        /// Open the model once exported
+        #[arg(long)]
+        open: bool,
+        /// Destination file, .vox for MagicaVoxel or .gox for Goxel
+        destination: PathBuf,
+        #[command(flatten)]
+        options: ExportOptions,
+    },
+    /// Run the export stored in a .voxuristi job file
+    Job {
+        /// Job file, saved from the recent exports of the graphical interface
+        job: PathBuf,
+        /// Open the model once exported
+        #[arg(long)]
+        open: bool,
+    },
+    /// Merge several exports in a single scene, from their manifests (see `--manifest`)
+    Merge {
+        /// Manifests of the exports to merge
+        #[arg(required = true)]
+        manifests: Vec<PathBuf>,
+        /// Name of the group of each export, in the order of the manifests.
+        /// The title of the export by default. Can be repeated
+        #[arg(long = "name")]
+        names: Vec<String>,
+        /// Offset of each export in tiles, in the order of the manifests. Can be repeated
+        #[arg(
+            long = "offset",
+            value_name = "X,Y,Z",
+            value_parser = parse_offset,
+            allow_hyphen_values = true
+        )]
+        offsets: Vec<(i32, i32, i32)>,
+        /// Destination file, .vox for MagicaVoxel or .gox for Goxel
+        #[arg(long)]
+        destination: PathBuf,
     },
     /// Check for new versions
     #[cfg(feature = "self-update")]
@@ -87,16 +216,56 @@ pub enum Command {
 #[derive(Subcommand)]
 pub enum DevCommand {
     /// Regen test data from df
-    RegenTestData,
+    RegenTestData {
+        /// Lua script to run first, such as changing the season or spawning a siege
+        #[arg(long)]
+        lua: Option<PathBuf>,
+        /// Tile to capture, instead of the current cursor level
+        #[arg(long, num_args = 3, value_names = ["X", "Y", "Z"])]
+        at: Option<Vec<i32>>,
+        /// Let the game run for this number of ticks before the capture
+        #[arg(long)]
+        advance_ticks: Option<u32>,
+    },
+    /// Run a lua script in DFHack and print what it passes to `emit(...)`
+    Lua {
+        /// Script to run
+        script: String,
+    },
+    /// Center the view on a tile and place the cursor on it
+    Teleport { x: i32, y: i32, z: i32 },
+    /// Let the game run for a number of ticks
+    AdvanceTime {
+        /// Number of ticks, 1200 per day
+        ticks: u32,
+    },
+    /// Validate the prefab configuration and models
+    CheckPrefabs,
+    /// Time the offline export pipeline over the test data
+    Bench {
+        /// Number of runs
+        #[arg(short, long, default_value_t = 5)]
+        iterations: usize,
+    },
     /// Debug the tile under the cursor
     Probe {
         /// Destination folder
         destination: PathBuf,
     },
+    /// Export a single building with its content on a small plinth
+    ViewBuilding {
+        /// Id of the building in Dwarf Fortress
+        building_id: i32,
+        /// Destination folder
+        destination: PathBuf,
+    },
     /// Dump the material, plant, raw lists...
     DumpLists {
         /// Destination folder
         destination: PathBuf,
+        /// Folder of an older dump to compare the new one with, field by field
+        #[arg(long)]
+        compare: Option<PathBuf>,
     },
     /// Set the view elevation
     SetElevation {
@@ -116,23 +285,180 @@ impl Cli {
     pub fn command(self) -> Command {
         self.command
     }
+
+    /// Parse the command line, noting which export options were given explicitly
+    fn parse_explicit() -> Self {
+        let matches = <Self as CommandFactory>::command().get_matches();
+        let mut cli = Self::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+        if let Some((_, matches)) = matches.subcommand() {
+            #[cfg(feature = "gui")]
+            let options = cli.command.as_mut().and_then(Command::options_mut);
+            #[cfg(not(feature = "gui"))]
+            let options = cli.command.options_mut();
+            if let Some(options) = options {
+                options.explicit = explicit_args(matches);
+            }
+        }
+        cli
+    }
+}
+
+impl Command {
+    fn options_mut(&mut self) -> Option<&mut ExportOptions> {
+        match self {
+            Command::Export { options, .. }
+            | Command::ExportYear { options, .. }
+            | Command::BatchSaves { options, .. }
+            | Command::Serve { options, .. }
+            | Command::Siege { options, .. }
+            | Command::Catacombs { options, .. } => Some(options),
+            _ => None,
+        }
+    }
+}
+
+/// Arguments given on the command line, rather than left to their default
+fn explicit_args(matches: &ArgMatches) -> Vec<String> {
+    matches
+        .ids()
+        .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+        .map(|id| id.to_string())
+        .collect()
+}
+
+/// Parse an elevation range such as `40..45`
+fn parse_range(range: &str) -> Result<(i32, i32), String> {
+    let (low, high) = range
+        .split_once("..")
+        .ok_or_else(|| format!("expected LOW..HIGH, got {range}"))?;
+    let low: i32 = low
+        .parse()
+        .map_err(|_| format!("invalid lower point {low}"))?;
+    let high: i32 = high
+        .parse()
+        .map_err(|_| format!("invalid higher point {high}"))?;
+    Ok((low.min(high), low.max(high)))
+}
+
+/// Parse an offset in tiles such as `192,0,-10`
+fn parse_offset(offset: &str) -> Result<(i32, i32, i32), String> {
+    let values: Vec<i32> = offset
+        .split(',')
+        .map(|value| {
+            value
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid offset {value}"))
+        })
+        .collect::<Result<_, _>>()?;
+    match values[..] {
+        [x, y, z] => Ok((x, y, z)),
+        _ => Err(format!("expected X,Y,Z, got {offset}")),
+    }
 }
 
 fn main() -> anyhow::Result<()> {
-    match Cli::parse().command() {
+    match Cli::parse_explicit().command() {
         #[cfg(feature = "gui")]
         Command::Gui => ui::gui::run(),
         Command::Export {
             low,
             high,
+            ranges,
+            separate_ranges,
+            open,
+            viewer,
             destination,
             month,
-        } => ui::cli::export(low.map(Elevation), high.map(Elevation), destination, month),
+            options,
+        } => {
+            let saved = ui::cli::export(
+                low.map(Elevation),
+                high.map(Elevation),
+                ranges
+                    .into_iter()
+                    .map(|(low, high)| (Elevation(low), Elevation(high)))
+                    .collect(),
+                separate_ranges,
+                destination,
+                month,
+                options,
+            )?;
+            if open {
+                for path in saved {
+                    ui::open_model(&path, viewer.as_deref())?;
+                }
+            }
+            Ok(())
+        }
         Command::ExportYear {
             low,
             high,
             destination,
-        } => ui::cli::export_year(low.map(Elevation), high.map(Elevation), destination),
+            options,
+        } => ui::cli::export_year(
+            low.map(Elevation),
+            high.map(Elevation),
+            destination,
+            options,
+        ),
+        Command::BatchSaves {
+            saves,
+            destination,
+            unload,
+            options,
+        } => ui::cli::batch_saves(saves, destination, unload, options),
+        Command::Serve {
+            low,
+            high,
+            trigger,
+            destination,
+            options,
+        } => ui::cli::serve(
+            low.map(Elevation),
+            high.map(Elevation),
+            trigger,
+            destination,
+            options,
+        ),
+        Command::Siege {
+            destination,
+            open,
+            options,
+        } => {
+            for path in ui::cli::siege(destination, options)? {
+                if open {
+                    ui::open_model(&path, None)?;
+                }
+            }
+            Ok(())
+        }
+        Command::Catacombs {
+            open,
+            destination,
+            options,
+        } => {
+            for path in ui::cli::catacombs(destination, options)? {
+                if open {
+                    ui::open_model(&path, None)?;
+                }
+            }
+            Ok(())
+        }
+        Command::Job { job, open } => {
+            for path in ui::job::Job::load(&job)?.run()? {
+                if open {
+                    ui::open_model(&path, None)?;
+                }
+            }
+            Ok(())
+        }
+        Command::Merge {
+            manifests,
+            names,
+            offsets,
+            destination,
+        } => ui::cli::merge(&manifests, &names, &offsets, &destination),
         #[cfg(feature = "self-update")]
         Command::CheckUpdate => ui::cli::check_update(),
         #[cfg(feature = "dev")]
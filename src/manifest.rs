@@ -0,0 +1,156 @@
+use crate::{BASE, HEIGHT, VERSION};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+use strum::{Display, EnumIter};
+
+/// Vertical axis expected by the tool importing the model
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Display, EnumIter, Serialize, Deserialize, ValueEnum,
+)]
+pub enum UpAxis {
+    /// MagicaVoxel, Blender
+    #[default]
+    Z,
+    /// Unreal, Unity, Godot...
+    Y,
+}
+
+impl UpAxis {
+    /// Rotation of the scene root, in the packed format of the MagicaVoxel `_r` attribute
+    ///
+    /// The rows of the rotation matrix are stored as the index of their non-zero entry
+    /// (bits 0-1 for the first row, 2-3 for the second) and their signs (bits 4, 5, 6).
+    pub fn rotation(&self) -> Option<u8> {
+        match self {
+            UpAxis::Z => None,
+            // (x, y, z) -> (x, z, -y)
+            UpAxis::Y => Some((2 << 2) | (1 << 6)),
+        }
+    }
+}
+
+/// Description of an export, written next to the model for the importers
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    /// Version of Vox Uristi that made the export
+    pub generator: String,
+    pub title: String,
+    /// Exported elevation ranges, both ends included
+    pub elevations: Vec<[i32; 2]>,
+    pub year_tick: i32,
+    pub up_axis: UpAxis,
+    /// Intended real-world size of a voxel, in meters
+    pub unit_scale: Option<f32>,
+    /// Voxels per tile on the x, y and z axis
+    pub tile_size: [usize; 3],
+    /// Model files written by the export
+    pub files: Vec<PathBuf>,
+    /// Finer figures of the units, written next to the models
+    pub close_ups: Option<CloseUps>,
+}
+
+/// File of the close-ups of the units, scaled down by `detail` to line up with the models
+#[derive(Serialize, Deserialize)]
+pub struct CloseUps {
+    pub file: PathBuf,
+    /// Voxels of the close-ups per voxel of the models, on each axis
+    pub detail: u8,
+}
+
+impl Manifest {
+    pub fn new(title: String, year_tick: i32, up_axis: UpAxis, unit_scale: Option<f32>) -> Self {
+        Self {
+            generator: format!("Vox Uristi {VERSION}"),
+            title,
+            elevations: Vec::new(),
+            year_tick,
+            up_axis,
+            unit_scale,
+            tile_size: [BASE, BASE, HEIGHT],
+            files: Vec::new(),
+            close_ups: None,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Opening the manifest {}", path.display()))?;
+        serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("Reading the manifest {}", path.display()))
+    }
+
+    /// Write the manifest as .json, named after the model path
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let manifest_path = path.with_file_name(format!("{stem}_manifest.json"));
+        serde_json::to_writer_pretty(File::create(manifest_path)?, self)?;
+        Ok(())
+    }
+}
+
+/// Frames of a sequence of exports, such as `export-year`, for the video assembly scripts
+#[derive(Serialize)]
+pub struct Timeline {
+    /// Version of Vox Uristi that made the exports
+    pub generator: String,
+    pub frames: Vec<TimelineFrame>,
+}
+
+#[derive(Serialize)]
+pub struct TimelineFrame {
+    /// In-game date, such as "Granite 251"
+    pub date: String,
+    pub year: i32,
+    pub year_tick: i32,
+    /// Model file, relative to the timeline
+    pub file: PathBuf,
+    pub camera: CameraSuggestion,
+}
+
+/// Camera looking at the whole model from above its south-east corner,
+/// in voxels in the MagicaVoxel axes (z up, y north), the model centered on x and y
+#[derive(Serialize, Clone, Copy)]
+pub struct CameraSuggestion {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+}
+
+impl CameraSuggestion {
+    /// Camera framing a model of the given size, in voxels
+    pub fn framing(width: f32, depth: f32, height: f32) -> Self {
+        let target = [0.0, 0.0, height / 2.0];
+        let distance = width.max(depth).max(height) * 1.2;
+        Self {
+            position: [
+                distance * 0.5,
+                -distance * 0.5,
+                target[2] + distance * std::f32::consts::FRAC_1_SQRT_2,
+            ],
+            target,
+        }
+    }
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            generator: format!("Vox Uristi {VERSION}"),
+            frames: Vec::new(),
+        }
+    }
+}
+
+impl Timeline {
+    /// Write the timeline as timeline.json in the folder of the frames
+    pub fn write(&self, folder: &Path) -> Result<()> {
+        let path = folder.join("timeline.json");
+        serde_json::to_writer_pretty(File::create(path)?, self)?;
+        Ok(())
+    }
+}
@@ -1,178 +1,202 @@
-use crate::{
-    context::DFContext,
-    coords::{WithBlockCoords, WithBoundingBox},
-    direction::{DirectionFlat, Neighbouring, Neighbouring8Flat, NeighbouringFlat},
-    rfr::{self, BlockTile, BuildingExt, BuildingFlags},
-    tile::BlockTileExt,
-    DFMapCoords, IsSomeAnd,
-};
-use dfhack_remote::{BuildingInstance, MapBlock};
-use itertools::Itertools;
-use std::collections::HashMap;
-
-#[derive(Default)]
-pub struct LevelData<'a> {
-    pub blocks: Vec<&'a MapBlock>,
-    pub buildings: Vec<&'a BuildingInstance>,
-}
-
-/// Intermediary format between DF and voxels
-#[derive(Default)]
-pub struct Map<'a> {
-    /// The map stored by layers
-    pub levels: HashMap<i32, LevelData<'a>>,
-    /// Quick access to the occupancy data of each tile, for connectivity checks
-    pub occupancy: HashMap<DFMapCoords, Occupancy<'a>>,
-    /// True if the building where added already, they are streamed multiple times
-    buildings_added: bool,
-}
-
-#[derive(Default)]
-pub struct Occupancy<'a> {
-    pub block_tile: Option<BlockTile<'a>>,
-    pub buildings: Vec<&'a BuildingInstance>,
-}
-
-impl<'a> Map<'a> {
-    pub fn add_block(&mut self, block: &'a MapBlock, context: &'a DFContext) {
-        if !self.buildings_added {
-            self.add_buildings(&block.buildings);
-        }
-        let level = block.block_coords().z;
-        self.levels.entry(level).or_default().blocks.push(block);
-
-        for tile in rfr::TileIterator::new(block, &context.tile_types) {
-            let coords = tile.global_coords();
-            self.occupancy.entry(coords).or_default().block_tile = Some(tile);
-        }
-    }
-
-    fn add_buildings(&mut self, buildings: &'a Vec<BuildingInstance>) {
-        for building in buildings {
-            if building.room.is_some() {
-                continue;
-            }
-
-            if !building
-                .building_flags_typed()
-                .contains(BuildingFlags::EXISTS)
-            {
-                continue;
-            }
-
-            self.levels
-                .entry(building.bounding_box().origin().z)
-                .or_default()
-                .buildings
-                .push(building);
-
-            let bounding_box = building.bounding_box();
-            for x in bounding_box.x.clone() {
-                for y in bounding_box.y.clone() {
-                    for z in bounding_box.z.clone() {
-                        self.occupancy
-                            .entry(DFMapCoords::new(x, y, z))
-                            .or_default()
-                            .buildings
-                            .push(building);
-                    }
-                }
-            }
-        }
-        self.buildings_added = true;
-    }
-
-    /// Compute a given function for all the neighbours including above and below
-    pub fn neighbouring<F, T>(&self, coords: DFMapCoords, func: F) -> Neighbouring<T>
-    where
-        F: Fn(&Occupancy<'a>) -> T,
-    {
-        let default = Occupancy::default();
-        Neighbouring::new(|direction| {
-            let neighbour = coords + direction;
-            func(self.occupancy.get(&neighbour).unwrap_or(&default))
-        })
-    }
-
-    /// Compute a given function for all the neighbours on the same plane
-    pub fn neighbouring_flat<F, T>(&self, coords: DFMapCoords, func: F) -> NeighbouringFlat<T>
-    where
-        F: Fn(&Occupancy<'a>) -> T,
-    {
-        let default = Occupancy::default();
-        NeighbouringFlat::new(|direction| {
-            let neighbour = coords + direction;
-            func(self.occupancy.get(&neighbour).unwrap_or(&default))
-        })
-    }
-
-    /// Compute a given function for all the neighbours on the same plane
-    pub fn neighbouring_8flat<F, T>(&self, coords: DFMapCoords, func: F) -> Neighbouring8Flat<T>
-    where
-        F: Fn(&Occupancy<'a>) -> T,
-    {
-        let default = Occupancy::default();
-        Neighbouring8Flat::new(|direction| {
-            let neighbour = coords + direction;
-            func(self.occupancy.get(&neighbour).unwrap_or(&default))
-        })
-    }
-
-    /// Find the most "wally" direction, ie the direction to put furniture against
-    pub fn wall_direction(&self, coords: DFMapCoords) -> DirectionFlat {
-        let z = coords.z;
-        // there's likely a nice way to write that
-        // N, E, S, W
-        const N: usize = 0;
-        const E: usize = 1;
-        const S: usize = 2;
-        const W: usize = 3;
-        let mut wallyness = [0, 0, 0, 0];
-        for x in -1..=1 {
-            for y in -1..=1 {
-                // increase the "wallyness" of a direction by 1 for corners and by 4 for direct contact
-                let wally = self
-                    .occupancy
-                    .get(&DFMapCoords::new(coords.x + x, coords.y + y, z))
-                    .some_and(|tile| tile.block_tile.some_and(|tile| tile.is_wall()));
-                if wally {
-                    if x == -1 {
-                        wallyness[W] += 1;
-                        if y == 0 {
-                            wallyness[W] += 3;
-                        }
-                    }
-
-                    if x == 1 {
-                        wallyness[E] += 1;
-                        if y == 0 {
-                            wallyness[E] += 3;
-                        }
-                    }
-
-                    if y == -1 {
-                        wallyness[N] += 1;
-                        if x == 0 {
-                            wallyness[N] += 3;
-                        }
-                    }
-
-                    if y == 1 {
-                        wallyness[S] += 1;
-                        if x == 0 {
-                            wallyness[S] += 3;
-                        }
-                    }
-                }
-            }
-        }
-
-        match wallyness.iter().position_max().unwrap() {
-            N => DirectionFlat::North,
-            E => DirectionFlat::East,
-            S => DirectionFlat::South,
-            W => DirectionFlat::West,
-            _ => unreachable!(),
-        }
-    }
-}
+use crate::{
+    building::BuildingInstanceExt,
+    context::DFContext,
+    coords::{WithBlockCoords, WithBoundingBox},
+    direction::{DirectionFlat, Neighbouring, Neighbouring8Flat, NeighbouringFlat},
+    rfr::{self, BlockTile, BuildingExt, BuildingFlags},
+    tile::BlockTileExt,
+    DFMapCoords, IsSomeAnd, WithDFCoords,
+};
+use dfhack_remote::{BuildingInstance, FlowInfo, MapBlock};
+use itertools::Itertools;
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub struct LevelData<'a> {
+    pub blocks: Vec<&'a MapBlock>,
+    pub buildings: Vec<&'a BuildingInstance>,
+}
+
+/// Intermediary format between DF and voxels
+#[derive(Default)]
+pub struct Map<'a> {
+    /// The map stored by layers
+    pub levels: HashMap<i32, LevelData<'a>>,
+    /// Quick access to the occupancy data of each tile, for connectivity checks
+    pub occupancy: HashMap<DFMapCoords, Occupancy<'a>>,
+    /// True if the building where added already, they are streamed multiple times
+    buildings_added: bool,
+}
+
+#[derive(Default)]
+pub struct Occupancy<'a> {
+    pub block_tile: Option<BlockTile<'a>>,
+    pub buildings: Vec<&'a BuildingInstance>,
+    pub flows: Vec<&'a FlowInfo>,
+    /// Standing part of a raised bridge, acting as a wall
+    pub bridge_wall: bool,
+}
+
+impl Occupancy<'_> {
+    /// Wall tile, or anything standing like one
+    pub fn is_wall(&self) -> bool {
+        self.bridge_wall || self.block_tile.some_and(|t| t.is_wall())
+    }
+}
+
+impl<'a> Map<'a> {
+    pub fn add_block(&mut self, block: &'a MapBlock, context: &'a DFContext) {
+        let terrain_only = context.settings.options.terrain_only;
+        if !self.buildings_added && !terrain_only {
+            self.add_buildings(&block.buildings, context);
+        }
+        let level = block.block_coords().z;
+        self.levels.entry(level).or_default().blocks.push(block);
+
+        for tile in rfr::TileIterator::new(block, &context.tile_types) {
+            let coords = tile.global_coords();
+            self.occupancy.entry(coords).or_default().block_tile = Some(tile);
+        }
+
+        for flow in block.flows.iter().filter(|_| !terrain_only) {
+            self.occupancy
+                .entry(flow.coords())
+                .or_default()
+                .flows
+                .push(flow);
+        }
+    }
+
+    fn add_buildings(&mut self, buildings: &'a Vec<BuildingInstance>, context: &DFContext) {
+        for building in buildings {
+            if building.room.is_some() {
+                continue;
+            }
+
+            if !building
+                .building_flags_typed()
+                .contains(BuildingFlags::EXISTS)
+            {
+                continue;
+            }
+
+            self.levels
+                .entry(building.bounding_box().origin().z)
+                .or_default()
+                .buildings
+                .push(building);
+
+            let bounding_box = building.bounding_box();
+            for x in bounding_box.x.clone() {
+                for y in bounding_box.y.clone() {
+                    for z in bounding_box.z.clone() {
+                        self.occupancy
+                            .entry(DFMapCoords::new(x, y, z))
+                            .or_default()
+                            .buildings
+                            .push(building);
+                    }
+                }
+            }
+
+            for coords in building.raised_wall_tiles(context) {
+                self.occupancy.entry(coords).or_default().bridge_wall = true;
+            }
+        }
+        self.buildings_added = true;
+    }
+
+    /// Compute a given function for all the neighbours including above and below
+    pub fn neighbouring<F, T>(&self, coords: DFMapCoords, func: F) -> Neighbouring<T>
+    where
+        F: Fn(&Occupancy<'a>) -> T,
+    {
+        let default = Occupancy::default();
+        Neighbouring::new(|direction| {
+            let neighbour = coords + direction;
+            func(self.occupancy.get(&neighbour).unwrap_or(&default))
+        })
+    }
+
+    /// Compute a given function for all the neighbours on the same plane
+    pub fn neighbouring_flat<F, T>(&self, coords: DFMapCoords, func: F) -> NeighbouringFlat<T>
+    where
+        F: Fn(&Occupancy<'a>) -> T,
+    {
+        let default = Occupancy::default();
+        NeighbouringFlat::new(|direction| {
+            let neighbour = coords + direction;
+            func(self.occupancy.get(&neighbour).unwrap_or(&default))
+        })
+    }
+
+    /// Compute a given function for all the neighbours on the same plane
+    pub fn neighbouring_8flat<F, T>(&self, coords: DFMapCoords, func: F) -> Neighbouring8Flat<T>
+    where
+        F: Fn(&Occupancy<'a>) -> T,
+    {
+        let default = Occupancy::default();
+        Neighbouring8Flat::new(|direction| {
+            let neighbour = coords + direction;
+            func(self.occupancy.get(&neighbour).unwrap_or(&default))
+        })
+    }
+
+    /// Find the most "wally" direction, ie the direction to put furniture against
+    pub fn wall_direction(&self, coords: DFMapCoords) -> DirectionFlat {
+        let z = coords.z;
+        // there's likely a nice way to write that
+        // N, E, S, W
+        const N: usize = 0;
+        const E: usize = 1;
+        const S: usize = 2;
+        const W: usize = 3;
+        let mut wallyness = [0, 0, 0, 0];
+        for x in -1..=1 {
+            for y in -1..=1 {
+                // increase the "wallyness" of a direction by 1 for corners and by 4 for direct contact
+                let wally = self
+                    .occupancy
+                    .get(&DFMapCoords::new(coords.x + x, coords.y + y, z))
+                    .some_and(|tile| tile.block_tile.some_and(|tile| tile.is_wall()));
+                if wally {
+                    if x == -1 {
+                        wallyness[W] += 1;
+                        if y == 0 {
+                            wallyness[W] += 3;
+                        }
+                    }
+
+                    if x == 1 {
+                        wallyness[E] += 1;
+                        if y == 0 {
+                            wallyness[E] += 3;
+                        }
+                    }
+
+                    if y == -1 {
+                        wallyness[N] += 1;
+                        if x == 0 {
+                            wallyness[N] += 3;
+                        }
+                    }
+
+                    if y == 1 {
+                        wallyness[S] += 1;
+                        if x == 0 {
+                            wallyness[S] += 3;
+                        }
+                    }
+                }
+            }
+        }
+
+        match wallyness.iter().position_max().unwrap() {
+            N => DirectionFlat::North,
+            E => DirectionFlat::East,
+            S => DirectionFlat::South,
+            W => DirectionFlat::West,
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use crate::palette::{DefaultMaterials, EffectiveMaterial};
+use anyhow::{Context, Result};
+use glob_match::glob_match;
+use serde::Deserialize;
+use std::{collections::BTreeMap, path::Path};
+
+/// MagicaVoxel material type of a custom entry
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MaterialKind {
+    #[default]
+    Diffuse,
+    Metal,
+    Glass,
+    Emit,
+}
+
+impl MaterialKind {
+    fn vox_type(&self) -> &'static str {
+        match self {
+            MaterialKind::Diffuse => "_diffuse",
+            MaterialKind::Metal => "_metal",
+            MaterialKind::Glass => "_glass",
+            MaterialKind::Emit => "_emit",
+        }
+    }
+}
+
+/// Entry of the material table, with the same properties as the default materials.
+/// The properties are in percent, except for the flux.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomMaterial {
+    pub color: [u8; 4],
+    #[serde(default)]
+    pub kind: MaterialKind,
+    pub metalness: Option<u8>,
+    pub roughness: Option<u8>,
+    pub transparency: Option<u8>,
+    pub emit: Option<u8>,
+    pub flux: Option<u8>,
+    pub ior: Option<u8>,
+    /// Glob patterns of the identifiers of the Dwarf Fortress materials drawn with this entry
+    #[serde(default)]
+    pub applies_to: Vec<String>,
+}
+
+impl CustomMaterial {
+    pub fn effective(&self) -> EffectiveMaterial {
+        let [r, g, b, a] = self.color;
+        EffectiveMaterial {
+            r,
+            g,
+            b,
+            a,
+            mat_type: Some(self.kind.vox_type()),
+            metalness: self.metalness,
+            roughness: self.roughness,
+            transparency: self.transparency,
+            emit: self.emit,
+            flux: self.flux,
+            ior: self.ior,
+        }
+    }
+}
+
+/// Materials configured by the user, by name. An entry named after a default material
+/// (`Water`, `Magma`...) replaces it.
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct MaterialTable(BTreeMap<String, CustomMaterial>);
+
+impl MaterialTable {
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Reading the material table {}", path.display()))?;
+        serde_yaml::from_slice(&bytes)
+            .with_context(|| format!("Parsing the material table {}", path.display()))
+    }
+
+    /// Entry replacing a default material
+    pub fn default_override(&self, default: DefaultMaterials) -> Option<&CustomMaterial> {
+        self.0.get(&format!("{default:?}"))
+    }
+
+    /// First entry, by name, with a pattern matching the identifier of a Dwarf Fortress material
+    pub fn matching(&self, id: &str) -> Option<&CustomMaterial> {
+        self.0.values().find(|material| {
+            material
+                .applies_to
+                .iter()
+                .any(|pattern| glob_match(pattern, id))
+        })
+    }
+}
@@ -0,0 +1,73 @@
+use crate::{
+    context::DFContext,
+    overlay::Overlay,
+    palette::{DefaultMaterials, Material, Palette},
+    rfr::DFHackExt,
+    DFMapCoords, VoxelCoords,
+};
+use anyhow::Result;
+use std::ops::Range;
+
+/// Link between a trigger (lever, pressure plate) and the building it operates
+#[derive(Debug, Clone)]
+pub struct MechanismLink {
+    pub source: DFMapCoords,
+    pub target: DFMapCoords,
+}
+
+/// Read the mechanism links of the levers and pressure plates
+pub fn read_links(client: &mut dfhack_remote::Client) -> Result<Vec<MechanismLink>> {
+    let output = client.lua_output(
+        r#"for _, trap in ipairs(df.global.world.buildings.other.TRAP) do
+  if trap.trap_type == df.trap_type.Lever or trap.trap_type == df.trap_type.PressurePlate then
+    for _, mechanism in ipairs(trap.linked_mechanisms) do
+      local ref = dfhack.items.getGeneralRef(mechanism, df.general_ref_type.BUILDING_TRIGGERTARGET)
+      local target = ref and ref:getBuilding()
+      if target then
+        emit(trap.centerx, trap.centery, trap.z, target.centerx, target.centery, target.z)
+      end
+    end
+  end
+end"#,
+    )?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let values: Vec<i32> = line
+                .split_whitespace()
+                .filter_map(|v| v.parse().ok())
+                .collect();
+            match values[..] {
+                [sx, sy, sz, tx, ty, tz] => Some(MechanismLink {
+                    source: DFMapCoords::new(sx, sy, sz),
+                    target: DFMapCoords::new(tx, ty, tz),
+                }),
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+/// Draw the links as wires running just above the floor, first along x, then y, then z
+pub fn build_links(
+    links: &[MechanismLink],
+    z_range: &Range<i32>,
+    context: &DFContext,
+    palette: &Palette,
+) -> Overlay {
+    let color = palette.get(&Material::Default(DefaultMaterials::Wire), context);
+    let mut overlay = Overlay::default();
+    for link in links {
+        if !z_range.contains(&link.source.z) || !z_range.contains(&link.target.z) {
+            continue;
+        }
+        let from = VoxelCoords::from_df(link.source, 1, 1, 1);
+        let to = VoxelCoords::from_df(link.target, 1, 1, 1);
+        let corner_x = VoxelCoords::new(to.x, from.y, from.z);
+        let corner_y = VoxelCoords::new(to.x, to.y, from.z);
+        overlay.line(from, corner_x, color);
+        overlay.line(corner_x, corner_y, color);
+        overlay.line(corner_y, to, color);
+    }
+    overlay
+}
@@ -0,0 +1,147 @@
+use crate::{
+    coords::DotVoxModelCoords,
+    dot_vox_builder::{DotVoxBuilder, LayerId, MaterialExt},
+    export,
+    manifest::Manifest,
+};
+use anyhow::{anyhow, bail, Result};
+use dot_vox::{Color, DotVoxData, Material};
+use itertools::Itertools;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+/// Palette shared by the merged scenes
+#[derive(Default)]
+struct MergedPalette {
+    /// Entries of the merged palette, with their deduplication key
+    entries: Vec<(String, Color, Material)>,
+    /// True if some entries had to be replaced by the closest color
+    overflow: bool,
+}
+
+impl MergedPalette {
+    /// Index in the merged palette of each palette index used by a scene
+    fn translate(&mut self, scene: &DotVoxData) -> Vec<u8> {
+        let used: HashSet<u8> = scene
+            .models
+            .iter()
+            .flat_map(|model| model.voxels.iter().map(|voxel| voxel.i))
+            .collect();
+        (0..=u8::MAX)
+            .map(|index| {
+                if used.contains(&index) {
+                    self.entry(scene, index)
+                } else {
+                    0
+                }
+            })
+            .collect()
+    }
+
+    fn entry(&mut self, scene: &DotVoxData, index: u8) -> u8 {
+        let id = index as u32 + 1;
+        let color = scene.palette.get(index as usize).copied().unwrap_or(Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        });
+        let material = scene
+            .materials
+            .iter()
+            .find(|material| material.id == id)
+            .cloned()
+            .unwrap_or_else(|| Material::diffuse(id));
+        // The material key alone is not stable across worlds, nor does it reflect the
+        // options changing the colors, so the same key only shares an entry when the
+        // color and the other properties match as well
+        let properties = material.properties.iter().sorted().collect_vec();
+        let key = format!("{color:?} {properties:?}");
+        if let Some(position) = self.entries.iter().position(|(entry, ..)| *entry == key) {
+            return position as u8;
+        }
+        if self.entries.len() < u8::MAX as usize {
+            self.entries.push((key, color, material));
+            return (self.entries.len() - 1) as u8;
+        }
+        self.overflow = true;
+        let distance = |other: &Color| {
+            (color.r as i32 - other.r as i32).pow(2)
+                + (color.g as i32 - other.g as i32).pow(2)
+                + (color.b as i32 - other.b as i32).pow(2)
+        };
+        self.entries
+            .iter()
+            .position_min_by_key(|(_, other, _)| distance(other))
+            .unwrap_or_default() as u8
+    }
+
+    fn write(&self, vox: &mut DotVoxData) {
+        for (index, (_, color, material)) in self.entries.iter().enumerate() {
+            vox.palette[index] = *color;
+            vox.materials[index + 1] = material.clone().with_id(index as u32 + 1);
+        }
+    }
+}
+
+/// Merge the models of several exports in a single scene, each in its own group
+/// named after the fortress and moved by an offset in tiles.
+/// Returns true if some colors were replaced for lack of palette entries
+pub fn merge(
+    manifests: &[PathBuf],
+    names: &[String],
+    offsets: &[(i32, i32, i32)],
+    destination: &Path,
+) -> Result<bool> {
+    let mut vox = DotVoxBuilder::default();
+    let mut palette = MergedPalette::default();
+    let mut up_axis = None;
+    let mut layers = None;
+    let mut titles = Vec::new();
+    for (index, manifest_path) in manifests.iter().enumerate() {
+        let manifest = Manifest::load(manifest_path)?;
+        match up_axis {
+            Some(up_axis) if up_axis != manifest.up_axis => bail!(
+                "{} was exported with a different up axis",
+                manifest_path.display()
+            ),
+            _ => up_axis = Some(manifest.up_axis),
+        }
+        let name = names
+            .get(index)
+            .cloned()
+            .unwrap_or_else(|| manifest.title.clone());
+        let (x, y, z) = offsets.get(index).copied().unwrap_or_default();
+        let [width, depth, height] = manifest.tile_size.map(|size| size as i32);
+        // The y axis of the models is going north
+        let coordinates = DotVoxModelCoords::new(x * width, -y * depth, z * height);
+        let group = vox.insert_group_node_simple(
+            vox.root_group,
+            name.clone(),
+            Some(coordinates),
+            LayerId(0),
+        );
+        let folder = manifest_path.parent().unwrap_or(Path::new("."));
+        for file in &manifest.files {
+            let path = folder.join(file);
+            let scene = dot_vox::load(&path.to_string_lossy())
+                .map_err(|err| anyhow!("Failed to load {}: {err}", path.display()))?;
+            let colors = palette.translate(&scene);
+            vox.insert_scene(group, &scene, &colors);
+            layers.get_or_insert(scene.layers);
+        }
+        titles.push(name);
+    }
+    vox.set_root_name(titles.join(", "));
+    if let Some(rotation) = up_axis.and_then(|up_axis| up_axis.rotation()) {
+        vox.set_root_rotation(rotation);
+    }
+    if let Some(layers) = layers {
+        vox.data.layers = layers;
+    }
+    palette.write(&mut vox.data);
+    export::save(&vox.data, destination)?;
+    Ok(palette.overflow)
+}
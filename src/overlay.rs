@@ -0,0 +1,116 @@
+use crate::{
+    context::DFContext,
+    coords::DotVoxModelCoords,
+    dot_vox_builder::{DotVoxBuilder, LayerId, NodeId},
+    VoxelCoords,
+};
+use dot_vox::{Model, Size, Voxel};
+use itertools::Itertools;
+use std::collections::HashMap;
+
+/// Maximum size of a .vox model on each axis
+const MAX_MODEL_SIZE: i32 = 256;
+
+/// Voxels placed freely on the map, independently from the blocks and levels
+///
+/// Used for the elements spanning over multiple levels, such as the mechanism links.
+#[derive(Default)]
+pub struct Overlay {
+    pub voxels: HashMap<VoxelCoords, u8>,
+}
+
+impl Overlay {
+    pub fn add(&mut self, coords: VoxelCoords, color: u8) {
+        self.voxels.insert(coords, color);
+    }
+
+    /// Add a straight line of voxels, both ends included
+    pub fn line(&mut self, from: VoxelCoords, to: VoxelCoords, color: u8) {
+        let steps = (to.x - from.x)
+            .abs()
+            .max((to.y - from.y).abs())
+            .max((to.z - from.z).abs());
+        if steps == 0 {
+            self.add(from, color);
+            return;
+        }
+        let lerp = |a: i32, b: i32, step: i32| {
+            a + ((b - a) as f32 * step as f32 / steps as f32).round() as i32
+        };
+        for step in 0..=steps {
+            self.add(
+                VoxelCoords::new(
+                    lerp(from.x, to.x, step),
+                    lerp(from.y, to.y, step),
+                    lerp(from.z, to.z, step),
+                ),
+                color,
+            );
+        }
+    }
+
+    /// Keep only the voxels matching the predicate
+    pub fn retain(&mut self, mut keep: impl FnMut(&VoxelCoords) -> bool) {
+        self.voxels.retain(|coords, _| keep(coords));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voxels.is_empty()
+    }
+
+    /// Insert the overlay in the scene, split in as many models as needed
+    pub fn insert(
+        self,
+        vox: &mut DotVoxBuilder,
+        parent_group: NodeId,
+        layer_id: LayerId,
+        name: &str,
+        context: &DFContext,
+        min_z: i32,
+    ) {
+        let chunks = self.voxels.into_iter().into_group_map_by(|(coords, _)| {
+            (
+                coords.x.div_euclid(MAX_MODEL_SIZE),
+                coords.y.div_euclid(MAX_MODEL_SIZE),
+                coords.z.div_euclid(MAX_MODEL_SIZE),
+            )
+        });
+        for (_, voxels) in chunks.into_iter().sorted_by_key(|(chunk, _)| *chunk) {
+            let (x0, x1) = minmax(voxels.iter().map(|(c, _)| c.x));
+            let (y0, y1) = minmax(voxels.iter().map(|(c, _)| c.y));
+            let (z0, z1) = minmax(voxels.iter().map(|(c, _)| c.z));
+            let size = Size {
+                x: (x1 - x0 + 1) as u32,
+                y: (y1 - y0 + 1) as u32,
+                z: (z1 - z0 + 1) as u32,
+            };
+            // The dwarf fortress y axis is going south
+            let model = Model {
+                size,
+                voxels: voxels
+                    .iter()
+                    .map(|(coords, color)| Voxel {
+                        x: (coords.x - x0) as u8,
+                        y: (y1 - coords.y) as u8,
+                        z: (coords.z - z0) as u8,
+                        i: *color,
+                    })
+                    .collect(),
+            };
+            // Models are centered on their translation
+            let coords = DotVoxModelCoords::new(
+                x0 - context.max_vox_x() + size.x as i32 / 2,
+                context.max_vox_y() - y1 + size.y as i32 / 2,
+                z0 - min_z + size.z as i32 / 2,
+            );
+            vox.insert_model_and_shape_node(parent_group, Some(coords), model, layer_id, name);
+        }
+    }
+}
+
+fn minmax(values: impl Iterator<Item = i32>) -> (i32, i32) {
+    values
+        .minmax()
+        .into_option()
+        .expect("Overlay chunks are never empty")
+}
@@ -1,340 +1,950 @@
-use crate::context::DFContext;
-use crate::rfr::RGBColor;
-use crate::{dot_vox_builder::MaterialExt, rfr::BasicMaterialInfoExt};
-use dfhack_remote::TiletypeMaterial;
-use dfhack_remote::{core_text_fragment::Color, MatPair};
-use dot_vox::DotVoxData;
-use num_enum::IntoPrimitive;
-use palette::{named, rgb::Rgb, FromColor, Hsv};
-use palette::{Darken, Srgb};
-use std::collections::HashMap;
-use strum::{EnumCount, EnumIter, IntoEnumIterator};
-
-/// A material to be exported as an entry in the palette
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
-pub enum Material {
-    /// Default colors for which Dwarf Fortress does not give indication (water, magma, smoke...)
-    Default(DefaultMaterials),
-    /// Generic material built procedurally from Dwarf Fortress
-    Generic(MatPair),
-    /// Darker variant of a generic material
-    DarkGeneric(MatPair),
-    /// Generic material with tile information
-    TileGeneric(MatPair, TiletypeMaterial),
-    /// Generic material with a growth console color associated to it
-    Plant {
-        material: MatPair,
-        source_color: Color,
-        dest_color: Color,
-    },
-}
-
-/// The default hard-coded materials
-#[derive(Debug, Clone, Copy, IntoPrimitive, EnumIter, EnumCount, Hash, PartialEq, Eq)]
-#[repr(u8)]
-pub enum DefaultMaterials {
-    /// Common material for all hidden tiles
-    Hidden,
-    Water,
-    Mist,
-    Magma,
-    Fire,
-    Smoke,
-    Miasma,
-    DarkGrass,
-    LightGrass,
-    DeadGrass,
-    Wood,
-    Light,
-}
-
-pub trait RGBAColor {
-    fn get_rgba(&self) -> (u8, u8, u8, u8);
-}
-
-impl<T: RGBColor> RGBAColor for T {
-    fn get_rgba(&self) -> (u8, u8, u8, u8) {
-        let rgb = self.rgb();
-        (rgb.red, rgb.green, rgb.blue, 255)
-    }
-}
-
-impl RGBAColor for DefaultMaterials {
-    fn get_rgba(&self) -> (u8, u8, u8, u8) {
-        match self {
-            DefaultMaterials::Hidden => (0, 0, 0, 255),
-            DefaultMaterials::Water => (0, 0, 255, 64),
-            DefaultMaterials::Mist => (255, 255, 255, 64),
-            DefaultMaterials::Magma => (255, 0, 0, 64),
-            DefaultMaterials::Fire => (255, 174, 0, 64),
-            DefaultMaterials::Smoke => (100, 100, 100, 64),
-            DefaultMaterials::Miasma => (208, 89, 255, 64),
-            DefaultMaterials::DarkGrass => (0, 102, 0, 255),
-            DefaultMaterials::LightGrass => (0, 153, 51, 255),
-            DefaultMaterials::DeadGrass => (102, 102, 0, 255),
-            DefaultMaterials::Wood => (75, 21, 0, 255),
-            DefaultMaterials::Light => (255, 255, 255, 255),
-        }
-    }
-}
-
-#[derive(Default)]
-pub struct Palette {
-    /// Effective palette. Two different DF material are the same
-    /// effective material if they have the same characteristics in .vox
-    pub materials: HashMap<EffectiveMaterial, u8>,
-    /// Cache to avoid building the EffectiveMaterial for each voxel
-    pub material_cache: HashMap<Material, u8>,
-}
-
-impl Palette {
-    pub fn get(&mut self, material: &Material, context: &DFContext) -> u8 {
-        if let Some(from_cache) = self.material_cache.get(material) {
-            return *from_cache;
-        }
-
-        let palette_size = self.materials.len();
-        let effective_material = EffectiveMaterial::from_material(material, context);
-        let color = *self.materials.entry(effective_material).or_insert_with(|| {
-            // would be nice to warn in case of palette overflow
-            palette_size
-                .min(std::u8::MAX as usize - 1)
-                .try_into()
-                .unwrap_or_default()
-        });
-        self.material_cache.insert(material.clone(), color);
-        color
-    }
-
-    pub fn cache_default_materials(&mut self, context: &DFContext) {
-        for default_material in DefaultMaterials::iter() {
-            let material = Material::Default(default_material);
-            self.get(&material, context);
-        }
-    }
-
-    pub fn write_palette(&self, vox: &mut DotVoxData) {
-        for (material, index) in &self.materials {
-            material.apply_material(
-                &mut vox.palette[*index as usize],
-                &mut vox.materials[*index as usize + 1],
-            );
-        }
-    }
-}
-
-/// Intermediary hashable material format to group together
-/// material that are the same from different sources
-#[derive(Hash, PartialEq, Eq, Default, Clone)]
-pub struct EffectiveMaterial {
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
-    pub a: u8,
-    pub mat_type: Option<&'static str>,
-    pub metalness: Option<u8>,
-    pub roughness: Option<u8>,
-    pub transparency: Option<u8>,
-    pub emit: Option<u8>,
-    pub flux: Option<u8>,
-    pub ior: Option<u8>,
-}
-
-impl EffectiveMaterial {
-    pub fn from_material(material: &Material, context: &DFContext) -> Self {
-        match material {
-            Material::Default(default) => {
-                let mut res = EffectiveMaterial::default();
-                (res.r, res.g, res.b, res.a) = default.get_rgba();
-                match default {
-                    DefaultMaterials::Water => {
-                        res.mat_type = Some("_glass");
-                        res.transparency = Some(50);
-                    }
-                    DefaultMaterials::Magma => {
-                        res.mat_type = Some("_emit");
-                        res.emit = Some(50);
-                        res.flux = Some(2);
-                    }
-                    DefaultMaterials::Fire => {
-                        res.mat_type = Some("_emit");
-                        res.emit = Some(50);
-                        res.flux = Some(1);
-                    }
-                    DefaultMaterials::Mist => {
-                        res.mat_type = Some("_glass");
-                        res.ior = Some(0);
-                        res.transparency = Some(75);
-                    }
-                    DefaultMaterials::Smoke | DefaultMaterials::Miasma => {
-                        res.mat_type = Some("_glass");
-                        res.ior = Some(0);
-                        res.transparency = Some(25);
-                    }
-                    DefaultMaterials::Light => {
-                        res.mat_type = Some("_emit");
-                        res.emit = Some(50);
-                        res.flux = Some(1);
-                    }
-                    _ => {
-                        res.mat_type = Some("_diffuse");
-                    }
-                };
-                res
-            }
-            Material::Generic(matpair) => Self::from_matpair(matpair, context),
-            Material::DarkGeneric(matpair) => {
-                let mut res = Self::from_matpair(matpair, context);
-                let color = Hsv::from_color(Srgb::new(res.r, res.g, res.b).into_linear());
-                let color = color.darken(0.5);
-                let color: Rgb<palette::encoding::Srgb, u8> =
-                    Rgb::from_linear(Rgb::from_color(color));
-                (res.r, res.g, res.b, res.a) = (color.red, color.green, color.blue, 255);
-                res
-            }
-            Material::TileGeneric(matpair, tiletype_material) => {
-                let mut res = Self::from_matpair(matpair, context);
-                if tiletype_material == &TiletypeMaterial::FROZEN_LIQUID {
-                    res.mat_type = Some("_glass");
-                    res.ior = Some(50);
-                    res.transparency = Some(50);
-                }
-                res
-            }
-            Material::Plant {
-                material: mat,
-                source_color,
-                dest_color,
-            } => {
-                let mut res = EffectiveMaterial {
-                    mat_type: Some("_diffuse"),
-                    ..Default::default()
-                };
-                let main_color = context
-                    .materials
-                    .material_list
-                    .iter()
-                    .find(|m| mat == m.mat_pair.get_or_default())
-                    .map_or(named::BLACK, |material| material.state_color.rgb());
-                if source_color == dest_color {
-                    (res.r, res.g, res.b, res.a) =
-                        (main_color.red, main_color.green, main_color.blue, 255);
-                    return res;
-                }
-                let mut hsv = Hsv::from_color(main_color.into_linear::<f32>());
-                let source_color = Hsv::from_color(source_color.rgb().into_linear::<f32>());
-                let dest_color = Hsv::from_color(dest_color.rgb().into_linear::<f32>());
-                // I have no idea what's going on here, I just did my best to replicate what is done in Armok Vision
-                // https://github.com/RosaryMala/armok-vision/blob/3027c785a54d7a8d9a7a9f7f2a10a1815c3bb500/Assets/Scripts/MapGen/DfColor.cs#L37
-                // and the result looks fairly similar to in-game colors.
-                hsv.hue += dest_color.hue - source_color.hue;
-                if source_color.value > dest_color.value {
-                    hsv.value *= dest_color.value / source_color.value;
-                } else {
-                    hsv.value = 1.0
-                        - ((1.0 - hsv.value)
-                            * ((1.0 - dest_color.value) / (1.0 - source_color.value)));
-                }
-                let rgb = Rgb::from_color(hsv);
-                let rgba: Rgb<palette::encoding::Srgb, u8> = Rgb::from_linear(rgb);
-                (res.r, res.g, res.b, res.a) = (rgba.red, rgba.green, rgba.blue, 255);
-                res
-            }
-        }
-    }
-
-    pub fn from_matpair(matpair: &MatPair, context: &DFContext) -> Self {
-        let mut res = EffectiveMaterial::default();
-        (res.r, res.g, res.b, res.a) = context
-            .materials
-            .material_list
-            .iter()
-            .find(|m| matpair == m.mat_pair.get_or_default())
-            .map_or((0, 0, 0, 0), |material| match material.id() {
-                // Water coloring exception, it's "clear" so no color, make it light blue for ice
-                "WATER" => (200, 200, 230, 255),
-                _ => material.state_color.get_rgba(),
-            });
-        if let Some(info) = context
-            .inorganic_materials_map
-            .get(&(matpair.mat_type(), matpair.mat_index()))
-        {
-            for flag in info.flag_names(&context.enums) {
-                match flag {
-                    "IS_METAL" => {
-                        res.mat_type = Some("_metal");
-                        res.metalness = Some(60);
-                        res.roughness = Some(20);
-                    }
-                    "IS_GEM" => {
-                        res.mat_type = Some("_glass");
-                        res.roughness = Some(3);
-                        res.transparency = Some(30);
-                    }
-                    "IS_GLASS" => {
-                        res.mat_type = Some("_glass");
-                        res.roughness = Some(5);
-                        res.transparency = Some(60);
-                    }
-                    "IS_CERAMIC" => {
-                        res.mat_type = Some("_glass");
-                        res.transparency = Some(0);
-                    }
-                    _ => {}
-                }
-            }
-            if info.token() == "MARBLE" {
-                res.mat_type = Some("_metal");
-                res.roughness = Some(50);
-                res.metalness = Some(50);
-            }
-        }
-        res
-    }
-
-    fn apply_material(&self, color: &mut dot_vox::Color, material: &mut dot_vox::Material) {
-        let Self {
-            r,
-            g,
-            b,
-            a,
-            mat_type,
-            metalness,
-            roughness,
-            transparency,
-            emit,
-            flux,
-            ior,
-        } = self.to_owned();
-        color.r = r;
-        color.g = g;
-        color.b = b;
-        color.a = a;
-        if let Some(mat_type) = mat_type {
-            material.set_type(mat_type);
-        }
-        if let Some(emit) = emit {
-            material.set_emit((emit as f32) / 100.0);
-        }
-
-        if let Some(metalness) = metalness {
-            material.set_metalness((metalness as f32) / 100.0);
-        }
-
-        if let Some(roughness) = roughness {
-            material.set_roughness((roughness as f32) / 100.0);
-        }
-
-        if let Some(transparency) = transparency {
-            material.set_transparency((transparency as f32) / 100.0);
-        }
-
-        if let Some(flux) = flux {
-            material.set_flux(flux as f32);
-        }
-
-        if let Some(ior) = ior {
-            material.set_ior((ior as f32) / 100.0);
-        }
-    }
-}
+use crate::color::{ColorSpace, WorkingColor};
+use crate::context::DFContext;
+use crate::rfr::RGBColor;
+use crate::{dot_vox_builder::MaterialExt, rfr::BasicMaterialInfoExt};
+use dfhack_remote::TiletypeMaterial;
+use dfhack_remote::{core_text_fragment::Color, MatPair};
+use dot_vox::DotVoxData;
+use itertools::Itertools;
+use num_enum::IntoPrimitive;
+use palette::{named, Srgb};
+use protobuf::Enum;
+use serde::Serialize;
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    sync::{Mutex, RwLock},
+};
+use strum::{EnumCount, EnumIter, IntoEnumIterator, IntoStaticStr};
+
+/// Number of steps of the blend between two growth prints, few to keep the palette small
+pub const GROWTH_BLEND_STEPS: u8 = 4;
+
+/// A material to be exported as an entry in the palette
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub enum Material {
+    /// Default colors for which Dwarf Fortress does not give indication (water, magma, smoke...)
+    Default(DefaultMaterials),
+    /// Generic material built procedurally from Dwarf Fortress
+    Generic(MatPair),
+    /// Darker variant of a generic material
+    DarkGeneric(MatPair),
+    /// Generic material soaked by the rain, darker and glossy
+    Wet(MatPair),
+    /// Generic material with tile information
+    TileGeneric(MatPair, TiletypeMaterial),
+    /// Generic material with a growth console color associated to it
+    Plant {
+        material: MatPair,
+        source_color: Color,
+        dest_color: Color,
+        /// Color of the next print, and how many steps of `GROWTH_BLEND_STEPS` it is blended in
+        blend: Option<(Color, u8)>,
+    },
+    /// Plain color not bound to a Dwarf Fortress material, such as the civilization colors
+    Color(u8, u8, u8),
+}
+
+/// The default hard-coded materials
+#[derive(
+    Debug, Clone, Copy, IntoPrimitive, EnumIter, EnumCount, IntoStaticStr, Hash, PartialEq, Eq,
+)]
+#[repr(u8)]
+pub enum DefaultMaterials {
+    /// Common material for all hidden tiles
+    Hidden,
+    Water,
+    StagnantWater,
+    SaltWater,
+    /// Water polluted by blood, vomit, or other liquid spatters
+    ContaminatedWater,
+    /// Water of the murky pools and swamps
+    SwampWater,
+    /// Water over thawing ice
+    Slush,
+    /// Surface water frozen by the winter
+    Ice,
+    /// Tar and pitch liquids added by mods
+    Tar,
+    Mist,
+    Magma,
+    Fire,
+    Smoke,
+    Miasma,
+    DarkGrass,
+    LightGrass,
+    DeadGrass,
+    /// Bare soil of the paths where the grass is worn
+    WornPath,
+    Wood,
+    Light,
+    Moss,
+    Lichen,
+    Cobweb,
+    /// Links between the levers and their mechanisms
+    Wire,
+    /// Rock still glowing from the magma
+    CoolingRock,
+    /// Clothes of the siege engine operators
+    Crew,
+    /// Flame above the temple altars
+    AltarGlow,
+}
+
+pub trait RGBAColor {
+    fn get_rgba(&self) -> (u8, u8, u8, u8);
+}
+
+impl<T: RGBColor> RGBAColor for T {
+    fn get_rgba(&self) -> (u8, u8, u8, u8) {
+        let rgb = self.rgb();
+        (rgb.red, rgb.green, rgb.blue, 255)
+    }
+}
+
+impl RGBAColor for DefaultMaterials {
+    fn get_rgba(&self) -> (u8, u8, u8, u8) {
+        match self {
+            DefaultMaterials::Hidden => (0, 0, 0, 255),
+            DefaultMaterials::Water => (0, 0, 255, 64),
+            DefaultMaterials::StagnantWater => (58, 92, 40, 96),
+            DefaultMaterials::SaltWater => (0, 24, 140, 80),
+            DefaultMaterials::ContaminatedWater => (110, 40, 40, 96),
+            DefaultMaterials::SwampWater => (70, 75, 35, 160),
+            DefaultMaterials::Slush => (210, 225, 235, 160),
+            DefaultMaterials::Ice => (190, 225, 245, 200),
+            DefaultMaterials::Tar => (20, 15, 10, 255),
+            DefaultMaterials::Mist => (255, 255, 255, 64),
+            DefaultMaterials::Magma => (255, 0, 0, 64),
+            DefaultMaterials::Fire => (255, 174, 0, 64),
+            DefaultMaterials::Smoke => (100, 100, 100, 64),
+            DefaultMaterials::Miasma => (208, 89, 255, 64),
+            DefaultMaterials::DarkGrass => (0, 102, 0, 255),
+            DefaultMaterials::LightGrass => (0, 153, 51, 255),
+            DefaultMaterials::DeadGrass => (102, 102, 0, 255),
+            DefaultMaterials::WornPath => (115, 90, 60, 255),
+            DefaultMaterials::Wood => (75, 21, 0, 255),
+            DefaultMaterials::Light => (255, 255, 255, 255),
+            DefaultMaterials::Moss => (74, 112, 35, 255),
+            DefaultMaterials::Lichen => (196, 204, 170, 255),
+            DefaultMaterials::Cobweb => (235, 235, 235, 128),
+            DefaultMaterials::Wire => (255, 200, 0, 255),
+            DefaultMaterials::CoolingRock => (120, 40, 20, 255),
+            DefaultMaterials::Crew => (140, 50, 40, 255),
+            DefaultMaterials::AltarGlow => (255, 210, 140, 255),
+        }
+    }
+}
+
+/// Lightness factors alternated between the stone types, so that neighbouring layers differ
+const STONE_STRATA: [f32; 4] = [1.0, 0.86, 1.1, 0.94];
+
+/// Number of independently locked parts of the material cache
+const CACHE_SHARDS: usize = 16;
+
+/// Palette shared by the threads building the blocks
+#[derive(Default)]
+pub struct Palette {
+    entries: Mutex<PaletteEntries>,
+    /// Cache to avoid building the EffectiveMaterial for each voxel. Sharded by hash,
+    /// so that the threads hitting the cache do not wait on each other
+    material_cache: [RwLock<HashMap<Material, u8>>; CACHE_SHARDS],
+    /// Entries imposed by a previous export, by material key
+    pub pinned: HashMap<String, u8>,
+    /// Colors and materials of the previous export, written back on the pinned entries
+    pub pinned_chunks: HashMap<u8, (dot_vox::Color, dot_vox::Material)>,
+}
+
+#[derive(Default)]
+struct PaletteEntries {
+    /// Effective palette. Two different DF material are the same
+    /// effective material if they have the same characteristics in .vox
+    materials: HashMap<EffectiveMaterial, u8>,
+    /// Darker variants of the palette entries, by entry and shade
+    shade_cache: HashMap<(u8, u8), u8>,
+    /// True if some materials could not get their own entry
+    overflow: bool,
+    /// Entries left untouched for the user's own models
+    reserved: HashSet<u8>,
+}
+
+/// Material property listing the materials of an entry, to reuse the palette
+pub const KEY_PROPERTY: &str = "_vox_uristi";
+/// Separator of the material keys in `KEY_PROPERTY`
+const KEY_SEPARATOR: char = '|';
+
+impl PaletteEntries {
+    /// Index of an effective material, allocating a new entry if needed.
+    /// The pinned index of the material is used for a new entry.
+    fn entry(
+        &mut self,
+        material: EffectiveMaterial,
+        pin: Option<u8>,
+        pinned: &HashMap<String, u8>,
+    ) -> u8 {
+        if let Some(index) = self.materials.get(&material) {
+            return *index;
+        }
+        let index = match pin {
+            Some(index) => index,
+            None => {
+                let used: HashSet<u8> = self
+                    .materials
+                    .values()
+                    .chain(pinned.values())
+                    .chain(self.reserved.iter())
+                    .copied()
+                    .collect();
+                (0..u8::MAX)
+                    .find(|index| !used.contains(index))
+                    .unwrap_or_else(|| {
+                        self.overflow = true;
+                        (0..u8::MAX)
+                            .rev()
+                            .find(|index| !self.reserved.contains(index))
+                            .unwrap_or(u8::MAX - 1)
+                    })
+            }
+        };
+        self.materials.insert(material, index);
+        index
+    }
+}
+
+impl Palette {
+    pub fn get(&self, material: &Material, context: &DFContext) -> u8 {
+        let shard = &self.material_cache[Self::shard(material)];
+        if let Some(from_cache) = shard.read().unwrap().get(material) {
+            return *from_cache;
+        }
+
+        // Built outside of the locks, two threads may build the same material at worst
+        let effective_material = EffectiveMaterial::from_material(material, context);
+        let pin = self.pinned.get(&material.key()).copied();
+        let color = self
+            .entries
+            .lock()
+            .unwrap()
+            .entry(effective_material, pin, &self.pinned);
+        shard.write().unwrap().insert(material.clone(), color);
+        color
+    }
+
+    fn shard(material: &Material) -> usize {
+        let mut hasher = DefaultHasher::new();
+        material.hash(&mut hasher);
+        hasher.finish() as usize % CACHE_SHARDS
+    }
+
+    /// True if some materials could not get their own entry
+    pub fn overflow(&self) -> bool {
+        self.entries.lock().unwrap().overflow
+    }
+
+    /// Keep the entries of a range out of the export, in the MagicaVoxel numbering
+    /// (1 to 255, the first row being 1..8).
+    /// Fails if an entry pinned from a previous export falls inside the range.
+    pub fn reserve(&mut self, first: u8, last: u8) -> anyhow::Result<()> {
+        let range = first.max(1)..=last;
+        if let Some((key, index)) = self
+            .pinned
+            .iter()
+            .filter(|(_, index)| range.contains(&(**index + 1)))
+            .min_by_key(|(_, index)| **index)
+        {
+            anyhow::bail!(
+                "The palette color {} of {key} is pinned from the previous export, \
+                 but falls inside the reserved range {first}..{last}",
+                index + 1
+            );
+        }
+        let entries = self.entries.get_mut().unwrap();
+        entries.reserved.extend(range.map(|color| color - 1));
+        Ok(())
+    }
+
+    /// Reuse the entries of a previous export, keeping their hand-tweaked colors and materials
+    pub fn pin_from(&mut self, previous: &DotVoxData) {
+        for (index, color) in previous.palette.iter().enumerate().take(u8::MAX as usize) {
+            let Some(material) = previous
+                .materials
+                .iter()
+                .find(|material| material.id as usize == index + 1)
+            else {
+                continue;
+            };
+            let Some(keys) = material.properties.get(KEY_PROPERTY) else {
+                continue;
+            };
+            for key in keys.split(KEY_SEPARATOR) {
+                self.pinned.entry(key.to_string()).or_insert(index as u8);
+            }
+            self.pinned_chunks
+                .insert(index as u8, (*color, material.clone()));
+        }
+    }
+
+    /// Darker variant of a palette entry, 0 being the original entry
+    pub fn shaded(&self, index: u8, shade: u8, context: &DFContext) -> u8 {
+        if shade == 0 {
+            return index;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(shaded) = entries.shade_cache.get(&(index, shade)) {
+            return *shaded;
+        }
+        let Some(mut material) = entries
+            .materials
+            .iter()
+            .find(|(_, i)| **i == index)
+            .map(|(material, _)| material.clone())
+        else {
+            return index;
+        };
+        let darkening = 0.12 * shade as f32;
+        match context.settings.options.color_space {
+            Some(space) => {
+                let color = Srgb::new(material.r, material.g, material.b);
+                let color = WorkingColor::from_srgb(color, space)
+                    .darken(darkening)
+                    .into_srgb();
+                (material.r, material.g, material.b) = (color.red, color.green, color.blue);
+            }
+            // Channel by channel, as the shades always were
+            None => {
+                let factor = 1.0 - darkening;
+                material.r = (material.r as f32 * factor) as u8;
+                material.g = (material.g as f32 * factor) as u8;
+                material.b = (material.b as f32 * factor) as u8;
+            }
+        }
+
+        let shaded = entries.entry(material, None, &self.pinned);
+        entries.shade_cache.insert((index, shade), shaded);
+        shaded
+    }
+
+    pub fn cache_default_materials(&self, context: &DFContext) {
+        for default_material in DefaultMaterials::iter() {
+            let material = Material::Default(default_material);
+            self.get(&material, context);
+        }
+    }
+
+    /// Analyse which Dwarf Fortress materials ended up sharing an entry,
+    /// and which entries are so close that they could be merged
+    pub fn report(&self, context: &DFContext) -> PaletteReport {
+        let mut materials: BTreeMap<u8, Vec<String>> = BTreeMap::new();
+        for shard in &self.material_cache {
+            for (material, index) in shard.read().unwrap().iter() {
+                materials
+                    .entry(*index)
+                    .or_default()
+                    .push(material.name(context));
+            }
+        }
+        let palette_entries = self.entries.lock().unwrap();
+        let entries: Vec<PaletteReportEntry> = palette_entries
+            .materials
+            .iter()
+            .map(|(material, index)| PaletteReportEntry {
+                index: *index,
+                color: [material.r, material.g, material.b, material.a],
+                mat_type: material.mat_type.unwrap_or_default(),
+                materials: materials
+                    .get(index)
+                    .cloned()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .sorted()
+                    .collect(),
+            })
+            .sorted_by_key(|entry| entry.index)
+            .collect();
+
+        let mut candidates = Vec::new();
+        for (a, b) in entries.iter().tuple_combinations() {
+            let distance = a.color_distance(b);
+            if a.mat_type == b.mat_type && distance < PaletteReport::MERGE_DISTANCE {
+                candidates.push(PaletteMergeCandidate {
+                    entries: [a.index, b.index],
+                    distance,
+                });
+            }
+        }
+
+        PaletteReport {
+            overflow: palette_entries.overflow,
+            entries,
+            candidates,
+        }
+    }
+
+    /// Write the entries in the palette of the model, numbered in the order of their material
+    pub fn write_palette(&self, vox: &mut DotVoxData) {
+        self.write_palettes(&mut [vox]);
+    }
+
+    /// Write the entries in the palettes of several models sharing them, such as the close-ups
+    /// of the units next to the scene
+    pub fn write_palettes(&self, voxes: &mut [&mut DotVoxData]) {
+        self.sort_entries(voxes);
+        let mut keys: BTreeMap<u8, Vec<String>> = BTreeMap::new();
+        for shard in &self.material_cache {
+            for (material, index) in shard.read().unwrap().iter() {
+                keys.entry(*index).or_default().push(material.key());
+            }
+        }
+        for vox in voxes.iter_mut() {
+            for (material, index) in &self.entries.lock().unwrap().materials {
+                if let Some((color, previous)) = self.pinned_chunks.get(index) {
+                    vox.palette[*index as usize] = *color;
+                    vox.materials[*index as usize + 1] = previous.clone();
+                    continue;
+                }
+                let vox_material = &mut vox.materials[*index as usize + 1];
+                material.apply_material(&mut vox.palette[*index as usize], vox_material);
+                // The shaded variants have no material of their own, they are shaded again
+                if let Some(keys) = keys.get(index) {
+                    let keys = keys.iter().sorted().join(&KEY_SEPARATOR.to_string());
+                    vox_material
+                        .properties
+                        .insert(KEY_PROPERTY.to_string(), keys);
+                }
+            }
+        }
+    }
+}
+
+impl Palette {
+    /// Renumber the entries in the order of their material, so that the palette doesn't depend
+    /// on which thread reached a material first. The pinned entries keep their place.
+    fn sort_entries(&self, voxes: &mut [&mut DotVoxData]) {
+        let mut entries = self.entries.lock().unwrap();
+        let pinned: HashSet<u8> = self.pinned.values().copied().collect();
+        // Several materials share the last entry when the palette is full, the first one wins
+        let mut keys: BTreeMap<u8, &EffectiveMaterial> = BTreeMap::new();
+        for (material, index) in &entries.materials {
+            if pinned.contains(index) {
+                continue;
+            }
+            match keys.get(index) {
+                Some(first) if *first <= material => {}
+                _ => {
+                    keys.insert(*index, material);
+                }
+            }
+        }
+        let renumbered: HashMap<u8, u8> = keys
+            .iter()
+            .sorted_by(|(_, a), (_, b)| a.cmp(b))
+            .map(|(index, _)| *index)
+            .zip(keys.keys().copied())
+            .collect();
+        let renumber = |index: &mut u8| {
+            if let Some(new_index) = renumbered.get(index) {
+                *index = *new_index;
+            }
+        };
+
+        entries.materials.values_mut().for_each(renumber);
+        entries.shade_cache = entries
+            .shade_cache
+            .drain()
+            .map(|((mut index, shade), mut shaded)| {
+                renumber(&mut index);
+                renumber(&mut shaded);
+                ((index, shade), shaded)
+            })
+            .collect();
+        for shard in &self.material_cache {
+            shard.write().unwrap().values_mut().for_each(renumber);
+        }
+        for model in voxes.iter_mut().flat_map(|vox| vox.models.iter_mut()) {
+            for voxel in &mut model.voxels {
+                renumber(&mut voxel.i);
+            }
+        }
+    }
+}
+
+impl Material {
+    /// Stable identifier of the material, stored in the exported files to reuse the palette
+    fn key(&self) -> String {
+        let matpair = |matpair: &MatPair| format!("{}:{}", matpair.mat_type(), matpair.mat_index());
+        match self {
+            Material::Default(default) => format!("default:{}", <&str>::from(default)),
+            Material::Generic(material) => matpair(material),
+            Material::DarkGeneric(material) => format!("dark:{}", matpair(material)),
+            Material::Wet(material) => format!("wet:{}", matpair(material)),
+            Material::TileGeneric(material, tiletype_material) => {
+                format!("{}:{}", matpair(material), tiletype_material.value())
+            }
+            Material::Plant {
+                material,
+                source_color,
+                dest_color,
+                blend,
+            } => {
+                let blend = blend.map_or_else(String::new, |(color, steps)| {
+                    format!(":{}:{steps}", color.value())
+                });
+                format!(
+                    "plant:{}:{}:{}{blend}",
+                    matpair(material),
+                    source_color.value(),
+                    dest_color.value()
+                )
+            }
+            Material::Color(r, g, b) => format!("#{r:02x}{g:02x}{b:02x}"),
+        }
+    }
+
+    /// Human readable name, for reports
+    pub fn name(&self, context: &DFContext) -> String {
+        let matpair_name = |matpair: &MatPair| {
+            context.material(matpair).map_or_else(
+                || format!("{}:{}", matpair.mat_type(), matpair.mat_index()),
+                |material| material.id().to_string(),
+            )
+        };
+        match self {
+            Material::Default(default) => format!("{default:?}"),
+            Material::Generic(matpair) => matpair_name(matpair),
+            Material::DarkGeneric(matpair) => format!("{} (dark)", matpair_name(matpair)),
+            Material::Wet(matpair) => format!("{} (wet)", matpair_name(matpair)),
+            Material::TileGeneric(matpair, tiletype_material) => {
+                format!("{} ({:?})", matpair_name(matpair), tiletype_material)
+            }
+            Material::Plant { material, .. } => format!("{} (growth)", matpair_name(material)),
+            Material::Color(r, g, b) => format!("#{r:02x}{g:02x}{b:02x}"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PaletteReport {
+    /// True if the palette was full and some materials were forced together
+    pub overflow: bool,
+    pub entries: Vec<PaletteReportEntry>,
+    /// Entries similar enough to be merged by a palette override
+    pub candidates: Vec<PaletteMergeCandidate>,
+}
+
+#[derive(Serialize)]
+pub struct PaletteReportEntry {
+    pub index: u8,
+    pub color: [u8; 4],
+    pub mat_type: &'static str,
+    /// Dwarf Fortress materials sharing this entry
+    pub materials: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct PaletteMergeCandidate {
+    pub entries: [u8; 2],
+    pub distance: f32,
+}
+
+impl PaletteReport {
+    /// Distance between two colors, in RGB units, under which they are hard to tell apart
+    const MERGE_DISTANCE: f32 = 12.0;
+
+    pub fn summary(&self) -> String {
+        let merged = self
+            .entries
+            .iter()
+            .filter(|entry| entry.materials.len() > 1)
+            .count();
+        let mut summary = format!(
+            "Palette: {} entries, {} of them shared by several materials, {} pairs could be merged.",
+            self.entries.len(),
+            merged,
+            self.candidates.len()
+        );
+        if self.overflow {
+            summary.push_str(" The palette is full, some materials were merged arbitrarily.");
+        }
+        summary
+    }
+}
+
+impl PaletteReportEntry {
+    fn color_distance(&self, other: &Self) -> f32 {
+        self.color
+            .iter()
+            .zip(other.color.iter())
+            .take(3)
+            .map(|(a, b)| (*a as f32 - *b as f32).powi(2))
+            .sum::<f32>()
+            .sqrt()
+    }
+}
+
+/// Intermediary hashable material format to group together
+/// material that are the same from different sources
+#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Default, Clone)]
+pub struct EffectiveMaterial {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+    pub mat_type: Option<&'static str>,
+    pub metalness: Option<u8>,
+    pub roughness: Option<u8>,
+    pub transparency: Option<u8>,
+    pub emit: Option<u8>,
+    pub flux: Option<u8>,
+    pub ior: Option<u8>,
+}
+
+impl EffectiveMaterial {
+    pub fn from_material(material: &Material, context: &DFContext) -> Self {
+        let mut res = Self::ungraded(material, context);
+        let options = &context.settings.options;
+        let (gamma, saturation) = (
+            options.gamma.unwrap_or(1.0),
+            options.saturation.unwrap_or(1.0),
+        );
+        if gamma != 1.0 || saturation != 1.0 {
+            let color = Srgb::new(res.r, res.g, res.b);
+            let color = WorkingColor::from_srgb(color, options.color_space())
+                .graded(gamma, saturation)
+                .into_srgb();
+            (res.r, res.g, res.b) = (color.red, color.green, color.blue);
+        }
+        res
+    }
+
+    fn ungraded(material: &Material, context: &DFContext) -> Self {
+        match material {
+            Material::Default(default) => {
+                if let Some(custom) = context.material_table.default_override(*default) {
+                    return custom.effective();
+                }
+                let mut res = EffectiveMaterial::default();
+                (res.r, res.g, res.b, res.a) = default.get_rgba();
+                match default {
+                    DefaultMaterials::Water | DefaultMaterials::SaltWater => {
+                        res.mat_type = Some("_glass");
+                        res.transparency = Some(50);
+                    }
+                    DefaultMaterials::StagnantWater | DefaultMaterials::ContaminatedWater => {
+                        // murkier, less see-through
+                        res.mat_type = Some("_glass");
+                        res.transparency = Some(30);
+                    }
+                    DefaultMaterials::SwampWater => {
+                        // barely see-through, with a dull surface
+                        res.mat_type = Some("_glass");
+                        res.transparency = Some(15);
+                        res.roughness = Some(40);
+                    }
+                    DefaultMaterials::Slush => {
+                        res.mat_type = Some("_glass");
+                        res.transparency = Some(20);
+                        res.roughness = Some(60);
+                    }
+                    DefaultMaterials::Ice => {
+                        res.mat_type = Some("_glass");
+                        res.transparency = Some(35);
+                        res.roughness = Some(15);
+                    }
+                    DefaultMaterials::Tar => {
+                        // opaque and glossy
+                        res.mat_type = Some("_metal");
+                        res.roughness = Some(5);
+                    }
+                    DefaultMaterials::Magma => {
+                        res.mat_type = Some("_emit");
+                        res.emit = Some(50);
+                        res.flux = Some(2);
+                    }
+                    DefaultMaterials::Fire => {
+                        res.mat_type = Some("_emit");
+                        res.emit = Some(50);
+                        res.flux = Some(1);
+                    }
+                    DefaultMaterials::Mist => {
+                        res.mat_type = Some("_glass");
+                        res.ior = Some(0);
+                        res.transparency = Some(75);
+                    }
+                    DefaultMaterials::Smoke | DefaultMaterials::Miasma => {
+                        res.mat_type = Some("_glass");
+                        res.ior = Some(0);
+                        res.transparency = Some(25);
+                    }
+                    DefaultMaterials::Light => {
+                        res.mat_type = Some("_emit");
+                        res.emit = Some(50);
+                        res.flux = Some(1);
+                    }
+                    DefaultMaterials::CoolingRock | DefaultMaterials::AltarGlow => {
+                        res.mat_type = Some("_emit");
+                        res.emit = Some(15);
+                        res.flux = Some(1);
+                    }
+                    DefaultMaterials::Cobweb => {
+                        res.mat_type = Some("_glass");
+                        res.ior = Some(0);
+                        res.transparency = Some(40);
+                    }
+                    _ => {
+                        res.mat_type = Some("_diffuse");
+                    }
+                };
+                res
+            }
+            Material::Generic(matpair) => Self::from_matpair(matpair, context),
+            Material::DarkGeneric(matpair) => {
+                let mut res = Self::from_matpair(matpair, context);
+                if context.settings.options.flat_shading {
+                    // Shares the palette entry of the generic material
+                    return res;
+                }
+                let space = context.settings.options.color_space();
+                let color = WorkingColor::from_srgb(Srgb::new(res.r, res.g, res.b), space)
+                    .darken(0.5)
+                    .into_srgb();
+                (res.r, res.g, res.b, res.a) = (color.red, color.green, color.blue, 255);
+                res
+            }
+            Material::Wet(matpair) => {
+                let mut res = Self::from_matpair(matpair, context);
+                let space = context.settings.options.color_space();
+                let color = WorkingColor::from_srgb(Srgb::new(res.r, res.g, res.b), space)
+                    .darken(0.25)
+                    .into_srgb();
+                (res.r, res.g, res.b) = (color.red, color.green, color.blue);
+                // A thin film of water over the material
+                if res.mat_type.is_none() || res.mat_type == Some("_diffuse") {
+                    res.mat_type = Some("_metal");
+                    res.metalness = Some(0);
+                }
+                res.roughness = Some(8);
+                res.ior = Some(33);
+                res
+            }
+            Material::TileGeneric(matpair, tiletype_material) => {
+                let mut res = Self::from_matpair(matpair, context);
+                if tiletype_material == &TiletypeMaterial::FROZEN_LIQUID {
+                    res.mat_type = Some("_glass");
+                    res.ior = Some(50);
+                    res.transparency = Some(50);
+                }
+                if context.settings.options.strata {
+                    res.stratify(matpair, *tiletype_material, context);
+                }
+                res
+            }
+            Material::Plant {
+                material: mat,
+                source_color,
+                dest_color,
+                blend,
+            } => {
+                let mut res = EffectiveMaterial {
+                    mat_type: Some("_diffuse"),
+                    ..Default::default()
+                };
+                let main_color = context
+                    .material(mat)
+                    .map_or(named::BLACK, |material| material.state_color.rgb());
+                let space = context.settings.options.color_space();
+                let mut rgb = growth_color(main_color, *source_color, *dest_color, space);
+                if let Some((next_color, step)) = blend {
+                    let next = growth_color(main_color, *source_color, *next_color, space);
+                    let mix = |from: u8, to: u8| {
+                        let (from, to) = (i32::from(from), i32::from(to));
+                        (from + (to - from) * i32::from(*step) / i32::from(GROWTH_BLEND_STEPS))
+                            as u8
+                    };
+                    rgb = Srgb::new(
+                        mix(rgb.red, next.red),
+                        mix(rgb.green, next.green),
+                        mix(rgb.blue, next.blue),
+                    );
+                }
+                (res.r, res.g, res.b, res.a) = (rgb.red, rgb.green, rgb.blue, 255);
+                res
+            }
+            Material::Color(r, g, b) => EffectiveMaterial {
+                r: *r,
+                g: *g,
+                b: *b,
+                a: 255,
+                mat_type: Some("_diffuse"),
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn from_matpair(matpair: &MatPair, context: &DFContext) -> Self {
+        let definition = context.material(matpair);
+        if let Some(custom) =
+            definition.and_then(|material| context.material_table.matching(material.id()))
+        {
+            return custom.effective();
+        }
+        let mut res = EffectiveMaterial::default();
+        (res.r, res.g, res.b, res.a) =
+            definition.map_or((0, 0, 0, 0), |material| match material.id() {
+                // Water coloring exception, it's "clear" so no color, make it light blue for ice
+                "WATER" => (200, 200, 230, 255),
+                _ => material.state_color.get_rgba(),
+            });
+        if let Some(info) = context
+            .inorganic_materials_map
+            .get(&(matpair.mat_type(), matpair.mat_index()))
+        {
+            for flag in info.flag_names(&context.enums) {
+                match flag {
+                    "IS_METAL" => {
+                        res.mat_type = Some("_metal");
+                        res.metalness = Some(60);
+                        res.roughness = Some(20);
+                    }
+                    "IS_GEM" => {
+                        res.mat_type = Some("_glass");
+                        res.roughness = Some(3);
+                        res.transparency = Some(30);
+                    }
+                    "IS_GLASS" => {
+                        res.mat_type = Some("_glass");
+                        res.roughness = Some(5);
+                        res.transparency = Some(60);
+                    }
+                    "IS_CERAMIC" => {
+                        res.mat_type = Some("_glass");
+                        res.transparency = Some(0);
+                    }
+                    _ => {}
+                }
+            }
+            if info.token() == "MARBLE" {
+                res.mat_type = Some("_metal");
+                res.roughness = Some(50);
+                res.metalness = Some(50);
+            }
+        }
+        res
+    }
+
+    /// Tint the soils by kind and alternate the lightness of the stones, so that the cliffs
+    /// and the cross-sections show the strata
+    fn stratify(
+        &mut self,
+        matpair: &MatPair,
+        tiletype_material: TiletypeMaterial,
+        context: &DFContext,
+    ) {
+        let id = context
+            .material(matpair)
+            .map_or("", |material| material.id());
+        // Hue pulled toward, by how much, saturation and value factors
+        let (target_hue, pull, saturation, value) = match tiletype_material {
+            TiletypeMaterial::SOIL if id.contains("SAND") => (45.0, 0.3, 0.8, 1.15),
+            TiletypeMaterial::SOIL if id.contains("CLAY") => (15.0, 0.3, 1.2, 0.95),
+            // Loam, silt, peat...
+            TiletypeMaterial::SOIL => (30.0, 0.15, 1.1, 0.8),
+            TiletypeMaterial::STONE => {
+                let stratum = matpair.mat_index().rem_euclid(STONE_STRATA.len() as i32);
+                (0.0, 0.0, 1.0, STONE_STRATA[stratum as usize])
+            }
+            _ => return,
+        };
+        let space = context.settings.options.color_space();
+        let [hue, s, v] = WorkingColor::from_srgb(Srgb::new(self.r, self.g, self.b), space).hsv();
+        // Shortest way around the color wheel
+        let hue = hue + ((target_hue - hue + 540.0) % 360.0 - 180.0) * pull;
+        let hsv = [hue, (s * saturation).min(1.0), (v * value).min(1.0)];
+        let color = WorkingColor::from_hsv(hsv, space).into_srgb();
+        (self.r, self.g, self.b) = (color.red, color.green, color.blue);
+    }
+
+    fn apply_material(&self, color: &mut dot_vox::Color, material: &mut dot_vox::Material) {
+        let Self {
+            r,
+            g,
+            b,
+            a,
+            mat_type,
+            metalness,
+            roughness,
+            transparency,
+            emit,
+            flux,
+            ior,
+        } = self.to_owned();
+        color.r = r;
+        color.g = g;
+        color.b = b;
+        color.a = a;
+        if let Some(mat_type) = mat_type {
+            material.set_type(mat_type);
+        }
+        if let Some(emit) = emit {
+            material.set_emit((emit as f32) / 100.0);
+        }
+
+        if let Some(metalness) = metalness {
+            material.set_metalness((metalness as f32) / 100.0);
+        }
+
+        if let Some(roughness) = roughness {
+            material.set_roughness((roughness as f32) / 100.0);
+        }
+
+        if let Some(transparency) = transparency {
+            material.set_transparency((transparency as f32) / 100.0);
+        }
+
+        if let Some(flux) = flux {
+            material.set_flux(flux as f32);
+        }
+
+        if let Some(ior) = ior {
+            material.set_ior((ior as f32) / 100.0);
+        }
+    }
+}
+
+/// Color of a growth print, from the color of its material and the console colors
+/// of its first print and of the print to render
+fn growth_color(
+    main_color: Srgb<u8>,
+    source_color: Color,
+    dest_color: Color,
+    space: ColorSpace,
+) -> Srgb<u8> {
+    if source_color == dest_color {
+        return main_color;
+    }
+    let [mut hue, saturation, mut value] = WorkingColor::from_srgb(main_color, space).hsv();
+    let [source_hue, _, source_value] = WorkingColor::from_srgb(source_color.rgb(), space).hsv();
+    let [dest_hue, _, dest_value] = WorkingColor::from_srgb(dest_color.rgb(), space).hsv();
+    // I have no idea what's going on here, I just did my best to replicate what is done in Armok Vision
+    // https://github.com/RosaryMala/armok-vision/blob/3027c785a54d7a8d9a7a9f7f2a10a1815c3bb500/Assets/Scripts/MapGen/DfColor.cs#L37
+    // and the result looks fairly similar to in-game colors.
+    hue += dest_hue - source_hue;
+    if source_value > dest_value {
+        value *= dest_value / source_value;
+    } else {
+        value = 1.0 - ((1.0 - value) * ((1.0 - dest_value) / (1.0 - source_value)));
+    }
+    WorkingColor::from_hsv([hue, saturation, value], space).into_srgb()
+}
+
+/// Parse a range of MagicaVoxel palette colors such as `1..8`, both ends included
+pub fn parse_palette_range(range: &str) -> Result<(u8, u8), String> {
+    let (first, last) = range
+        .split_once("..")
+        .ok_or_else(|| format!("expected FIRST..LAST, got {range}"))?;
+    let color = |value: &str| match value.trim().parse::<u8>() {
+        Ok(color) if color >= 1 => Ok(color),
+        _ => Err(format!("invalid palette color {value}, expected 1 to 255")),
+    };
+    let (first, last) = (color(first)?, color(last)?);
+    Ok((first.min(last), first.max(last)))
+}
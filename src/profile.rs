@@ -0,0 +1,67 @@
+use crate::export::ExportOptions;
+use anyhow::{bail, Context, Result};
+use itertools::Itertools;
+use serde_json::{Map, Value};
+use std::{collections::BTreeMap, path::PathBuf};
+
+static BUILTIN_PROFILES: &[u8] = include_bytes!("../assets/profiles.yaml");
+
+/// Options set by a profile, by option name
+type Profile = Map<String, Value>;
+
+/// Folder of the user configuration
+pub fn config_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|base| base.join("vox-uristi"))
+}
+
+/// Built-in profiles, and the ones of the user configuration replacing them by name
+fn load_profiles() -> Result<BTreeMap<String, Profile>> {
+    let mut profiles: BTreeMap<String, Profile> = serde_yaml::from_slice(BUILTIN_PROFILES)?;
+    if let Some(path) = config_dir().map(|dir| dir.join("profiles.yaml")) {
+        if path.exists() {
+            let file = std::fs::File::open(&path)
+                .with_context(|| format!("Opening the profiles {}", path.display()))?;
+            let user: BTreeMap<String, Profile> = serde_yaml::from_reader(file)
+                .with_context(|| format!("Reading the profiles {}", path.display()))?;
+            profiles.extend(user);
+        }
+    }
+    Ok(profiles)
+}
+
+/// Options with the selected profile applied.
+/// The options given on the command line are kept
+pub fn resolve(options: ExportOptions) -> Result<ExportOptions> {
+    let Some(name) = &options.profile else {
+        return Ok(options);
+    };
+    let profiles = load_profiles()?;
+    let Some(profile) = profiles.get(name) else {
+        bail!(
+            "Unknown profile {name}, the profiles are {}",
+            profiles.keys().join(", ")
+        );
+    };
+    let defaults = serde_json::to_value(ExportOptions::default())?;
+    let mut resolved = serde_json::to_value(&options)?;
+    for (key, value) in profile {
+        if defaults.get(key).is_none() {
+            bail!("Unknown option {key} in the profile {name}");
+        }
+        if !options.explicit.contains(key) {
+            resolved[key] = value.clone();
+        }
+    }
+    let mut resolved: ExportOptions =
+        serde_json::from_value(resolved).with_context(|| format!("Applying the profile {name}"))?;
+    // Not serialized
+    resolved.force = options.force;
+    Ok(resolved)
+}
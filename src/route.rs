@@ -0,0 +1,159 @@
+use crate::{
+    block::BLOCK_SIZE,
+    context::DFContext,
+    direction::DirectionFlat,
+    map::Map,
+    overlay::Overlay,
+    palette::{Material, Palette},
+    rfr::DFHackExt,
+    DFBoundingBox, DFMapCoords, VoxelCoords,
+};
+use anyhow::Result;
+use dfhack_remote::TiletypeShape;
+use std::collections::{HashMap, VecDeque};
+
+/// Color of the dots of the route
+const ROUTE_COLOR: (u8, u8, u8) = (250, 215, 90);
+
+/// Read the footprint of the trade depots the wagons can reach
+pub fn read_accessible_depots(client: &mut dfhack_remote::Client) -> Result<Vec<DFBoundingBox>> {
+    let output = client.lua_output(
+        r#"for _, depot in ipairs(df.global.world.buildings.other.TRADE_DEPOT) do
+  if depot.accessible == 1 or depot.accessible == true then
+    emit(depot.x1, depot.y1, depot.x2, depot.y2, depot.z)
+  end
+end"#,
+    )?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let values: Vec<i32> = line
+                .split_whitespace()
+                .map(|value| value.parse().ok())
+                .collect::<Option<_>>()?;
+            match values[..] {
+                [x1, y1, x2, y2, z] => Some(DFBoundingBox {
+                    x: x1..=x2,
+                    y: y1..=y2,
+                    z: z..=z,
+                }),
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+/// Tile a wagon can roll on
+fn passable(map: &Map, coords: DFMapCoords) -> bool {
+    map.occupancy
+        .get(&coords)
+        .and_then(|occupancy| occupancy.block_tile.as_ref())
+        .is_some_and(|tile| {
+            !tile.hidden()
+                && matches!(
+                    tile.tile_type().shape(),
+                    TiletypeShape::FLOOR
+                        | TiletypeShape::PEBBLES
+                        | TiletypeShape::SHRUB
+                        | TiletypeShape::RAMP
+                )
+        })
+}
+
+/// Wagons are three tiles wide, the tiles around the center must be passable too
+fn wide_enough(map: &Map, coords: DFMapCoords) -> bool {
+    (-1..=1).all(|dx| {
+        (-1..=1).all(|dy| {
+            passable(
+                map,
+                DFMapCoords::new(coords.x + dx, coords.y + dy, coords.z),
+            )
+        })
+    })
+}
+
+fn is_ramp(map: &Map, coords: DFMapCoords) -> bool {
+    map.occupancy
+        .get(&coords)
+        .and_then(|occupancy| occupancy.block_tile.as_ref())
+        .is_some_and(|tile| tile.tile_type().shape() == TiletypeShape::RAMP)
+}
+
+/// Tiles a wagon can go to from a tile, going up and down the ramps
+fn next_tiles(map: &Map, coords: DFMapCoords) -> Vec<DFMapCoords> {
+    let mut next = Vec::new();
+    for direction in [
+        DirectionFlat::North,
+        DirectionFlat::East,
+        DirectionFlat::South,
+        DirectionFlat::West,
+    ] {
+        let neighbour = coords + direction;
+        let up = DFMapCoords::new(neighbour.x, neighbour.y, neighbour.z + 1);
+        let down = DFMapCoords::new(neighbour.x, neighbour.y, neighbour.z - 1);
+        if wide_enough(map, neighbour) {
+            next.push(neighbour);
+        } else if is_ramp(map, coords) && wide_enough(map, up) {
+            next.push(up);
+        } else if is_ramp(map, down) && wide_enough(map, down) {
+            next.push(down);
+        }
+    }
+    next
+}
+
+/// Shortest wagon path from the edges of the map to each depot, drawn as a dotted line
+pub fn build_wagon_route(
+    depots: &[DFBoundingBox],
+    map: &Map,
+    context: &DFContext,
+    palette: &Palette,
+) -> Overlay {
+    let mut overlay = Overlay::default();
+    if depots.is_empty() {
+        return overlay;
+    }
+    let max_x = context.map_info.block_size_x() * BLOCK_SIZE as i32;
+    let max_y = context.map_info.block_size_y() * BLOCK_SIZE as i32;
+    // The wagons enter with their center one tile away from the edge
+    let on_edge = |coords: &DFMapCoords| {
+        coords.x == 1 || coords.y == 1 || coords.x == max_x - 2 || coords.y == max_y - 2
+    };
+    let mut previous: HashMap<DFMapCoords, Option<DFMapCoords>> = HashMap::new();
+    let mut queue = VecDeque::new();
+    for coords in map.occupancy.keys().filter(|coords| on_edge(coords)) {
+        if wide_enough(map, *coords) {
+            previous.insert(*coords, None);
+            queue.push_back(*coords);
+        }
+    }
+    while let Some(coords) = queue.pop_front() {
+        for next in next_tiles(map, coords) {
+            if !previous.contains_key(&next) {
+                previous.insert(next, Some(coords));
+                queue.push_back(next);
+            }
+        }
+    }
+
+    let (r, g, b) = ROUTE_COLOR;
+    let color = palette.get(&Material::Color(r, g, b), context);
+    for depot in depots {
+        let center = DFMapCoords::new(
+            (depot.x.start() + depot.x.end()) / 2,
+            (depot.y.start() + depot.y.end()) / 2,
+            *depot.z.start(),
+        );
+        let mut step = Some(center).filter(|center| previous.contains_key(center));
+        let mut index = 0;
+        while let Some(coords) = step {
+            // Every other tile, for a dotted line
+            if index % 2 == 0 {
+                overlay.add(VoxelCoords::from_df(coords, 1, 1, 1), color);
+            }
+            index += 1;
+            step = previous.get(&coords).copied().flatten();
+        }
+    }
+    overlay
+}
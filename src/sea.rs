@@ -0,0 +1,80 @@
+use crate::{
+    block::BLOCK_SIZE,
+    context::DFContext,
+    map::Map,
+    overlay::Overlay,
+    palette::{DefaultMaterials, Material, Palette},
+    DFMapCoords, VoxelCoords, BASE, HEIGHT,
+};
+use itertools::Itertools;
+use std::{collections::HashMap, ops::Range};
+
+/// Minimum number of liquid tiles on a level to consider it a sea rather than a spill
+const MIN_SEA_TILES: usize = 64;
+
+/// Fill the tiles missing from the export with the ocean or magma sea of their level,
+/// so that the seas reach the edges of the map instead of ending in a cliff
+pub fn extend_seas(
+    map: &Map,
+    z_range: &Range<i32>,
+    context: &DFContext,
+    palette: &Palette,
+) -> Overlay {
+    // Depths of the salt water and magma tiles on each level
+    let mut depths: HashMap<i32, (Vec<i32>, Vec<i32>)> = HashMap::new();
+    for (coords, occupancy) in &map.occupancy {
+        let Some(tile) = &occupancy.block_tile else {
+            continue;
+        };
+        let (water, magma) = depths.entry(coords.z).or_default();
+        if tile.water() > 0 && tile.water_salt() {
+            water.push(tile.water());
+        }
+        if tile.magma() > 0 {
+            magma.push(tile.magma());
+        }
+    }
+
+    let width = context.map_info.block_size_x() * BLOCK_SIZE as i32;
+    let height = context.map_info.block_size_y() * BLOCK_SIZE as i32;
+    let mut overlay = Overlay::default();
+    for z in z_range.clone() {
+        let Some((water, magma)) = depths.get(&z) else {
+            continue;
+        };
+        let (liquid, depths) = if water.len() >= magma.len() {
+            (DefaultMaterials::SaltWater, water)
+        } else {
+            (DefaultMaterials::Magma, magma)
+        };
+        if depths.len() < MIN_SEA_TILES {
+            continue;
+        }
+        // Most common depth of the sea on this level, clamped like the liquid tiles
+        let Some((depth, _)) = depths.iter().counts().into_iter().max_by_key(|(_, n)| *n) else {
+            continue;
+        };
+        let depth = (*depth).clamp(2, 7).min(HEIGHT as i32) as usize;
+        let color = palette.get(&Material::Default(liquid), context);
+        for x in 0..width {
+            for y in 0..height {
+                let coords = DFMapCoords::new(x, y, z);
+                let present = map
+                    .occupancy
+                    .get(&coords)
+                    .is_some_and(|occupancy| occupancy.block_tile.is_some());
+                if present {
+                    continue;
+                }
+                for sub_x in 0..BASE {
+                    for sub_y in 0..BASE {
+                        for sub_z in 0..depth {
+                            overlay.add(VoxelCoords::from_df(coords, sub_x, sub_y, sub_z), color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    overlay
+}
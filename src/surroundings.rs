@@ -0,0 +1,130 @@
+use crate::{
+    block::BLOCK_SIZE,
+    calendar::Month,
+    context::DFContext,
+    overlay::Overlay,
+    palette::{DefaultMaterials, Material, Palette},
+    DFMapCoords, VoxelCoords, BASE,
+};
+use anyhow::Result;
+use dfhack_remote::MatPair;
+use std::ops::Range;
+
+/// Width of a region tile (an embark square), in blocks
+const REGION_TILE_BLOCKS: i32 = 3;
+/// Width of a world tile, in region tiles
+const WORLD_TILE_REGION_TILES: i32 = 16;
+/// Vegetation above which the surface is rendered as grass
+const GRASS_VEGETATION: i32 = 50;
+
+/// Region tile around the embark, in region tile units from the embark origin
+pub struct RegionColumn {
+    pub x: i32,
+    pub y: i32,
+    /// Surface level, in the coordinates of the map
+    pub elevation: i32,
+    /// Level of the water surface, when above the ground
+    pub water_elevation: Option<i32>,
+    pub vegetation: i32,
+    pub material: MatPair,
+}
+
+/// Read the region tiles within `ring` region tiles of the embark
+pub fn read_surroundings(
+    client: &mut dfhack_remote::Client,
+    context: &DFContext,
+    ring: i32,
+) -> Result<Vec<RegionColumn>> {
+    let map_info = &context.map_info;
+    // Embark position and size in region tiles
+    let origin_x = map_info.block_pos_x() / REGION_TILE_BLOCKS;
+    let origin_y = map_info.block_pos_y() / REGION_TILE_BLOCKS;
+    let width = map_info.block_size_x() / REGION_TILE_BLOCKS;
+    let height = map_info.block_size_y() / REGION_TILE_BLOCKS;
+    let origin_z = map_info.block_pos_z();
+
+    let region_maps = client.remote_fortress_reader().get_region_maps()?;
+    let mut columns = Vec::new();
+    for region_map in &region_maps.region_maps {
+        for (index, tile) in region_map.tiles.iter().enumerate() {
+            let index = index as i32;
+            let x = region_map.map_x() * WORLD_TILE_REGION_TILES + index % WORLD_TILE_REGION_TILES
+                - origin_x;
+            let y = region_map.map_y() * WORLD_TILE_REGION_TILES + index / WORLD_TILE_REGION_TILES
+                - origin_y;
+            let inside = (0..width).contains(&x) && (0..height).contains(&y);
+            let in_ring = (-ring..width + ring).contains(&x) && (-ring..height + ring).contains(&y);
+            if inside || !in_ring {
+                continue;
+            }
+            let elevation = tile.elevation() - origin_z;
+            let water_elevation = tile.water_elevation() - origin_z;
+            columns.push(RegionColumn {
+                x,
+                y,
+                elevation,
+                water_elevation: (water_elevation > elevation).then_some(water_elevation),
+                vegetation: tile.vegetation(),
+                material: tile.surface_material.get_or_default().clone(),
+            });
+        }
+    }
+    Ok(columns)
+}
+
+/// Flat, low detail surface of the region tiles, shrunk to one block each around the embark
+pub fn build_surroundings(
+    columns: &[RegionColumn],
+    context: &DFContext,
+    palette: &Palette,
+) -> Overlay {
+    let grass = if Month::from_year_tick(context.settings.year_tick).is_dry_season() {
+        DefaultMaterials::DeadGrass
+    } else {
+        DefaultMaterials::LightGrass
+    };
+    let grass = palette.get(&Material::Default(grass), context);
+    let water = palette.get(&Material::Default(DefaultMaterials::Water), context);
+    let width = context.map_info.block_size_x() / REGION_TILE_BLOCKS;
+    let height = context.map_info.block_size_y() / REGION_TILE_BLOCKS;
+    let mut overlay = Overlay::default();
+    for column in columns {
+        let ground = if column.vegetation > GRASS_VEGETATION {
+            grass
+        } else {
+            palette.get(&Material::Generic(column.material.clone()), context)
+        };
+        let (z, color) = match column.water_elevation {
+            Some(water_elevation) => (water_elevation, water),
+            None => (column.elevation, ground),
+        };
+        let tiles_x = shrunk_tiles(column.x, width);
+        let tiles_y = shrunk_tiles(column.y, height);
+        for x in tiles_x {
+            for y in tiles_y.clone() {
+                let coords = DFMapCoords::new(x, y, z);
+                for sub_x in 0..BASE {
+                    for sub_y in 0..BASE {
+                        overlay.add(VoxelCoords::from_df(coords, sub_x, sub_y, 0), color);
+                    }
+                }
+            }
+        }
+    }
+    overlay
+}
+
+/// Tiles where a region tile around the embark is drawn along an axis, the ring of region tiles
+/// being shrunk to one block per region tile against the edges of the embark
+fn shrunk_tiles(region_tile: i32, embark_size: i32) -> Range<i32> {
+    let blocks = if region_tile < 0 {
+        region_tile..region_tile + 1
+    } else if region_tile < embark_size {
+        // Along the embark, at its real scale to line up with it
+        region_tile * REGION_TILE_BLOCKS..(region_tile + 1) * REGION_TILE_BLOCKS
+    } else {
+        let block = embark_size * REGION_TILE_BLOCKS + region_tile - embark_size;
+        block..block + 1
+    };
+    blocks.start * BLOCK_SIZE as i32..blocks.end * BLOCK_SIZE as i32
+}
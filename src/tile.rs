@@ -1,154 +1,364 @@
-mod generic;
-mod tree;
-use std::collections::HashSet;
-
-use crate::{
-    block::BlockModels,
-    context::DFContext,
-    export::Layers,
-    palette::{DefaultMaterials, Material},
-    rfr::BlockTile,
-    shape::{box_from_fn, box_from_levels, box_full, slice_const, Box3D},
-    voxel::voxels_from_uniform_shape,
-    GenBoolSafe, StableRng, WithDFCoords,
-};
-use dfhack_remote::{MatterState, TiletypeMaterial, TiletypeShape};
-pub use generic::BlockTileExt;
-use rand::Rng;
-pub use tree::BlockTilePlantExt;
-
-impl WithDFCoords for BlockTile<'_> {
-    fn coords(&self) -> crate::DFMapCoords {
-        self.global_coords()
-    }
-}
-
-impl BlockTile<'_> {
-    pub fn build(
-        &self,
-        models: &mut BlockModels,
-        map: &crate::map::Map,
-        context: &DFContext,
-        palette: &mut crate::palette::Palette,
-    ) {
-        let mut rng = self.stable_rng();
-
-        // Voxels that spatters can sit on top
-        let mut occupied_for_spatters: HashSet<(u8, u8, u8)> = HashSet::new();
-
-        if self.hidden() {
-            let shape: Box3D<bool> = box_full();
-
-            models.extend(
-                Layers::Hidden,
-                voxels_from_uniform_shape(
-                    shape,
-                    self.local_coords(),
-                    palette.get(&Material::Default(DefaultMaterials::Hidden), context),
-                ),
-            );
-            return;
-        }
-
-        match (self.tile_type().material(), self.tile_type().shape()) {
-            (
-                TiletypeMaterial::ROOT
-                | TiletypeMaterial::MUSHROOM
-                | TiletypeMaterial::PLANT
-                | TiletypeMaterial::TREE_MATERIAL,
-                _,
-            )
-            | (
-                _,
-                TiletypeShape::SAPLING
-                | TiletypeShape::TWIG
-                | TiletypeShape::SHRUB
-                | TiletypeShape::BRANCH,
-            ) => {
-                // plant, trees
-                let trees = self.build_trees(map, context, palette);
-                occupied_for_spatters.extend(trees.iter().map(|v| (v.x, v.y, v.z)));
-                models.extend(Layers::Vegetation, trees);
-            }
-            _ => {
-                // classic tile structure
-                let (terrain, roughness) = self.build_terrain(map, context, palette);
-                occupied_for_spatters.extend(terrain.iter().map(|v| (v.x, v.y, v.z)));
-                models.extend(Layers::Terrain, terrain);
-                models.extend(Layers::Roughness, roughness);
-            }
-        }
-
-        // liquids
-        if self.water() > 0 {
-            let water_shape: Box3D<bool> =
-                box_from_levels(slice_const(self.water().min(7).max(2) as usize));
-            models.extend(
-                Layers::Liquid,
-                voxels_from_uniform_shape(
-                    water_shape,
-                    self.local_coords(),
-                    palette.get(&Material::Default(DefaultMaterials::Water), context),
-                ),
-            );
-        }
-
-        if self.magma() > 0 {
-            let magma_shape: Box3D<bool> =
-                box_from_levels(slice_const(self.magma().min(7).max(2) as usize));
-            models.extend(
-                Layers::Liquid,
-                voxels_from_uniform_shape(
-                    magma_shape,
-                    self.local_coords(),
-                    palette.get(&Material::Default(DefaultMaterials::Magma), context),
-                ),
-            );
-        }
-
-        // spatters
-        for spatter in self.spatters() {
-            // spatters sit on top of existing voxels, when there is some space
-            let material = Material::Generic(spatter.material.get_or_default().clone());
-
-            for (x, y, z) in &occupied_for_spatters {
-                let coords = (*x, *y, *z + 1);
-                if !occupied_for_spatters.contains(&coords) {
-                    let gen = match spatter.state() {
-                        // solid spatter is stuff like fruits and leaves, from zero to 10 000.
-                        // there are a lot of them, so step down the probability
-                        MatterState::Solid => rng.gen_bool_safe(spatter.amount() as f64 / 50_000.0),
-                        // liquid spatter is blood etc, from 0 to 255.
-                        // completely covered is a bit weird, half the probability
-                        MatterState::Liquid => rng.gen_bool_safe(spatter.amount() as f64 / 512.0),
-                        // powder spatter is likely snow, going from 0 to 100. We want 100% snow to covere the ground
-                        MatterState::Powder => rng.gen_bool_safe(spatter.amount() as f64 / 100.0),
-                        // gas, paste and other, I don't know how the can occur
-                        _ => false,
-                    };
-                    if gen {
-                        models.extend(
-                            Layers::Spatter,
-                            Some(dot_vox::Voxel {
-                                x: coords.0,
-                                y: coords.1,
-                                z: coords.2,
-                                i: palette.get(&material, context),
-                            }),
-                        );
-                    }
-                }
-            }
-        }
-
-        // Fire is identified as a special tiletype material
-        if self.tile_type().material() == TiletypeMaterial::FIRE {
-            let shape: Box3D<bool> = box_from_fn(|_, _, _| rng.gen_bool(0.1));
-            let material = palette.get(&Material::Default(DefaultMaterials::Fire), context);
-            models.extend(
-                Layers::Fire,
-                voxels_from_uniform_shape(shape, self.local_coords(), material),
-            );
-        }
-    }
-}
+mod generic;
+mod tree;
+use std::collections::HashSet;
+
+use crate::{
+    block::{BlockModels, BLOCK_SIZE},
+    calendar::Month,
+    context::DFContext,
+    export::Layers,
+    palette::{DefaultMaterials, Material, Palette},
+    rfr::{BlockTile, SpatterExt},
+    shape::{box_from_fn, box_from_levels, box_full, slice_const, Box3D},
+    voxel::voxels_from_uniform_shape,
+    GenBoolSafe, StableRng, WithDFCoords, BASE, HEIGHT,
+};
+use dfhack_remote::{MatterState, Spatter, TiletypeMaterial, TiletypeShape};
+pub use generic::BlockTileExt;
+use rand::{rngs::StdRng, Rng};
+pub use tree::BlockTilePlantExt;
+
+/// Share of a liquid spatter above which it is drawn as streaks rather than speckles
+const STREAK_AMOUNT: f32 = 0.5;
+
+impl WithDFCoords for BlockTile<'_> {
+    fn coords(&self) -> crate::DFMapCoords {
+        self.global_coords()
+    }
+}
+
+impl BlockTile<'_> {
+    /// Kind of water on this tile
+    fn water_material(&self, context: &DFContext) -> DefaultMaterials {
+        let contaminated = context.capabilities.spatter_piles
+            && self
+                .spatters()
+                .iter()
+                .any(|spatter| spatter.state() == MatterState::Liquid && spatter.amount() > 0);
+        if contaminated {
+            DefaultMaterials::ContaminatedWater
+        } else if self.water_salt() {
+            DefaultMaterials::SaltWater
+        } else if self.tile_type().material() == TiletypeMaterial::FROZEN_LIQUID {
+            DefaultMaterials::Slush
+        } else if self.tile_type().material() == TiletypeMaterial::POOL {
+            DefaultMaterials::SwampWater
+        } else if self.water_stagnant() {
+            DefaultMaterials::StagnantWater
+        } else {
+            DefaultMaterials::Water
+        }
+    }
+
+    /// Surface water frozen by the winter, when exporting a winter month
+    fn frozen_by_winter(&self, context: &DFContext) -> bool {
+        context.settings.options.winter_ice
+            && Month::from_year_tick(context.settings.year_tick).is_winter()
+            && self.outside()
+            && !self.subterranean()
+            && !self.water_salt()
+    }
+
+    /// Ice replacing the water of the tile. Falling water freezes in a column
+    fn build_ice(
+        &self,
+        map: &crate::map::Map,
+        context: &DFContext,
+        palette: &Palette,
+    ) -> Vec<dot_vox::Voxel> {
+        let falling = self.tile_type().shape() == TiletypeShape::EMPTY
+            && map
+                .neighbouring(self.global_coords(), |o| {
+                    o.block_tile.as_ref().is_some_and(|tile| tile.water() > 0)
+                })
+                .b;
+        let shape: Box3D<bool> = if falling {
+            box_from_fn(|x, y, _| x == 1 || y == 1)
+        } else {
+            box_from_levels(slice_const(self.water().clamp(2, 7) as usize))
+        };
+        voxels_from_uniform_shape(
+            shape,
+            self.local_coords(),
+            palette.get(&Material::Default(DefaultMaterials::Ice), context),
+        )
+    }
+
+    /// Icicles hanging from the overhangs in the winter
+    fn build_icicles(
+        &self,
+        map: &crate::map::Map,
+        context: &DFContext,
+        palette: &Palette,
+        rng: &mut impl Rng,
+    ) -> Vec<dot_vox::Voxel> {
+        if !self.frozen_by_winter(context) || self.tile_type().shape() != TiletypeShape::EMPTY {
+            return Vec::new();
+        }
+        let overhang = map
+            .neighbouring(self.global_coords(), |o| {
+                o.block_tile.as_ref().is_some_and(|tile| {
+                    !matches!(
+                        tile.tile_type().shape(),
+                        TiletypeShape::EMPTY | TiletypeShape::RAMP_TOP
+                    )
+                })
+            })
+            .a;
+        if !overhang {
+            return Vec::new();
+        }
+        // Up to two voxels long, in the corners of the tile
+        let lengths: [[usize; 3]; 3] =
+            std::array::from_fn(|_| std::array::from_fn(|_| rng.gen_range(0..3)));
+        let shape: Box3D<bool> =
+            box_from_fn(|x, y, z| x != 1 && y != 1 && z + lengths[y][x] >= HEIGHT);
+        voxels_from_uniform_shape(
+            shape,
+            self.local_coords(),
+            palette.get(&Material::Default(DefaultMaterials::Ice), context),
+        )
+    }
+
+    /// Runs of voxels across the tile, along the direction the amount of the same liquid
+    /// varies the most in the neighbouring tiles, as if dragged from the heavier pools.
+    /// A random direction when it is even all around
+    fn build_streaks(
+        &self,
+        spatter: &Spatter,
+        map: &crate::map::Map,
+        occupied: &HashSet<(u8, u8, u8)>,
+        rng: &mut StdRng,
+    ) -> Vec<(u8, u8, u8)> {
+        let amount = map.neighbouring_flat(self.global_coords(), |occupancy| {
+            occupancy.block_tile.as_ref().map_or(0, |tile| {
+                tile.spatters()
+                    .iter()
+                    .filter(|other| {
+                        other.state() == MatterState::Liquid && other.material == spatter.material
+                    })
+                    .map(|other| other.amount())
+                    .sum::<u32>() as i32
+            })
+        });
+        // model y is going north while df y is going south
+        let (gradient_x, gradient_y) = (amount.e - amount.w, amount.n - amount.s);
+        let step: (i32, i32) = match (gradient_x.abs(), gradient_y.abs()) {
+            (0, 0) => [(1, 0), (0, 1), (1, 1), (1, -1)][rng.gen_range(0..4)],
+            (x, y) if x > 2 * y => (1, 0),
+            (x, y) if y > 2 * x => (0, 1),
+            _ => (1, gradient_x.signum() * gradient_y.signum()),
+        };
+        let origin = self.local_coords();
+        let tile_x = (origin.x * BASE as u8) as i32;
+        let tile_y = ((BLOCK_SIZE as u8 - origin.y - 1) * BASE as u8) as i32;
+        let (range_x, range_y) = (tile_x..tile_x + BASE as i32, tile_y..tile_y + BASE as i32);
+        let inside = |x: i32, y: i32| range_x.contains(&x) && range_y.contains(&y);
+        // The heavier pools get a second streak
+        let count = if spatter.amount_normalized() > 0.8 {
+            2
+        } else {
+            1
+        };
+        let mut streaks = Vec::new();
+        for _ in 0..count {
+            let (seed_x, seed_y) = (
+                tile_x + rng.gen_range(0..BASE as i32),
+                tile_y + rng.gen_range(0..BASE as i32),
+            );
+            for offset in -(BASE as i32)..=BASE as i32 {
+                let (x, y) = (seed_x + offset * step.0, seed_y + offset * step.1);
+                if !inside(x, y) {
+                    continue;
+                }
+                let top = occupied
+                    .iter()
+                    .filter(|(vx, vy, _)| *vx as i32 == x && *vy as i32 == y)
+                    .map(|(_, _, z)| *z)
+                    .max();
+                if let Some(top) = top {
+                    let voxel = (x as u8, y as u8, top + 1);
+                    if !streaks.contains(&voxel) {
+                        streaks.push(voxel);
+                    }
+                }
+            }
+        }
+        streaks
+    }
+
+    /// Material of a spatter, the tar and pitch of the mods have their own
+    fn spatter_material(spatter: &Spatter, context: &DFContext) -> Material {
+        let matpair = spatter.material.get_or_default();
+        let tar = spatter.state() == MatterState::Liquid
+            && context.material(matpair).is_some_and(|material| {
+                material
+                    .id()
+                    .split(':')
+                    .any(|part| part == "TAR" || part == "PITCH")
+            });
+        if tar {
+            Material::Default(DefaultMaterials::Tar)
+        } else {
+            Material::Generic(matpair.clone())
+        }
+    }
+
+    pub fn build(
+        &self,
+        models: &mut BlockModels,
+        map: &crate::map::Map,
+        context: &DFContext,
+        palette: &crate::palette::Palette,
+    ) {
+        let mut rng = self.stable_rng();
+
+        // Voxels that spatters can sit on top
+        let mut occupied_for_spatters: HashSet<(u8, u8, u8)> = HashSet::new();
+
+        if self.hidden() {
+            let shape: Box3D<bool> = box_full();
+
+            models.extend(
+                Layers::Hidden,
+                voxels_from_uniform_shape(
+                    shape,
+                    self.local_coords(),
+                    palette.get(&Material::Default(DefaultMaterials::Hidden), context),
+                ),
+            );
+            return;
+        }
+
+        if context.settings.options.terrain_only {
+            models.extend(Layers::Terrain, self.build_blockout(context, palette));
+            return;
+        }
+
+        match (self.tile_type().material(), self.tile_type().shape()) {
+            (
+                TiletypeMaterial::ROOT
+                | TiletypeMaterial::MUSHROOM
+                | TiletypeMaterial::PLANT
+                | TiletypeMaterial::TREE_MATERIAL,
+                _,
+            )
+            | (
+                _,
+                TiletypeShape::SAPLING
+                | TiletypeShape::TWIG
+                | TiletypeShape::SHRUB
+                | TiletypeShape::BRANCH,
+            ) => {
+                // plant, trees
+                let trees = self.build_trees(map, context, palette);
+                occupied_for_spatters.extend(trees.iter().map(|v| (v.x, v.y, v.z)));
+                models.extend(Layers::Vegetation, trees);
+            }
+            _ => {
+                // classic tile structure
+                let (terrain, roughness) = self.build_terrain(map, context, palette);
+                occupied_for_spatters.extend(terrain.iter().map(|v| (v.x, v.y, v.z)));
+                models.extend(Layers::Terrain, terrain);
+                models.extend(Layers::Roughness, roughness);
+            }
+        }
+
+        // liquids
+        if self.water() > 0 && self.frozen_by_winter(context) {
+            models.extend(Layers::Liquid, self.build_ice(map, context, palette));
+        } else if self.water() > 0 {
+            let water_shape: Box3D<bool> =
+                box_from_levels(slice_const(self.water().min(7).max(2) as usize));
+            models.extend(
+                Layers::Liquid,
+                voxels_from_uniform_shape(
+                    water_shape,
+                    self.local_coords(),
+                    palette.get(&Material::Default(self.water_material(context)), context),
+                ),
+            );
+        }
+        models.extend(
+            Layers::Liquid,
+            self.build_icicles(map, context, palette, &mut rng),
+        );
+
+        if self.magma() > 0 {
+            let magma_shape: Box3D<bool> =
+                box_from_levels(slice_const(self.magma().min(7).max(2) as usize));
+            models.extend(
+                Layers::Liquid,
+                voxels_from_uniform_shape(
+                    magma_shape,
+                    self.local_coords(),
+                    palette.get(&Material::Default(DefaultMaterials::Magma), context),
+                ),
+            );
+        }
+
+        // spatters
+        let spatters = if context.capabilities.spatter_piles {
+            self.spatters().as_slice()
+        } else {
+            &[]
+        };
+        for spatter in spatters {
+            // spatters sit on top of existing voxels, when there is some space
+            let material = Self::spatter_material(spatter, context);
+            if context.settings.options.blood_streaks
+                && spatter.state() == MatterState::Liquid
+                && spatter.amount_normalized() > STREAK_AMOUNT
+            {
+                let color = palette.get(&material, context);
+                let streaks = self.build_streaks(spatter, map, &occupied_for_spatters, &mut rng);
+                models.extend(
+                    Layers::Spatter,
+                    streaks
+                        .into_iter()
+                        .map(|(x, y, z)| dot_vox::Voxel { x, y, z, i: color }),
+                );
+                continue;
+            }
+
+            for (x, y, z) in &occupied_for_spatters {
+                let coords = (*x, *y, *z + 1);
+                if !occupied_for_spatters.contains(&coords) {
+                    let gen = match spatter.state() {
+                        // solid spatter is stuff like fruits and leaves, from zero to 10 000.
+                        // there are a lot of them, so step down the probability
+                        MatterState::Solid => rng.gen_bool_safe(spatter.amount() as f64 / 50_000.0),
+                        // liquid spatter is blood etc, from 0 to 255.
+                        // completely covered is a bit weird, half the probability
+                        MatterState::Liquid => rng.gen_bool_safe(spatter.amount() as f64 / 512.0),
+                        // powder spatter is likely snow, going from 0 to 100. We want 100% snow to covere the ground
+                        MatterState::Powder => rng.gen_bool_safe(spatter.amount() as f64 / 100.0),
+                        // gas, paste and other, I don't know how the can occur
+                        _ => false,
+                    };
+                    if gen {
+                        models.extend(
+                            Layers::Spatter,
+                            Some(dot_vox::Voxel {
+                                x: coords.0,
+                                y: coords.1,
+                                z: coords.2,
+                                i: palette.get(&material, context),
+                            }),
+                        );
+                    }
+                }
+            }
+        }
+
+        // Fire is identified as a special tiletype material
+        if self.tile_type().material() == TiletypeMaterial::FIRE {
+            let shape: Box3D<bool> = box_from_fn(|_, _, _| rng.gen_bool(0.1));
+            let material = palette.get(&Material::Default(DefaultMaterials::Fire), context);
+            models.extend(
+                Layers::Fire,
+                voxels_from_uniform_shape(shape, self.local_coords(), material),
+            );
+        }
+    }
+}
@@ -1,19 +1,28 @@
 use super::tree::{connectivity_from_direction_string, PlantPart};
 use crate::{
+    calendar::Month,
     context::DFContext,
     direction::{Neighbouring8Flat, Rotating},
-    map::Map,
+    map::{Map, Occupancy},
     palette::{DefaultMaterials, EffectiveMaterial, Material, Palette},
     rfr::BlockTile,
-    shape::{box_empty, box_from_levels, slice_empty, slice_from_fn, slice_full, Box3D},
+    shape::{
+        box_empty, box_from_levels, box_full, slice_const, slice_empty, slice_from_fn, slice_full,
+        Box3D, Slice2D,
+    },
     voxel::{voxels_from_shape, voxels_from_uniform_shape},
-    DFMapCoords, IsSomeAnd, StableRng,
+    DFMapCoords, IsSomeAnd, StableRng, BASE, HEIGHT,
 };
-use dfhack_remote::{TiletypeMaterial, TiletypeShape, TiletypeSpecial};
+use dfhack_remote::{FlowType, TiletypeMaterial, TiletypeShape, TiletypeSpecial};
 use easy_ext::ext;
 use rand::Rng;
 
 pub fn ramp_shape(map: &Map, coords: DFMapCoords) -> [[[bool; 3]; 3]; 5] {
+    box_from_levels(bank_levels(map, coords, ramp_levels(map, coords)))
+}
+
+/// Height of each column of a ramp, rising toward the walls
+fn ramp_levels(map: &Map, coords: DFMapCoords) -> [[usize; 3]; 3] {
     let c = map.neighbouring_8flat(coords, |o| {
         o.block_tile
             .as_ref()
@@ -38,9 +47,77 @@ pub fn ramp_shape(map: &Map, coords: DFMapCoords) -> [[[bool; 3]; 3]; 5] {
 
     let max = nw.max(ne).max(sw).max(se);
 
-    let levels = [[c.nw, c.n, c.ne], [c.w, max / 2, c.e], [c.sw, c.s, c.se]];
+    [[c.nw, c.n, c.ne], [c.w, max / 2, c.e], [c.sw, c.s, c.se]]
+}
 
-    box_from_levels(levels)
+/// Erode the ramps along flowing water into smoother, partially submerged banks
+fn bank_levels(map: &Map, coords: DFMapCoords, levels: [[usize; 3]; 3]) -> [[usize; 3]; 3] {
+    let water = map.neighbouring_8flat(coords, |o| {
+        o.block_tile
+            .some_and(|t| t.water() > 0 && !t.water_stagnant())
+    });
+    let facing_water = [
+        [
+            water.nw || water.n || water.w,
+            water.n,
+            water.ne || water.n || water.e,
+        ],
+        [water.w, false, water.e],
+        [
+            water.sw || water.s || water.w,
+            water.s,
+            water.se || water.s || water.e,
+        ],
+    ];
+    if !facing_water.iter().flatten().any(|w| *w) {
+        return levels;
+    }
+
+    // Lower the edges facing the water
+    let mut carved = levels;
+    for (y, row) in carved.iter_mut().enumerate() {
+        for (x, level) in row.iter_mut().enumerate() {
+            if facing_water[y][x] {
+                *level = level.saturating_sub(2).max(1);
+            }
+        }
+    }
+
+    // Smooth out the steps by averaging each column with its direct neighbours
+    let mut smoothed = carved;
+    for (y, row) in smoothed.iter_mut().enumerate() {
+        for (x, level) in row.iter_mut().enumerate() {
+            let neighbours = [
+                Some(carved[y][x]),
+                y.checked_sub(1).map(|y| carved[y][x]),
+                carved.get(y + 1).map(|row| row[x]),
+                x.checked_sub(1).map(|x| carved[y][x]),
+                carved[y].get(x + 1).copied(),
+            ];
+            let (sum, count) = neighbours
+                .into_iter()
+                .flatten()
+                .fold((0, 0), |(sum, count), level| (sum + level, count + 1));
+            // Never grow above the original ramp
+            *level = ((sum + count / 2) / count).clamp(1, levels[y][x].max(1));
+        }
+    }
+    smoothed
+}
+
+/// Surface detail growing on a cavern wall, depending on what's in front of it
+fn cavern_detail(occupancy: &Occupancy) -> Option<Material> {
+    if occupancy.flows.iter().any(|f| f.type_() == FlowType::Web) {
+        return Some(Material::Default(DefaultMaterials::Cobweb));
+    }
+    let tile = occupancy.block_tile.as_ref().filter(|t| !t.is_wall())?;
+    match tile.tile_type().material() {
+        TiletypeMaterial::GRASS_LIGHT | TiletypeMaterial::GRASS_DARK => {
+            Some(Material::Default(DefaultMaterials::Moss))
+        }
+        _ if tile.water() > 0 => Some(Material::Default(DefaultMaterials::Lichen)),
+        _ => None,
+    }
 }
 
 #[ext(BlockTileExt)]
@@ -52,6 +129,28 @@ pub impl BlockTile<'_> {
         )
     }
 
+    /// Natural wall deep underground, where moss and webs can grow
+    fn is_cavern_wall(&self) -> bool {
+        self.subterranean()
+            && !self.outside()
+            && self.tile_type().material() != TiletypeMaterial::CONSTRUCTION
+            && !matches!(
+                self.tile_type().special(),
+                TiletypeSpecial::SMOOTH | TiletypeSpecial::SMOOTH_DEAD
+            )
+    }
+
+    /// Smoothed or constructed floor, drawn with the seams between its slabs
+    fn is_finished_floor(&self) -> bool {
+        let tile_type = self.tile_type();
+        tile_type.shape() == TiletypeShape::FLOOR
+            && (tile_type.material() == TiletypeMaterial::CONSTRUCTION
+                || matches!(
+                    tile_type.special(),
+                    TiletypeSpecial::SMOOTH | TiletypeSpecial::SMOOTH_DEAD
+                ))
+    }
+
     fn ramp_contact_height(&self) -> usize {
         if self.is_wall() {
             6
@@ -60,51 +159,132 @@ pub impl BlockTile<'_> {
         }
     }
 
-    // Returns a tuple with the terrain voxels and the "roughness" voxels
-    fn build_terrain(
-        &self,
-        map: &Map,
-        context: &DFContext,
-        palette: &mut Palette,
-    ) -> (Vec<dot_vox::Voxel>, Vec<dot_vox::Voxel>) {
-        let mut rng = self.stable_rng();
-        let coords = self.global_coords();
-        let tile_type = self.tile_type();
-        let material = match self.tile_type().material() {
+    fn terrain_material(&self, context: &DFContext) -> Material {
+        // The surface grass follows the exported month rather than the current one,
+        // the caverns have no seasons
+        let surface = !self.subterranean();
+        let dry = surface && Month::from_year_tick(context.settings.year_tick).is_dry_season();
+        match self.tile_type().material() {
             // Grass don't have proper materials in the raw
+            TiletypeMaterial::GRASS_LIGHT | TiletypeMaterial::GRASS_DARK if dry => {
+                Material::Default(DefaultMaterials::DeadGrass)
+            }
             TiletypeMaterial::GRASS_LIGHT => Material::Default(DefaultMaterials::LightGrass),
             TiletypeMaterial::GRASS_DARK => Material::Default(DefaultMaterials::DarkGrass),
+            // Only dry for the season, green again in the exported month
+            TiletypeMaterial::GRASS_DRY if surface && !dry => {
+                Material::Default(DefaultMaterials::LightGrass)
+            }
             TiletypeMaterial::GRASS_DRY | TiletypeMaterial::GRASS_DEAD => {
                 Material::Default(DefaultMaterials::DeadGrass)
             }
             // Generic material from raw
             mat => Material::TileGeneric(self.material().clone(), mat),
+        }
+    }
+
+    /// Fraction of the tile still covered by grass, None if it's not a grass tile
+    fn grass_coverage(&self, context: &DFContext) -> Option<f64> {
+        let grass = matches!(
+            self.tile_type().material(),
+            TiletypeMaterial::GRASS_LIGHT
+                | TiletypeMaterial::GRASS_DARK
+                | TiletypeMaterial::GRASS_DRY
+                | TiletypeMaterial::GRASS_DEAD
+        );
+        (grass && context.capabilities.grass_percent)
+            .then(|| self.grass_percent().clamp(0, 100) as f64 / 100.0)
+    }
+
+    /// Plain single-material shape of the tile, without any detail, for the terrain only exports
+    fn build_blockout(&self, context: &DFContext, palette: &Palette) -> Vec<dot_vox::Voxel> {
+        let shape: Box3D<bool> = match self.tile_type().shape() {
+            TiletypeShape::WALL | TiletypeShape::FORTIFICATION => box_full(),
+            TiletypeShape::RAMP => box_from_levels(slice_const(3)),
+            TiletypeShape::FLOOR
+            | TiletypeShape::BOULDER
+            | TiletypeShape::PEBBLES
+            | TiletypeShape::STAIR_UP
+            | TiletypeShape::STAIR_UPDOWN => box_from_levels(slice_const(1)),
+            _ => box_empty(),
         };
+        voxels_from_uniform_shape(
+            shape,
+            self.local_coords(),
+            palette.get(&self.terrain_material(context), context),
+        )
+    }
+
+    // Returns a tuple with the terrain voxels and the "roughness" voxels
+    fn build_terrain(
+        &self,
+        map: &Map,
+        context: &DFContext,
+        palette: &Palette,
+    ) -> (Vec<dot_vox::Voxel>, Vec<dot_vox::Voxel>) {
+        let mut rng = self.stable_rng();
+        let coords = self.global_coords();
+        let tile_type = self.tile_type();
+        let material = match tile_type.material() {
+            TiletypeMaterial::CONSTRUCTION => context.magma_safety.tint(self.material()),
+            _ => None,
+        }
+        .unwrap_or_else(|| self.terrain_material(context));
+        let grass_coverage = self.grass_coverage(context);
+        let clean = context.settings.options.clean;
         let (shape_base, shape_rough): (Box3D<bool>, Box3D<bool>) = match tile_type.shape() {
             TiletypeShape::FLOOR | TiletypeShape::BOULDER | TiletypeShape::PEBBLES => {
-                let item_on_tile = map
+                // The floor details would poke through the model of the buildings
+                let under_building = map
                     .occupancy
                     .get(&coords)
                     .is_some_and(|t| !t.buildings.is_empty());
-                let rough = !item_on_tile // no roughness if there is a rendered item
+                let rough = !clean
+                    && !under_building
                     && tile_type.material() != TiletypeMaterial::FROZEN_LIQUID // no roughness for ice, it looks bad
                     && !matches!(
                         tile_type.special(),
                         TiletypeSpecial::SMOOTH | TiletypeSpecial::SMOOTH_DEAD
                     );
+                // Stones lying on the floor, the top layer first
+                let [stones_top, stones]: [Slice2D<bool>; 2] = match tile_type.shape() {
+                    _ if under_building => [slice_empty(), slice_empty()],
+                    // Plain cross, the same on every tile
+                    TiletypeShape::BOULDER if clean => [
+                        slice_from_fn(|x, y| x == 1 && y == 1),
+                        slice_from_fn(|x, y| x == 1 || y == 1),
+                    ],
+                    // Rounded lump, wider at the base
+                    TiletypeShape::BOULDER => [
+                        slice_from_fn(|x, y| {
+                            (x == 1 && y == 1) || ((x == 1 || y == 1) && rng.gen_bool(0.5))
+                        }),
+                        slice_from_fn(|x, y| x == 1 || y == 1 || rng.gen_bool(0.3)),
+                    ],
+                    // A few small stones scattered over the floor
+                    TiletypeShape::PEBBLES if !clean => {
+                        [slice_empty(), slice_from_fn(|_, _| rng.gen_bool(0.25))]
+                    }
+                    _ => [slice_empty(), slice_empty()],
+                };
                 (
                     [
                         slice_empty(),
                         slice_empty(),
-                        slice_empty(),
-                        slice_empty(),
+                        stones_top,
+                        stones,
                         slice_full(),
                     ],
                     [
                         slice_empty(),
                         slice_empty(),
                         slice_empty(),
-                        slice_from_fn(|_, _| rough && rng.gen_bool(1.0 / 7.0)),
+                        // Less grass blades where the grass is depleted
+                        slice_from_fn(|x, y| {
+                            rough
+                                && !stones[y][x]
+                                && rng.gen_bool(grass_coverage.unwrap_or(1.0) / 7.0)
+                        }),
                         slice_empty(),
                     ],
                 )
@@ -133,7 +313,34 @@ pub impl BlockTile<'_> {
                         })
                     })
                 });
-                let shape = [slice, slice, slice, slice, slice];
+                let mut shape = [slice, slice, slice, slice, slice];
+                if self.is_cavern_wall() && !clean {
+                    let details = map.neighbouring_flat(coords, cavern_detail);
+                    let sides = [
+                        (details.n, [(0, 0), (1, 0), (2, 0)]),
+                        (details.e, [(2, 0), (2, 1), (2, 2)]),
+                        (details.s, [(0, 2), (1, 2), (2, 2)]),
+                        (details.w, [(0, 0), (0, 1), (0, 2)]),
+                    ];
+                    for (detail, edge) in sides {
+                        let Some(detail) = detail else {
+                            continue;
+                        };
+                        let detail = palette.get(&detail, context);
+                        for (z, level) in shape.iter_mut().enumerate() {
+                            // Growing from the ground, sparser higher on the wall
+                            let probability = if z >= 3 { 0.4 } else { 0.15 };
+                            for (x, y) in edge {
+                                if rng.gen_bool(probability) {
+                                    level[y][x] = Some(detail);
+                                }
+                            }
+                        }
+                    }
+                }
+                if context.settings.options.stylized {
+                    bevel_wall(&mut shape, &c, map.neighbouring(coords, open).a);
+                }
                 return (voxels_from_shape(shape, self.local_coords()), vec![]);
             }
             TiletypeShape::FORTIFICATION => {
@@ -165,19 +372,65 @@ pub impl BlockTile<'_> {
                 ];
                 (shape, box_empty())
             }
-            TiletypeShape::STAIR_UP => (stairs(true, true, false, true, coords.z), box_empty()),
-            TiletypeShape::STAIR_DOWN => (stairs(false, false, true, false, coords.z), box_empty()),
-            TiletypeShape::STAIR_UPDOWN => (stairs(true, true, true, false, coords.z), box_empty()),
+            TiletypeShape::STAIR_UP | TiletypeShape::STAIR_DOWN | TiletypeShape::STAIR_UPDOWN => {
+                let up = tile_type.shape() != TiletypeShape::STAIR_DOWN;
+                let down = tile_type.shape() != TiletypeShape::STAIR_UP;
+                let carved = tile_type.material() != TiletypeMaterial::CONSTRUCTION;
+                let rotation = stairs_rotation(map, coords);
+                (stairs(up, down, carved, rotation), box_empty())
+            }
             TiletypeShape::RAMP => (ramp_shape(map, coords), box_empty()),
             _ => (box_empty(), box_empty()),
         };
 
+        let mut terrain = voxels_from_uniform_shape(
+            shape_base,
+            self.local_coords(),
+            palette.get(&material, context),
+        );
+        if self.is_finished_floor() {
+            // One voxel wide seams between slabs of 2x2 tiles draw a light grid across the room
+            let seam = palette.get(&Material::DarkGeneric(self.material().clone()), context);
+            let slab = 2 * BASE;
+            for voxel in terrain.iter_mut() {
+                if voxel.x as usize % slab == 0 || voxel.y as usize % slab == 0 {
+                    voxel.i = seam;
+                }
+            }
+        }
+        // The exposed floors and roofs are soaked by the rain
+        let wet = context.rain && self.outside() && tile_type.shape() == TiletypeShape::FLOOR;
+        let wet_material = match &material {
+            Material::TileGeneric(matpair, _) if wet => Some(Material::Wet(matpair.clone())),
+            _ => None,
+        };
+        if let Some(wet_material) = wet_material {
+            let soaked = palette.get(&wet_material, context);
+            let dry = palette.get(&material, context);
+            for voxel in terrain.iter_mut().filter(|voxel| voxel.i == dry) {
+                voxel.i = soaked;
+            }
+        }
+        if let Some(coverage) = grass_coverage.filter(|_| !clean) {
+            // Worn paths show the soil where the traffic depleted the grass
+            let worn = palette.get(&Material::Default(DefaultMaterials::WornPath), context);
+            for voxel in terrain.iter_mut() {
+                if !rng.gen_bool(coverage) {
+                    voxel.i = worn;
+                }
+            }
+        }
+        if context.settings.options.stylized && tile_type.shape() == TiletypeShape::FLOOR {
+            // Staggered rows of slightly darker voxels, like a honeycomb
+            for voxel in terrain.iter_mut().filter(|voxel| voxel.z == 0) {
+                if voxel.y % 2 == 0 && (voxel.x + voxel.y) % 4 == 0 {
+                    voxel.i = palette.shaded(voxel.i, 1, context);
+                }
+            }
+        }
+
         (
-            voxels_from_uniform_shape(
-                shape_base,
-                self.local_coords(),
-                palette.get(&material, context),
-            ),
+            terrain,
             voxels_from_uniform_shape(
                 shape_rough,
                 self.local_coords(),
@@ -208,34 +461,134 @@ pub impl BlockTile<'_> {
     }
 }
 
-fn stairs(up: bool, middle: bool, down: bool, floor: bool, z: i32) -> Box3D<bool> {
-    #[rustfmt::skip]
-    let shape = [
-        [
-            [false, false, false],
-            [false, false, false],
-            [up, up, up],
-        ],
-        [
-            [false, false, middle],
-            [false, false, middle],
-            [false, false, middle],
-        ],
-        [
-            [middle, middle, middle],
-            [false, false, false],
-            [false, false, false]
-        ],
-        [
-            [middle, false, false],
-            [middle, false, false],
-            [middle, false, false]
-        ],
-        [
-            [floor, floor, floor],
-            [floor, floor, floor],
-            [down || floor, down || floor, down || floor]
-        ],
+/// Open space, where the top of a wall below is visible
+fn open(occupancy: &Occupancy) -> bool {
+    occupancy
+        .block_tile
+        .as_ref()
+        .is_some_and(|tile| tile.tile_type().shape() == TiletypeShape::EMPTY)
+}
+
+/// Cut the outer corners of a wall along its whole height, and chamfer the exposed edges
+/// of its top when nothing lies on it
+fn bevel_wall(shape: &mut Box3D<Option<u8>>, walls: &Neighbouring8Flat<bool>, open_above: bool) {
+    let corners = [
+        (!walls.n && !walls.w, 0, 0),
+        (!walls.n && !walls.e, BASE - 1, 0),
+        (!walls.s && !walls.w, 0, BASE - 1),
+        (!walls.s && !walls.e, BASE - 1, BASE - 1),
     ];
-    shape.rotated_by((z % 4) as usize)
+    for (convex, x, y) in corners {
+        if convex {
+            for level in shape.iter_mut() {
+                level[y][x] = None;
+            }
+        }
+    }
+    if !open_above {
+        return;
+    }
+    // The first level is the top of the wall
+    for (y, row) in shape[0].iter_mut().enumerate() {
+        for (x, voxel) in row.iter_mut().enumerate() {
+            let exposed = (!walls.n && y == 0)
+                || (!walls.s && y == BASE - 1)
+                || (!walls.w && x == 0)
+                || (!walls.e && x == BASE - 1);
+            if exposed {
+                *voxel = None;
+            }
+        }
+    }
+}
+
+/// Positions around the center of a tile, in the order the steps wind up
+const SPIRAL: [(usize, usize); 8] = [
+    (0, 0),
+    (1, 0),
+    (2, 0),
+    (2, 1),
+    (2, 2),
+    (1, 2),
+    (0, 2),
+    (0, 1),
+];
+
+/// Spiral stairs, doing a full turn on each level
+///
+/// The carved stairs wind around a center pole, and their top landing
+/// has a railing along the opening.
+fn stairs(up: bool, down: bool, carved: bool, rotation: usize) -> Box3D<bool> {
+    let mut shape: Box3D<bool> = box_empty();
+    // The shape is indexed from the top, the floor is the last slice
+    let mut set = |x: usize, y: usize, z: usize| shape[HEIGHT - 1 - z][y][x] = true;
+    if up {
+        if !down {
+            // The first steps rest on a floor
+            for (x, y) in SPIRAL {
+                set(x, y, 0);
+            }
+        }
+        for (step, (x, y)) in SPIRAL.into_iter().enumerate() {
+            set(x, y, step * HEIGHT / SPIRAL.len());
+        }
+        if carved {
+            for z in 0..HEIGHT {
+                set(1, 1, z);
+            }
+        }
+    } else if down {
+        // Landing on the last half turn of the stairs below
+        for (x, y) in &SPIRAL[5..] {
+            set(*x, *y, 0);
+        }
+        if carved {
+            // Posts at both ends of the opening, and a handrail over the pole
+            for z in 0..3 {
+                set(1, 1, z);
+            }
+            set(0, 0, 1);
+            set(2, 2, 1);
+            for (x, y) in [(0, 0), (1, 1), (2, 2)] {
+                set(x, y, 2);
+            }
+        }
+    }
+    shape.rotated_by(rotation)
+}
+
+/// Rotation of the stairs, shared by the whole stairwell so that the steps of each level
+/// continue the ones below. It comes from the nearest stairs of the column whose tile type
+/// has a direction, the stairs below first.
+fn stairs_rotation(map: &Map, coords: DFMapCoords) -> usize {
+    let at = |z: i32| stairs_direction(map, DFMapCoords::new(coords.x, coords.y, z));
+    if let Some(Some(rotation)) = at(coords.z) {
+        return rotation;
+    }
+    let below = (1..)
+        .map(|offset| at(coords.z - offset))
+        .map_while(|stairs| stairs);
+    let above = (1..)
+        .map(|offset| at(coords.z + offset))
+        .map_while(|stairs| stairs);
+    below.chain(above).flatten().next().unwrap_or_default()
+}
+
+/// Rotation given by the direction of a stairs tile type, None for the other tiles
+fn stairs_direction(map: &Map, coords: DFMapCoords) -> Option<Option<usize>> {
+    let tile = map.occupancy.get(&coords)?.block_tile.as_ref()?;
+    let tile_type = tile.tile_type();
+    if !matches!(
+        tile_type.shape(),
+        TiletypeShape::STAIR_UP | TiletypeShape::STAIR_DOWN | TiletypeShape::STAIR_UPDOWN
+    ) {
+        return None;
+    }
+    Some(match tile_type.direction().chars().find(|c| *c != '-') {
+        Some('N') => Some(0),
+        Some('E') => Some(1),
+        Some('S') => Some(2),
+        Some('W') => Some(3),
+        _ => None,
+    })
 }
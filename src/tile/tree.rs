@@ -1,374 +1,427 @@
-use super::BlockTileExt;
-use crate::{
-    context::DFContext,
-    direction::{DirectionFlat, NeighbouringFlat},
-    map::Map,
-    palette::{DefaultMaterials, Material, Palette},
-    rfr::{BlockTile, ConsoleColor, GetTiming},
-    shape::{self, Box3D},
-    voxel::{voxels_from_shape, voxels_from_uniform_shape},
-    IsSomeAnd, StableRng,
-};
-use dfhack_remote::{MatPair, TiletypeSpecial};
-use easy_ext::ext;
-use itertools::Itertools;
-use rand::{rngs::StdRng, seq::SliceRandom, Rng};
-
-#[ext(BlockTilePlantExt)]
-pub impl BlockTile<'_> {
-    fn build_trees(
-        &self,
-        map: &Map,
-        context: &DFContext,
-        palette: &mut Palette,
-    ) -> Vec<dot_vox::Voxel> {
-        let mut rng = self.stable_rng();
-        let part = self.plant_part();
-        let tile_type = self.tile_type();
-        let plant_index = self.material().mat_index();
-        let alive = !matches!(
-            tile_type.special(),
-            TiletypeSpecial::DEAD | TiletypeSpecial::SMOOTH_DEAD
-        );
-        // The "structure material" for plants looks like it's always an ugly default brown.
-        // For tree, in mat_type 420 is generally the wood, which is nicer.
-        // For other plants, use the hard-coded grass one.
-        let structure_material = match part {
-            PlantPart::Root
-            | PlantPart::HeavyBranch { .. }
-            | PlantPart::LightBranch
-            | PlantPart::Trunk => Material::Generic(MatPair {
-                mat_type: Some(420),
-                mat_index: Some(plant_index),
-                ..Default::default()
-            }),
-            _ => Material::Default(if alive {
-                DefaultMaterials::LightGrass
-            } else {
-                DefaultMaterials::DeadGrass
-            }),
-        };
-        let mut voxels = voxels_from_uniform_shape(
-            self.plant_structure_shape(&part, map),
-            self.local_coords(),
-            palette.get(&structure_material, context),
-        );
-        let growth_materials = self
-            .growth_materials(&part, context)
-            .into_iter()
-            .map(|m| palette.get(&m, context))
-            .collect_vec();
-        if alive && !growth_materials.is_empty() {
-            let growth = BlockTile::growth_shape(&part, &mut rng).map(|slice| {
-                slice.map(|col| {
-                    col.map(|t| {
-                        if t {
-                            growth_materials.choose(&mut rng).cloned()
-                        } else {
-                            None
-                        }
-                    })
-                })
-            });
-            voxels.append(&mut voxels_from_shape(growth, self.local_coords()));
-        }
-        voxels
-    }
-
-    fn plant_structure_shape(&self, part: &PlantPart, map: &Map) -> Box3D<bool> {
-        let mut r = self.stable_rng();
-        let coords = self.global_coords();
-        let origin = self.tree_origin();
-        // The horror
-        match part {
-            PlantPart::Root => shape::box_full(),
-            PlantPart::Trunk | PlantPart::Cap => {
-                let on_floor = coords == origin;
-                [
-                    [
-                        [false, true, false],
-                        [true, true, true],
-                        [false, true, false],
-                    ],
-                    [
-                        [false, true, false],
-                        [true, true, true],
-                        [false, true, false],
-                    ],
-                    [
-                        [false, true, false],
-                        [true, true, true],
-                        [false, true, false],
-                    ],
-                    [
-                        [false, true, false],
-                        [true, true, true],
-                        [false, true, false],
-                    ],
-                    [
-                        [on_floor, true, on_floor],
-                        [true, true, true],
-                        [on_floor, true, on_floor],
-                    ],
-                ]
-            }
-            PlantPart::Sapling | PlantPart::Shrub => [
-                shape::slice_empty(),
-                shape::slice_empty(),
-                shape::slice_empty(),
-                [
-                    [r.gen_ratio(1, 7), r.gen_ratio(1, 7), r.gen_ratio(1, 7)],
-                    [r.gen_ratio(1, 7), r.gen_ratio(1, 7), r.gen_ratio(1, 7)],
-                    [r.gen_ratio(1, 7), r.gen_ratio(1, 7), r.gen_ratio(1, 7)],
-                ],
-                shape::slice_full(),
-            ],
-            PlantPart::HeavyBranch { connectivity: from } => {
-                // light branch connections
-                let to = map.neighbouring(coords, |o| {
-                    o.block_tile.some_and(|t| {
-                        t.tree_origin() == origin && t.plant_part() == PlantPart::LightBranch
-                    })
-                });
-
-                #[rustfmt::skip]
-                let shape = [
-                    [
-                        [false, false, false],
-                        [false, to.a, false],
-                        [false, false, false],
-                    ],
-                    [
-                        [false, false, false],
-                        [false, to.a, false],
-                        [false, false, false],
-                    ],
-                    [
-                        [false, to.n | from.n, false],
-                        [to.w | from.w, true, to.e | from.e],
-                        [false, to.s | from.s, false],
-                    ],
-                    [
-                        [false, from.n, false],
-                        [from.w, false, from.e],
-                        [false, from.s, false],
-                    ],
-                    shape::slice_empty(),
-                ];
-
-                shape
-            }
-            PlantPart::LightBranch => {
-                let c = map.neighbouring(coords, |o| {
-                    o.block_tile.some_and(|t| {
-                        t.tree_origin() == origin
-                            && matches!(
-                                t.plant_part(),
-                                PlantPart::HeavyBranch { .. } | PlantPart::Twig
-                            )
-                    })
-                });
-
-                #[rustfmt::skip]
-                let shape = [
-                    [
-                        [false, false, false],
-                        [false, c.a, false],
-                        [false, false, false],
-                    ],
-                    [
-                        [false, c.n, false],
-                        [c.w, true, c.e],
-                        [false, c.s, false],
-                    ],
-                    [
-                        [false, false, false],
-                        [false, c.b, false],
-                        [false, false, false],
-                    ],
-                    [
-                        [false, false, false],
-                        [false, c.b, false],
-                        [false, false, false],
-                    ],
-                    [
-                        [false, false, false],
-                        [false, c.b, false],
-                        [false, false, false],
-                    ],
-                ];
-                shape
-            }
-            PlantPart::Twig => {
-                let c = map.neighbouring(coords, |o| {
-                    o.block_tile.some_and(|t| {
-                        t.tree_origin() == origin && t.plant_part() == PlantPart::LightBranch
-                    })
-                });
-
-                #[rustfmt::skip]
-                let shape = [
-                    [
-                        [false, c.n, false],
-                        [c.w, false, c.e],
-                        [false, c.s, false],
-                    ],
-                    shape::slice_empty(),
-                    shape::slice_empty(),
-                    shape::slice_empty(),
-                    [
-                        [false, false, false],
-                        [false, c.b, false],
-                        [false, false, false],
-                    ],
-                ];
-                shape
-            }
-        }
-    }
-
-    fn growth_shape(part: &PlantPart, r: &mut StdRng) -> Box3D<bool> {
-        match part {
-            PlantPart::Root | PlantPart::Trunk | PlantPart::Cap | PlantPart::HeavyBranch { .. } => {
-                [
-                    shape::slice_empty(),
-                    [
-                        [r.gen_ratio(1, 5), false, r.gen_ratio(1, 5)],
-                        [false, false, false],
-                        [r.gen_ratio(1, 5), false, r.gen_ratio(1, 5)],
-                    ],
-                    [
-                        [r.gen_ratio(1, 5), false, r.gen_ratio(1, 5)],
-                        [false, false, false],
-                        [r.gen_ratio(1, 5), false, r.gen_ratio(1, 5)],
-                    ],
-                    [
-                        [r.gen_ratio(1, 5), false, r.gen_ratio(1, 5)],
-                        [false, false, false],
-                        [r.gen_ratio(1, 5), false, r.gen_ratio(1, 5)],
-                    ],
-                    shape::slice_empty(),
-                ]
-            }
-            PlantPart::Twig | PlantPart::LightBranch => [
-                shape::slice_empty(),
-                [
-                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
-                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
-                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
-                ],
-                [
-                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
-                    [r.gen_ratio(1, 5), true, r.gen_ratio(1, 5)],
-                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
-                ],
-                [
-                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
-                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
-                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
-                ],
-                shape::slice_empty(),
-            ],
-            PlantPart::Sapling | PlantPart::Shrub => [
-                shape::slice_empty(),
-                shape::slice_empty(),
-                shape::slice_empty(),
-                [
-                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
-                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
-                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
-                ],
-                shape::slice_empty(),
-            ],
-        }
-    }
-
-    fn growth_materials(&self, part: &PlantPart, context: &DFContext) -> Vec<Material> {
-        let plant_index = self.material().mat_index();
-        if let Some(plant_raw) = context.plant_raws.plant_raws.get(plant_index as usize) {
-            plant_raw
-                .growths
-                .iter()
-                .filter(|growth| {
-                    growth.timing().contains(&context.settings.year_tick)
-                        && match part {
-                            PlantPart::Cap => growth.cap(),
-                            PlantPart::Root => growth.roots(),
-                            PlantPart::Sapling => growth.sapling(),
-                            PlantPart::Shrub => true,
-                            PlantPart::Trunk => growth.trunk(),
-                            PlantPart::HeavyBranch { .. } => growth.heavy_branches(),
-                            PlantPart::LightBranch => growth.light_branches(),
-                            PlantPart::Twig => growth.twigs(),
-                        }
-                })
-                .map(|growth| {
-                    let material = growth.mat.clone().unwrap_or_default();
-                    let current_print = growth
-                        .prints
-                        .iter()
-                        .find(|print| print.timing().contains(&context.settings.year_tick));
-                    let fresh_print = growth
-                        .prints
-                        .iter()
-                        .min_by_key(|print| print.timing_start());
-                    match (current_print, fresh_print) {
-                        (Some(current_print), Some(fresh_print)) => Material::Plant {
-                            material,
-                            source_color: fresh_print.get_console_color(),
-                            dest_color: current_print.get_console_color(),
-                        },
-                        _ => Material::Generic(material),
-                    }
-                })
-                .collect()
-        } else {
-            vec![]
-        }
-    }
-}
-
-#[derive(Debug, PartialEq)]
-pub enum PlantPart {
-    Root,
-    Sapling,
-    Shrub,
-    Trunk,
-    HeavyBranch {
-        connectivity: NeighbouringFlat<bool>,
-    },
-    LightBranch,
-    Twig,
-    Cap,
-}
-
-pub fn connectivity_from_direction_string(direction_string: &str) -> NeighbouringFlat<bool> {
-    let directions: Vec<DirectionFlat> = direction_string
-        .chars()
-        .filter_map(|c| match c {
-            'N' => Some(DirectionFlat::North),
-            'E' => Some(DirectionFlat::East),
-            'S' => Some(DirectionFlat::South),
-            'W' => Some(DirectionFlat::West),
-            _ => None,
-        })
-        .collect();
-    if directions.len() <= 1 {
-        // When there is a single direction, the direction indicates
-        // where the branch is heading, so the connectivity is opposite
-        NeighbouringFlat {
-            n: directions.contains(&DirectionFlat::South),
-            e: directions.contains(&DirectionFlat::West),
-            s: directions.contains(&DirectionFlat::North),
-            w: directions.contains(&DirectionFlat::East),
-        }
-    } else {
-        // When there are multiple direction, it indicates the connectivity
-        NeighbouringFlat {
-            n: directions.contains(&DirectionFlat::North),
-            e: directions.contains(&DirectionFlat::East),
-            s: directions.contains(&DirectionFlat::South),
-            w: directions.contains(&DirectionFlat::West),
-        }
-    }
-}
+use super::BlockTileExt;
+use crate::{
+    context::DFContext,
+    direction::{DirectionFlat, NeighbouringFlat},
+    map::Map,
+    palette::{DefaultMaterials, Material, Palette, GROWTH_BLEND_STEPS},
+    rfr::{BlockTile, ConsoleColor, GetTiming},
+    shape::{self, Box3D},
+    voxel::{voxels_from_shape, voxels_from_uniform_shape},
+    IsSomeAnd, StableRng,
+};
+use dfhack_remote::{MatPair, TiletypeSpecial};
+use easy_ext::ext;
+use itertools::Itertools;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng};
+use std::ops::RangeInclusive;
+
+#[ext(BlockTilePlantExt)]
+pub impl BlockTile<'_> {
+    fn build_trees(
+        &self,
+        map: &Map,
+        context: &DFContext,
+        palette: &Palette,
+    ) -> Vec<dot_vox::Voxel> {
+        let mut rng = self.stable_rng();
+        let part = self.plant_part();
+        let tile_type = self.tile_type();
+        let plant_index = self.material().mat_index();
+        let alive = !matches!(
+            tile_type.special(),
+            TiletypeSpecial::DEAD | TiletypeSpecial::SMOOTH_DEAD
+        );
+        // The "structure material" for plants looks like it's always an ugly default brown.
+        // For tree, in mat_type 420 is generally the wood, which is nicer.
+        // For other plants, use the hard-coded grass one.
+        let structure_material = match part {
+            PlantPart::Root
+            | PlantPart::HeavyBranch { .. }
+            | PlantPart::LightBranch
+            | PlantPart::Trunk => Material::Generic(MatPair {
+                mat_type: Some(420),
+                mat_index: Some(plant_index),
+                ..Default::default()
+            }),
+            _ => Material::Default(if alive {
+                DefaultMaterials::LightGrass
+            } else {
+                DefaultMaterials::DeadGrass
+            }),
+        };
+        let mut voxels = voxels_from_uniform_shape(
+            self.plant_structure_shape(&part, map),
+            self.local_coords(),
+            palette.get(&structure_material, context),
+        );
+        let growth_materials = self
+            .growth_materials(&part, context)
+            .into_iter()
+            .map(|(m, density)| (palette.get(&m, context), density))
+            .collect_vec();
+        if alive && !growth_materials.is_empty() && !context.settings.options.clean {
+            let growth = BlockTile::growth_shape(&part, &mut rng).map(|slice| {
+                slice.map(|col| {
+                    col.map(|t| {
+                        if t {
+                            growth_materials
+                                .choose(&mut rng)
+                                // Only thinned out growths draw, to keep the full ones as before
+                                .filter(|(_, density)| {
+                                    *density >= 1.0 || rng.gen::<f32>() < *density
+                                })
+                                .map(|(material, _)| *material)
+                        } else {
+                            None
+                        }
+                    })
+                })
+            });
+            voxels.append(&mut voxels_from_shape(growth, self.local_coords()));
+        }
+        voxels
+    }
+
+    fn plant_structure_shape(&self, part: &PlantPart, map: &Map) -> Box3D<bool> {
+        let mut r = self.stable_rng();
+        let coords = self.global_coords();
+        let origin = self.tree_origin();
+        // The horror
+        match part {
+            PlantPart::Root => shape::box_full(),
+            PlantPart::Trunk | PlantPart::Cap => {
+                let on_floor = coords == origin;
+                [
+                    [
+                        [false, true, false],
+                        [true, true, true],
+                        [false, true, false],
+                    ],
+                    [
+                        [false, true, false],
+                        [true, true, true],
+                        [false, true, false],
+                    ],
+                    [
+                        [false, true, false],
+                        [true, true, true],
+                        [false, true, false],
+                    ],
+                    [
+                        [false, true, false],
+                        [true, true, true],
+                        [false, true, false],
+                    ],
+                    [
+                        [on_floor, true, on_floor],
+                        [true, true, true],
+                        [on_floor, true, on_floor],
+                    ],
+                ]
+            }
+            PlantPart::Sapling | PlantPart::Shrub => [
+                shape::slice_empty(),
+                shape::slice_empty(),
+                shape::slice_empty(),
+                [
+                    [r.gen_ratio(1, 7), r.gen_ratio(1, 7), r.gen_ratio(1, 7)],
+                    [r.gen_ratio(1, 7), r.gen_ratio(1, 7), r.gen_ratio(1, 7)],
+                    [r.gen_ratio(1, 7), r.gen_ratio(1, 7), r.gen_ratio(1, 7)],
+                ],
+                shape::slice_full(),
+            ],
+            PlantPart::HeavyBranch { connectivity: from } => {
+                // light branch connections
+                let to = map.neighbouring(coords, |o| {
+                    o.block_tile.some_and(|t| {
+                        t.tree_origin() == origin && t.plant_part() == PlantPart::LightBranch
+                    })
+                });
+
+                #[rustfmt::skip]
+                let shape = [
+                    [
+                        [false, false, false],
+                        [false, to.a, false],
+                        [false, false, false],
+                    ],
+                    [
+                        [false, false, false],
+                        [false, to.a, false],
+                        [false, false, false],
+                    ],
+                    [
+                        [false, to.n | from.n, false],
+                        [to.w | from.w, true, to.e | from.e],
+                        [false, to.s | from.s, false],
+                    ],
+                    [
+                        [false, from.n, false],
+                        [from.w, false, from.e],
+                        [false, from.s, false],
+                    ],
+                    shape::slice_empty(),
+                ];
+
+                shape
+            }
+            PlantPart::LightBranch => {
+                let c = map.neighbouring(coords, |o| {
+                    o.block_tile.some_and(|t| {
+                        t.tree_origin() == origin
+                            && matches!(
+                                t.plant_part(),
+                                PlantPart::HeavyBranch { .. } | PlantPart::Twig
+                            )
+                    })
+                });
+
+                #[rustfmt::skip]
+                let shape = [
+                    [
+                        [false, false, false],
+                        [false, c.a, false],
+                        [false, false, false],
+                    ],
+                    [
+                        [false, c.n, false],
+                        [c.w, true, c.e],
+                        [false, c.s, false],
+                    ],
+                    [
+                        [false, false, false],
+                        [false, c.b, false],
+                        [false, false, false],
+                    ],
+                    [
+                        [false, false, false],
+                        [false, c.b, false],
+                        [false, false, false],
+                    ],
+                    [
+                        [false, false, false],
+                        [false, c.b, false],
+                        [false, false, false],
+                    ],
+                ];
+                shape
+            }
+            PlantPart::Twig => {
+                let c = map.neighbouring(coords, |o| {
+                    o.block_tile.some_and(|t| {
+                        t.tree_origin() == origin && t.plant_part() == PlantPart::LightBranch
+                    })
+                });
+
+                #[rustfmt::skip]
+                let shape = [
+                    [
+                        [false, c.n, false],
+                        [c.w, false, c.e],
+                        [false, c.s, false],
+                    ],
+                    shape::slice_empty(),
+                    shape::slice_empty(),
+                    shape::slice_empty(),
+                    [
+                        [false, false, false],
+                        [false, c.b, false],
+                        [false, false, false],
+                    ],
+                ];
+                shape
+            }
+        }
+    }
+
+    fn growth_shape(part: &PlantPart, r: &mut StdRng) -> Box3D<bool> {
+        match part {
+            PlantPart::Root | PlantPart::Trunk | PlantPart::Cap | PlantPart::HeavyBranch { .. } => {
+                [
+                    shape::slice_empty(),
+                    [
+                        [r.gen_ratio(1, 5), false, r.gen_ratio(1, 5)],
+                        [false, false, false],
+                        [r.gen_ratio(1, 5), false, r.gen_ratio(1, 5)],
+                    ],
+                    [
+                        [r.gen_ratio(1, 5), false, r.gen_ratio(1, 5)],
+                        [false, false, false],
+                        [r.gen_ratio(1, 5), false, r.gen_ratio(1, 5)],
+                    ],
+                    [
+                        [r.gen_ratio(1, 5), false, r.gen_ratio(1, 5)],
+                        [false, false, false],
+                        [r.gen_ratio(1, 5), false, r.gen_ratio(1, 5)],
+                    ],
+                    shape::slice_empty(),
+                ]
+            }
+            PlantPart::Twig | PlantPart::LightBranch => [
+                shape::slice_empty(),
+                [
+                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
+                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
+                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
+                ],
+                [
+                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
+                    [r.gen_ratio(1, 5), true, r.gen_ratio(1, 5)],
+                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
+                ],
+                [
+                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
+                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
+                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
+                ],
+                shape::slice_empty(),
+            ],
+            PlantPart::Sapling | PlantPart::Shrub => [
+                shape::slice_empty(),
+                shape::slice_empty(),
+                shape::slice_empty(),
+                [
+                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
+                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
+                    [r.gen_ratio(1, 5), r.gen_ratio(1, 5), r.gen_ratio(1, 5)],
+                ],
+                shape::slice_empty(),
+            ],
+        }
+    }
+
+    /// Materials of the growths of the plant part at the time of the export,
+    /// with the share of the growth voxels still present
+    fn growth_materials(&self, part: &PlantPart, context: &DFContext) -> Vec<(Material, f32)> {
+        let year_tick = context.settings.year_tick;
+        let blend = context.settings.options.growth_blend;
+        let plant_index = self.material().mat_index();
+        if let Some(plant_raw) = context.plant_raws.plant_raws.get(plant_index as usize) {
+            plant_raw
+                .growths
+                .iter()
+                .filter(|growth| {
+                    growth.timing().contains(&context.settings.year_tick)
+                        && match part {
+                            PlantPart::Cap => growth.cap(),
+                            PlantPart::Root => growth.roots(),
+                            PlantPart::Sapling => growth.sapling(),
+                            PlantPart::Shrub => true,
+                            PlantPart::Trunk => growth.trunk(),
+                            PlantPart::HeavyBranch { .. } => growth.heavy_branches(),
+                            PlantPart::LightBranch => growth.light_branches(),
+                            PlantPart::Twig => growth.twigs(),
+                        }
+                })
+                .map(|growth| {
+                    let material = growth.mat.clone().unwrap_or_default();
+                    let current_print = growth
+                        .prints
+                        .iter()
+                        .find(|print| print.timing().contains(&year_tick));
+                    let fresh_print = growth
+                        .prints
+                        .iter()
+                        .min_by_key(|print| print.timing_start());
+                    // Blend towards the next print, or thin out the growth before it falls
+                    let next_print = current_print.and_then(|current_print| {
+                        growth
+                            .prints
+                            .iter()
+                            .filter(|print| print.timing_start() > current_print.timing_end())
+                            .min_by_key(|print| print.timing_start())
+                    });
+                    let step = current_print
+                        .filter(|_| blend)
+                        .and_then(|print| timing_step(print.timing(), year_tick));
+                    let density = match (blend, next_print) {
+                        (true, None) => growth_density(growth.timing(), year_tick),
+                        _ => 1.0,
+                    };
+                    let material = match (current_print, fresh_print) {
+                        (Some(current_print), Some(fresh_print)) => Material::Plant {
+                            material,
+                            source_color: fresh_print.get_console_color(),
+                            dest_color: current_print.get_console_color(),
+                            blend: next_print
+                                .zip(step)
+                                .filter(|(_, step)| *step > 0)
+                                .map(|(print, step)| (print.get_console_color(), step)),
+                        },
+                        _ => Material::Generic(material),
+                    };
+                    (material, density)
+                })
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+}
+
+/// Progress through a timing, in steps of `GROWTH_BLEND_STEPS`. None if it lasts all year
+fn timing_step(timing: RangeInclusive<i32>, year_tick: i32) -> Option<u8> {
+    let (start, end) = (*timing.start(), *timing.end());
+    if start == i32::MIN || end == i32::MAX || end <= start {
+        return None;
+    }
+    let progress = (year_tick - start) as f32 / (end - start + 1) as f32;
+    let step = (progress.clamp(0.0, 1.0) * GROWTH_BLEND_STEPS as f32) as u8;
+    Some(step.min(GROWTH_BLEND_STEPS - 1))
+}
+
+/// Share of the growth voxels still present, thinning out during the last quarter
+/// of the growth timing
+fn growth_density(timing: RangeInclusive<i32>, year_tick: i32) -> f32 {
+    let (start, end) = (*timing.start(), *timing.end());
+    if start == i32::MIN || end == i32::MAX || end <= start {
+        return 1.0;
+    }
+    let fading = (end - start + 1) as f32 / 4.0;
+    ((end - year_tick) as f32 / fading).clamp(0.0, 1.0)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PlantPart {
+    Root,
+    Sapling,
+    Shrub,
+    Trunk,
+    HeavyBranch {
+        connectivity: NeighbouringFlat<bool>,
+    },
+    LightBranch,
+    Twig,
+    Cap,
+}
+
+pub fn connectivity_from_direction_string(direction_string: &str) -> NeighbouringFlat<bool> {
+    let directions: Vec<DirectionFlat> = direction_string
+        .chars()
+        .filter_map(|c| match c {
+            'N' => Some(DirectionFlat::North),
+            'E' => Some(DirectionFlat::East),
+            'S' => Some(DirectionFlat::South),
+            'W' => Some(DirectionFlat::West),
+            _ => None,
+        })
+        .collect();
+    if directions.len() <= 1 {
+        // When there is a single direction, the direction indicates
+        // where the branch is heading, so the connectivity is opposite
+        NeighbouringFlat {
+            n: directions.contains(&DirectionFlat::South),
+            e: directions.contains(&DirectionFlat::West),
+            s: directions.contains(&DirectionFlat::North),
+            w: directions.contains(&DirectionFlat::East),
+        }
+    } else {
+        // When there are multiple direction, it indicates the connectivity
+        NeighbouringFlat {
+            n: directions.contains(&DirectionFlat::North),
+            e: directions.contains(&DirectionFlat::East),
+            s: directions.contains(&DirectionFlat::South),
+            w: directions.contains(&DirectionFlat::West),
+        }
+    }
+}
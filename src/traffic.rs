@@ -0,0 +1,98 @@
+use crate::{
+    context::DFContext,
+    export::Cancel,
+    overlay::Overlay,
+    palette::{Material, Palette},
+    DFMapCoords, VoxelCoords, BASE,
+};
+use anyhow::Result;
+use dfhack_remote::UnitDefinition;
+use std::{
+    collections::HashMap,
+    ops::Range,
+    sync::mpsc::Receiver,
+    time::{Duration, Instant},
+};
+
+/// Unit flags of the ambushers still hidden from the player (`hidden_in_ambush`, `hidden_ambusher`)
+const HIDDEN_AMBUSHER_FLAGS: u32 = (1 << 18) | (1 << 21);
+
+/// Interval between two samples of the unit positions
+const TRAFFIC_POLL: Duration = Duration::from_millis(200);
+/// Colors of the heat map, from the least to the most visited tiles
+const HEAT_COLORS: [(u8, u8, u8); 4] =
+    [(40, 60, 200), (40, 200, 120), (240, 200, 40), (230, 40, 30)];
+
+/// Let the game run and count how many times the units were seen on each tile.
+/// The hidden ambushers and thieves are left out unless `ambushers` is set.
+/// None if the export was cancelled meanwhile
+pub fn sample_traffic(
+    client: &mut dfhack_remote::Client,
+    duration: Duration,
+    ambushers: bool,
+    cancel_rx: &Receiver<Cancel>,
+) -> Result<Option<HashMap<DFMapCoords, u32>>> {
+    let was_paused = client.remote_fortress_reader().get_pause_state()?;
+    client.remote_fortress_reader().set_pause_state(false)?;
+    let visits = count_visits(client, duration, ambushers, cancel_rx);
+    // Restored whatever happened, the sampling error is the most telling one
+    let restored = client.remote_fortress_reader().set_pause_state(was_paused);
+    let visits = visits?;
+    restored?;
+    Ok(visits)
+}
+
+/// Sampling loop of `sample_traffic`, with the game running
+fn count_visits(
+    client: &mut dfhack_remote::Client,
+    duration: Duration,
+    ambushers: bool,
+    cancel_rx: &Receiver<Cancel>,
+) -> Result<Option<HashMap<DFMapCoords, u32>>> {
+    let start = Instant::now();
+    let mut visits = HashMap::new();
+    while start.elapsed() < duration {
+        if cancel_rx.try_iter().next().is_some() {
+            return Ok(None);
+        }
+        let units = client.remote_fortress_reader().get_unit_list()?;
+        let visible = |unit: &&UnitDefinition| {
+            unit.is_valid() && (ambushers || unit.flags1() & HIDDEN_AMBUSHER_FLAGS == 0)
+        };
+        for unit in units.creature_list.iter().filter(visible) {
+            let coords = DFMapCoords::new(unit.pos_x(), unit.pos_y(), unit.pos_z());
+            *visits.entry(coords).or_insert(0) += 1;
+        }
+        std::thread::sleep(TRAFFIC_POLL);
+    }
+    Ok(Some(visits))
+}
+
+/// Heat map of the visits, laid on the floors
+pub fn build_traffic(
+    visits: &HashMap<DFMapCoords, u32>,
+    z_range: &Range<i32>,
+    context: &DFContext,
+    palette: &Palette,
+) -> Overlay {
+    let mut overlay = Overlay::default();
+    let Some(max) = visits.values().max() else {
+        return overlay;
+    };
+    let colors = HEAT_COLORS.map(|(r, g, b)| palette.get(&Material::Color(r, g, b), context));
+    for (coords, count) in visits {
+        if !z_range.contains(&coords.z) {
+            continue;
+        }
+        // Logarithmic scale, the main arteries are visited orders of magnitude more often
+        let heat = (*count as f32).ln_1p() / (*max as f32).ln_1p().max(f32::EPSILON);
+        let level = (heat * (colors.len() - 1) as f32).round() as usize;
+        let color = colors[level.min(colors.len() - 1)];
+        for sub_x in 0..BASE {
+            for sub_y in 0..BASE {
+                overlay.add(VoxelCoords::from_df(*coords, sub_x, sub_y, 1), color);
+            }
+        }
+    }
+    overlay
+}
@@ -1,86 +1,259 @@
-use std::{
-    path::PathBuf,
-    sync::mpsc::{Receiver, Sender},
-};
-
-use anyhow::Result;
-use serde::{Deserialize, Serialize};
-
-use crate::{
-    calendar::TimeOfTheYear,
-    export::{Cancel, Elevation, ExportParams, Progress},
-    FromDwarfFortress,
-};
-
-/// Command line interface
-pub mod cli;
-/// Graphical user interface
-#[cfg(feature = "gui")]
-pub mod gui;
-
-/// Serializable application state
-#[derive(Serialize, Deserialize)]
-#[serde(default)]
-pub struct State {
-    low_elevation: Elevation,
-    high_elevation: Elevation,
-    time: TimeOfTheYear,
-
-    #[serde(skip)]
-    error: Option<String>,
-    #[serde(skip)]
-    progress: Option<(Progress, Receiver<Progress>, Sender<Cancel>)>,
-    #[serde(skip)]
-    exported_path: Option<PathBuf>,
-    #[serde(skip)]
-    #[cfg(feature = "self-update")]
-    update_status: CheckUpdateStatus,
-}
-
-#[cfg(feature = "self-update")]
-enum CheckUpdateStatus {
-    NotDone,
-    Doing(Receiver<Result<crate::update::UpdateStatus>>),
-    Done(crate::update::UpdateStatus),
-}
-
-impl Default for State {
-    fn default() -> Self {
-        Self {
-            low_elevation: Elevation(0),
-            high_elevation: Elevation(10),
-            time: Default::default(),
-            error: Default::default(),
-            progress: Default::default(),
-            exported_path: Default::default(),
-            #[cfg(feature = "self-update")]
-            update_status: Default::default(),
-        }
-    }
-}
-
-impl State {
-    fn export_params(&self, path: PathBuf) -> ExportParams {
-        ExportParams {
-            elevation_low: self.low_elevation,
-            elevation_high: self.high_elevation,
-            time: self.time,
-            path,
-        }
-    }
-}
-
-#[cfg(feature = "self-update")]
-impl Default for CheckUpdateStatus {
-    fn default() -> Self {
-        Self::NotDone
-    }
-}
-
-impl FromDwarfFortress for TimeOfTheYear {
-    fn read_from_df(&mut self, _df: &mut dfhack_remote::Client) -> Result<()> {
-        // todo: refine for better display
-        *self = TimeOfTheYear::Current;
-        Ok(())
-    }
-}
+#[cfg(feature = "gui")]
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::mpsc::{Receiver, Sender},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    calendar::TimeOfTheYear,
+    export::{Cancel, Elevation, ExportOptions, ExportParams, ExportSummary, Progress},
+    FromDwarfFortress,
+};
+
+/// Command line interface
+pub mod cli;
+/// Graphical user interface
+#[cfg(feature = "gui")]
+pub mod gui;
+/// Export settings stored in a file
+pub mod job;
+/// Top-down preview of the export
+#[cfg(feature = "gui")]
+mod preview;
+/// Diagnostic bundle of the failed exports
+#[cfg(feature = "gui")]
+mod report;
+/// Jump list and file association on Windows
+#[cfg(feature = "gui")]
+mod shell;
+/// Connection to DFHack in a background thread
+#[cfg(feature = "gui")]
+mod worker;
+
+/// Open an exported model with the given application, or the one associated to the file
+pub fn open_model(path: &Path, viewer: Option<&Path>) -> Result<()> {
+    match viewer {
+        Some(viewer) => {
+            std::process::Command::new(viewer).arg(path).spawn()?;
+        }
+        None => opener::open(path)?,
+    }
+    Ok(())
+}
+
+/// Open each of the models of an export split in several files
+pub fn open_models(paths: &[PathBuf], viewer: Option<&Path>) -> Result<()> {
+    for path in paths {
+        open_model(path, viewer)?;
+    }
+    Ok(())
+}
+
+/// Serializable application state
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct State {
+    low_elevation: Elevation,
+    high_elevation: Elevation,
+    time: TimeOfTheYear,
+    options: ExportOptions,
+    /// Latest exports first
+    recent_exports: Vec<RecentExport>,
+    /// Application opening the exported models, the one associated to .vox files if not set
+    viewer: Option<PathBuf>,
+    /// Open the model once exported
+    open_when_done: bool,
+    /// Zoom of the interface, for a larger text
+    text_scale: f32,
+    /// Black and white theme with the focused widgets highlighted
+    high_contrast: bool,
+
+    #[serde(skip)]
+    error: Option<String>,
+    #[serde(skip)]
+    infos: Vec<String>,
+    #[serde(skip)]
+    warnings: Vec<String>,
+    /// Latest lines of the export log, for the issue reports
+    #[serde(skip)]
+    log: VecDeque<String>,
+    /// Include the map blocks in the issue reports
+    #[serde(skip)]
+    report_blocks: bool,
+    #[serde(skip)]
+    progress: Option<(Progress, Receiver<Progress>, Sender<Cancel>)>,
+    #[serde(skip)]
+    /// Files written by the latest export
+    exported_paths: Vec<PathBuf>,
+    /// Statistics of the latest export
+    #[serde(skip)]
+    summary: Option<ExportSummary>,
+    /// Export waiting for a confirmation, with the reason
+    #[serde(skip)]
+    confirm_export: Option<(PathBuf, String)>,
+    /// Export in progress, recorded in the recent exports once done
+    #[serde(skip)]
+    current_export: Option<RecentExport>,
+    #[serde(skip)]
+    #[cfg(feature = "self-update")]
+    update_status: CheckUpdateStatus,
+}
+
+/// Export done from the GUI, with the settings to run it again
+#[derive(Serialize, Deserialize, Clone)]
+struct RecentExport {
+    path: PathBuf,
+    low_elevation: Elevation,
+    high_elevation: Elevation,
+    time: TimeOfTheYear,
+    options: ExportOptions,
+    /// Seconds since the unix epoch
+    timestamp: u64,
+}
+
+const MAX_RECENT_EXPORTS: usize = 10;
+
+#[cfg(feature = "gui")]
+impl RecentExport {
+    /// Job running the export again
+    fn job(&self) -> job::Job {
+        job::Job {
+            low_elevation: self.low_elevation,
+            high_elevation: self.high_elevation,
+            time: self.time,
+            destination: self.path.clone(),
+            options: self.options.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "self-update")]
+enum CheckUpdateStatus {
+    NotDone,
+    Doing(Receiver<Result<crate::update::UpdateStatus>>),
+    Done(crate::update::UpdateStatus),
+    Installing(Receiver<Result<PathBuf>>),
+    Installed(PathBuf),
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            low_elevation: Elevation(0),
+            high_elevation: Elevation(10),
+            time: Default::default(),
+            options: Default::default(),
+            recent_exports: Default::default(),
+            viewer: Default::default(),
+            open_when_done: Default::default(),
+            text_scale: 1.0,
+            high_contrast: Default::default(),
+            error: Default::default(),
+            infos: Default::default(),
+            warnings: Default::default(),
+            log: Default::default(),
+            report_blocks: Default::default(),
+            progress: Default::default(),
+            exported_paths: Default::default(),
+            summary: Default::default(),
+            confirm_export: Default::default(),
+            current_export: Default::default(),
+            #[cfg(feature = "self-update")]
+            update_status: Default::default(),
+        }
+    }
+}
+
+impl State {
+    #[cfg(feature = "gui")]
+    fn start_export(&mut self, path: PathBuf, force: bool) {
+        let mut params = self.export_params(path.clone());
+        params.options.force = force;
+        let (progress_rx, cancel_tx, _) = crate::export::run_export_thread(params, None);
+        self.log.clear();
+        self.progress = Some((
+            Progress::undetermined("Connecting..."),
+            progress_rx,
+            cancel_tx,
+        ));
+        self.current_export = Some(RecentExport {
+            path,
+            low_elevation: self.low_elevation,
+            high_elevation: self.high_elevation,
+            time: self.time,
+            options: self.options.clone(),
+            timestamp: 0,
+        });
+    }
+
+    /// Keep a line of the export log, dropping the oldest ones
+    #[cfg(feature = "gui")]
+    fn log(&mut self, line: String) {
+        self.log.push_back(line);
+        while self.log.len() > report::LOG_LINES {
+            self.log.pop_front();
+        }
+    }
+
+    /// Add the export that just finished at the top of the recent exports
+    #[cfg(feature = "gui")]
+    fn record_export(&mut self) {
+        let Some(mut export) = self.current_export.take() else {
+            return;
+        };
+        export.timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        if cfg!(windows) {
+            if let Err(err) = shell::remember_job(&export.job()) {
+                self.warnings
+                    .push(format!("Failed to add the export to the jump list: {err}"));
+            }
+        }
+        self.recent_exports
+            .retain(|recent| recent.path != export.path);
+        self.recent_exports.insert(0, export);
+        self.recent_exports.truncate(MAX_RECENT_EXPORTS);
+    }
+
+    /// Restore the settings of a previous export and run it again
+    #[cfg(feature = "gui")]
+    fn export_again(&mut self, export: RecentExport) {
+        self.low_elevation = export.low_elevation;
+        self.high_elevation = export.high_elevation;
+        self.time = export.time;
+        self.options = export.options;
+        self.start_export(export.path, false);
+    }
+
+    fn export_params(&self, path: PathBuf) -> ExportParams {
+        ExportParams {
+            elevation_low: self.low_elevation,
+            elevation_high: self.high_elevation,
+            extra_ranges: Vec::new(),
+            time: self.time,
+            path,
+            options: self.options.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "self-update")]
+impl Default for CheckUpdateStatus {
+    fn default() -> Self {
+        Self::NotDone
+    }
+}
+
+impl FromDwarfFortress for TimeOfTheYear {
+    fn read_from_df(&mut self, _df: &mut dfhack_remote::Client) -> Result<()> {
+        // todo: refine for better display
+        *self = TimeOfTheYear::Current;
+        Ok(())
+    }
+}
@@ -1,118 +1,412 @@
-use crate::{
-    calendar::{Month, TimeOfTheYear},
-    export::{self, run_export_thread, Elevation, ExportParams},
-    rfr::DFHackExt,
-};
-use anyhow::Result;
-use indicatif::{ProgressBar, ProgressStyle};
-use std::path::PathBuf;
-use strum::IntoEnumIterator;
-
-#[cfg(feature = "dev")]
-pub mod dev;
-
-pub fn export(
-    low: Option<Elevation>,
-    high: Option<Elevation>,
-    path: PathBuf,
-    month: Option<Month>,
-) -> Result<()> {
-    let pb = ProgressBar::new(1);
-    pb.set_style(
-        ProgressStyle::with_template("[{elapsed_precise}] [{wide_bar:.cyan/blue}]")
-            .unwrap()
-            .progress_chars("#>-"),
-    );
-    let mut df = dfhack_remote::connect()?;
-    let time = match month {
-        Some(month) => TimeOfTheYear::Month(month),
-        None => TimeOfTheYear::Current,
-    };
-
-    let (elevation_low, elevation_high) = match (low, high) {
-        (Some(low), Some(high)) => (low, high),
-        (Some(elevation), None) | (None, Some(elevation)) => (elevation, elevation),
-        (None, None) => {
-            let elevation = Elevation(df.elevation()?);
-            (elevation, elevation)
-        }
-    };
-    let (progress_rx, _cancel_tx, handle) = run_export_thread(
-        ExportParams {
-            elevation_low,
-            elevation_high,
-            time,
-            path,
-        },
-        Some(df),
-    );
-
-    'outer: loop {
-        for progress in progress_rx.try_iter() {
-            match progress {
-                export::Progress::Undetermined { message } => {
-                    pb.println(message);
-                }
-                export::Progress::Start { message, total } => {
-                    pb.println(message);
-                    pb.set_length(total as u64);
-                }
-                export::Progress::Update {
-                    message: _,
-                    curr,
-                    total: _,
-                } => {
-                    pb.set_position(curr as u64);
-                }
-                export::Progress::Done { path } => {
-                    pb.println(format!("Sucessfully saved to {}", path.to_string_lossy()));
-                    pb.finish_and_clear();
-                    break 'outer;
-                }
-                export::Progress::Error(e) => {
-                    pb.println(e.to_string());
-                    pb.abandon();
-                    break 'outer;
-                }
-            }
-        }
-    }
-    handle.join().unwrap();
-    Ok(())
-}
-
-pub fn export_year(
-    elevation_low: Option<Elevation>,
-    elevation_high: Option<Elevation>,
-    destination: PathBuf,
-) -> Result<()> {
-    for (index, month) in Month::iter().enumerate() {
-        let mut destination = destination.clone();
-        destination.push(format!("{:02}-{}.vox", index + 1, month));
-        export(elevation_low, elevation_high, destination, Some(month))?;
-    }
-    Ok(())
-}
-
-#[cfg(feature = "self-update")]
-pub fn check_update() -> Result<()> {
-    use crate::update;
-    match update::check_update()? {
-        update::UpdateStatus::UpToDate => {
-            println!("Up to date");
-        }
-        update::UpdateStatus::NewVersion {
-            name,
-            release_url,
-            asset_url,
-        } => {
-            println!("Vox Uristi {name} is available");
-            println!("URL: {release_url}");
-            if let Some(asset_url) = asset_url {
-                println!("Download: {asset_url}");
-            }
-        }
-    };
-
-    Ok(())
-}
+use crate::{
+    block::BLOCK_SIZE,
+    building,
+    calendar::{Month, TimeOfTheYear},
+    export::{self, run_export_thread, Elevation, ExportOptions, ExportParams},
+    manifest::{CameraSuggestion, Timeline, TimelineFrame},
+    merge,
+    rfr::DFHackExt,
+    unit, BASE, HEIGHT,
+};
+use anyhow::{bail, Context, Result};
+use indicatif::{ProgressBar, ProgressStyle};
+use itertools::Itertools;
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use strum::IntoEnumIterator;
+
+/// Maximum time to load or unload a save
+const LOAD_TIMEOUT: Duration = Duration::from_secs(600);
+/// Interval between two checks of the trigger file
+const TRIGGER_POLL: Duration = Duration::from_millis(500);
+/// Levels exported above and below the fighting
+const SIEGE_DEPTH: i32 = 2;
+
+#[cfg(feature = "dev")]
+pub mod dev;
+
+/// Export the ranges, returning the path of the models saved
+pub fn export(
+    low: Option<Elevation>,
+    high: Option<Elevation>,
+    mut ranges: Vec<(Elevation, Elevation)>,
+    separate_ranges: bool,
+    path: PathBuf,
+    month: Option<Month>,
+    options: ExportOptions,
+) -> Result<Vec<PathBuf>> {
+    let mut df = dfhack_remote::connect()?;
+    let time = match month {
+        Some(month) => TimeOfTheYear::Month(month),
+        None => TimeOfTheYear::Current,
+    };
+
+    if low.is_some() || high.is_some() || ranges.is_empty() {
+        let range = match (low, high) {
+            (Some(low), Some(high)) => (low, high),
+            (Some(elevation), None) | (None, Some(elevation)) => (elevation, elevation),
+            (None, None) => {
+                let elevation = Elevation(df.elevation()?);
+                (elevation, elevation)
+            }
+        };
+        ranges.insert(0, range);
+    }
+
+    if !separate_ranges {
+        let (elevation_low, elevation_high) = ranges.remove(0);
+        let params = ExportParams {
+            elevation_low,
+            elevation_high,
+            extra_ranges: ranges,
+            time,
+            path,
+            options,
+        };
+        return run(params, df);
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    let mut df = Some(df);
+    let mut saved = Vec::new();
+    for (elevation_low, elevation_high) in ranges {
+        let params = ExportParams {
+            elevation_low,
+            elevation_high,
+            extra_ranges: Vec::new(),
+            time,
+            path: path.with_file_name(format!(
+                "{stem}_{elevation_low}-{elevation_high}.{extension}"
+            )),
+            options: options.clone(),
+        };
+        // The connection is consumed by each export
+        let df = match df.take() {
+            Some(df) => df,
+            None => dfhack_remote::connect()?,
+        };
+        saved.extend(run(params, df)?);
+    }
+    Ok(saved)
+}
+
+/// Run an export, displaying its progress. Returns the path of the saved models
+fn run(params: ExportParams, df: dfhack_remote::Client) -> Result<Vec<PathBuf>> {
+    let pb = ProgressBar::new(1);
+    pb.set_style(
+        ProgressStyle::with_template("[{elapsed_precise}] [{wide_bar:.cyan/blue}]")
+            .unwrap()
+            .progress_chars("#>-"),
+    );
+    let (progress_rx, _cancel_tx, handle) = run_export_thread(params, Some(df));
+
+    let mut saved = Vec::new();
+    'outer: loop {
+        for progress in progress_rx.try_iter() {
+            match progress {
+                export::Progress::Undetermined { message } => {
+                    pb.println(message);
+                }
+                export::Progress::Start { message, total } => {
+                    pb.println(message);
+                    pb.set_length(total as u64);
+                }
+                export::Progress::Update {
+                    message: _,
+                    curr,
+                    total: _,
+                } => {
+                    pb.set_position(curr as u64);
+                }
+                export::Progress::Done { paths } => {
+                    for path in &paths {
+                        pb.println(format!("Sucessfully saved to {}", path.to_string_lossy()));
+                    }
+                    pb.finish_and_clear();
+                    saved = paths;
+                    break 'outer;
+                }
+                export::Progress::Summary(summary) => {
+                    pb.println(summary.to_string());
+                }
+                export::Progress::Info(info) => {
+                    pb.println(info);
+                }
+                export::Progress::Warning(warning) => {
+                    pb.println(format!("Warning: {warning}"));
+                }
+                export::Progress::Error(e) => {
+                    pb.println(e.to_string());
+                    pb.abandon();
+                    break 'outer;
+                }
+            }
+        }
+    }
+    handle.join().unwrap();
+    Ok(saved)
+}
+
+pub fn export_year(
+    elevation_low: Option<Elevation>,
+    elevation_high: Option<Elevation>,
+    destination: PathBuf,
+    options: ExportOptions,
+) -> Result<()> {
+    let mut df = dfhack_remote::connect()?;
+    let year = df.remote_fortress_reader().get_world_map()?.cur_year();
+    let map_info = df.remote_fortress_reader().get_map_info()?;
+    let elevation = df.elevation()?;
+    let (low, high) = match (elevation_low, elevation_high) {
+        (Some(low), Some(high)) => (low.0, high.0),
+        (Some(Elevation(one)), None) | (None, Some(Elevation(one))) => (one, one),
+        (None, None) => (elevation, elevation),
+    };
+    let camera = CameraSuggestion::framing(
+        (map_info.block_size_x() * (BLOCK_SIZE * BASE) as i32) as f32,
+        (map_info.block_size_y() * (BLOCK_SIZE * BASE) as i32) as f32,
+        ((high - low + 1) * HEIGHT as i32) as f32,
+    );
+
+    // Each export opens its own connection
+    drop(df);
+    let mut timeline = Timeline::default();
+    for (index, month) in Month::iter().enumerate() {
+        let mut destination = destination.clone();
+        destination.push(format!("{:02}-{}.vox", index + 1, month));
+        let saved = export(
+            elevation_low,
+            elevation_high,
+            Vec::new(),
+            false,
+            destination,
+            Some(month),
+            options.clone(),
+        )?;
+        timeline
+            .frames
+            .extend(saved.iter().map(|path| TimelineFrame {
+                date: format!("{month} {year}"),
+                year,
+                year_tick: month.year_tick(),
+                file: path.file_name().map(PathBuf::from).unwrap_or_default(),
+                camera,
+            }));
+    }
+    timeline.write(&destination)?;
+    Ok(())
+}
+
+/// Load each save of a Dwarf Fortress save folder in turn, and export the current view
+/// of each fortress
+pub fn batch_saves(
+    saves: PathBuf,
+    destination: PathBuf,
+    unload: Option<String>,
+    options: ExportOptions,
+) -> Result<()> {
+    let names = std::fs::read_dir(&saves)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        // Leftover of the older versions of Dwarf Fortress
+        .filter(|name| name != "current")
+        .sorted()
+        .collect_vec();
+    if names.is_empty() {
+        bail!("No save found in {}", saves.display());
+    }
+    // Each save stays loaded after its export, checked before exporting any of them
+    if names.len() > 1 && unload.is_none() {
+        bail!(
+            "{} saves found in {}, use --unload to return to the title screen between them",
+            names.len(),
+            saves.display()
+        );
+    }
+    std::fs::create_dir_all(&destination)?;
+
+    for name in names {
+        let mut df = dfhack_remote::connect()?;
+        if df.map_loaded()? {
+            let Some(unload) = &unload else {
+                bail!("A world is already loaded. Go back to the title screen, or use --unload");
+            };
+            let mut arguments = unload.split_whitespace();
+            let command = arguments.next().unwrap_or_default();
+            run_and_wait(&mut df, command, &arguments.collect_vec(), false)?;
+        }
+        println!("Loading {name}...");
+        run_and_wait(&mut df, "load-save", &[&name], true)?;
+        export(
+            None,
+            None,
+            Vec::new(),
+            false,
+            destination.join(format!("{name}.vox")),
+            None,
+            options.clone(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Export the view each time the trigger file is created, until interrupted
+pub fn serve(
+    low: Option<Elevation>,
+    high: Option<Elevation>,
+    trigger: PathBuf,
+    destination: PathBuf,
+    options: ExportOptions,
+) -> Result<()> {
+    std::fs::create_dir_all(&destination)?;
+    println!("Waiting for {}...", trigger.display());
+    loop {
+        if !trigger.exists() {
+            std::thread::sleep(TRIGGER_POLL);
+            continue;
+        }
+        // Consume the request first, another one can be made during the export
+        std::fs::remove_file(&trigger)?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let path = destination.join(format!("screenshot-{timestamp}.vox"));
+        // A failed export, such as when no fortress is loaded, does not stop the service
+        if let Err(err) = export(low, high, Vec::new(), false, path, None, options.clone()) {
+            println!("Export failed: {err}");
+        }
+        println!("Waiting for {}...", trigger.display());
+    }
+}
+
+/// Export the levels around the invaders, with the units and the projectiles in flight,
+/// to a file named after the current time
+pub fn siege(destination: PathBuf, mut options: ExportOptions) -> Result<Vec<PathBuf>> {
+    let mut df = dfhack_remote::connect()?;
+    let invaders = unit::read_invaders(&mut df)?;
+    if invaders.is_empty() {
+        bail!("No invader on the map");
+    }
+    // Median level of the invaders, the stragglers do not move the export away from the fight
+    let levels = invaders
+        .iter()
+        .map(|coords| coords.z)
+        .sorted()
+        .collect_vec();
+    let center = Elevation(levels[levels.len() / 2] + df.elevation_offset()?);
+    drop(df);
+
+    options.units = true;
+    options.projectiles = true;
+    std::fs::create_dir_all(&destination)?;
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    export(
+        Some(center - SIEGE_DEPTH),
+        Some(center + SIEGE_DEPTH),
+        Vec::new(),
+        false,
+        destination.join(format!("siege-{timestamp}.vox")),
+        None,
+        options,
+    )
+}
+
+/// Export only the levels holding coffins or tombs, without the levels above them
+/// so that the burial halls are seen from above
+pub fn catacombs(path: PathBuf, options: ExportOptions) -> Result<Vec<PathBuf>> {
+    let mut df = dfhack_remote::connect()?;
+    let levels = building::read_burial_levels(&mut df)?;
+    if levels.is_empty() {
+        bail!("No coffin or tomb on the map");
+    }
+    let offset = df.elevation_offset()?;
+    drop(df);
+
+    // Consecutive levels are exported together
+    let ranges = levels
+        .into_iter()
+        .map(|level| (level, level))
+        .coalesce(|(low, high), (next, _)| {
+            if next == high + 1 {
+                Ok((low, next))
+            } else {
+                Err(((low, high), (next, next)))
+            }
+        })
+        .map(|(low, high)| (Elevation(low + offset), Elevation(high + offset)))
+        .collect();
+    export(None, None, ranges, false, path, None, options)
+}
+
+/// Merge several exports in a single scene
+pub fn merge(
+    manifests: &[PathBuf],
+    names: &[String],
+    offsets: &[(i32, i32, i32)],
+    destination: &Path,
+) -> Result<()> {
+    if merge::merge(manifests, names, offsets, destination)? {
+        println!("Warning: too many colors, some were replaced by the closest one");
+    }
+    println!("Successfully saved to {}", destination.display());
+    Ok(())
+}
+
+/// Wait for a fortress to be loaded or unloaded
+/// Run a DFHack command loading or unloading a world, and wait for it to take effect.
+/// The console output of the command explains why when it never does.
+fn run_and_wait(
+    df: &mut dfhack_remote::Client,
+    command: &str,
+    arguments: &[&str],
+    loaded: bool,
+) -> Result<()> {
+    let output = df
+        .run_command_output(command, arguments)
+        .with_context(|| format!("Running `{command}`"))?;
+    wait_for(df, loaded).with_context(|| match output.trim() {
+        "" => format!("`{command}` had no effect"),
+        output => format!("`{command}` had no effect: {output}"),
+    })
+}
+
+fn wait_for(df: &mut dfhack_remote::Client, loaded: bool) -> Result<()> {
+    let start = Instant::now();
+    // The game may not answer while it is loading
+    while !df.map_loaded().is_ok_and(|map_loaded| map_loaded == loaded) {
+        if start.elapsed() > LOAD_TIMEOUT {
+            bail!("Timed out while waiting for the save to load");
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "self-update")]
+pub fn check_update() -> Result<()> {
+    use crate::update;
+    match update::check_update()? {
+        update::UpdateStatus::UpToDate => {
+            println!("Up to date");
+        }
+        update::UpdateStatus::NewVersion {
+            name,
+            release_url,
+            asset,
+        } => {
+            println!("Vox Uristi {name} is available");
+            println!("URL: {release_url}");
+            if let Some(asset) = asset {
+                println!("Download: {}", asset.browser_download_url);
+            }
+        }
+    };
+
+    Ok(())
+}
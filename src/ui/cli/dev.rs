@@ -1,158 +1,642 @@
-use std::path::{Path, PathBuf};
-
-use anyhow::Result;
-use dfhack_remote::{BasicMaterialInfoMask, BlockRequest, ListMaterialsIn};
-use protobuf::{Message, MessageDyn, MessageField};
-
-use crate::{rfr, rfr::DFHackExt, DFCoords, DevCommand};
-
-pub fn run(cmd: DevCommand) -> Result<(), anyhow::Error> {
-    match cmd {
-        DevCommand::DumpLists { destination } => dump_lists(destination),
-        DevCommand::Probe { destination } => probe(destination),
-        DevCommand::RegenTestData => regen_test_data(),
-        DevCommand::SetElevation { elevation } => set_elevation(elevation),
-    }
-}
-
-pub fn probe(destination: PathBuf) -> Result<(), anyhow::Error> {
-    let mut client = dfhack_remote::connect()?;
-    let view_info = client.remote_fortress_reader().get_view_info()?;
-    let x = view_info.cursor_pos_x();
-    let y = view_info.cursor_pos_y();
-    let z = view_info.cursor_pos_z();
-    let tile_type_list = client.remote_fortress_reader().get_tiletype_list()?;
-    let probe = DFCoords::new(x, y, z);
-    for block_list in rfr::BlockListIterator::try_new(&mut client, 100, 0..1000, 0..1000, z..z + 1)?
-    {
-        for block in block_list?.map_blocks {
-            for tile in rfr::TileIterator::new(&block, &tile_type_list) {
-                if tile.coords() == probe {
-                    println!("{}", tile);
-                }
-            }
-
-            for (i, building) in block.buildings.into_iter().enumerate() {
-                let bx = building.pos_x_min()..=building.pos_x_max();
-                let by = building.pos_y_min()..=building.pos_y_max();
-                let bz = building.pos_z_min()..=building.pos_z_max();
-                if building.room.is_none() && bx.contains(&x) && by.contains(&y) && bz.contains(&z)
-                {
-                    dump(
-                        &building,
-                        &destination,
-                        format!("building_{i}.json").as_str(),
-                    )?;
-                }
-            }
-            for (i, flow) in block.flows.iter().enumerate() {
-                if DFCoords::from(flow.pos.get_or_default()) == probe {
-                    dump(flow, &destination, format!("flow_{i}.json").as_str())?;
-                }
-            }
-        }
-    }
-
-    Ok(())
-}
-
-fn regen_test_data() -> Result<(), anyhow::Error> {
-    let destination = PathBuf::from("testdata");
-    let mut client = dfhack_remote::connect()?;
-    client.remote_fortress_reader().reset_map_hashes()?;
-    let view_info = client.remote_fortress_reader().get_view_info()?;
-    let z = view_info.cursor_pos_z();
-    for (index, block_list) in
-        rfr::BlockListIterator::try_new(&mut client, 100, 0..1000, 0..1000, z..z + 1)?.enumerate()
-    {
-        let data = block_list?.write_to_bytes()?;
-        let mut dest = destination.clone();
-        dest.push(format!("block_{index}.dat"));
-        println!("{}", &dest.display());
-        std::fs::write(dest, data)?;
-    }
-
-    let building_defs = client.remote_fortress_reader().get_building_def_list()?;
-    let data = building_defs.write_to_bytes()?;
-    let mut dest = destination.clone();
-    dest.push("building_defs.dat");
-    println!("{}", &dest.display());
-    std::fs::write(dest, data)?;
-
-    Ok(())
-}
-
-fn dump_lists(destination: PathBuf) -> Result<()> {
-    let mut client = dfhack_remote::connect()?;
-
-    let req = ListMaterialsIn {
-        mask: MessageField::some(BasicMaterialInfoMask {
-            flags: Some(true),
-            reaction: Some(true),
-            ..Default::default()
-        }),
-        inorganic: Some(true),
-        builtin: Some(true),
-        ..Default::default()
-    };
-
-    let basic_materials = client.core().list_materials(req)?;
-    dump(&basic_materials, &destination, "basic_materials.json")?;
-
-    let materials = client.remote_fortress_reader().get_material_list()?;
-    dump(&materials, &destination, "materials.json")?;
-
-    let plants = client.remote_fortress_reader().get_plant_raws()?;
-    dump(&plants, &destination, "plant_raws.json")?;
-
-    let ttypes = client.remote_fortress_reader().get_tiletype_list()?;
-    dump(&ttypes, &destination, "tiletypes.json")?;
-
-    let building_defs = client.remote_fortress_reader().get_building_def_list()?;
-    dump(&building_defs, &destination, "building_defs.json")?;
-
-    let growth_list = client.remote_fortress_reader().get_growth_list()?;
-    dump(&growth_list, &destination, "growths.json")?;
-
-    let item_list = client.remote_fortress_reader().get_item_list()?;
-    dump(&item_list, &destination, "items.json")?;
-
-    let language = client.remote_fortress_reader().get_language()?;
-    dump(&language, &destination, "language.json")?;
-
-    let view_info = client.remote_fortress_reader().get_view_info()?;
-    client.remote_fortress_reader().reset_map_hashes()?;
-    let z = view_info.cursor_pos_z();
-    let req = BlockRequest {
-        blocks_needed: Some(1),
-        min_x: Some(0),
-        max_x: Some(1000),
-        min_y: Some(0),
-        max_y: Some(1000),
-        min_z: Some(z),
-        max_z: Some(z + 1),
-        ..Default::default()
-    };
-    let blocks = client.remote_fortress_reader().get_block_list(req)?;
-    dump(&blocks, &destination, "blocks.json")?;
-
-    let enums = client.core().list_enums()?;
-    dump(&enums, &destination, "enums.json")?;
-
-    Ok(())
-}
-
-fn dump(message: &dyn MessageDyn, folder: &Path, filename: &str) -> Result<()> {
-    let json = protobuf_json_mapping::print_to_string(message)?;
-    let mut dest = folder.to_path_buf();
-    dest.push(filename);
-    println!("{}", &dest.display());
-    std::fs::write(dest, json)?;
-    Ok(())
-}
-
-pub fn set_elevation(elevation: i32) -> Result<(), anyhow::Error> {
-    let mut client = dfhack_remote::connect()?;
-    client.set_elevation(elevation)?;
-    Ok(())
-}
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use dfhack_remote::{BasicMaterialInfoMask, BlockList, BlockRequest, ListMaterialsIn, MapBlock};
+use dot_vox::DotVoxData;
+use itertools::Itertools;
+use protobuf::{Message, MessageDyn, MessageField};
+use serde_json::Value;
+use strum::IntoEnumIterator;
+
+use crate::{
+    block::{BlockModels, BLOCK_VOX_SIZE},
+    building::BuildingInstanceExt,
+    capabilities::Capabilities,
+    context::{self, DFContext},
+    coords::DotVoxModelCoords,
+    dot_vox_builder::DotVoxBuilder,
+    export::{self, Cancel, ExportData, ExportOptions, ExportSettings, Layers, Progress},
+    map::Map,
+    overlay::Overlay,
+    palette::{Material, Palette},
+    prefabs, rfr,
+    rfr::DFHackExt,
+    DFMapCoords, DevCommand, VoxelCoords, WithDFCoords, BASE, HEIGHT,
+};
+
+pub fn run(cmd: DevCommand) -> Result<(), anyhow::Error> {
+    match cmd {
+        DevCommand::DumpLists {
+            destination,
+            compare,
+        } => {
+            dump_lists(destination.clone())?;
+            match compare {
+                Some(older) => compare_dumps(&older, &destination),
+                None => Ok(()),
+            }
+        }
+        DevCommand::Probe { destination } => probe(destination),
+        DevCommand::ViewBuilding {
+            building_id,
+            destination,
+        } => view_building(building_id, &destination),
+        DevCommand::RegenTestData {
+            lua,
+            at,
+            advance_ticks,
+        } => regen_test_data(lua, at, advance_ticks),
+        DevCommand::Lua { script } => lua(&script),
+        DevCommand::Teleport { x, y, z } => {
+            dfhack_remote::connect()?.teleport(DFMapCoords::new(x, y, z))?;
+            Ok(())
+        }
+        DevCommand::AdvanceTime { ticks } => {
+            dfhack_remote::connect()?.advance_time(ticks)?;
+            Ok(())
+        }
+        DevCommand::CheckPrefabs => check_prefabs(),
+        DevCommand::Bench { iterations } => bench(iterations),
+        DevCommand::SetElevation { elevation } => set_elevation(elevation),
+    }
+}
+
+pub fn probe(destination: PathBuf) -> Result<(), anyhow::Error> {
+    let mut client = dfhack_remote::connect()?;
+    let view_info = client.remote_fortress_reader().get_view_info()?;
+    let x = view_info.cursor_pos_x();
+    let y = view_info.cursor_pos_y();
+    let z = view_info.cursor_pos_z();
+    let tile_type_list = client.remote_fortress_reader().get_tiletype_list()?;
+    let probe = DFMapCoords::new(x, y, z);
+    for block_list in rfr::BlockListIterator::try_new(&mut client, 100, 0..1000, 0..1000, z..z + 1)?
+    {
+        for block in block_list?.map_blocks {
+            for tile in rfr::TileIterator::new(&block, &tile_type_list) {
+                if tile.coords() == probe {
+                    println!("{}", tile);
+                }
+            }
+
+            for (i, building) in block.buildings.into_iter().enumerate() {
+                let bx = building.pos_x_min()..=building.pos_x_max();
+                let by = building.pos_y_min()..=building.pos_y_max();
+                let bz = building.pos_z_min()..=building.pos_z_max();
+                if building.room.is_none() && bx.contains(&x) && by.contains(&y) && bz.contains(&z)
+                {
+                    dump(
+                        &building,
+                        &destination,
+                        format!("building_{i}.json").as_str(),
+                    )?;
+                }
+            }
+            for (i, flow) in block.flows.iter().enumerate() {
+                if DFMapCoords::from(flow.pos.get_or_default()) == probe {
+                    dump(flow, &destination, format!("flow_{i}.json").as_str())?;
+                }
+            }
+        }
+    }
+
+    probe_vox(&mut client, probe, &destination)
+}
+
+/// Export the probed tile and its 26 neighbours, as they would be in a real export
+fn probe_vox(
+    client: &mut dfhack_remote::Client,
+    probe: DFMapCoords,
+    destination: &Path,
+) -> Result<()> {
+    let settings = ExportSettings {
+        year_tick: client
+            .remote_fortress_reader()
+            .get_world_map()?
+            .cur_year_tick(),
+        options: ExportOptions::default(),
+    };
+    let capabilities = Capabilities::probe(client)?;
+    let context = DFContext::try_new(client, settings, capabilities)?;
+    let z_range = (probe.z - 1)..(probe.z + 2);
+    let mut blocks = Vec::new();
+    for block_list in
+        rfr::BlockListIterator::try_new(client, 100, 0..1000, 0..1000, z_range.clone())?
+    {
+        blocks.extend(block_list?.map_blocks);
+    }
+    let mut map = Map::default();
+    for block in &blocks {
+        map.add_block(block, &context);
+    }
+    let near = |coords: DFMapCoords| {
+        (coords.x - probe.x).abs() <= 1
+            && (coords.y - probe.y).abs() <= 1
+            && (coords.z - probe.z).abs() <= 1
+    };
+
+    let palette = Palette::default();
+    palette.cache_default_materials(&context);
+    let mut vox = DotVoxBuilder::default();
+    vox.set_root_name(format!("probe {} {} {}", probe.x, probe.y, probe.z));
+    for layer in Layers::iter() {
+        vox.data.layers[*layer.id()]
+            .attributes
+            .insert("_name".to_string(), format!("{}", layer).to_lowercase());
+    }
+    let min_z = z_range.start * HEIGHT as i32;
+
+    // Tiles, with the voxels moved from their block model to the map
+    let mut overlays: HashMap<Layers, Overlay> = HashMap::new();
+    for block in &blocks {
+        for tile in rfr::TileIterator::new(block, &context.tile_types) {
+            let coords = tile.global_coords();
+            if !near(coords) {
+                continue;
+            }
+            let mut models = BlockModels::default();
+            tile.build(&mut models, &map, &context, &palette);
+            for (layer, model) in models.models {
+                let overlay = overlays.entry(layer).or_default();
+                for voxel in model.voxels {
+                    let coords = VoxelCoords::new(
+                        block.map_x() * BASE as i32 + voxel.x as i32,
+                        block.map_y() * BASE as i32 + BLOCK_VOX_SIZE.y as i32 - 1 - voxel.y as i32,
+                        coords.z * HEIGHT as i32 + voxel.z as i32,
+                    );
+                    overlay.add(coords, voxel.i);
+                }
+            }
+        }
+    }
+    let root_group = vox.root_group;
+    for (layer, overlay) in overlays.into_iter().sorted_by_key(|(layer, _)| *layer) {
+        let name = layer.to_string().to_lowercase();
+        overlay.insert(&mut vox, root_group, layer.id(), &name, &context, min_z);
+    }
+
+    // Buildings covering one of the tiles
+    for (level, level_data) in map.levels.iter().sorted_by_key(|(l, _)| *l) {
+        let z = HEIGHT as i32 / 2 + level * HEIGHT as i32 - min_z;
+        let level_group = vox.insert_group_node_simple(
+            vox.root_group,
+            format!("level {level}"),
+            Some(DotVoxModelCoords::new(0, 0, z)),
+            Layers::All.id(),
+        );
+        for building in &level_data.buildings {
+            let covers = (building.pos_x_min() - 1..=building.pos_x_max() + 1).contains(&probe.x)
+                && (building.pos_y_min() - 1..=building.pos_y_max() + 1).contains(&probe.y);
+            if covers && near(DFMapCoords::new(probe.x, probe.y, *level)) {
+                building.build(&map, &context, &mut vox, &palette, level_group);
+            }
+        }
+    }
+
+    let mut vox: DotVoxData = vox.into();
+    palette.write_palette(&mut vox);
+    let dest = destination.join("probe.vox");
+    println!("{}", &dest.display());
+    vox.write_vox(&mut std::fs::File::create(dest)?)?;
+    Ok(())
+}
+
+/// Export a single building with its content, on a plinth one tile wider than the building
+fn view_building(building_id: i32, destination: &Path) -> Result<()> {
+    let mut client = dfhack_remote::connect()?;
+    let position = client.lua_output(&format!(
+        r#"local building = df.building.find({building_id})
+if building then emit(building.z) end"#
+    ))?;
+    let Ok(z) = position.trim().parse::<i32>() else {
+        bail!("No building with the id {building_id}");
+    };
+    let settings = ExportSettings {
+        year_tick: client
+            .remote_fortress_reader()
+            .get_world_map()?
+            .cur_year_tick(),
+        options: ExportOptions::default(),
+    };
+    let capabilities = Capabilities::probe(&mut client)?;
+    let context = DFContext::try_new(&mut client, settings, capabilities)?;
+    // The levels around are read for the connections to the walls and floors
+    let z_range = (z - 1)..(z + 2);
+    let mut blocks = Vec::new();
+    for block_list in
+        rfr::BlockListIterator::try_new(&mut client, 100, 0..1000, 0..1000, z_range.clone())?
+    {
+        blocks.extend(block_list?.map_blocks);
+    }
+    let mut map = Map::default();
+    for block in &blocks {
+        map.add_block(block, &context);
+    }
+    let Some(building) = map.levels.get(&z).and_then(|level| {
+        level
+            .buildings
+            .iter()
+            .find(|building| building.index() == building_id)
+    }) else {
+        bail!("The building {building_id} is not visible on the map");
+    };
+
+    let palette = Palette::default();
+    palette.cache_default_materials(&context);
+    let mut vox = DotVoxBuilder::default();
+    let root_group = vox.root_group;
+    vox.set_root_name(format!("building {building_id}"));
+    let min_z = z_range.start * HEIGHT as i32;
+
+    let mut plinth = Overlay::default();
+    let stone = palette.get(&Material::Color(110, 110, 110), &context);
+    for x in building.pos_x_min() - 1..=building.pos_x_max() + 1 {
+        for y in building.pos_y_min() - 1..=building.pos_y_max() + 1 {
+            for sub_x in 0..BASE {
+                for sub_y in 0..BASE {
+                    let coords = DFMapCoords::new(x, y, z - 1);
+                    plinth.add(
+                        VoxelCoords::from_df(coords, sub_x, sub_y, HEIGHT - 1),
+                        stone,
+                    );
+                }
+            }
+        }
+    }
+    plinth.insert(
+        &mut vox,
+        root_group,
+        Layers::Terrain.id(),
+        "plinth",
+        &context,
+        min_z,
+    );
+
+    let level_group = vox.insert_group_node_simple(
+        root_group,
+        format!("level {z}"),
+        Some(DotVoxModelCoords::new(
+            0,
+            0,
+            HEIGHT as i32 / 2 + z * HEIGHT as i32 - min_z,
+        )),
+        Layers::All.id(),
+    );
+    building.build(&map, &context, &mut vox, &palette, level_group);
+
+    let mut vox: DotVoxData = vox.into();
+    palette.write_palette(&mut vox);
+    let dest = destination.join(format!("building_{building_id}.vox"));
+    println!("{}", &dest.display());
+    vox.write_vox(&mut std::fs::File::create(dest)?)?;
+    Ok(())
+}
+
+fn regen_test_data(
+    lua: Option<PathBuf>,
+    at: Option<Vec<i32>>,
+    advance_ticks: Option<u32>,
+) -> Result<(), anyhow::Error> {
+    let destination = PathBuf::from("testdata");
+    let mut client = dfhack_remote::connect()?;
+    // Scripted setup, to make the datasets reproducible
+    if let Some(lua) = lua {
+        client.run_lua(&std::fs::read_to_string(lua)?)?;
+    }
+    if let Some([x, y, z]) = at.as_deref() {
+        client.teleport(DFMapCoords::new(*x, *y, *z))?;
+    }
+    if let Some(ticks) = advance_ticks {
+        client.advance_time(ticks)?;
+    }
+    client.remote_fortress_reader().reset_map_hashes()?;
+    let view_info = client.remote_fortress_reader().get_view_info()?;
+    let z = view_info.cursor_pos_z();
+    for (index, block_list) in
+        rfr::BlockListIterator::try_new(&mut client, 100, 0..1000, 0..1000, z..z + 1)?.enumerate()
+    {
+        let data = block_list?.write_to_bytes()?;
+        let mut dest = destination.clone();
+        dest.push(format!("block_{index}.dat"));
+        println!("{}", &dest.display());
+        std::fs::write(dest, data)?;
+    }
+
+    let building_defs = client.remote_fortress_reader().get_building_def_list()?;
+    write_dat(&building_defs, &destination, "building_defs.dat")?;
+
+    // Lists needed to run the export offline
+    let tile_types = client.remote_fortress_reader().get_tiletype_list()?;
+    write_dat(&tile_types, &destination, "tiletypes.dat")?;
+    let materials = client.remote_fortress_reader().get_material_list()?;
+    write_dat(&materials, &destination, "materials.dat")?;
+    let map_info = client.remote_fortress_reader().get_map_info()?;
+    write_dat(&map_info, &destination, "map_info.dat")?;
+    let plant_raws = client.remote_fortress_reader().get_plant_raws()?;
+    write_dat(&plant_raws, &destination, "plant_raws.dat")?;
+    let enums = client.core().list_enums()?;
+    write_dat(&enums, &destination, "enums.dat")?;
+    let inorganics = client
+        .core()
+        .list_materials(context::inorganic_materials_request())?;
+    write_dat(&inorganics, &destination, "inorganics.dat")?;
+
+    Ok(())
+}
+
+fn lua(script: &str) -> Result<()> {
+    let mut client = dfhack_remote::connect()?;
+    print!("{}", client.lua_output(script)?);
+    Ok(())
+}
+
+fn check_prefabs() -> Result<()> {
+    let problems = prefabs::lint();
+    for problem in &problems {
+        println!("{problem}");
+    }
+    if !problems.is_empty() {
+        bail!("{} problems found in the prefabs", problems.len());
+    }
+    println!("All the prefabs are valid");
+    Ok(())
+}
+
+fn write_dat(message: &dyn MessageDyn, folder: &Path, filename: &str) -> Result<()> {
+    let dest = folder.join(filename);
+    println!("{}", &dest.display());
+    std::fs::write(dest, message.write_to_bytes_dyn()?)?;
+    Ok(())
+}
+
+/// Min and mean durations of a phase over all the runs
+struct Timings {
+    name: &'static str,
+    runs: Vec<Duration>,
+}
+
+impl Timings {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            runs: Vec::new(),
+        }
+    }
+
+    fn time<T>(&mut self, func: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = func();
+        self.runs.push(start.elapsed());
+        result
+    }
+
+    fn min(&self) -> Duration {
+        self.runs.iter().min().copied().unwrap_or_default()
+    }
+
+    fn mean(&self) -> Duration {
+        self.runs.iter().sum::<Duration>() / self.runs.len().max(1) as u32
+    }
+}
+
+fn bench(iterations: usize) -> Result<()> {
+    let folder = Path::new("testdata");
+    let block_files = std::fs::read_dir(folder)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("block_"))
+        })
+        .sorted()
+        .collect_vec();
+    let read_blocks = || -> Result<Vec<MapBlock>> {
+        let mut blocks = Vec::new();
+        for path in &block_files {
+            blocks.extend(BlockList::parse_from_bytes(&std::fs::read(path)?)?.map_blocks);
+        }
+        Ok(blocks)
+    };
+    let settings = ExportSettings {
+        year_tick: 0,
+        options: ExportOptions::default(),
+    };
+    let context = DFContext::from_test_data(folder, settings)?;
+    let mut data = ExportData::from_test_data(context, read_blocks()?);
+    let path = std::env::temp_dir().join(format!("vox-uristi-bench-{}.vox", std::process::id()));
+    // The same pipeline as the exports, reporting to nobody and never cancelled
+    let (mut progress, _) = std::sync::mpsc::channel::<Progress>();
+    let (_cancel_tx, cancel_rx) = std::sync::mpsc::channel::<Cancel>();
+
+    let mut parse = Timings::new("parse");
+    let mut assemble = Timings::new("assemble");
+    let mut build = Timings::new("build");
+    let mut write = Timings::new("write");
+    let mut voxel_count = 0;
+    for iteration in 0..iterations {
+        println!("Run {}/{iterations}", iteration + 1);
+        data.blocks = parse.time(read_blocks)?;
+        let map = assemble.time(|| data.assemble(&mut progress));
+        let Some(scene) =
+            build.time(|| export::build_scene(&data, &map, &mut progress, &cancel_rx))?
+        else {
+            bail!("The benchmark was cancelled");
+        };
+        voxel_count = scene
+            .vox
+            .data
+            .models
+            .iter()
+            .map(|model| model.voxels.len())
+            .sum::<usize>();
+        write.time(|| export::save_scene(scene, &data, &map, &[], path.clone(), &mut progress))?;
+    }
+    let _ = std::fs::remove_file(&path);
+
+    println!();
+    for timings in [&parse, &assemble, &build, &write] {
+        println!(
+            "{:<10} min {:>10.2?} mean {:>10.2?}",
+            timings.name,
+            timings.min(),
+            timings.mean()
+        );
+    }
+    let build_seconds = build.mean().as_secs_f64();
+    if build_seconds > 0.0 {
+        let throughput = voxel_count as f64 / build_seconds;
+        println!("{voxel_count} voxels, {throughput:.0} voxels/s");
+    }
+    Ok(())
+}
+
+fn dump_lists(destination: PathBuf) -> Result<()> {
+    let mut client = dfhack_remote::connect()?;
+
+    let req = ListMaterialsIn {
+        mask: MessageField::some(BasicMaterialInfoMask {
+            flags: Some(true),
+            reaction: Some(true),
+            ..Default::default()
+        }),
+        inorganic: Some(true),
+        builtin: Some(true),
+        ..Default::default()
+    };
+
+    let basic_materials = client.core().list_materials(req)?;
+    dump(&basic_materials, &destination, "basic_materials.json")?;
+
+    let materials = client.remote_fortress_reader().get_material_list()?;
+    dump(&materials, &destination, "materials.json")?;
+
+    let plants = client.remote_fortress_reader().get_plant_raws()?;
+    dump(&plants, &destination, "plant_raws.json")?;
+
+    let ttypes = client.remote_fortress_reader().get_tiletype_list()?;
+    dump(&ttypes, &destination, "tiletypes.json")?;
+
+    let building_defs = client.remote_fortress_reader().get_building_def_list()?;
+    dump(&building_defs, &destination, "building_defs.json")?;
+
+    let growth_list = client.remote_fortress_reader().get_growth_list()?;
+    dump(&growth_list, &destination, "growths.json")?;
+
+    let item_list = client.remote_fortress_reader().get_item_list()?;
+    dump(&item_list, &destination, "items.json")?;
+
+    let language = client.remote_fortress_reader().get_language()?;
+    dump(&language, &destination, "language.json")?;
+
+    let view_info = client.remote_fortress_reader().get_view_info()?;
+    client.remote_fortress_reader().reset_map_hashes()?;
+    let z = view_info.cursor_pos_z();
+    let req = BlockRequest {
+        blocks_needed: Some(1),
+        min_x: Some(0),
+        max_x: Some(1000),
+        min_y: Some(0),
+        max_y: Some(1000),
+        min_z: Some(z),
+        max_z: Some(z + 1),
+        ..Default::default()
+    };
+    let blocks = client.remote_fortress_reader().get_block_list(req)?;
+    dump(&blocks, &destination, "blocks.json")?;
+
+    let enums = client.core().list_enums()?;
+    dump(&enums, &destination, "enums.json")?;
+
+    Ok(())
+}
+
+fn dump(message: &dyn MessageDyn, folder: &Path, filename: &str) -> Result<()> {
+    let json = protobuf_json_mapping::print_to_string(message)?;
+    let mut dest = folder.to_path_buf();
+    dest.push(filename);
+    println!("{}", &dest.display());
+    std::fs::write(dest, json)?;
+    Ok(())
+}
+
+/// Changes listed for each file, the others are only counted
+const LISTED_CHANGES: usize = 20;
+
+/// Change of a single field between two dumps
+enum FieldChange {
+    Added(String),
+    Removed(String),
+    Changed(String),
+}
+
+/// Summarize the changes between the .json files of two dumps
+fn compare_dumps(older: &Path, newer: &Path) -> Result<()> {
+    let files = std::fs::read_dir(newer)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name())
+        .filter(|name| name.to_string_lossy().ends_with(".json"))
+        .sorted()
+        .collect_vec();
+    for file in files {
+        let name = file.to_string_lossy();
+        let older_path = older.join(&file);
+        if !older_path.exists() {
+            println!("{name}: new file");
+            continue;
+        }
+        let read = |path: &Path| -> Result<Value> {
+            Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+        };
+        let mut changes = Vec::new();
+        diff_json(
+            "",
+            &read(&older_path)?,
+            &read(&newer.join(&file))?,
+            &mut changes,
+        );
+        if changes.is_empty() {
+            println!("{name}: unchanged");
+            continue;
+        }
+        let (mut added, mut removed, mut changed) = (0, 0, 0);
+        for change in &changes {
+            match change {
+                FieldChange::Added(_) => added += 1,
+                FieldChange::Removed(_) => removed += 1,
+                FieldChange::Changed(_) => changed += 1,
+            }
+        }
+        println!("{name}: {added} added, {removed} removed, {changed} changed");
+        for change in changes.iter().take(LISTED_CHANGES) {
+            match change {
+                FieldChange::Added(path) => println!("  + {path}"),
+                FieldChange::Removed(path) => println!("  - {path}"),
+                FieldChange::Changed(path) => println!("  ~ {path}"),
+            }
+        }
+        if changes.len() > LISTED_CHANGES {
+            println!("  ... and {} more", changes.len() - LISTED_CHANGES);
+        }
+    }
+    Ok(())
+}
+
+/// Collect the fields that differ between two json values, by path
+fn diff_json(path: &str, older: &Value, newer: &Value, changes: &mut Vec<FieldChange>) {
+    match (older, newer) {
+        (Value::Object(older), Value::Object(newer)) => {
+            for (key, value) in older {
+                let field = format!("{path}.{key}");
+                match newer.get(key) {
+                    Some(new_value) => diff_json(&field, value, new_value, changes),
+                    None => changes.push(FieldChange::Removed(field)),
+                }
+            }
+            for key in newer.keys().filter(|key| !older.contains_key(*key)) {
+                changes.push(FieldChange::Added(format!("{path}.{key}")));
+            }
+        }
+        (Value::Array(older), Value::Array(newer)) => {
+            for (index, value) in older.iter().enumerate() {
+                let field = format!("{path}[{index}]");
+                match newer.get(index) {
+                    Some(new_value) => diff_json(&field, value, new_value, changes),
+                    None => changes.push(FieldChange::Removed(field)),
+                }
+            }
+            for index in older.len()..newer.len() {
+                changes.push(FieldChange::Added(format!("{path}[{index}]")));
+            }
+        }
+        (older, newer) if older != newer => changes.push(FieldChange::Changed(path.to_string())),
+        _ => {}
+    }
+}
+
+pub fn set_elevation(elevation: i32) -> Result<(), anyhow::Error> {
+    let mut client = dfhack_remote::connect()?;
+    client.set_elevation(elevation)?;
+    Ok(())
+}
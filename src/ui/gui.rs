@@ -1,359 +1,1358 @@
-use crate::{
-    calendar::{Month, TimeOfTheYear},
-    export::{run_export_thread, Cancel, Elevation, Progress},
-    FromDwarfFortress,
-};
-use anyhow::{anyhow, Context, Result};
-use eframe::{
-    egui::{self, Button, DragValue, ProgressBar, Response, RichText, Ui},
-    epaint::Vec2,
-};
-use serde::{Deserialize, Serialize};
-use strum::IntoEnumIterator;
-
-use super::State;
-use crate::ui;
-use crate::VERSION;
-
-const ICON: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/icon"));
-
-pub fn run() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
-    let options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default()
-            .with_inner_size([320.0, 240.0])
-            .with_icon(egui::IconData {
-                rgba: ICON.to_vec(),
-                width: 256,
-                height: 256,
-            }),
-        ..Default::default()
-    };
-    match eframe::run_native(
-        format!("Vox Uristi v{VERSION}").as_str(),
-        options,
-        Box::new(|cc| Box::<App>::new(App::new(cc))),
-    ) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(anyhow::format_err!("{}", e.to_string())),
-    }
-}
-
-#[derive(Serialize, Deserialize)]
-#[serde(default)]
-pub struct App {
-    state: crate::ui::State,
-    #[serde(skip)]
-    df: Result<dfhack_remote::Client>,
-}
-
-impl App {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        if let Some(storage) = cc.storage {
-            return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
-        }
-
-        Default::default()
-    }
-
-    fn central_panel(&mut self, ui: &mut Ui, ctx: &egui::Context) {
-        ui.heading("☀Vox Uristi☀");
-
-        let mut canceled = false;
-        match &mut self.state.progress {
-            Some((progress, rx, tx)) => {
-                ctx.request_repaint();
-                if ui.button("Cancel").clicked() {
-                    canceled = true;
-                    if let Err(err) = tx.send(Cancel) {
-                        self.state.error = Some(format!("Failed to cancel: {err}"));
-                    }
-                }
-                if let Some(new_progress) = rx.try_iter().last() {
-                    *progress = new_progress;
-                }
-                ui.label("Do not unpause the game during the export.");
-                match progress {
-                    Progress::Undetermined { message } => {
-                        ui.label(*message);
-                        ui.spinner();
-                    }
-                    Progress::Update {
-                        message,
-                        curr,
-                        total,
-                    } => {
-                        ui.add(ProgressBar::new(*curr as f32 / *total as f32).text(*message));
-                    }
-                    Progress::Start {
-                        message: _,
-                        total: _,
-                    } => {}
-                    Progress::Done { path } => {
-                        self.state.exported_path = Some(path.to_path_buf());
-                        self.state.progress = None;
-                    }
-                    Progress::Error(err) => {
-                        self.state.error = Some(err.to_string());
-                        self.state.progress = None;
-                    }
-                }
-            }
-            None => {
-                ui.group(|ui| {
-                    ui.add(df_client_group(&mut self.df, |ui, df| {
-                        ui.label("Pick the elevation range to export");
-                        ui.label("It works best by covering the surface level.");
-                        ui.horizontal(|ui| {
-                            ui.add_space(ui.available_width());
-                        });
-                        if elevation_picker(ui, "⏶", &mut self.state.high_elevation, df)?.changed()
-                        {
-                            self.state.low_elevation.0 =
-                                self.state.low_elevation.0.min(self.state.high_elevation.0);
-                        };
-                        if elevation_picker(ui, "⏷", &mut self.state.low_elevation, df)?.changed()
-                        {
-                            self.state.high_elevation.0 =
-                                self.state.high_elevation.0.max(self.state.low_elevation.0);
-                        }
-
-                        time_picker(ui, &mut self.state.time, df)?;
-                        ui.separator();
-                        let button = Button::new(RichText::new("💾 Export").heading());
-                        if ui
-                            .add_sized(Vec2::new(ui.available_width(), 40.0), button)
-                            .clicked()
-                        {
-                            self.state.error = None;
-                            let world_map = df.remote_fortress_reader().get_world_map()?;
-                            let file_name = format!(
-                                "{}_{}.vox",
-                                world_map.name_english(),
-                                world_map.cur_year()
-                            );
-
-                            if let Some(path) = rfd::FileDialog::new()
-                                .set_title("Model destination")
-                                .set_file_name(file_name)
-                                .add_filter("MagicaVoxel", &["vox"])
-                                .save_file()
-                            {
-                                let (progress_rx, cancel_tx, _) =
-                                    run_export_thread(self.state.export_params(path), None);
-                                self.state.progress = Some((
-                                    Progress::undetermined("Connecting..."),
-                                    progress_rx,
-                                    cancel_tx,
-                                ));
-                            }
-                        }
-                        Ok(())
-                    }));
-                });
-            }
-        }
-        if canceled {
-            self.state.progress = None;
-        }
-
-        if let Some(path) = &self.state.exported_path {
-            ui.group(|ui| {
-                ui.horizontal(|ui| {
-                    if ui.button("🗁 Show in explorer").clicked() {
-                        if let Err(err) = opener::reveal(path) {
-                            self.state.error = Some(err.to_string());
-                        }
-                    }
-                    ui.label(format!(
-                        "'{}' exported",
-                        path.file_name().unwrap_or_default().to_string_lossy()
-                    ));
-                    ui.add_space(ui.available_width());
-                });
-            });
-        }
-
-        if let Some(err) = &self.state.error {
-            ui.label("Is Dwarf Fortress running with DFHack installed?");
-            ui.label(err);
-        }
-
-        ui.collapsing("？ Information", |ui| {
-            ui.hyperlink_to(" Source Code", "https://github.com/plule/vox-uristi");
-            ui.hyperlink_to(
-                " Dwarf Fortress",
-                "https://store.steampowered.com/app/975370/Dwarf_Fortress",
-            );
-            ui.hyperlink_to(
-                " DFHack",
-                "https://store.steampowered.com/app/2346660/DFHack__Dwarf_Fortress_Modding_Engine",
-            );
-            ui.hyperlink_to("👁 MagicaVoxel", "https://ephtracy.github.io/");
-        });
-    }
-
-    fn status_bar(&mut self, ui: &mut Ui) {
-        #[cfg(feature = "self-update")]
-        ui.horizontal(|ui| match &self.state.update_status {
-            ui::CheckUpdateStatus::NotDone => {
-                if ui.button("🔃 Check for updates").clicked() {
-                    let (sender, receiver) = std::sync::mpsc::channel();
-                    self.state.update_status = ui::CheckUpdateStatus::Doing(receiver);
-                    let ctx = ui.ctx().clone();
-                    std::thread::spawn(move || {
-                        sender.send(crate::update::check_update()).unwrap();
-                        ctx.request_repaint();
-                    });
-                }
-            }
-            ui::CheckUpdateStatus::Doing(_) => {
-                ui.spinner();
-            }
-            ui::CheckUpdateStatus::Done(crate::update::UpdateStatus::UpToDate) => {
-                ui.label("✔ Up to date");
-            }
-            ui::CheckUpdateStatus::Done(crate::update::UpdateStatus::NewVersion {
-                name,
-                release_url,
-                asset_url,
-            }) => {
-                ui.label(format!("⮉ {name} is available."));
-                ui.horizontal(|ui| {
-                    ui.hyperlink_to(" Open", release_url);
-                    if let Some(asset_url) = asset_url {
-                        ui.hyperlink_to("⬇ Download", asset_url);
-                    }
-                });
-            }
-        });
-    }
-}
-
-impl Default for App {
-    fn default() -> Self {
-        let df = match dfhack_remote::connect() {
-            Ok(df) => Ok(df),
-            Err(err) => Err(anyhow!(err)),
-        };
-        Self {
-            state: State::default(),
-            df,
-        }
-    }
-}
-
-impl eframe::App for App {
-    fn save(&mut self, storage: &mut dyn eframe::Storage) {
-        eframe::set_value(storage, eframe::APP_KEY, self);
-    }
-
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        #[cfg(feature = "self-update")]
-        if let ui::CheckUpdateStatus::Doing(receiver) = &self.state.update_status {
-            if let Some(update_status) = receiver.try_iter().last() {
-                match update_status {
-                    Ok(update_status) => {
-                        self.state.update_status = ui::CheckUpdateStatus::Done(update_status);
-                    }
-                    Err(err) => {
-                        self.state.update_status = ui::CheckUpdateStatus::NotDone;
-                        self.state.error = Some(err.to_string());
-                    }
-                }
-            }
-        }
-        egui::CentralPanel::default().show(ctx, |ui| {
-            self.central_panel(ui, ctx);
-        });
-
-        egui::TopBottomPanel::bottom("status").show(ctx, |ui| {
-            self.status_bar(ui);
-        });
-    }
-}
-
-fn elevation_picker(
-    ui: &mut Ui,
-    text: &str,
-    elevation: &mut Elevation,
-    df: &mut dfhack_remote::Client,
-) -> Result<Response> {
-    ui.horizontal(|ui| {
-        ui.label(text);
-        let button = ui
-            .button("☉")
-            .on_hover_text("Set the elevation from the current view.");
-        if button.clicked() {
-            elevation.read_from_df(df)?;
-        }
-        let mut resp = ui
-            .add(DragValue::new(&mut elevation.0).clamp_range(-200..=200))
-            .on_hover_text("Defines the elevation range that will be exported.");
-        if button.clicked() {
-            resp.mark_changed();
-        }
-        Ok(resp)
-    })
-    .inner
-}
-
-fn time_picker(
-    ui: &mut Ui,
-    time: &mut TimeOfTheYear,
-    df: &mut dfhack_remote::Client,
-) -> Result<()> {
-    ui.horizontal(|ui| {
-        ui.label("📆");
-        if ui
-            .button("☉")
-            .on_hover_text("Set the time of the year to the current time.")
-            .clicked()
-        {
-            time.read_from_df(df)?;
-        }
-        egui::ComboBox::from_label("")
-            .selected_text(format!("{}", time))
-            .show_ui(ui, |ui| {
-                for month in Month::iter() {
-                    let text = egui::RichText::new(format!("{}", month)).color(month.gui_color());
-                    ui.selectable_value(time, TimeOfTheYear::Month(month), text);
-                }
-            }).response.on_hover_text("Define the time of the year of the export. This affects the vegetation appearance.");
-
-        Ok(())
-    })
-    .inner
-}
-
-fn df_client_group<'a, R>(
-    df: &'a mut Result<dfhack_remote::Client>,
-    add_contents: impl FnOnce(&mut Ui, &mut dfhack_remote::Client) -> Result<R> + 'a,
-) -> impl egui::Widget + 'a {
-    move |ui: &mut Ui| {
-        let mut new_df = None;
-        let response = match df {
-            Ok(df) => {
-                ui.add_enabled_ui(true, |ui| {
-                    if let Err(err) = add_contents(ui, df) {
-                        new_df = Some(Err(err));
-                    }
-                })
-                .response
-            }
-            Err(err) => ui.vertical(|ui| {
-                ui.label("Failed to communicate with Dwarf Fortress. Is it running with DFHack installed?");
-                ui.label(err.to_string());
-                if ui.button("Reconnect").clicked() {
-                    new_df = Some(dfhack_remote::connect().context("Connecting to DFHack"));
-                }
-            }).response,
-        };
-
-        if let Some(new_df) = new_df {
-            *df = new_df;
-        }
-
-        response
-    }
-}
+use crate::{
+    calendar::{Month, TimeOfTheYear},
+    color::ColorSpace,
+    export::{Cancel, DataOverlay, Elevation, ExportEstimate, ExportOptions, Layers, Progress},
+    heightmap::TopView,
+    language::{self, NameLanguage},
+    manifest::UpAxis,
+    FromDwarfFortress,
+};
+use anyhow::Result;
+use eframe::{
+    egui::{self, Button, DragValue, ProgressBar, Response, RichText, Ui},
+    epaint::Vec2,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use strum::IntoEnumIterator;
+
+use super::{
+    job::JOB_EXTENSION,
+    preview::PreviewCache,
+    report::IssueReport,
+    worker::{DFWorker, Pending},
+    State,
+};
+use crate::ui;
+use crate::VERSION;
+use help::{HelpExt, Topic};
+
+mod help;
+
+const ICON: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/icon"));
+/// Interval between two checks of the connection to DFHack
+const HEALTH_INTERVAL: Duration = Duration::from_secs(5);
+
+pub fn run() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_inner_size([320.0, 240.0])
+            .with_icon(egui::IconData {
+                rgba: ICON.to_vec(),
+                width: 256,
+                height: 256,
+            }),
+        ..Default::default()
+    };
+    match eframe::run_native(
+        format!("Vox Uristi v{VERSION}").as_str(),
+        options,
+        Box::new(|cc| Box::<App>::new(App::new(cc))),
+    ) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(anyhow::format_err!("{}", e.to_string())),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct App {
+    state: crate::ui::State,
+    #[serde(skip)]
+    df: DFWorker,
+    /// Requests sent to DFHack, not answered yet
+    #[serde(skip)]
+    awaited: Vec<Pending<Answer>>,
+    /// Why the latest request to DFHack failed, None while it answers
+    #[serde(skip)]
+    df_error: Option<String>,
+    /// Result of the latest check of the connection
+    #[serde(skip)]
+    health: Option<ConnectionHealth>,
+    /// Check of the connection sent to DFHack, not answered yet
+    #[serde(skip)]
+    health_check: Option<Pending<ConnectionHealth>>,
+    /// Issue report waiting for the map blocks, with its destination
+    #[serde(skip)]
+    report: Option<(Pending<Result<PathBuf, String>>, PathBuf)>,
+    #[serde(skip)]
+    last_check: Option<Instant>,
+    /// Blocks and context kept between the previews, used by the worker
+    #[serde(skip)]
+    preview_cache: Arc<Mutex<PreviewCache>>,
+    /// Latest preview, None when nothing is visible, with what it shows
+    #[serde(skip)]
+    preview: Option<(Option<egui::TextureHandle>, PreviewKey)>,
+    /// Preview sent to DFHack, not answered yet
+    #[serde(skip)]
+    pending_preview: Option<(Pending<Option<TopView>>, PreviewKey)>,
+}
+
+/// What a preview shows, it is rendered again when this changes
+#[derive(Clone, PartialEq)]
+struct PreviewKey {
+    low: i32,
+    high: i32,
+    time: TimeOfTheYear,
+    /// Serialized options, several of them change the colors
+    options: String,
+}
+
+/// State of the connection to DFHack
+struct ConnectionHealth {
+    dfhack_version: String,
+    /// Name of the fortress site, None when no fortress is loaded
+    fortress: Option<String>,
+    /// Response time of DFHack
+    ping: Duration,
+}
+
+/// Answer of DFHack to a request of the interface
+enum Answer {
+    HighElevation(Elevation),
+    LowElevation(Elevation),
+    Time(TimeOfTheYear),
+    /// Default file name and size estimate, before picking the destination of the export.
+    /// The estimate is missing if it could not be computed.
+    Export {
+        file_name: String,
+        estimate: Option<ExportEstimate>,
+    },
+}
+
+impl ConnectionHealth {
+    fn check(df: &mut dfhack_remote::Client) -> Result<Self> {
+        let start = Instant::now();
+        let version_info = df.remote_fortress_reader().get_version_info()?;
+        let ping = start.elapsed();
+        let fortress = language::read_site_name(df, NameLanguage::English)
+            .ok()
+            .flatten();
+        Ok(Self {
+            dfhack_version: version_info.dfhack_version().to_string(),
+            fortress,
+            ping,
+        })
+    }
+}
+
+impl App {
+    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let app: Self = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default();
+        cc.egui_ctx.set_zoom_factor(app.state.text_scale);
+        app
+    }
+
+    /// Check the connection periodically, the worker reconnects when it is lost
+    fn check_connection(&mut self, ctx: &egui::Context) {
+        ctx.request_repaint_after(HEALTH_INTERVAL);
+        // No need to pile up checks while one is pending
+        if self.health_check.is_some()
+            || self
+                .last_check
+                .is_some_and(|last_check| last_check.elapsed() < HEALTH_INTERVAL)
+        {
+            return;
+        }
+        self.last_check = Some(Instant::now());
+        self.health_check = Some(self.df.request(ctx, ConnectionHealth::check));
+    }
+
+    /// Apply the answers of DFHack that arrived since the last frame
+    fn receive(&mut self) {
+        let mut answers = Vec::new();
+        self.awaited.retain(|pending| match pending.poll() {
+            Some(answer) => {
+                answers.push(answer);
+                false
+            }
+            None => true,
+        });
+        for answer in answers {
+            match answer {
+                Ok(answer) => {
+                    self.df_error = None;
+                    self.apply(answer);
+                }
+                Err(err) => {
+                    self.health = None;
+                    self.df_error = Some(format!("{err:#}"));
+                }
+            }
+        }
+        if let Some(health) = self.health_check.as_ref().and_then(Pending::poll) {
+            self.health_check = None;
+            match health {
+                Ok(health) => {
+                    self.df_error = None;
+                    self.health = Some(health);
+                }
+                Err(err) => {
+                    self.health = None;
+                    self.df_error = Some(format!("{err:#}"));
+                }
+            }
+        }
+        let written = self.report.as_ref().and_then(|(pending, _)| pending.poll());
+        if let Some(written) = written {
+            let Some((_, path)) = self.report.take() else {
+                return;
+            };
+            match written {
+                Ok(Ok(path)) => self
+                    .state
+                    .infos
+                    .push(format!("Issue report written to {}", path.display())),
+                Ok(Err(err)) => {
+                    self.state.error = Some(format!("Failed to write the issue report: {err}"));
+                }
+                Err(err) => {
+                    self.state.warnings.push(format!(
+                        "The map blocks are left out of the issue report: {err:#}"
+                    ));
+                    self.write_report(&path);
+                }
+            }
+        }
+    }
+
+    fn apply(&mut self, answer: Answer) {
+        let state = &mut self.state;
+        match answer {
+            Answer::HighElevation(elevation) => {
+                state.high_elevation = elevation;
+                state.low_elevation.0 = state.low_elevation.0.min(elevation.0);
+            }
+            Answer::LowElevation(elevation) => {
+                state.low_elevation = elevation;
+                state.high_elevation.0 = state.high_elevation.0.max(elevation.0);
+            }
+            Answer::Time(time) => state.time = time,
+            Answer::Export {
+                file_name,
+                estimate,
+            } => {
+                if let Some(path) = rfd::FileDialog::new()
+                    .set_title("Model destination")
+                    .set_file_name(file_name)
+                    .add_filter("MagicaVoxel", &["vox"])
+                    .add_filter("Goxel", &["gox"])
+                    .save_file()
+                {
+                    match estimate.and_then(|estimate| estimate.warning()) {
+                        Some(warning) => state.confirm_export = Some((path, warning)),
+                        None => state.start_export(path, false),
+                    }
+                }
+            }
+        }
+    }
+
+    fn display_settings(&mut self, ui: &mut Ui, ctx: &egui::Context) {
+        ui.collapsing("🔍 Display", |ui| {
+            let text_scale = egui::Slider::new(&mut self.state.text_scale, 0.75..=2.0);
+            if ui.add(text_scale.text("Text scale")).changed() {
+                ctx.set_zoom_factor(self.state.text_scale);
+            }
+            let high_contrast = ui.checkbox(&mut self.state.high_contrast, "High contrast");
+            if high_contrast.changed() && !self.state.high_contrast {
+                ctx.set_visuals(egui::Visuals::default());
+            }
+        });
+    }
+
+    fn central_panel(&mut self, ui: &mut Ui, ctx: &egui::Context) {
+        ui.heading("☀Vox Uristi☀");
+
+        let mut canceled = false;
+        match &mut self.state.progress {
+            Some((progress, rx, tx)) => {
+                ctx.request_repaint();
+                if ui.button("Cancel").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                    canceled = true;
+                    if let Err(err) = tx.send(Cancel) {
+                        self.state.error = Some(format!("Failed to cancel: {err}"));
+                    }
+                }
+                for new_progress in rx.try_iter() {
+                    if let Some(line) = new_progress.log_line() {
+                        self.state.log(line);
+                    }
+                    match new_progress {
+                        Progress::Summary(summary) => self.state.summary = Some(summary),
+                        Progress::Info(info) => self.state.infos.push(info),
+                        Progress::Warning(warning) => self.state.warnings.push(warning),
+                        new_progress => *progress = new_progress,
+                    }
+                }
+                if !self.state.options.no_pause {
+                    ui.label("Do not unpause the game during the export.");
+                }
+                match progress {
+                    Progress::Undetermined { message } => {
+                        ui.label(*message);
+                        ui.spinner();
+                    }
+                    Progress::Update {
+                        message,
+                        curr,
+                        total,
+                    } => {
+                        ui.add(ProgressBar::new(*curr as f32 / *total as f32).text(*message));
+                    }
+                    Progress::Start {
+                        message: _,
+                        total: _,
+                    } => {}
+                    Progress::Done { paths } => {
+                        if self.state.open_when_done {
+                            if let Err(err) =
+                                crate::ui::open_models(paths, self.state.viewer.as_deref())
+                            {
+                                self.state.error = Some(err.to_string());
+                            }
+                        }
+                        self.state.exported_paths = paths.clone();
+                        self.state.progress = None;
+                        self.state.record_export();
+                    }
+                    Progress::Summary(_) | Progress::Info(_) | Progress::Warning(_) => {}
+                    Progress::Error(err) => {
+                        self.state.error = Some(err.to_string());
+                        self.state.progress = None;
+                    }
+                }
+            }
+            None => {
+                let mut reconnect = false;
+                ui.group(|ui| {
+                    let (df, awaited, state) = (&self.df, &mut self.awaited, &mut self.state);
+                    let contents = |ui: &mut Ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Pick the elevation range to export");
+                            help::help_marker(ui, Topic::Elevation);
+                        });
+                        ui.label("It works best by covering the surface level.");
+                        ui.label("PageUp/PageDown shift the whole range, the preview follows.");
+                        ui.label("Enter exports, Esc cancels.");
+                        ui.horizontal(|ui| {
+                            ui.add_space(ui.available_width());
+                        });
+                        let read_high = || {
+                            awaited.push(df.request(ctx, |df| {
+                                let mut elevation = Elevation(0);
+                                elevation.read_from_df(df)?;
+                                Ok(Answer::HighElevation(elevation))
+                            }))
+                        };
+                        let high = elevation_picker(ui, "⏶", &mut state.high_elevation, read_high);
+                        if high.changed() {
+                            state.low_elevation.0 =
+                                state.low_elevation.0.min(state.high_elevation.0);
+                        };
+                        let read_low = || {
+                            awaited.push(df.request(ctx, |df| {
+                                let mut elevation = Elevation(0);
+                                elevation.read_from_df(df)?;
+                                Ok(Answer::LowElevation(elevation))
+                            }))
+                        };
+                        let low = elevation_picker(ui, "⏷", &mut state.low_elevation, read_low);
+                        if low.changed() {
+                            state.high_elevation.0 =
+                                state.high_elevation.0.max(state.low_elevation.0);
+                        }
+
+                        let read_time = || {
+                            awaited.push(df.request(ctx, |df| {
+                                let mut time = TimeOfTheYear::default();
+                                time.read_from_df(df)?;
+                                Ok(Answer::Time(time))
+                            }))
+                        };
+                        time_picker(ui, &mut state.time, read_time);
+                        options_picker(ui, &mut state.options);
+                        viewer_picker(ui, &mut state.viewer, &mut state.open_when_done);
+                        ui.separator();
+                        let button = Button::new(RichText::new("💾 Export").heading());
+                        // Enter on a focused widget is for the widget
+                        let enter = state.confirm_export.is_none()
+                            && ui.memory(|memory| memory.focused().is_none())
+                            && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        if ui
+                            .add_sized(Vec2::new(ui.available_width(), 40.0), button)
+                            .clicked()
+                            || enter
+                        {
+                            state.error = None;
+                            state.summary = None;
+                            state.infos.clear();
+                            state.warnings.clear();
+                            // The destination is asked once the file name and estimate arrive
+                            let ranges = [state.low_elevation..(state.high_elevation + 1)];
+                            awaited.push(df.request(ctx, move |df| {
+                                let world_map = df.remote_fortress_reader().get_world_map()?;
+                                let file_name = format!(
+                                    "{}_{}.vox",
+                                    world_map.name_english(),
+                                    world_map.cur_year()
+                                );
+                                let estimate = ExportEstimate::compute(df, &ranges).ok();
+                                Ok(Answer::Export {
+                                    file_name,
+                                    estimate,
+                                })
+                            }));
+                        }
+                        if let Some((path, warning)) = state.confirm_export.clone() {
+                            ui.label(
+                                RichText::new(format!("⚠ {warning}")).color(egui::Color32::YELLOW),
+                            );
+                            ui.horizontal(|ui| {
+                                if ui.button("Export anyway").clicked() {
+                                    state.confirm_export = None;
+                                    state.start_export(path, true);
+                                }
+                                if ui.button("Cancel").clicked()
+                                    || ui.input(|i| i.key_pressed(egui::Key::Escape))
+                                {
+                                    state.confirm_export = None;
+                                }
+                            });
+                        }
+                    };
+                    ui.add(df_client_group(
+                        self.df_error.as_deref(),
+                        &mut reconnect,
+                        contents,
+                    ));
+                });
+                if reconnect {
+                    self.last_check = None;
+                }
+                self.preview_panel(ui, ctx);
+                self.recent_exports(ui);
+            }
+        }
+        if canceled {
+            self.state.progress = None;
+        }
+
+        if let Some(path) = self.state.exported_paths.first() {
+            let paths = &self.state.exported_paths;
+            ui.group(|ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("🗁 Show in explorer").clicked() {
+                        if let Err(err) = opener::reveal(path) {
+                            self.state.error = Some(err.to_string());
+                        }
+                    }
+                    if ui.button("👁 Open").clicked() {
+                        if let Err(err) =
+                            crate::ui::open_models(paths, self.state.viewer.as_deref())
+                        {
+                            self.state.error = Some(err.to_string());
+                        }
+                    }
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    ui.label(match paths.len() {
+                        1 => format!("'{name}' exported"),
+                        count => format!("'{name}' and {} other files exported", count - 1),
+                    });
+                    ui.add_space(ui.available_width());
+                });
+                if let Some(summary) = &self.state.summary {
+                    ui.label(summary.to_string());
+                }
+            });
+        }
+
+        for info in &self.state.infos {
+            ui.label(format!("ℹ {info}"));
+        }
+
+        for warning in &self.state.warnings {
+            ui.label(RichText::new(format!("⚠ {warning}")).color(egui::Color32::YELLOW));
+        }
+
+        if let Some(err) = &self.state.error {
+            ui.label("Is Dwarf Fortress running with DFHack installed?");
+            ui.label(err);
+            if self.state.current_export.is_some() {
+                self.issue_report(ui);
+            }
+        }
+
+        self.display_settings(ui, ctx);
+
+        ui.collapsing("？ Information", |ui| {
+            ui.hyperlink_to(" Source Code", "https://github.com/plule/vox-uristi");
+            ui.hyperlink_to(
+                " Dwarf Fortress",
+                "https://store.steampowered.com/app/975370/Dwarf_Fortress",
+            );
+            ui.hyperlink_to(
+                " DFHack",
+                "https://store.steampowered.com/app/2346660/DFHack__Dwarf_Fortress_Modding_Engine",
+            );
+            ui.hyperlink_to("👁 MagicaVoxel", "https://ephtracy.github.io/");
+            #[cfg(windows)]
+            if ui
+                .button("Open the .voxuristi jobs with Vox Uristi")
+                .on_hover_text("Double-clicking a job file runs its export again.")
+                .clicked()
+            {
+                if let Err(err) = super::shell::associate_jobs() {
+                    self.state.error = Some(err.to_string());
+                }
+            }
+        });
+    }
+
+    fn recent_exports(&mut self, ui: &mut Ui) {
+        if self.state.recent_exports.is_empty() {
+            return;
+        }
+        let mut export_again = None;
+        let mut save_job = None;
+        ui.collapsing("🕘 Recent exports", |ui| {
+            for export in &self.state.recent_exports {
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("Export again")
+                        .on_hover_text("Export again with the same settings.")
+                        .clicked()
+                    {
+                        export_again = Some(export.clone());
+                    }
+                    if ui.button("🗁 Show in explorer").clicked() {
+                        if let Err(err) = opener::reveal(&export.path) {
+                            self.state.error = Some(err.to_string());
+                        }
+                    }
+                    if ui
+                        .button("💾")
+                        .on_hover_text("Save the export settings as a .voxuristi job, run with `vox-uristi job`.")
+                        .clicked()
+                    {
+                        save_job = Some(export.clone());
+                    }
+                    ui.label(format!(
+                        "{}: {} to {}, {}, {}",
+                        export.path.file_name().unwrap_or_default().to_string_lossy(),
+                        export.low_elevation.0,
+                        export.high_elevation.0,
+                        export.time,
+                        age(export.timestamp)
+                    ))
+                    .on_hover_text(export.path.display().to_string());
+                });
+            }
+        });
+        if let Some(export) = save_job {
+            let stem = export
+                .path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy();
+            if let Some(path) = rfd::FileDialog::new()
+                .set_title("Export job")
+                .set_file_name(format!("{stem}.{JOB_EXTENSION}"))
+                .add_filter("Vox Uristi job", &[JOB_EXTENSION])
+                .save_file()
+            {
+                if let Err(err) = export.job().save(&path) {
+                    self.state.error = Some(err.to_string());
+                }
+            }
+        }
+        if let Some(export) = export_again {
+            self.state.error = None;
+            self.state.summary = None;
+            self.state.infos.clear();
+            self.state.warnings.clear();
+            self.state.export_again(export);
+        }
+    }
+
+    /// Offer to bundle the details of the failed export for a GitHub issue
+    fn issue_report(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            let write = ui.button("📦 Write an issue report...").on_hover_text(
+                "Gather the settings, the DFHack version and the export log in a .zip to attach to a GitHub issue.",
+            );
+            ui.checkbox(&mut self.state.report_blocks, "Include the map blocks")
+                .on_hover_text("Also include the map of the lowest exported level. It reveals the fortress.");
+            if !write.clicked() {
+                return;
+            }
+            let Some(export) = &self.state.current_export else {
+                return;
+            };
+            if self.report.is_some() {
+                return;
+            }
+            let Some(path) = rfd::FileDialog::new()
+                .set_title("Issue report")
+                .set_file_name("vox-uristi-report.zip")
+                .add_filter("Zip", &["zip"])
+                .save_file()
+            else {
+                return;
+            };
+            let error = self.state.error.clone().unwrap_or_default();
+            let dfhack_version = self.health.as_ref().map(|health| health.dfhack_version.clone());
+            if self.state.report_blocks {
+                // The blocks are read by the worker, the report is written once they arrive
+                let (settings, log) = (export.clone(), self.state.log.clone());
+                let destination = path.clone();
+                let written = self.df.request(ui.ctx(), move |df| {
+                    let report = IssueReport {
+                        error: &error,
+                        settings: &settings,
+                        dfhack_version: dfhack_version.as_deref(),
+                        log: &log,
+                    };
+                    let blocks = Some((df, settings.low_elevation));
+                    let written = report.write(&destination, blocks).map(|()| destination);
+                    Ok(written.map_err(|err| format!("{err:#}")))
+                });
+                // Written without the blocks if DFHack can't be reached
+                self.report = Some((written, path));
+                return;
+            }
+            self.write_report(&path);
+        });
+    }
+
+    /// Write the issue report of the current export, without the map blocks
+    fn write_report(&mut self, path: &Path) {
+        let Some(export) = &self.state.current_export else {
+            return;
+        };
+        let report = IssueReport {
+            error: self.state.error.as_deref().unwrap_or_default(),
+            settings: export,
+            dfhack_version: self
+                .health
+                .as_ref()
+                .map(|health| health.dfhack_version.as_str()),
+            log: &self.state.log,
+        };
+        match report.write(path, None) {
+            Ok(()) => self
+                .state
+                .infos
+                .push(format!("Issue report written to {}", path.display())),
+            Err(err) => {
+                self.state.error = Some(format!("Failed to write the issue report: {err}"));
+            }
+        }
+    }
+
+    /// What the preview shows with the current settings
+    fn preview_key(&self) -> PreviewKey {
+        PreviewKey {
+            low: self.state.low_elevation.0,
+            high: self.state.high_elevation.0,
+            time: self.state.time,
+            options: serde_json::to_string(&self.state.options).unwrap_or_default(),
+        }
+    }
+
+    fn request_preview(&mut self, ctx: &egui::Context) {
+        let key = self.preview_key();
+        let cache = Arc::clone(&self.preview_cache);
+        let (low, high) = (self.state.low_elevation, self.state.high_elevation);
+        let (time, options) = (self.state.time, self.state.options.clone());
+        let pending = self.df.request(ctx, move |df| {
+            let mut cache = cache.lock().unwrap_or_else(PoisonError::into_inner);
+            cache.render(df, low, high, time, options)
+        });
+        self.pending_preview = Some((pending, key));
+    }
+
+    /// Show the answered preview, and render it again once the elevation range, the time
+    /// or the options changed. Nothing is rendered until a first preview is asked for.
+    fn refresh_preview(&mut self, ctx: &egui::Context) {
+        let answer = self
+            .pending_preview
+            .as_ref()
+            .and_then(|(pending, _)| pending.poll());
+        if let Some(answer) = answer {
+            let Some((_, key)) = self.pending_preview.take() else {
+                return;
+            };
+            match answer {
+                Ok(view) => {
+                    let texture = view.map(|view| {
+                        let size = [view.width as usize, view.height as usize];
+                        let image = egui::ColorImage::from_rgba_unmultiplied(size, &view.colors);
+                        ctx.load_texture("preview", image, egui::TextureOptions::NEAREST)
+                    });
+                    self.preview = Some((texture, key));
+                }
+                Err(err) => {
+                    // Not retried on every frame, until asked again
+                    self.preview = None;
+                    self.df_error = Some(format!("{err:#}"));
+                }
+            }
+        }
+        let outdated = self
+            .preview
+            .as_ref()
+            .is_some_and(|(_, key)| *key != self.preview_key());
+        if outdated && self.pending_preview.is_none() {
+            self.request_preview(ctx);
+        }
+    }
+
+    /// Top-down view of the export, following the settings once shown
+    fn preview_panel(&mut self, ui: &mut Ui, ctx: &egui::Context) {
+        ui.collapsing("🗺 Preview", |ui| {
+            ui.horizontal(|ui| {
+                let text = if self.preview.is_some() {
+                    "⟳ Refresh"
+                } else {
+                    "Preview"
+                };
+                let button = ui
+                    .button(text)
+                    .on_hover_text("Read the map from DFHack and show it from above.");
+                if button.clicked() && self.pending_preview.is_none() {
+                    // The fortress may have changed since the blocks were read
+                    self.preview_cache
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .clear();
+                    self.request_preview(ctx);
+                }
+                if self.pending_preview.is_some() {
+                    ui.spinner();
+                }
+            });
+            match &self.preview {
+                Some((Some(texture), _)) => {
+                    let size = texture.size_vec2();
+                    let scale = ui.available_width() / size.x.max(1.0);
+                    ui.image((texture.id(), size * scale));
+                }
+                Some((None, _)) => {
+                    ui.label("Nothing is visible in this elevation range.");
+                }
+                None => {}
+            }
+        });
+    }
+
+    /// Shift the whole elevation range with PageUp/PageDown
+    fn nudge_elevation(&mut self, ctx: &egui::Context) {
+        let shift = ctx.input(|i| {
+            i.num_presses(egui::Key::PageUp) as i32 - i.num_presses(egui::Key::PageDown) as i32
+        });
+        if shift == 0 {
+            return;
+        }
+        let shift = shift
+            .min(200 - self.state.high_elevation.0)
+            .max(-200 - self.state.low_elevation.0);
+        self.state.low_elevation = self.state.low_elevation + shift;
+        self.state.high_elevation = self.state.high_elevation + shift;
+    }
+
+    fn status_bar(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| match (&self.df_error, &self.health) {
+            (None, Some(health)) => {
+                ui.label(RichText::new("●").color(egui::Color32::GREEN));
+                ui.label(format!(
+                    "DFHack {}, {}, {} ms",
+                    health.dfhack_version,
+                    health.fortress.as_deref().unwrap_or("no fortress loaded"),
+                    health.ping.as_millis()
+                ));
+            }
+            (None, None) => {
+                ui.label(RichText::new("●").color(egui::Color32::YELLOW));
+                ui.label("Connecting to DFHack...");
+            }
+            (Some(_), _) => {
+                ui.label(RichText::new("●").color(egui::Color32::RED));
+                ui.label("Not connected to DFHack, retrying...");
+            }
+        });
+        #[cfg(feature = "self-update")]
+        ui.horizontal(|ui| match &self.state.update_status {
+            ui::CheckUpdateStatus::NotDone => {
+                if ui.button("🔃 Check for updates").clicked() {
+                    let (sender, receiver) = std::sync::mpsc::channel();
+                    self.state.update_status = ui::CheckUpdateStatus::Doing(receiver);
+                    let ctx = ui.ctx().clone();
+                    std::thread::spawn(move || {
+                        sender.send(crate::update::check_update()).unwrap();
+                        ctx.request_repaint();
+                    });
+                }
+            }
+            ui::CheckUpdateStatus::Doing(_) => {
+                ui.spinner();
+            }
+            ui::CheckUpdateStatus::Done(crate::update::UpdateStatus::UpToDate) => {
+                ui.label("✔ Up to date");
+            }
+            ui::CheckUpdateStatus::Done(crate::update::UpdateStatus::NewVersion {
+                name,
+                release_url,
+                asset,
+            }) => {
+                ui.label(format!("⮉ {name} is available."));
+                let mut install = None;
+                ui.horizontal(|ui| {
+                    ui.hyperlink_to(" Open", release_url);
+                    if let Some(asset) = asset {
+                        ui.hyperlink_to("⬇ Download", &asset.browser_download_url);
+                        if ui
+                            .button("🔃 Install update")
+                            .on_hover_text("Download the new version and replace this one.")
+                            .clicked()
+                        {
+                            install = Some(asset.clone());
+                        }
+                    }
+                });
+                if let Some(asset) = install {
+                    let (sender, receiver) = std::sync::mpsc::channel();
+                    self.state.update_status = ui::CheckUpdateStatus::Installing(receiver);
+                    let ctx = ui.ctx().clone();
+                    std::thread::spawn(move || {
+                        sender.send(crate::update::install_update(&asset)).unwrap();
+                        ctx.request_repaint();
+                    });
+                }
+            }
+            ui::CheckUpdateStatus::Installing(_) => {
+                ui.spinner();
+                ui.label("Installing the update...");
+            }
+            ui::CheckUpdateStatus::Installed(executable) => {
+                ui.label("✔ Update installed.");
+                if ui.button("Restart").clicked() {
+                    if let Err(err) = crate::update::restart(executable) {
+                        self.state.error = Some(err.to_string());
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl Default for App {
+    fn default() -> Self {
+        Self {
+            state: State::default(),
+            df: DFWorker::default(),
+            awaited: Vec::new(),
+            df_error: None,
+            health: None,
+            health_check: None,
+            report: None,
+            last_check: None,
+            preview_cache: Arc::default(),
+            preview: None,
+            pending_preview: None,
+        }
+    }
+}
+
+impl eframe::App for App {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, self);
+    }
+
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        #[cfg(feature = "self-update")]
+        if let ui::CheckUpdateStatus::Doing(receiver) = &self.state.update_status {
+            if let Some(update_status) = receiver.try_iter().last() {
+                match update_status {
+                    Ok(update_status) => {
+                        self.state.update_status = ui::CheckUpdateStatus::Done(update_status);
+                    }
+                    Err(err) => {
+                        self.state.update_status = ui::CheckUpdateStatus::NotDone;
+                        self.state.error = Some(err.to_string());
+                    }
+                }
+            }
+        }
+        #[cfg(feature = "self-update")]
+        if let ui::CheckUpdateStatus::Installing(receiver) = &self.state.update_status {
+            if let Some(installed) = receiver.try_iter().last() {
+                match installed {
+                    Ok(executable) => {
+                        self.state.update_status = ui::CheckUpdateStatus::Installed(executable);
+                    }
+                    Err(err) => {
+                        self.state.update_status = ui::CheckUpdateStatus::NotDone;
+                        self.state.error = Some(format!("Failed to install the update: {err}"));
+                    }
+                }
+            }
+        }
+        if self.state.high_contrast {
+            ctx.set_visuals(high_contrast_visuals());
+        }
+        self.receive();
+        if self.state.progress.is_none() {
+            self.check_connection(ctx);
+            self.nudge_elevation(ctx);
+            self.refresh_preview(ctx);
+        }
+        egui::CentralPanel::default().show(ctx, |ui| {
+            self.central_panel(ui, ctx);
+        });
+
+        egui::TopBottomPanel::bottom("status").show(ctx, |ui| {
+            self.status_bar(ui);
+        });
+    }
+}
+
+/// Black background, white text and borders, yellow on the hovered and focused widgets
+fn high_contrast_visuals() -> egui::Visuals {
+    use egui::{Color32, Stroke};
+    let mut visuals = egui::Visuals::dark();
+    visuals.override_text_color = Some(Color32::WHITE);
+    visuals.panel_fill = Color32::BLACK;
+    visuals.window_fill = Color32::BLACK;
+    visuals.extreme_bg_color = Color32::BLACK;
+    visuals.faint_bg_color = Color32::BLACK;
+    visuals.hyperlink_color = Color32::YELLOW;
+    visuals.selection.bg_fill = Color32::from_rgb(0, 70, 150);
+    visuals.selection.stroke = Stroke::new(2.0, Color32::YELLOW);
+    let widgets = &mut visuals.widgets;
+    for widget in [&mut widgets.noninteractive, &mut widgets.inactive] {
+        widget.bg_fill = Color32::BLACK;
+        widget.weak_bg_fill = Color32::BLACK;
+        widget.bg_stroke = Stroke::new(1.0, Color32::WHITE);
+        widget.fg_stroke = Stroke::new(1.5, Color32::WHITE);
+    }
+    for widget in [&mut widgets.hovered, &mut widgets.active, &mut widgets.open] {
+        widget.bg_fill = Color32::BLACK;
+        widget.weak_bg_fill = Color32::BLACK;
+        widget.bg_stroke = Stroke::new(2.0, Color32::YELLOW);
+        widget.fg_stroke = Stroke::new(2.0, Color32::YELLOW);
+    }
+    visuals
+}
+
+/// Elevation field, `read` requests the elevation of the current view
+fn elevation_picker(
+    ui: &mut Ui,
+    text: &str,
+    elevation: &mut Elevation,
+    read: impl FnOnce(),
+) -> Response {
+    ui.horizontal(|ui| {
+        ui.label(text);
+        let button = ui
+            .button("☉")
+            .on_hover_text("Set the elevation from the current view.");
+        if button.clicked() {
+            read();
+        }
+        ui.add(DragValue::new(&mut elevation.0).clamp_range(-200..=200))
+            .on_hover_help(Topic::Elevation)
+    })
+    .inner
+}
+
+/// Month field, `read` requests the current time of the year
+fn time_picker(ui: &mut Ui, time: &mut TimeOfTheYear, read: impl FnOnce()) {
+    ui.horizontal(|ui| {
+        ui.label("📆");
+        if ui
+            .button("☉")
+            .on_hover_text("Set the time of the year to the current time.")
+            .clicked()
+        {
+            read();
+        }
+        egui::ComboBox::from_label("")
+            .selected_text(format!("{}", time))
+            .show_ui(ui, |ui| {
+                for month in Month::iter() {
+                    let text = egui::RichText::new(format!("{}", month)).color(month.gui_color());
+                    ui.selectable_value(time, TimeOfTheYear::Month(month), text);
+                }
+            })
+            .response
+            .on_hover_help(Topic::YearTick);
+    });
+}
+
+/// How long ago a timestamp was, such as "3 h ago"
+fn age(timestamp: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    match now.saturating_sub(timestamp) / 60 {
+        0 => "just now".to_string(),
+        minutes if minutes < 60 => format!("{minutes} min ago"),
+        minutes if minutes < 60 * 24 => format!("{} h ago", minutes / 60),
+        minutes => format!("{} days ago", minutes / 60 / 24),
+    }
+}
+
+fn viewer_picker(ui: &mut Ui, viewer: &mut Option<PathBuf>, open_when_done: &mut bool) {
+    ui.horizontal(|ui| {
+        ui.checkbox(open_when_done, "Open when done");
+        if ui
+            .button("👁 Viewer...")
+            .on_hover_text(
+                "Application opening the exported models, such as MagicaVoxel. The one associated to .vox files by default.",
+            )
+            .clicked()
+        {
+            if let Some(path) = rfd::FileDialog::new().pick_file() {
+                *viewer = Some(path);
+            }
+        }
+        let mut clear = false;
+        if let Some(path) = viewer {
+            ui.label(path.file_name().unwrap_or_default().to_string_lossy());
+            clear = ui.button("✖").clicked();
+        }
+        if clear {
+            *viewer = None;
+        }
+    });
+}
+
+fn options_picker(ui: &mut Ui, options: &mut ExportOptions) {
+    ui.collapsing("⚙ Options", |ui| {
+        ui.checkbox(&mut options.split_layers, "Split layers")
+            .on_hover_text("Write one .vox file per layer instead of a single file.");
+        ui.checkbox(&mut options.terrain_only, "Terrain only").on_hover_text(
+            "Only export the terrain as plain blocks, for quick previews of large maps.",
+        );
+        ui.checkbox(&mut options.reveal, "Reveal all").on_hover_text(
+            "Reveal the whole map, including the caverns the dwarves did not discover yet.",
+        );
+        ui.checkbox(&mut options.mechanisms, "Mechanisms")
+            .on_hover_text("Draw the links between the levers, pressure plates and their targets.");
+        ui.checkbox(&mut options.units, "Units").on_hover_text(
+            "Render the units as small figures dressed in the materials of their worn equipment, four voxels tall. The soldiers wear the color of their squad and hold out their weapon.",
+        );
+        if options.units {
+            ui.horizontal(|ui| {
+                let mut close_ups = options.unit_close_ups.is_some();
+                ui.checkbox(&mut close_ups, "Unit close-ups").on_hover_text(
+                    "Also write the units in a _close-ups file as finer figures, for the close-up renders. Scaled down by the detail, it lines up with the scene.",
+                );
+                if close_ups {
+                    let detail = options.unit_close_ups.get_or_insert(4);
+                    ui.add(DragValue::new(detail).clamp_range(2..=8).suffix("×"));
+                } else {
+                    options.unit_close_ups = None;
+                }
+            });
+        }
+        ui.checkbox(&mut options.projectiles, "Projectiles")
+            .on_hover_text("Render the arrows, bolts and thrown items in flight.");
+        ui.checkbox(&mut options.base_slab, "Base slab").on_hover_text(
+            "Add a thin neutral slab under the exported levels, to present the model as a diorama.",
+        );
+        ui.checkbox(&mut options.edge_walls, "Edge walls").on_hover_text(
+            "Enclose the sides of the map with neutral walls up to the terrain, hiding the cut through the ground.",
+        );
+        ui.checkbox(&mut options.crenellations, "Crenellations")
+            .on_hover_text("Add crenellations on the exterior wall tops open to the sky.");
+        ui.checkbox(&mut options.flair, "Banners")
+            .on_hover_text("Decorate the trade depots and floodgates with the banners of the civilization.");
+        ui.checkbox(&mut options.locations, "Locations")
+            .on_hover_text("Decorate the temples, guildhalls and taverns, and name them in the scene.");
+        ui.checkbox(&mut options.watermark, "Watermark").on_hover_text(
+            "Add a plaque with the fortress name and the date next to the model, on its own layer.",
+        );
+        ui.checkbox(&mut options.winter_ice, "Winter ice").on_hover_text(
+            "When exporting a winter month, freeze the surface water and hang icicles under the overhangs.",
+        );
+        ui.checkbox(&mut options.sag_bridges, "Sagging bridges").on_hover_text(
+            "Sag the bridges hanging over open space between their supports, like rope bridges.",
+        );
+        ui.checkbox(&mut options.stylized, "Stylized (experimental)")
+            .on_hover_text("Staggered pattern on the floors, and bevelled wall corners and tops.");
+        ui.checkbox(&mut options.clean, "Clean").on_hover_text(
+            "Leave out the random grass blades, stones, moss, worn paths and growths, for crisp architectural renders.",
+        );
+        ui.checkbox(&mut options.growth_blend, "Blend the growths").on_hover_text(
+            "Blend the growth colors from one print to the next, and thin out the growths before they fall, so that the exports of consecutive dates do not pop.",
+        );
+        ui.checkbox(&mut options.blood_streaks, "Blood streaks").on_hover_text(
+            "Draw the heavy blood spatters as streaks dragged from the heavier pools around, rather than speckles.",
+        );
+        ui.checkbox(&mut options.scaffolding, "Scaffolding").on_hover_text(
+            "Render the unfinished constructions as scaffolding, filled in steps as their materials are brought and a dwarf starts building them.",
+        );
+        let mut traffic = options.overlays.contains(&DataOverlay::Traffic);
+        if ui
+            .checkbox(&mut traffic, "Traffic overlay")
+            .on_hover_text("Let the game run for 30 seconds, and draw the tiles the units walk on the most.")
+            .changed()
+        {
+            options.overlays.retain(|overlay| *overlay != DataOverlay::Traffic);
+            if traffic {
+                options.overlays.push(DataOverlay::Traffic);
+            }
+        }
+        if traffic || options.units {
+            ui.checkbox(&mut options.ambushers, "Reveal the ambushers").on_hover_text(
+                "Include the ambushers and thieves the player did not discover in the traffic and the units.",
+            );
+        }
+        let mut wagon_route = options.overlays.contains(&DataOverlay::WagonRoute);
+        if ui
+            .checkbox(&mut wagon_route, "Wagon route")
+            .on_hover_text("Draw the route of the wagons from the edge of the map to the trade depots.")
+            .changed()
+        {
+            options.overlays.retain(|overlay| *overlay != DataOverlay::WagonRoute);
+            if wagon_route {
+                options.overlays.push(DataOverlay::WagonRoute);
+            }
+        }
+        let mut magma_safety = options.overlays.contains(&DataOverlay::MagmaSafety);
+        if ui
+            .checkbox(&mut magma_safety, "Magma safety")
+            .on_hover_text("Tint the constructions and buildings green if their material is magma-safe, red otherwise.")
+            .changed()
+        {
+            options.overlays.retain(|overlay| *overlay != DataOverlay::MagmaSafety);
+            if magma_safety {
+                options.overlays.push(DataOverlay::MagmaSafety);
+            }
+        }
+        ui.checkbox(&mut options.siege_crew, "Siege engine crew")
+            .on_hover_text("Add an operator behind each siege engine.");
+        ui.checkbox(&mut options.adaptive_chunk, "Adaptive reading").on_hover_text(
+            "Tune the number of map blocks requested at once from the response times.",
+        );
+        ui.checkbox(&mut options.no_pause, "Do not pause")
+            .on_hover_text("Keep the game running during the export. The model may be torn if the map changes meanwhile.");
+        ui.checkbox(&mut options.restore_pause, "Restore pause state")
+            .on_hover_text("Unpause the game after the export if it was running before.");
+        ui.checkbox(&mut options.extend_seas, "Extend the seas").on_hover_text(
+            "Extend the ocean and the magma sea up to the edges of the map where blocks are missing.",
+        );
+        ui.checkbox(&mut options.save_partial, "Save on cancel")
+            .on_hover_text("Save the levels completed so far when the export is cancelled.");
+        ui.checkbox(&mut options.flat_shading, "Flat shading").on_hover_text(
+            "Use a single flat color per material, to leave more room in the palette.",
+        );
+        ui.checkbox(&mut options.depth_cue, "Depth cueing")
+            .on_hover_text("Darken the deeper levels to give a sense of depth.");
+        ui.checkbox(&mut options.strata, "Strata").on_hover_text(
+            "Tint the soils by kind and vary the stone layers, to show the strata in the cliffs.",
+        );
+        ui.checkbox(&mut options.weather_effects, "Weather effects")
+            .on_hover_text("Wet the exposed floors and roofs when it is raining.");
+        ui.checkbox(&mut options.heightmap, "Heightmap")
+            .on_hover_text("Also write a heightmap and a top-down color map as .png.");
+        ui.checkbox(&mut options.palette_report, "Palette report").on_hover_text(
+            "Write a .json report of the palette entries and of the materials that could be merged.",
+        );
+        ui.horizontal(|ui| {
+            if ui
+                .button("🎨 Palette from...")
+                .on_hover_text(
+                    "Reuse the palette of a previous export, to keep its hand-tweaked colors and materials.",
+                )
+                .clicked()
+            {
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("MagicaVoxel", &["vox"])
+                    .pick_file()
+                {
+                    options.palette_from = Some(path);
+                }
+            }
+            let mut clear = false;
+            if let Some(path) = &options.palette_from {
+                ui.label(path.file_name().unwrap_or_default().to_string_lossy());
+                clear = ui.button("✖").clicked();
+            }
+            if clear {
+                options.palette_from = None;
+            }
+        });
+        ui.checkbox(&mut options.split_palette, "Split on full palette").on_hover_text(
+            "When the materials do not fit in the palette, split the export in several files by elevation, each with its own palette.",
+        );
+        let mut reserve_row = options.reserved_palette.contains(&(1, 8));
+        if ui
+            .checkbox(&mut reserve_row, "Reserve the first palette row")
+            .on_hover_text(
+                "Leave the first row of the palette untouched, for the hand-painted models composited with the export.",
+            )
+            .changed()
+        {
+            options.reserved_palette.retain(|range| *range != (1, 8));
+            if reserve_row {
+                options.reserved_palette.push((1, 8));
+            }
+        }
+        ui.horizontal(|ui| {
+            if ui
+                .button("🧪 Material table...")
+                .on_hover_text(
+                    "Custom materials replacing the default ones, or the Dwarf Fortress materials matching their patterns.",
+                )
+                .clicked()
+            {
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("YAML", &["yaml", "yml"])
+                    .pick_file()
+                {
+                    options.material_table = Some(path);
+                }
+            }
+            let mut clear = false;
+            if let Some(path) = &options.material_table {
+                ui.label(path.file_name().unwrap_or_default().to_string_lossy());
+                clear = ui.button("✖").clicked();
+            }
+            if clear {
+                options.material_table = None;
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui
+                .button("🗂 Layer rules...")
+                .on_hover_text(
+                    "Rules moving the buildings matching their patterns, or whole layers, to other layers or new ones.",
+                )
+                .clicked()
+            {
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("YAML", &["yaml", "yml"])
+                    .pick_file()
+                {
+                    options.layer_rules = Some(path);
+                }
+            }
+            let mut clear = false;
+            if let Some(path) = &options.layer_rules {
+                ui.label(path.file_name().unwrap_or_default().to_string_lossy());
+                clear = ui.button("✖").clicked();
+            }
+            if clear {
+                options.layer_rules = None;
+            }
+        });
+        egui::ComboBox::from_label("Names")
+            .selected_text(options.names.to_string())
+            .show_ui(ui, |ui| {
+                for language in NameLanguage::iter() {
+                    ui.selectable_value(&mut options.names, language, language.to_string());
+                }
+            })
+            .response
+            .on_hover_text("Language of the fortress and month names in the scene.");
+        egui::ComboBox::from_label("Color space")
+            .selected_text(
+                options
+                    .color_space
+                    .map_or_else(|| "Default".to_string(), |space| space.to_string()),
+            )
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut options.color_space, None, "Default");
+                for space in ColorSpace::iter() {
+                    ui.selectable_value(&mut options.color_space, Some(space), space.to_string());
+                }
+            })
+            .response
+            .on_hover_text("Space in which the colors are darkened and shifted.");
+        egui::ComboBox::from_label("Up axis")
+            .selected_text(options.up_axis.to_string())
+            .show_ui(ui, |ui| {
+                for axis in UpAxis::iter() {
+                    ui.selectable_value(&mut options.up_axis, axis, axis.to_string());
+                }
+            })
+            .response
+            .on_hover_text("Vertical axis of the tool importing the model.");
+        ui.checkbox(&mut options.manifest, "Manifest")
+            .on_hover_text("Write a .json manifest describing the export, for the importers of other tools.");
+        ui.horizontal(|ui| {
+            ui.add(DragValue::new(&mut options.surroundings).clamp_range(0..=4));
+            ui.label("Surroundings").on_hover_text(
+                "Region tiles of low detail terrain around the embark. 0 to disable.",
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.add(DragValue::new(&mut options.flow_frames).clamp_range(0..=24));
+            ui.label("Flow animation frames")
+                .on_hover_text("Animate the smoke, mist and fire over several frames. 0 for a still export.");
+        });
+        ui.collapsing("👁 Initial visibility", |ui| {
+            let mut hidden_layers = options.hidden_layers();
+            for layer in Layers::iter().filter(|layer| *layer != Layers::All) {
+                let mut visible = !hidden_layers.contains(&layer);
+                if ui
+                    .checkbox(&mut visible, layer.to_string())
+                    .on_hover_help(Topic::Layer(layer))
+                    .changed()
+                {
+                    if visible {
+                        hidden_layers.retain(|hidden| *hidden != layer);
+                    } else {
+                        hidden_layers.push(layer);
+                    }
+                    options.hidden_layers = Some(hidden_layers.clone());
+                }
+            }
+            ui.horizontal(|ui| {
+                let mut hide_above = options.hide_above.is_some();
+                ui.checkbox(&mut hide_above, "Hide the levels above")
+                    .on_hover_text("Hide the levels above this elevation when opening the model.");
+                help::help_marker(ui, Topic::Elevation);
+                match (hide_above, options.hide_above) {
+                    (true, None) => options.hide_above = Some(0),
+                    (false, Some(_)) => options.hide_above = None,
+                    _ => {}
+                }
+                if let Some(elevation) = &mut options.hide_above {
+                    ui.add(DragValue::new(elevation));
+                }
+            });
+        });
+    });
+}
+
+/// Contents relying on DFHack, replaced by the error while DFHack does not answer
+fn df_client_group<'a>(
+    error: Option<&'a str>,
+    reconnect: &'a mut bool,
+    add_contents: impl FnOnce(&mut Ui) + 'a,
+) -> impl egui::Widget + 'a {
+    move |ui: &mut Ui| {
+        match error {
+        None => ui.add_enabled_ui(true, add_contents).response,
+        Some(err) => ui.vertical(|ui| {
+            ui.label("Failed to communicate with Dwarf Fortress. Is it running with DFHack installed?");
+            ui.label(err);
+            if ui.button("Reconnect").clicked() {
+                *reconnect = true;
+            }
+        }).response,
+    }
+    }
+}
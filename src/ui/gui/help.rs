@@ -0,0 +1,184 @@
+use crate::{calendar::Month, export::Layers};
+use eframe::{
+    egui::{self, Align2, Color32, FontId, Rect, Response, Sense, Stroke, Ui},
+    epaint::Vec2,
+};
+use strum::IntoEnumIterator;
+
+/// Width of the tooltips, diagrams included
+const HELP_WIDTH: f32 = 320.0;
+
+/// Dwarf Fortress notions explained in the tooltips
+#[derive(Clone, Copy, Debug)]
+pub enum Topic {
+    Elevation,
+    YearTick,
+    Layer(Layers),
+}
+
+impl Topic {
+    fn title(&self) -> String {
+        match self {
+            Topic::Elevation => "Elevation".to_string(),
+            Topic::YearTick => "Time of the year".to_string(),
+            Topic::Layer(layer) => format!("{layer} layer"),
+        }
+    }
+
+    fn text(&self) -> &'static str {
+        match self {
+            Topic::Elevation => {
+                "The elevation is the level number displayed by Dwarf Fortress, \
+                on the right of the screen. Internally, the levels are counted from the bottom \
+                of the map, and the two differ by an offset that depends on the embark. \
+                Vox Uristi converts between them, the range is always the displayed one."
+            }
+            Topic::YearTick => {
+                "A Dwarf Fortress year lasts 403 200 ticks, split in 12 months of \
+                33 600 ticks. The month picks the season of the vegetation, the ice and the \
+                lighting. The year tick is written in the manifest of the export."
+            }
+            Topic::Layer(layer) => layer_description(*layer),
+        }
+    }
+
+    /// Draw the small illustration of the topic, if it has one
+    fn diagram(&self, ui: &mut Ui) {
+        match self {
+            Topic::Elevation => elevation_diagram(ui),
+            Topic::YearTick => year_diagram(ui),
+            Topic::Layer(_) => {}
+        }
+    }
+
+    /// Content of the tooltip
+    pub fn show(&self, ui: &mut Ui) {
+        ui.set_max_width(HELP_WIDTH);
+        ui.strong(self.title());
+        ui.label(self.text());
+        self.diagram(ui);
+    }
+}
+
+/// What each layer of the scene holds
+fn layer_description(layer: Layers) -> &'static str {
+    match layer {
+        Layers::All => "Every voxel of the export.",
+        Layers::Building => "Workshops, furniture, doors, bridges and the other buildings.",
+        Layers::Items => "Items lying on the floor and the content of the stockpiles.",
+        Layers::Terrain => "Walls, floors, ramps and stairs, natural or constructed.",
+        Layers::Vegetation => "Grass, shrubs, saplings and trees.",
+        Layers::Roughness => "Small bumps on the rough natural floors.",
+        Layers::Liquid => "Water and magma, by depth.",
+        Layers::Spatter => "Blood, vomit, snow and the other contaminants on the tiles.",
+        Layers::Fire => "Burning tiles and the light of the fires.",
+        Layers::Flows => "Smoke, mist, miasma and the other clouds.",
+        Layers::Mechanisms => "Links between the levers, the pressure plates and their targets.",
+        Layers::Flair => "Banners and the decorations of the locations.",
+        Layers::Traffic => "Data overlays: traffic heat map, wagon route, magma safety.",
+        Layers::Units => "Figures of the units, and the projectiles in flight.",
+        Layers::Watermark => "Plaque with the fortress name and the date.",
+        Layers::Hidden => "Tiles not yet discovered, drawn as black blocks.",
+    }
+}
+
+/// Stack of levels, with the displayed elevation next to the internal one
+fn elevation_diagram(ui: &mut Ui) {
+    let (rect, _) = ui.allocate_exact_size(Vec2::new(HELP_WIDTH, 90.0), Sense::hover());
+    let painter = ui.painter_at(rect);
+    let font = FontId::monospace(11.0);
+    let color = ui.visuals().text_color();
+    painter.text(
+        rect.left_top(),
+        Align2::LEFT_TOP,
+        "Displayed",
+        font.clone(),
+        color,
+    );
+    painter.text(
+        rect.right_top(),
+        Align2::RIGHT_TOP,
+        "Internal z",
+        font.clone(),
+        color,
+    );
+    let levels = [(5, 155, "Surface"), (0, 150, ""), (-5, 145, "Caverns")];
+    for (row, (elevation, z, name)) in levels.iter().enumerate() {
+        let top = rect.top() + 18.0 + row as f32 * 24.0;
+        let level = Rect::from_min_size(
+            egui::pos2(rect.left() + 70.0, top),
+            Vec2::new(HELP_WIDTH - 140.0, 18.0),
+        );
+        painter.rect_filled(level, 2.0, Color32::from_gray(90 - row as u8 * 20));
+        painter.text(
+            level.center(),
+            Align2::CENTER_CENTER,
+            name,
+            font.clone(),
+            Color32::WHITE,
+        );
+        painter.text(
+            egui::pos2(rect.left(), level.center().y),
+            Align2::LEFT_CENTER,
+            elevation.to_string(),
+            font.clone(),
+            color,
+        );
+        painter.text(
+            egui::pos2(rect.right(), level.center().y),
+            Align2::RIGHT_CENTER,
+            z.to_string(),
+            font.clone(),
+            color,
+        );
+    }
+}
+
+/// The months of the year on a tick scale, colored by season
+fn year_diagram(ui: &mut Ui) {
+    let (rect, _) = ui.allocate_exact_size(Vec2::new(HELP_WIDTH, 40.0), Sense::hover());
+    let painter = ui.painter_at(rect);
+    let font = FontId::monospace(10.0);
+    let color = ui.visuals().text_color();
+    let month_width = rect.width() / 12.0;
+    for (index, month) in Month::iter().enumerate() {
+        let cell = Rect::from_min_size(
+            egui::pos2(rect.left() + index as f32 * month_width, rect.top()),
+            Vec2::new(month_width - 1.0, 14.0),
+        );
+        painter.rect_filled(cell, 1.0, month.gui_color().gamma_multiply(0.6));
+    }
+    painter.line_segment(
+        [
+            egui::pos2(rect.left(), rect.top() + 18.0),
+            egui::pos2(rect.right(), rect.top() + 18.0),
+        ],
+        Stroke::new(1.0, color),
+    );
+    for (tick, align, x) in [
+        ("0", Align2::LEFT_TOP, rect.left()),
+        ("201 600", Align2::CENTER_TOP, rect.center().x),
+        ("403 200", Align2::RIGHT_TOP, rect.right()),
+    ] {
+        painter.text(
+            egui::pos2(x, rect.top() + 22.0),
+            align,
+            tick,
+            font.clone(),
+            color,
+        );
+    }
+}
+
+/// Tooltips explaining a Dwarf Fortress notion
+#[easy_ext::ext(HelpExt)]
+pub impl Response {
+    fn on_hover_help(self, topic: Topic) -> Self {
+        self.on_hover_ui(|ui| topic.show(ui))
+    }
+}
+
+/// Small "?" marker showing the help of a topic when hovered
+pub fn help_marker(ui: &mut Ui, topic: Topic) -> Response {
+    ui.weak("(?)").on_hover_help(topic)
+}
@@ -0,0 +1,54 @@
+use crate::{
+    calendar::TimeOfTheYear,
+    export::{Elevation, ExportOptions},
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+/// Extension of the export job files
+pub const JOB_EXTENSION: &str = "voxuristi";
+
+/// Export stored in a .voxuristi file, run again with `vox-uristi job`
+#[derive(Serialize, Deserialize)]
+pub struct Job {
+    pub low_elevation: Elevation,
+    pub high_elevation: Elevation,
+    pub time: TimeOfTheYear,
+    /// Model written by the export
+    pub destination: PathBuf,
+    pub options: ExportOptions,
+}
+
+impl Job {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Opening the job {}", path.display()))?;
+        serde_json::from_reader(file).with_context(|| format!("Reading the job {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        serde_json::to_writer_pretty(File::create(path)?, self)?;
+        Ok(())
+    }
+
+    /// Run the export, returning the path of the models saved
+    pub fn run(self) -> Result<Vec<PathBuf>> {
+        let month = match self.time {
+            TimeOfTheYear::Current => None,
+            TimeOfTheYear::Month(month) => Some(month),
+        };
+        super::cli::export(
+            Some(self.low_elevation),
+            Some(self.high_elevation),
+            Vec::new(),
+            false,
+            self.destination,
+            month,
+            self.options,
+        )
+    }
+}
@@ -0,0 +1,105 @@
+use crate::{
+    calendar::TimeOfTheYear,
+    capabilities::Capabilities,
+    context::DFContext,
+    export::{Elevation, ExportOptions, ExportSettings},
+    heightmap::TopView,
+    map::Map,
+    rfr::{self, DFHackExt},
+};
+use anyhow::Result;
+use dfhack_remote::MapBlock;
+use itertools::Itertools;
+use std::collections::HashMap;
+
+/// Blocks and context read for the previews, kept from one preview to the next
+///
+/// Shifting the elevation range only reads the levels not seen yet, and changing the
+/// options or the time only reads again the parts of the context depending on them.
+#[derive(Default)]
+pub struct PreviewCache {
+    /// Context of the latest preview
+    context: Option<DFContext>,
+    /// Blocks of each level read so far
+    levels: HashMap<i32, Vec<MapBlock>>,
+}
+
+impl PreviewCache {
+    /// Forget what was read so far, to see what changed in the fortress
+    pub fn clear(&mut self) {
+        self.context = None;
+        self.levels.clear();
+    }
+
+    /// Top-down view of the elevation range, both ends included.
+    /// None if nothing is visible.
+    pub fn render(
+        &mut self,
+        client: &mut dfhack_remote::Client,
+        low: Elevation,
+        high: Elevation,
+        time: TimeOfTheYear,
+        options: ExportOptions,
+    ) -> Result<Option<TopView>> {
+        let settings = ExportSettings {
+            year_tick: time.ticks(client),
+            options,
+        };
+        let context = match self.context.take() {
+            Some(mut context) => {
+                context.update_settings(client, settings)?;
+                context
+            }
+            None => {
+                let capabilities = Capabilities::probe(client)?;
+                DFContext::try_new(client, settings, capabilities)?
+            }
+        };
+
+        let z_offset = client.elevation_offset()?;
+        let z_range = (low.0 - z_offset)..(high.0 + 1 - z_offset);
+        let missing = z_range
+            .clone()
+            .filter(|z| !self.levels.contains_key(z))
+            .collect_vec();
+        if !missing.is_empty() {
+            // Only the blocks changed since the previous request are sent otherwise
+            client.remote_fortress_reader().reset_map_hashes()?;
+        }
+        for z in missing {
+            let mut blocks = Vec::new();
+            let block_lists = rfr::BlockListIterator::try_new(
+                client,
+                rfr::DEFAULT_BLOCKS_PER_IT,
+                0..1000,
+                0..1000,
+                z..z + 1,
+            )?;
+            for block_list in block_lists {
+                blocks.extend(block_list?.map_blocks);
+            }
+            self.levels.insert(z, blocks);
+        }
+
+        let mut blocks = z_range
+            .clone()
+            .filter_map(|z| self.levels.get(&z))
+            .flatten()
+            .cloned()
+            .collect_vec();
+        if context.settings.options.reveal {
+            for block in &mut blocks {
+                block.hidden.fill(false);
+            }
+        }
+        let view = {
+            let mut map = Map::default();
+            for block in &blocks {
+                map.add_block(block, &context);
+            }
+            TopView::new(&map, &context, &z_range)
+        };
+        self.context = Some(context);
+        Ok(view)
+    }
+}
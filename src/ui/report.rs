@@ -0,0 +1,74 @@
+use crate::{
+    export::Elevation,
+    rfr::{BlockListIterator, DFHackExt},
+    VERSION,
+};
+use anyhow::Result;
+use dfhack_remote::BlockList;
+use protobuf::Message;
+use serde::Serialize;
+use std::{collections::VecDeque, fs::File, io::Write, path::Path};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+/// Lines of the export log kept for the issue reports
+pub const LOG_LINES: usize = 200;
+
+/// Content of the diagnostic bundle attached to the GitHub issues
+pub struct IssueReport<'a, S: Serialize> {
+    pub error: &'a str,
+    /// Settings of the failed export
+    pub settings: &'a S,
+    pub dfhack_version: Option<&'a str>,
+    pub log: &'a VecDeque<String>,
+}
+
+impl<S: Serialize> IssueReport<'_, S> {
+    /// Write the report as a .zip. With a connection to DFHack, the map blocks
+    /// of the given level are included, to reproduce the export
+    pub fn write(
+        &self,
+        path: &Path,
+        blocks: Option<(&mut dfhack_remote::Client, Elevation)>,
+    ) -> Result<()> {
+        let mut zip = ZipWriter::new(File::create(path)?);
+        let options = SimpleFileOptions::default();
+
+        zip.start_file("system.txt", options)?;
+        writeln!(zip, "Vox Uristi {VERSION}")?;
+        writeln!(
+            zip,
+            "OS: {} {}",
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        )?;
+        writeln!(
+            zip,
+            "DFHack: {}",
+            self.dfhack_version.unwrap_or("not connected")
+        )?;
+
+        zip.start_file("error.txt", options)?;
+        writeln!(zip, "{}", self.error)?;
+
+        zip.start_file("settings.json", options)?;
+        serde_json::to_writer_pretty(&mut zip, self.settings)?;
+
+        zip.start_file("log.txt", options)?;
+        for line in self.log {
+            writeln!(zip, "{line}")?;
+        }
+
+        if let Some((df, elevation)) = blocks {
+            let z = elevation.0 - df.elevation_offset()?;
+            let mut dump = BlockList::new();
+            for block_list in BlockListIterator::try_new(df, 100, 0..1000, 0..1000, z..z + 1)? {
+                dump.map_blocks.extend(block_list?.map_blocks);
+            }
+            zip.start_file(format!("blocks_{elevation}.bin"), options)?;
+            zip.write_all(&dump.write_to_bytes()?)?;
+        }
+
+        zip.finish()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use super::job::{Job, JOB_EXTENSION};
+use anyhow::Result;
+#[cfg(windows)]
+use anyhow::{bail, Context};
+use std::path::Path;
+
+/// Registry class of the job files
+#[cfg(windows)]
+const JOB_CLASS: &str = "VoxUristi.Job";
+
+/// Add a file to the recent documents of the shell. The jump list of the application
+/// lists the recent files of the types it is associated with
+#[cfg(windows)]
+pub fn add_recent(path: &Path) {
+    use std::{ffi::c_void, os::windows::ffi::OsStrExt};
+
+    /// `pv` is a null terminated wide string path
+    const SHARD_PATHW: u32 = 3;
+    #[link(name = "shell32")]
+    extern "system" {
+        fn SHAddToRecentDocs(flags: u32, pv: *const c_void);
+    }
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    // SAFETY: the path is null terminated and outlives the call
+    unsafe { SHAddToRecentDocs(SHARD_PATHW, wide.as_ptr().cast()) };
+}
+
+#[cfg(not(windows))]
+pub fn add_recent(_path: &Path) {}
+
+/// Open the .voxuristi job files with this executable, for the current user
+#[cfg(windows)]
+pub fn associate_jobs() -> Result<()> {
+    let exe = std::env::current_exe()?;
+    let command = format!("\"{}\" job \"%1\"", exe.display());
+    let keys = [
+        (
+            format!(r"HKCU\Software\Classes\.{JOB_EXTENSION}"),
+            JOB_CLASS.to_string(),
+        ),
+        (
+            format!(r"HKCU\Software\Classes\{JOB_CLASS}"),
+            "Vox Uristi export".to_string(),
+        ),
+        (
+            format!(r"HKCU\Software\Classes\{JOB_CLASS}\shell\open\command"),
+            command,
+        ),
+    ];
+    for (key, value) in keys {
+        let status = std::process::Command::new("reg")
+            .args(["add", &key, "/ve", "/d", &value, "/f"])
+            .status()
+            .context("Running reg")?;
+        if !status.success() {
+            bail!("Failed to write the registry key {key}");
+        }
+    }
+    Ok(())
+}
+
+/// Keep the job of a recent export in the configuration folder, and list it in the
+/// jump list so that it can be run again from the taskbar
+pub fn remember_job(job: &Job) -> Result<()> {
+    let Some(folder) = crate::profile::config_dir().map(|dir| dir.join("recent")) else {
+        return Ok(());
+    };
+    std::fs::create_dir_all(&folder)?;
+    let stem = job
+        .destination
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let path = folder.join(format!("{stem}.{JOB_EXTENSION}"));
+    job.save(&path)?;
+    add_recent(&path);
+    Ok(())
+}
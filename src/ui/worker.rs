@@ -0,0 +1,79 @@
+use anyhow::{anyhow, Context, Result};
+use eframe::egui;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+
+type Job = Box<dyn FnOnce(&mut Option<dfhack_remote::Client>) + Send>;
+
+/// Connection to DFHack owned by a background thread, so that the slow remote calls never
+/// block the interface. The requests are queued and run in order, several can be sent
+/// before the first answer
+pub struct DFWorker {
+    jobs: Sender<Job>,
+}
+
+/// Answer of a request, polled by the interface
+pub struct Pending<T> {
+    receiver: Receiver<Result<T>>,
+}
+
+impl Default for DFWorker {
+    fn default() -> Self {
+        let (jobs, receiver) = channel::<Job>();
+        std::thread::spawn(move || {
+            // Connected on the first request
+            let mut df = None;
+            for job in receiver {
+                job(&mut df);
+            }
+        });
+        Self { jobs }
+    }
+}
+
+impl DFWorker {
+    /// Queue a request, the interface is repainted once it is answered
+    pub fn request<T: Send + 'static>(
+        &self,
+        ctx: &egui::Context,
+        request: impl FnOnce(&mut dfhack_remote::Client) -> Result<T> + Send + 'static,
+    ) -> Pending<T> {
+        let (sender, receiver) = channel();
+        let ctx = ctx.clone();
+        let job: Job = Box::new(move |df| {
+            // The interface may not wait for the answer anymore
+            let _ = sender.send(run(df, request));
+            ctx.request_repaint();
+        });
+        // The worker only stops with the application, the request then fails when polled
+        let _ = self.jobs.send(job);
+        Pending { receiver }
+    }
+}
+
+impl<T> Pending<T> {
+    /// The answer, once it arrived
+    pub fn poll(&self) -> Option<Result<T>> {
+        match self.receiver.try_recv() {
+            Ok(answer) => Some(answer),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(anyhow!("The DFHack worker stopped"))),
+        }
+    }
+}
+
+/// Run a request, connecting first if needed. The connection is dropped when the request
+/// fails, and opened again by the next one
+fn run<T>(
+    df: &mut Option<dfhack_remote::Client>,
+    request: impl FnOnce(&mut dfhack_remote::Client) -> Result<T>,
+) -> Result<T> {
+    let mut client = match df.take() {
+        Some(client) => client,
+        None => dfhack_remote::connect().context("Connecting to DFHack")?,
+    };
+    let answer = request(&mut client);
+    if answer.is_ok() {
+        *df = Some(client);
+    }
+    answer
+}
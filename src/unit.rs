@@ -0,0 +1,392 @@
+use crate::{
+    context::DFContext,
+    overlay::Overlay,
+    palette::{Material, Palette},
+    rfr::DFHackExt,
+    DFMapCoords, VoxelCoords, BASE, HEIGHT,
+};
+use anyhow::Result;
+use dfhack_remote::MatPair;
+use dot_vox::{Model, Size, Voxel};
+use itertools::Itertools;
+use std::ops::Range;
+
+/// Colors of the squads without a uniform color, by squad
+const SQUAD_COLORS: [(u8, u8, u8); 6] = [
+    (180, 40, 40),
+    (40, 90, 180),
+    (40, 150, 60),
+    (200, 160, 30),
+    (130, 50, 160),
+    (30, 160, 160),
+];
+
+/// Part of the body covered by a worn item, or holding a weapon
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodySlot {
+    Head,
+    Torso,
+    Legs,
+    Feet,
+    Hand,
+}
+
+/// Unit standing on the map, with the materials of its worn clothes and armor
+#[derive(Debug, Clone)]
+pub struct UnitFigure {
+    pub coords: DFMapCoords,
+    pub worn: Vec<(BodySlot, MatPair)>,
+    /// Squad of the soldiers, with the color of its uniform if it has one
+    pub squad: Option<(i32, Option<(u8, u8, u8)>)>,
+}
+
+/// Read the living units on the map and their worn equipment.
+/// The ambushers and thieves the player did not discover are left out unless `ambushers` is set
+pub fn read_units(client: &mut dfhack_remote::Client, ambushers: bool) -> Result<Vec<UnitFigure>> {
+    let output = client.lua_output(
+        r#"local slots = {
+  [df.item_type.HELM] = "head",
+  [df.item_type.ARMOR] = "torso",
+  [df.item_type.PANTS] = "legs",
+  [df.item_type.SHOES] = "feet",
+}
+-- Color of the first uniform item with a color, -1 -1 -1 if none
+local function uniform_color(squad)
+  local ok, color = pcall(function()
+    for _, position in ipairs(squad.positions) do
+      for _, specs in ipairs(position.equipment.uniform) do
+        for _, spec in ipairs(specs) do
+          if spec.color >= 0 then return df.global.world.raws.descriptors.colors[spec.color] end
+        end
+      end
+    end
+  end)
+  if ok and color then
+    return math.floor(color.red * 255), math.floor(color.green * 255), math.floor(color.blue * 255)
+  end
+  return -1, -1, -1
+end
+for _, unit in ipairs(df.global.world.units.active) do
+  if dfhack.units.isActive(unit) and not dfhack.units.isDead(unit) and not unit.flags1.caged then
+    local hidden = (unit.flags1.hidden_in_ambush or unit.flags1.hidden_ambusher) and 1 or 0
+    local squad = df.squad.find(unit.military.squad_id)
+    local r, g, b = -1, -1, -1
+    if squad then r, g, b = uniform_color(squad) end
+    local line = {unit.pos.x, unit.pos.y, unit.pos.z, hidden, squad and squad.id or -1, r, g, b}
+    for _, inventory in ipairs(unit.inventory) do
+      local slot = slots[inventory.item:getType()]
+      if inventory.mode == df.unit_inventory_item.T_mode.Weapon then slot = "hand"
+      elseif inventory.mode ~= df.unit_inventory_item.T_mode.Worn then slot = nil end
+      if slot then
+        table.insert(line, slot)
+        table.insert(line, inventory.item:getActualMaterial())
+        table.insert(line, inventory.item:getActualMaterialIndex())
+      end
+    end
+    emit(table.unpack(line))
+  end
+end"#,
+    )?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let x = parts.next()?.parse().ok()?;
+            let y = parts.next()?.parse().ok()?;
+            let z = parts.next()?.parse().ok()?;
+            let hidden = parts.next()? == "1";
+            if hidden && !ambushers {
+                return None;
+            }
+            let squad_id: i32 = parts.next()?.parse().ok()?;
+            let color: Vec<i32> = parts
+                .by_ref()
+                .take(3)
+                .filter_map(|v| v.parse().ok())
+                .collect();
+            let uniform = match color[..] {
+                [r, g, b] if r >= 0 => Some((r as u8, g as u8, b as u8)),
+                _ => None,
+            };
+            let worn = parts
+                .tuples()
+                .filter_map(|(slot, mat_type, mat_index)| {
+                    let slot = match slot {
+                        "head" => BodySlot::Head,
+                        "torso" => BodySlot::Torso,
+                        "legs" => BodySlot::Legs,
+                        "feet" => BodySlot::Feet,
+                        "hand" => BodySlot::Hand,
+                        _ => return None,
+                    };
+                    let material = MatPair {
+                        mat_type: Some(mat_type.parse().ok()?),
+                        mat_index: Some(mat_index.parse().ok()?),
+                        ..Default::default()
+                    };
+                    Some((slot, material))
+                })
+                .collect();
+            Some(UnitFigure {
+                coords: DFMapCoords::new(x, y, z),
+                worn,
+                squad: (squad_id >= 0).then_some((squad_id, uniform)),
+            })
+        })
+        .collect())
+}
+
+/// Palette entries of the parts of a unit
+struct Outfit {
+    head: u8,
+    torso: u8,
+    legs: u8,
+    feet: u8,
+    weapon: Option<u8>,
+}
+
+impl Outfit {
+    /// Materials of the worn equipment, the squad color over the torso of the soldiers
+    fn new(unit: &UnitFigure, context: &DFContext, palette: &Palette) -> Self {
+        let skin = palette.get(&Material::Color(200, 160, 120), context);
+        let cloth = palette.get(&Material::Color(120, 100, 80), context);
+        let worn = |slot: BodySlot| {
+            unit.worn
+                .iter()
+                .find(|(worn_slot, _)| *worn_slot == slot)
+                .map(|(_, material)| palette.get(&Material::Generic(material.clone()), context))
+        };
+        let legs = worn(BodySlot::Legs).unwrap_or(cloth);
+        let squad_color = unit.squad.map(|(id, uniform)| {
+            let (r, g, b) =
+                uniform.unwrap_or(SQUAD_COLORS[id.rem_euclid(SQUAD_COLORS.len() as i32) as usize]);
+            palette.get(&Material::Color(r, g, b), context)
+        });
+        Self {
+            head: worn(BodySlot::Head).unwrap_or(skin),
+            torso: squad_color
+                .or_else(|| worn(BodySlot::Torso))
+                .unwrap_or(cloth),
+            legs,
+            feet: worn(BodySlot::Feet).unwrap_or(legs),
+            weapon: worn(BodySlot::Hand),
+        }
+    }
+}
+
+/// Small figures standing on the floor: feet, legs, torso and head,
+/// dressed in the materials of their worn equipment.
+/// The soldiers wear the color of their squad, and hold their weapon out
+pub fn build_units(
+    units: &[UnitFigure],
+    z_range: &Range<i32>,
+    context: &DFContext,
+    palette: &Palette,
+) -> Overlay {
+    let mut overlay = Overlay::default();
+    for unit in units {
+        if !z_range.contains(&unit.coords.z) {
+            continue;
+        }
+        let outfit = Outfit::new(unit, context, palette);
+        // The floor is at z=0
+        for (x, z, color) in [
+            (0, 1, outfit.feet),
+            (2, 1, outfit.feet),
+            (0, 2, outfit.legs),
+            (1, 2, outfit.legs),
+            (2, 2, outfit.legs),
+            (0, 3, outfit.torso),
+            (1, 3, outfit.torso),
+            (2, 3, outfit.torso),
+            (1, 4, outfit.head),
+        ] {
+            overlay.add(VoxelCoords::from_df(unit.coords, x, 1, z), color);
+        }
+        if let Some(weapon) = outfit.weapon {
+            // Held in front of the torso
+            overlay.add(VoxelCoords::from_df(unit.coords, 2, 0, 3), weapon);
+        }
+    }
+    overlay
+}
+
+/// Part of a close-up figure, dressed in the matching material of the outfit
+#[derive(Clone, Copy)]
+enum Part {
+    Head,
+    Torso,
+    Legs,
+    Feet,
+    Weapon,
+}
+
+/// Parts of the close-up figures, as boxes in tenths of the voxels of a tile: x and y
+/// from 0 to 30 (the y axis going south), z from 0 to 50 with the floor below 10
+const CLOSE_UP_PARTS: [(Part, [(u8, u8); 3]); 9] = [
+    (Part::Feet, [(5, 13), (10, 20), (10, 14)]),
+    (Part::Feet, [(17, 25), (10, 20), (10, 14)]),
+    (Part::Legs, [(6, 13), (11, 19), (14, 26)]),
+    (Part::Legs, [(17, 24), (11, 19), (14, 26)]),
+    (Part::Torso, [(5, 25), (10, 20), (26, 39)]),
+    // Sleeves
+    (Part::Torso, [(2, 5), (12, 18), (27, 38)]),
+    (Part::Torso, [(25, 28), (12, 18), (27, 38)]),
+    (Part::Head, [(10, 20), (10, 20), (39, 48)]),
+    // Held forward in the right hand
+    (Part::Weapon, [(25, 28), (2, 12), (29, 32)]),
+];
+
+/// Paper dolls of the units, `detail` times finer than the tiles, for the close-up renders
+///
+/// Each unit is a model of its own covering its tile, with separate head, torso, arms, legs
+/// and feet in the materials of its worn equipment. Returned with the tile of the unit.
+pub fn build_close_ups(
+    units: &[UnitFigure],
+    z_range: &Range<i32>,
+    context: &DFContext,
+    palette: &Palette,
+    detail: u8,
+) -> Vec<(DFMapCoords, Model)> {
+    let detail = detail as u32;
+    let size = Size {
+        x: BASE as u32 * detail,
+        y: BASE as u32 * detail,
+        z: HEIGHT as u32 * detail,
+    };
+    // Rounded to the nearest voxel of the close-up
+    let scale = |tenths: u8| (tenths as u32 * detail + 5) / 10;
+    units
+        .iter()
+        .filter(|unit| z_range.contains(&unit.coords.z))
+        .map(|unit| {
+            let outfit = Outfit::new(unit, context, palette);
+            let mut model = Model {
+                size,
+                voxels: Vec::new(),
+            };
+            for (part, [x, y, z]) in CLOSE_UP_PARTS {
+                let color = match part {
+                    Part::Head => outfit.head,
+                    Part::Torso => outfit.torso,
+                    Part::Legs => outfit.legs,
+                    Part::Feet => outfit.feet,
+                    Part::Weapon => match outfit.weapon {
+                        Some(weapon) => weapon,
+                        None => continue,
+                    },
+                };
+                for (x, y, z) in itertools::iproduct!(
+                    scale(x.0)..scale(x.1),
+                    scale(y.0)..scale(y.1),
+                    scale(z.0)..scale(z.1)
+                ) {
+                    // The y axis of the models is going north
+                    model.voxels.push(Voxel {
+                        x: x as u8,
+                        y: (size.y - 1 - y) as u8,
+                        z: z as u8,
+                        i: color,
+                    });
+                }
+            }
+            (unit.coords, model)
+        })
+        .collect()
+}
+
+/// Item flying across the map, such as an arrow or a bolt
+#[derive(Debug, Clone)]
+pub struct Projectile {
+    pub coords: DFMapCoords,
+    /// Direction of the flight on the x and y axis, each -1, 0 or 1
+    pub direction: (i32, i32),
+    pub material: MatPair,
+}
+
+/// Read the items in flight
+pub fn read_projectiles(client: &mut dfhack_remote::Client) -> Result<Vec<Projectile>> {
+    let output = client.lua_output(
+        r#"local link = df.global.world.projectiles.all.next
+while link do
+  local projectile = link.item
+  if df.proj_itemst:is_instance(projectile) and projectile.item then
+    local dx, dy = 0, 0
+    if projectile.prev_pos.x >= 0 then
+      dx = projectile.cur_pos.x - projectile.prev_pos.x
+      dy = projectile.cur_pos.y - projectile.prev_pos.y
+    end
+    emit(projectile.cur_pos.x, projectile.cur_pos.y, projectile.cur_pos.z, dx, dy,
+      projectile.item:getActualMaterial(), projectile.item:getActualMaterialIndex())
+  end
+  link = link.next
+end"#,
+    )?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let values: Vec<i32> = line
+                .split_whitespace()
+                .map(|value| value.parse().ok())
+                .collect::<Option<_>>()?;
+            let [x, y, z, dx, dy, mat_type, mat_index] = values[..] else {
+                return None;
+            };
+            Some(Projectile {
+                coords: DFMapCoords::new(x, y, z),
+                direction: (dx.signum(), dy.signum()),
+                material: MatPair {
+                    mat_type: Some(mat_type),
+                    mat_index: Some(mat_index),
+                    ..Default::default()
+                },
+            })
+        })
+        .collect())
+}
+
+/// Short dash in the air, pointing where the projectile flies to
+pub fn build_projectiles(
+    projectiles: &[Projectile],
+    z_range: &Range<i32>,
+    context: &DFContext,
+    palette: &Palette,
+) -> Overlay {
+    let mut overlay = Overlay::default();
+    for projectile in projectiles {
+        if !z_range.contains(&projectile.coords.z) {
+            continue;
+        }
+        let color = palette.get(&Material::Generic(projectile.material.clone()), context);
+        let (dx, dy) = projectile.direction;
+        // Head in the center of the tile, tail behind it
+        for (x, y) in [(1, 1), (1 - dx, 1 - dy)].into_iter().dedup() {
+            overlay.add(
+                VoxelCoords::from_df(projectile.coords, x as usize, y as usize, 3),
+                color,
+            );
+        }
+    }
+    overlay
+}
+
+/// Positions of the living invaders, empty when no siege is going on
+pub fn read_invaders(client: &mut dfhack_remote::Client) -> Result<Vec<DFMapCoords>> {
+    let output = client.lua_output(
+        r#"for _, unit in ipairs(df.global.world.units.active) do
+  if dfhack.units.isActive(unit) and not dfhack.units.isDead(unit) and dfhack.units.isInvader(unit) then
+    emit(unit.pos.x, unit.pos.y, unit.pos.z)
+  end
+end"#,
+    )?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let x = parts.next()?.parse().ok()?;
+            let y = parts.next()?.parse().ok()?;
+            let z = parts.next()?.parse().ok()?;
+            Some(DFMapCoords::new(x, y, z))
+        })
+        .collect())
+}
@@ -1,71 +1,194 @@
-use anyhow::Result;
-use reqwest::header::HeaderValue;
-use semver::Version;
-use serde::Deserialize;
-
-#[derive(Deserialize, Debug)]
-pub struct Release {
-    pub name: String,
-    pub html_url: String,
-    pub tag_name: String,
-    pub assets: Vec<Asset>,
-}
-
-#[derive(Deserialize, Debug, Clone)]
-pub struct Asset {
-    pub browser_download_url: String,
-    pub name: String,
-}
-
-pub enum UpdateStatus {
-    UpToDate,
-    NewVersion {
-        name: String,
-        release_url: String,
-        asset_url: Option<String>,
-    },
-}
-
-#[cfg(target_os = "windows")]
-fn assets_is_for_current_platform(asset: &Asset) -> bool {
-    asset.name.contains("windows")
-}
-
-#[cfg(target_os = "linux")]
-fn assets_is_for_current_platform(asset: &Asset) -> bool {
-    asset.name.contains("linux")
-}
-
-pub fn check_update() -> Result<UpdateStatus> {
-    let client = reqwest::blocking::Client::builder()
-        .user_agent("plule/vox-uristi")
-        .build()?;
-    let latest: Release = client
-        .get("https://api.github.com/repos/plule/vox-uristi/releases/latest")
-        .header(
-            "Accept",
-            HeaderValue::from_static("application/vnd.github+json"),
-        )
-        .send()?
-        .json()?;
-
-    let latest_version = Version::parse(&latest.tag_name.replace('v', ""))?;
-    let current_version = Version::parse(crate::VERSION)?;
-
-    if latest_version > current_version {
-        let asset_url = latest.assets.iter().find_map(|asset| {
-            if assets_is_for_current_platform(asset) {
-                Some(asset.browser_download_url.clone())
-            } else {
-                None
-            }
-        });
-        Ok(UpdateStatus::NewVersion {
-            name: latest.name,
-            release_url: latest.html_url,
-            asset_url,
-        })
-    } else {
-        Ok(UpdateStatus::UpToDate)
-    }
-}
+use anyhow::{anyhow, bail, Context, Result};
+use reqwest::header::HeaderValue;
+use semver::Version;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+#[derive(Deserialize, Debug)]
+pub struct Release {
+    pub name: String,
+    pub html_url: String,
+    pub tag_name: String,
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Asset {
+    pub browser_download_url: String,
+    pub name: String,
+    /// Checksum of the asset, in the form "sha256:<hex>"
+    #[serde(default)]
+    pub digest: Option<String>,
+}
+
+pub enum UpdateStatus {
+    UpToDate,
+    NewVersion {
+        name: String,
+        release_url: String,
+        asset: Option<Asset>,
+    },
+}
+
+#[cfg(target_os = "windows")]
+fn assets_is_for_current_platform(asset: &Asset) -> bool {
+    asset.name.contains("windows")
+}
+
+#[cfg(target_os = "linux")]
+fn assets_is_for_current_platform(asset: &Asset) -> bool {
+    asset.name.contains("linux")
+}
+
+fn http_client() -> Result<reqwest::blocking::Client> {
+    Ok(reqwest::blocking::Client::builder()
+        .user_agent("plule/vox-uristi")
+        .build()?)
+}
+
+pub fn check_update() -> Result<UpdateStatus> {
+    let client = http_client()?;
+    let latest: Release = client
+        .get("https://api.github.com/repos/plule/vox-uristi/releases/latest")
+        .header(
+            "Accept",
+            HeaderValue::from_static("application/vnd.github+json"),
+        )
+        .send()?
+        .json()?;
+
+    let latest_version = Version::parse(&latest.tag_name.replace('v', ""))?;
+    let current_version = Version::parse(crate::VERSION)?;
+
+    if latest_version > current_version {
+        let asset = latest
+            .assets
+            .into_iter()
+            .find(assets_is_for_current_platform);
+        Ok(UpdateStatus::NewVersion {
+            name: latest.name,
+            release_url: latest.html_url,
+            asset,
+        })
+    } else {
+        Ok(UpdateStatus::UpToDate)
+    }
+}
+
+/// Download the asset, check it against its published checksum and replace the
+/// running executable by it. Returns the path of the updated executable.
+pub fn install_update(asset: &Asset) -> Result<PathBuf> {
+    let bytes = http_client()?
+        .get(&asset.browser_download_url)
+        .send()?
+        .error_for_status()?
+        .bytes()?;
+    verify_checksum(&bytes, asset.digest.as_deref())?;
+    let executable = extract_executable(&asset.name, &bytes)?;
+
+    let current = std::env::current_exe()?;
+    let new = current.with_extension("new");
+    let old = current.with_extension("old");
+    std::fs::write(&new, executable)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&new, std::fs::Permissions::from_mode(0o755))?;
+    }
+    // A running executable can be renamed but not overwritten on Windows
+    let _ = std::fs::remove_file(&old);
+    std::fs::rename(&current, &old).context("Moving the current executable")?;
+    if let Err(err) = std::fs::rename(&new, &current) {
+        // Put the previous version back in place
+        std::fs::rename(&old, &current)?;
+        return Err(err).context("Installing the new executable");
+    }
+    Ok(current)
+}
+
+/// Start the given executable and exit the current process
+pub fn restart(executable: &Path) -> Result<()> {
+    std::process::Command::new(executable).spawn()?;
+    std::process::exit(0);
+}
+
+fn verify_checksum(bytes: &[u8], digest: Option<&str>) -> Result<()> {
+    let expected = digest
+        .and_then(|digest| digest.strip_prefix("sha256:"))
+        .ok_or_else(|| anyhow!("The release does not publish a checksum for this file"))?;
+    let actual: String = Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    if !actual.eq_ignore_ascii_case(expected) {
+        bail!("Checksum mismatch for the downloaded update");
+    }
+    Ok(())
+}
+
+/// Release assets are either the bare executable or a tar archive containing it,
+/// found by its file name among the other files of the archive
+fn extract_executable(name: &str, bytes: &[u8]) -> Result<Vec<u8>> {
+    if !name.ends_with(".tar") {
+        return Ok(bytes.to_vec());
+    }
+    let executable_name = format!("{}{}", env!("CARGO_PKG_NAME"), std::env::consts::EXE_SUFFIX);
+    let mut archive = tar::Archive::new(bytes);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let is_executable = entry.header().entry_type().is_file()
+            && entry
+                .path()?
+                .file_name()
+                .is_some_and(|file_name| file_name == executable_name.as_str());
+        if is_executable {
+            let mut executable = Vec::new();
+            entry.read_to_end(&mut executable)?;
+            return Ok(executable);
+        }
+    }
+    bail!("The update archive does not contain {executable_name}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum_verification() {
+        let digest = "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        assert!(verify_checksum(b"hello", Some(digest)).is_ok());
+        assert!(verify_checksum(b"hello!", Some(digest)).is_err());
+        assert!(verify_checksum(b"hello", None).is_err());
+    }
+
+    #[test]
+    fn executable_extraction() {
+        let executable_name = format!("{}{}", env!("CARGO_PKG_NAME"), std::env::consts::EXE_SUFFIX);
+        let archive = |files: &[(&str, &[u8])]| {
+            let mut builder = tar::Builder::new(Vec::new());
+            for (path, content) in files {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(content.len() as u64);
+                header.set_mode(0o755);
+                header.set_cksum();
+                builder.append_data(&mut header, path, *content).unwrap();
+            }
+            builder.into_inner().unwrap()
+        };
+        let release = archive(&[
+            ("README.md", b"readme"),
+            (&format!("release/{executable_name}"), b"executable"),
+        ]);
+        assert_eq!(
+            b"executable".to_vec(),
+            extract_executable("linux.tar", &release).unwrap()
+        );
+        let readme_only = archive(&[("README.md", b"readme")]);
+        assert!(extract_executable("linux.tar", &readme_only).is_err());
+    }
+}
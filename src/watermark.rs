@@ -0,0 +1,123 @@
+use crate::{
+    block::BLOCK_SIZE,
+    context::DFContext,
+    overlay::Overlay,
+    palette::{Material, Palette},
+    VoxelCoords, BASE, HEIGHT,
+};
+use std::ops::Range;
+
+/// Size of the glyphs of the font, in voxels
+const GLYPH_WIDTH: i32 = 3;
+const GLYPH_HEIGHT: i32 = 5;
+/// Gap between the glyphs, between the lines and around the text, in voxels
+const SPACING: i32 = 1;
+const PLAQUE_COLOR: Material = Material::Color(60, 50, 45);
+const TEXT_COLOR: Material = Material::Color(220, 190, 90);
+
+/// 3x5 font, rows going from north to south
+#[rustfmt::skip]
+const FONT: [(char, [&str; 5]); 40] = [
+    ('A', [".#.", "#.#", "###", "#.#", "#.#"]),
+    ('B', ["##.", "#.#", "##.", "#.#", "##."]),
+    ('C', [".##", "#..", "#..", "#..", ".##"]),
+    ('D', ["##.", "#.#", "#.#", "#.#", "##."]),
+    ('E', ["###", "#..", "##.", "#..", "###"]),
+    ('F', ["###", "#..", "##.", "#..", "#.."]),
+    ('G', [".##", "#..", "#.#", "#.#", ".##"]),
+    ('H', ["#.#", "#.#", "###", "#.#", "#.#"]),
+    ('I', ["###", ".#.", ".#.", ".#.", "###"]),
+    ('J', ["..#", "..#", "..#", "#.#", ".#."]),
+    ('K', ["#.#", "#.#", "##.", "#.#", "#.#"]),
+    ('L', ["#..", "#..", "#..", "#..", "###"]),
+    ('M', ["#.#", "###", "###", "#.#", "#.#"]),
+    ('N', ["##.", "#.#", "#.#", "#.#", "#.#"]),
+    ('O', [".#.", "#.#", "#.#", "#.#", ".#."]),
+    ('P', ["##.", "#.#", "##.", "#..", "#.."]),
+    ('Q', [".#.", "#.#", "#.#", "##.", ".##"]),
+    ('R', ["##.", "#.#", "##.", "#.#", "#.#"]),
+    ('S', [".##", "#..", ".#.", "..#", "##."]),
+    ('T', ["###", ".#.", ".#.", ".#.", ".#."]),
+    ('U', ["#.#", "#.#", "#.#", "#.#", "###"]),
+    ('V', ["#.#", "#.#", "#.#", "#.#", ".#."]),
+    ('W', ["#.#", "#.#", "###", "###", "#.#"]),
+    ('X', ["#.#", "#.#", ".#.", "#.#", "#.#"]),
+    ('Y', ["#.#", "#.#", ".#.", ".#.", ".#."]),
+    ('Z', ["###", "..#", ".#.", "#..", "###"]),
+    ('0', ["###", "#.#", "#.#", "#.#", "###"]),
+    ('1', [".#.", "##.", ".#.", ".#.", "###"]),
+    ('2', ["##.", "..#", ".#.", "#..", "###"]),
+    ('3', ["##.", "..#", ".#.", "..#", "##."]),
+    ('4', ["#.#", "#.#", "###", "..#", "..#"]),
+    ('5', ["###", "#..", "##.", "..#", "##."]),
+    ('6', [".##", "#..", "###", "#.#", "###"]),
+    ('7', ["###", "..#", ".#.", ".#.", ".#."]),
+    ('8', ["###", "#.#", "###", "#.#", "###"]),
+    ('9', ["###", "#.#", "###", "..#", "##."]),
+    (',', ["...", "...", "...", ".#.", "#.."]),
+    ('.', ["...", "...", "...", "...", ".#."]),
+    ('-', ["...", "...", "###", "...", "..."]),
+    ('\'', [".#.", ".#.", "...", "...", "..."]),
+];
+
+/// Glyph of a character, the accents of the dwarven names dropped.
+/// Blank for the spaces and the characters missing from the font
+fn glyph(c: char) -> [&'static str; 5] {
+    let c = match c.to_lowercase().next().unwrap_or(c) {
+        'á' | 'à' | 'â' | 'ä' | 'å' => 'A',
+        'é' | 'è' | 'ê' | 'ë' => 'E',
+        'í' | 'ì' | 'î' | 'ï' => 'I',
+        'ó' | 'ò' | 'ô' | 'ö' => 'O',
+        'ú' | 'ù' | 'û' | 'ü' => 'U',
+        'ñ' => 'N',
+        'ç' => 'C',
+        c => c.to_ascii_uppercase(),
+    };
+    FONT.iter()
+        .find(|(character, _)| *character == c)
+        .map_or(["..."; 5], |(_, rows)| *rows)
+}
+
+/// Plaque lying just south of the south-west corner of the map, at the lowest exported level,
+/// with the lines of text in relief
+pub fn build_watermark(
+    lines: &[String],
+    z_range: &Range<i32>,
+    context: &DFContext,
+    palette: &Palette,
+) -> Overlay {
+    let plaque = palette.get(&PLAQUE_COLOR, context);
+    let text = palette.get(&TEXT_COLOR, context);
+    let columns = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0) as i32;
+    let width = columns * (GLYPH_WIDTH + SPACING) + SPACING;
+    let depth = lines.len() as i32 * (GLYPH_HEIGHT + SPACING) + SPACING;
+    // Leave a gap between the map and the plaque
+    let origin_y = context.map_info.block_size_y() * (BLOCK_SIZE * BASE) as i32 + SPACING;
+    let z = z_range.start * HEIGHT as i32;
+
+    let mut overlay = Overlay::default();
+    for x in 0..width {
+        for y in 0..depth {
+            overlay.add(VoxelCoords::new(x, origin_y + y, z), plaque);
+        }
+    }
+    for (row, line) in lines.iter().enumerate() {
+        let top = origin_y + SPACING + row as i32 * (GLYPH_HEIGHT + SPACING);
+        for (column, c) in line.chars().enumerate() {
+            let left = SPACING + column as i32 * (GLYPH_WIDTH + SPACING);
+            for (dy, pattern) in glyph(c).iter().enumerate() {
+                for (dx, pixel) in pattern.chars().enumerate() {
+                    if pixel == '#' {
+                        let coords = VoxelCoords::new(left + dx as i32, top + dy as i32, z + 1);
+                        overlay.add(coords, text);
+                    }
+                }
+            }
+        }
+    }
+    overlay
+}
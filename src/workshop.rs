@@ -0,0 +1,73 @@
+use crate::{
+    context::DFContext,
+    overlay::Overlay,
+    palette::{DefaultMaterials, Material, Palette},
+    rfr::DFHackExt,
+    DFMapCoords, VoxelCoords,
+};
+use anyhow::Result;
+use std::ops::Range;
+
+/// Read the center of the furnaces and forges currently working on a job
+pub fn read_active_furnaces(client: &mut dfhack_remote::Client) -> Result<Vec<DFMapCoords>> {
+    let output = client.lua_output(
+        r#"local forges = {
+  [df.workshop_type.MetalsmithsForge] = true,
+  [df.workshop_type.MagmaForge] = true,
+}
+for _, building in ipairs(df.global.world.buildings.all) do
+  local hot = df.building_furnacest:is_instance(building)
+    or (df.building_workshopst:is_instance(building) and forges[building.type])
+  if hot then
+    for _, job in ipairs(building.jobs) do
+      if job.flags.working then
+        emit(building.centerx, building.centery, building.z)
+        break
+      end
+    end
+  end
+end"#,
+    )?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let values: Vec<i32> = line
+                .split_whitespace()
+                .filter_map(|v| v.parse().ok())
+                .collect();
+            match values[..] {
+                [x, y, z] => Some(DFMapCoords::new(x, y, z)),
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+/// Fire burning in the hearth of the active furnaces, and smoke escaping from their chimney
+pub fn build_fires(
+    furnaces: &[DFMapCoords],
+    z_range: &Range<i32>,
+    context: &DFContext,
+    palette: &Palette,
+) -> (Overlay, Overlay) {
+    let fire = palette.get(&Material::Default(DefaultMaterials::Fire), context);
+    let smoke = palette.get(&Material::Default(DefaultMaterials::Smoke), context);
+    let mut fires = Overlay::default();
+    let mut smokes = Overlay::default();
+    for furnace in furnaces {
+        if !z_range.contains(&furnace.z) {
+            continue;
+        }
+        for z in 1..=2 {
+            fires.add(VoxelCoords::from_df(*furnace, 1, 1, z), fire);
+        }
+        // The chimney goes through the top of the building, in the level above
+        let above = DFMapCoords::new(furnace.x, furnace.y, furnace.z + 1);
+        if z_range.contains(&above.z) {
+            for (x, y, z) in [(1, 1, 0), (1, 1, 1), (2, 1, 2), (1, 0, 3)] {
+                smokes.add(VoxelCoords::from_df(above, x, y, z), smoke);
+            }
+        }
+    }
+    (fires, smokes)
+}